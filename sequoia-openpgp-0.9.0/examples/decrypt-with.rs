@@ -10,6 +10,7 @@ extern crate sequoia_openpgp as openpgp;
 
 use openpgp::crypto::{KeyPair, SessionKey};
 use openpgp::constants::SymmetricAlgorithm;
+use openpgp::policy::StandardPolicy;
 use openpgp::parse::{
     Parse,
     stream::{
@@ -37,8 +38,10 @@ pub fn main() {
         }).collect();
 
     // Now, create a decryptor with a helper using the given TPKs.
+    let policy = StandardPolicy::new();
     let mut decryptor =
-        Decryptor::from_reader(io::stdin(), Helper::new(tpks), None).unwrap();
+        Decryptor::from_reader(&policy, io::stdin(), Helper::new(tpks), None)
+        .unwrap();
 
     // Finally, stream the decrypted data to stdout.
     io::copy(&mut decryptor, &mut io::stdout())
@@ -0,0 +1,108 @@
+/// Decrypts OpenPGP messages using secret keys and passwords.
+///
+/// Uses the openpgp crate, Sequoia's low-level API.
+
+use std::env;
+use std::io;
+
+extern crate failure;
+extern crate sequoia_openpgp as openpgp;
+
+use openpgp::policy::StandardPolicy;
+use openpgp::parse::{
+    Parse,
+    stream::{
+        DecryptedWith,
+        Decryptor,
+        MessageLayer,
+        MessageStructure,
+        SecretsHelper,
+        VerificationHelper,
+        VerificationResult,
+    },
+};
+
+pub fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        panic!("A simple decryption filter.\n\n\
+                Usage: {} [--password <password>]... [<keyfile>...] \
+                <input >output\n", args[0]);
+    }
+
+    // Collect the passwords and the transferable secret keys.
+    let mut passwords = Vec::new();
+    let mut tpks = Vec::new();
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        if arg == "--password" {
+            passwords.push(args.next().expect("Missing password").clone());
+        } else {
+            tpks.push(openpgp::TPK::from_file(arg)
+                      .expect("Failed to read key"));
+        }
+    }
+
+    let mut helper = SecretsHelper::new(Helper { tpks: tpks.clone() });
+    for tpk in tpks.iter() {
+        helper = helper.add_tpk(tpk);
+    }
+    for password in passwords {
+        helper = helper.add_password(password);
+    }
+
+    // Now, create a decryptor with the helper.
+    let policy = StandardPolicy::new();
+    let mut decryptor =
+        Decryptor::from_reader(&policy, io::stdin(), helper, None)
+        .expect("Decryption failed");
+
+    match decryptor.helper_ref().decrypted_with() {
+        Some(DecryptedWith::Key { tpk, key }) =>
+            eprintln!("Decrypted using key {} of {}", key, tpk),
+        Some(DecryptedWith::Password(i)) =>
+            eprintln!("Decrypted using password #{}", i + 1),
+        None => unreachable!("the message has been decrypted"),
+    }
+
+    // Finally, stream the decrypted data to stdout.
+    io::copy(&mut decryptor, &mut io::stdout())
+        .expect("Decryption failed");
+}
+
+/// Fetches public keys for the signature verification and implements
+/// the verification policy.
+struct Helper {
+    tpks: Vec<openpgp::TPK>,
+}
+
+impl VerificationHelper for Helper {
+    fn get_public_keys(&mut self, _ids: &[openpgp::KeyID])
+                       -> failure::Fallible<Vec<openpgp::TPK>> {
+        // The given keys may also have been used to sign the message.
+        Ok(self.tpks.clone())
+    }
+
+    fn check(&mut self, structure: &MessageStructure)
+             -> failure::Fallible<()> {
+        use self::VerificationResult::*;
+        for layer in structure.iter() {
+            if let MessageLayer::SignatureGroup { ref results } = layer {
+                for result in results {
+                    match result {
+                        GoodChecksum(ref sig, ..) =>
+                            eprintln!("Good signature from {}",
+                                      sig.issuer().expect("has an issuer")),
+                        MissingKey(ref sig) =>
+                            eprintln!("No key to check signature from {:?}",
+                                      sig.issuer()),
+                        BadChecksum(ref sig) =>
+                            eprintln!("Bad signature from {:?}",
+                                      sig.issuer()),
+                    }
+                }
+            }
+        }
+        Ok(()) // Implement your verification policy here.
+    }
+}
@@ -7,6 +7,7 @@ use openpgp::crypto::SessionKey;
 use openpgp::constants::SymmetricAlgorithm;
 use openpgp::serialize::stream::*;
 use openpgp::parse::stream::*;
+use openpgp::policy::StandardPolicy;
 
 const MESSAGE: &'static str = "дружба";
 
@@ -74,7 +75,9 @@ fn decrypt(sink: &mut Write, ciphertext: &[u8], recipient: &openpgp::TPK)
     };
 
     // Now, create a decryptor with a helper using the given TPKs.
-    let mut decryptor = Decryptor::from_bytes(ciphertext, helper, None)?;
+    let policy = StandardPolicy::new();
+    let mut decryptor = Decryptor::from_bytes(&policy, ciphertext, helper,
+                                              None)?;
 
     // Decrypt the data.
     io::copy(&mut decryptor, sink)?;
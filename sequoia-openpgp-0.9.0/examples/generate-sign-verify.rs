@@ -6,6 +6,7 @@ extern crate failure;
 extern crate sequoia_openpgp as openpgp;
 use openpgp::serialize::stream::*;
 use openpgp::parse::stream::*;
+use openpgp::policy::StandardPolicy;
 
 const MESSAGE: &'static str = "дружба";
 
@@ -73,7 +74,9 @@ fn verify(sink: &mut Write, signed_message: &[u8], sender: &openpgp::TPK)
     };
 
     // Now, create a verifier with a helper using the given TPKs.
-    let mut verifier = Verifier::from_bytes(signed_message, helper, None)?;
+    let policy = StandardPolicy::new();
+    let mut verifier = Verifier::from_bytes(&policy, signed_message, helper,
+                                            None)?;
 
     // Verify the data.
     io::copy(&mut verifier, sink)?;
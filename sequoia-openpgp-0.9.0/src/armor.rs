@@ -6,8 +6,13 @@
 //!
 //! # Scope
 //!
-//! This implements a subset of the ASCII Armor specification.  Not
-//! supported multipart messages.
+//! This implements a subset of the ASCII Armor specification.
+//! Multi-part messages (i.e. `BEGIN PGP MESSAGE, PART X/Y`) are
+//! supported, but only if the total number of parts is given.  Use
+//! [`MultipartReader`] to transparently concatenate consecutive
+//! parts.
+//!
+//! [`MultipartReader`]: struct.MultipartReader.html
 //!
 //! # Memory allocations
 //!
@@ -47,6 +52,28 @@ pub(crate) const LINE_LENGTH: usize = 64;
 
 const LINE_ENDING: &str = "\n";
 
+/// The armor header line of a cleartext signed message (see [RFC
+/// 4880, section 7]).
+///
+/// [RFC 4880, section 7]: https://tools.ietf.org/html/rfc4880#section-7
+pub(crate) const BEGIN_SIGNED_MESSAGE: &[u8] =
+    b"-----BEGIN PGP SIGNED MESSAGE-----";
+
+/// Strips trailing spaces and tabs.
+///
+/// These are not part of the text signed in a cleartext signed
+/// message.
+pub(crate) fn trim_trailing_whitespace(mut line: &[u8]) -> &[u8] {
+    while let Some(&c) = line.last() {
+        if c == b' ' || c == b'\t' {
+            line = &line[..line.len() - 1];
+        } else {
+            break;
+        }
+    }
+    line
+}
+
 /// Specifies the type of data (see [RFC 4880, section 6.2]).
 ///
 /// [RFC 4880, section 6.2]: https://tools.ietf.org/html/rfc4880#section-6.2
@@ -90,7 +117,9 @@ impl Kind {
         }
 
         let kind = &blurb[15..];
-        if kind.starts_with(b"MESSAGE-----") {
+        if kind.starts_with(b"MESSAGE-----")
+            || kind.starts_with(b"MESSAGE, PART ")
+        {
             Some(Kind::Message)
         } else if kind.starts_with(b"PUBLIC KEY BLOCK-----") {
             Some(Kind::PublicKey)
@@ -105,6 +134,39 @@ impl Kind {
         }
     }
 
+    /// Checks whether the given top-level packets may be armored
+    /// using this kind.
+    ///
+    /// Key blocks must start with a key packet of the right type, a
+    /// detached signature must consist of signature packets only,
+    /// and a message must start with a packet that can start an
+    /// OpenPGP message.  Anything can be armored as `Kind::File`.
+    pub(crate) fn check_packets<'a, I>(&self, packets: I) -> ::Result<()>
+        where I: IntoIterator<Item=&'a Packet>
+    {
+        let mut packets = packets.into_iter().peekable();
+        let first = packets.peek().map(|p| p.tag());
+        let ok = match (self, first) {
+            (_, None) => true,
+            (&Kind::File, _) => true,
+            (&Kind::PublicKey, Some(tag)) => tag == Tag::PublicKey,
+            (&Kind::SecretKey, Some(tag)) => tag == Tag::SecretKey,
+            (&Kind::Signature, _) =>
+                packets.all(|p| p.tag() == Tag::Signature),
+            (&Kind::Message, Some(tag)) =>
+                tag.valid_start_of_message()
+                && tag != Tag::PublicKey && tag != Tag::SecretKey,
+        };
+
+        if ok {
+            Ok(())
+        } else {
+            Err(::Error::InvalidArgument(
+                format!("Packets do not match armor kind {:?}", self))
+                .into())
+        }
+    }
+
     fn blurb(&self) -> &str {
         match self {
             &Kind::Message => "MESSAGE",
@@ -115,12 +177,12 @@ impl Kind {
         }
     }
 
-    fn begin(&self) -> String {
-        format!("-----BEGIN PGP {}-----", self.blurb())
+    fn begin(&self, part: Option<(usize, usize)>) -> String {
+        format!("-----BEGIN PGP {}{}-----", self.blurb(), part_suffix(part))
     }
 
-    fn end(&self) -> String {
-        format!("-----END PGP {}-----", self.blurb())
+    fn end(&self, part: Option<(usize, usize)>) -> String {
+        format!("-----END PGP {}{}-----", self.blurb(), part_suffix(part))
     }
 
     /// Returns the length of the header.
@@ -137,21 +199,69 @@ impl Kind {
     }
 
     /// Returns the maximal size of the footer with CRC.
-    fn footer_max_len(&self) -> usize {
+    fn footer_max_len(&self, part: Option<(usize, usize)>) -> usize {
         (5    // CRC
          + 4  // CR NL CR NL
          + 18 // "-----END PGP -----"
          + self.blurb().len()
+         + part_suffix(part).len()
          + 2  // CR NL
         )
     }
 }
 
+/// Returns the suffix that is appended to the blurb of the armor
+/// header and footer lines of the given part of a multi-part message.
+fn part_suffix(part: Option<(usize, usize)>) -> String {
+    match part {
+        Some((x, y)) => format!(", PART {}/{}", x, y),
+        None => String::new(),
+    }
+}
+
+/// Parses the part numbering following the blurb of an armor header
+/// line, e.g. `, PART 1/2-----`.
+///
+/// Returns the part number, the total number of parts, and the length
+/// of the suffix, not including the trailing dashes.
+fn parse_part_suffix(s: &[u8]) -> Option<((usize, usize), usize)> {
+    const PREFIX: &[u8] = b", PART ";
+
+    if ! s.starts_with(PREFIX) {
+        return None;
+    }
+
+    let digits = |s: &[u8]| s.iter().take_while(|c| c.is_ascii_digit()).count();
+    let number = |s: &[u8]| -> Option<usize> {
+        str::from_utf8(s).ok().and_then(|s| s.parse().ok())
+    };
+
+    let rest = &s[PREFIX.len()..];
+    let x_len = digits(rest);
+    if x_len == 0 || rest.get(x_len) != Some(&b'/') {
+        return None;
+    }
+    let y_len = digits(&rest[x_len + 1..]);
+    if y_len == 0 || ! rest[x_len + 1 + y_len..].starts_with(b"-----") {
+        return None;
+    }
+
+    let x = number(&rest[..x_len])?;
+    let y = number(&rest[x_len + 1..x_len + 1 + y_len])?;
+    if x == 0 || x > y {
+        return None;
+    }
+
+    Some(((x, y), PREFIX.len() + x_len + 1 + y_len))
+}
+
 /// A filter that applies ASCII Armor to the data written to it.
 pub struct Writer<W: Write> {
     sink: W,
     kind: Kind,
+    part: Option<(usize, usize)>,
     stash: Vec<u8>,
+    line_length: usize,
     column: usize,
     crc: CRC,
     epilogue: Vec<u8>,
@@ -191,10 +301,114 @@ impl<W: Write> Writer<W> {
     /// # }
     /// ```
     pub fn new(inner: W, kind: Kind, headers: &[(&str, &str)]) -> Result<Self> {
+        Self::make(inner, kind, None, headers, LINE_LENGTH)
+    }
+
+    /// Constructs a new filter that wraps the base64 encoded data at
+    /// the given column.
+    ///
+    /// [RFC 4880] limits lines to at most 76 characters, but some
+    /// consumers expect a particular width.  The default is 64.
+    /// Widths of zero or larger than 255 characters are rejected.
+    ///
+    ///   [RFC 4880]: https://tools.ietf.org/html/rfc4880#section-6.3
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::io::Write;
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::armor::{Writer, Kind};
+    /// # use std::io::{self, Result};
+    /// # fn main() { f().unwrap(); }
+    /// # fn f() -> Result<()> {
+    /// let mut buffer = io::Cursor::new(vec![]);
+    /// {
+    ///     let mut writer = Writer::with_line_length(&mut buffer, Kind::File,
+    ///                                               &[][..], 8)?;
+    ///     writer.write_all(b"Hello world!")?;
+    /// }
+    /// assert_eq!(
+    ///     String::from_utf8_lossy(buffer.get_ref()),
+    ///     "-----BEGIN PGP ARMORED FILE-----
+    ///
+    /// SGVsbG8g
+    /// d29ybGQh
+    /// =s4Gu
+    /// -----END PGP ARMORED FILE-----
+    /// ");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_line_length(inner: W, kind: Kind, headers: &[(&str, &str)],
+                            cols: usize)
+                            -> Result<Self> {
+        if cols == 0 || cols > 255 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("Invalid line length: {}", cols)));
+        }
+
+        Self::make(inner, kind, None, headers, cols)
+    }
+
+    /// Constructs a new filter for a part of a multi-part message.
+    ///
+    /// `part` is a tuple containing the (one-based) number of this
+    /// part, and the total number of parts.  The header and footer
+    /// lines will be of the form `BEGIN PGP MESSAGE, PART X/Y`.  Only
+    /// `Kind::Message` can be split into multiple parts.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::io::Write;
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::armor::{Writer, Kind};
+    /// # use std::io::{self, Result};
+    /// # fn main() { f().unwrap(); }
+    /// # fn f() -> Result<()> {
+    /// let mut buffer = io::Cursor::new(vec![]);
+    /// {
+    ///     let mut writer = Writer::new_part(&mut buffer, Kind::Message,
+    ///                                       (1, 2), &[][..])?;
+    ///     writer.write_all(b"Hello world!")?;
+    /// }
+    /// assert_eq!(
+    ///     String::from_utf8_lossy(buffer.get_ref()),
+    ///     "-----BEGIN PGP MESSAGE, PART 1/2-----
+    ///
+    /// SGVsbG8gd29ybGQh
+    /// =s4Gu
+    /// -----END PGP MESSAGE, PART 1/2-----
+    /// ");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_part(inner: W, kind: Kind, part: (usize, usize),
+                    headers: &[(&str, &str)])
+                    -> Result<Self> {
+        if kind != Kind::Message {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  "Only messages can be split into parts"));
+        }
+        if part.0 == 0 || part.0 > part.1 {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("Invalid part number {}/{}",
+                                          part.0, part.1)));
+        }
+
+        Self::make(inner, kind, Some(part), headers, LINE_LENGTH)
+    }
+
+    fn make(inner: W, kind: Kind, part: Option<(usize, usize)>,
+            headers: &[(&str, &str)], line_length: usize)
+            -> Result<Self> {
         let mut w = Writer {
             sink: inner,
             kind: kind,
+            part: part,
             stash: Vec::<u8>::with_capacity(2),
+            line_length: line_length,
             column: 0,
             crc: CRC::new(),
             epilogue: Vec::with_capacity(128),
@@ -204,7 +418,7 @@ impl<W: Write> Writer<W> {
 
         {
             let mut cur = Cursor::new(&mut w.epilogue);
-            write!(&mut cur, "{}{}", kind.begin(), LINE_ENDING)?;
+            write!(&mut cur, "{}{}", kind.begin(part), LINE_ENDING)?;
 
             for h in headers {
                 write!(&mut cur, "{}: {}{}", h.0, h.1, LINE_ENDING)?;
@@ -246,11 +460,9 @@ impl<W: Write> Writer<W> {
 
         // Write any stashed bytes and pad.
         if self.stash.len() > 0 {
-            self.sink.write_all(base64::encode_config(&self.stash,
-                                                      base64::STANDARD).as_bytes())?;
-            self.column += 4;
+            let encoded = base64::encode_config(&self.stash, base64::STANDARD);
+            self.write_encoded(encoded.as_bytes())?;
         }
-        self.linebreak()?;
         if self.column > 0 {
             write!(self.sink, "{}", LINE_ENDING)?;
         }
@@ -265,7 +477,7 @@ impl<W: Write> Writer<W> {
         // CRC and footer.
         write!(self.sink, "={}{}{}{}",
                base64::encode_config(&bytes, base64::STANDARD_NO_PAD),
-               LINE_ENDING, self.kind.end(), LINE_ENDING)?;
+               LINE_ENDING, self.kind.end(self.part), LINE_ENDING)?;
 
         self.finalized = true;
         Ok(())
@@ -273,13 +485,25 @@ impl<W: Write> Writer<W> {
 
     /// Inserts a line break if necessary.
     fn linebreak(&mut self) -> Result<()> {
-        assert!(self.column <= LINE_LENGTH);
-        if self.column == LINE_LENGTH {
+        assert!(self.column <= self.line_length);
+        if self.column == self.line_length {
             write!(self.sink, "{}", LINE_ENDING)?;
             self.column = 0;
         }
         Ok(())
     }
+
+    /// Writes base64 encoded data, wrapping lines as necessary.
+    fn write_encoded(&mut self, mut enc: &[u8]) -> Result<()> {
+        while enc.len() > 0 {
+            let n = min(self.line_length - self.column, enc.len());
+            self.sink.write_all(&enc[..n])?;
+            enc = &enc[n..];
+            self.column += n;
+            self.linebreak()?;
+        }
+        Ok(())
+    }
 }
 
 impl<W: Write> Write for Writer<W> {
@@ -316,10 +540,9 @@ impl<W: Write> Write for Writer<W> {
 
             // If this fails for some reason, and the caller retries
             // the write, we might end up with a stash of size 3.
-            self.sink.write_all(base64::encode_config(&self.stash,
-                                                      base64::STANDARD_NO_PAD).as_bytes())?;
-            self.column += 4;
-            self.linebreak()?;
+            let encoded = base64::encode_config(&self.stash,
+                                                base64::STANDARD_NO_PAD);
+            self.write_encoded(encoded.as_bytes())?;
             self.stash.clear();
         }
 
@@ -338,14 +561,7 @@ impl<W: Write> Write for Writer<W> {
         assert!(input.len() % 3 == 0);
         let encoded = base64::encode_config(input, base64::STANDARD_NO_PAD);
         written += input.len();
-        let mut enc = encoded.as_bytes();
-        while enc.len() > 0 {
-            let n = min(LINE_LENGTH - self.column, enc.len());
-            self.sink.write_all(&enc[..n])?;
-            enc = &enc[n..];
-            self.column += n;
-            self.linebreak()?;
-        }
+        self.write_encoded(encoded.as_bytes())?;
 
         assert_eq!(written, buf.len());
         Ok(written)
@@ -397,6 +613,7 @@ pub enum ReaderMode {
 pub struct Reader<'a> {
     source: Box<'a + BufferedReader<()>>,
     kind: Option<Kind>,
+    part: Option<(usize, usize)>,
     mode: ReaderMode,
     buffer: Vec<u8>,
     crc: CRC,
@@ -530,6 +747,7 @@ impl<'a> Reader<'a> {
         Reader {
             source: Box::new(buffered_reader::Generic::new(inner, None)),
             kind: None,
+            part: None,
             mode: mode,
             buffer: Vec::<u8>::with_capacity(1024),
             crc: CRC::new(),
@@ -549,6 +767,31 @@ impl<'a> Reader<'a> {
         self.kind
     }
 
+    /// Returns the part number and the total number of parts, if this
+    /// is a part of a multi-part message.
+    ///
+    /// Multi-part messages use armor header lines of the form
+    /// `BEGIN PGP MESSAGE, PART X/Y`.  Like [`kind`], this returns
+    /// None until the header has been encountered.
+    ///
+    ///   [`kind`]: #method.kind
+    pub fn part(&self) -> Option<(usize, usize)> {
+        self.part
+    }
+
+    /// Prepares the reader for reading the next armored block from
+    /// the same source.
+    fn reset(&mut self) {
+        self.kind = None;
+        self.part = None;
+        self.buffer.clear();
+        self.crc = CRC::new();
+        self.expect_crc = None;
+        self.initialized = false;
+        self.headers.clear();
+        self.finalized = false;
+    }
+
     /// Returns the armored headers.
     ///
     /// The tuples contain a key and a value.
@@ -651,16 +894,27 @@ impl<'a> Reader<'a> {
                             expected_kind = Some(kind);
                         }
 
+                        let (part, suffix_len) = if kind == Kind::Message {
+                            parse_part_suffix(
+                                &input[kind.header_len() - 5..])
+                                .map(|(p, l)| (Some(p), l))
+                                .unwrap_or((None, 0))
+                        } else {
+                            (None, 0)
+                        };
+
                         if expected_kind == None {
                             // Found any!
                             self.kind = Some(kind);
-                            break 'search kind.header_len();
+                            self.part = part;
+                            break 'search kind.header_len() + suffix_len;
                         }
 
                         if expected_kind == Some(kind) {
                             // Found it!
                             self.kind = Some(kind);
-                            break 'search kind.header_len();
+                            self.part = part;
+                            break 'search kind.header_len() + suffix_len;
                         }
                     }
                 } else if self.mode == ReaderMode::VeryTolerant {
@@ -756,7 +1010,9 @@ impl<'a> Reader<'a> {
     }
 
     /// Parses the footer.
-    fn finalize(footer: &[u8], kind: Option<Kind>) -> Result<Option<u32>> {
+    fn finalize(footer: &[u8], kind: Option<Kind>,
+                part: Option<(usize, usize)>)
+                -> Result<Option<u32>> {
         let mut off = 0;
 
         /* Look for CRC.  The CRC is optional.  */
@@ -787,7 +1043,7 @@ impl<'a> Reader<'a> {
         };
 
         if let Some(kind) = kind {
-            if ! footer[off..].starts_with(&kind.end().into_bytes()) {
+            if ! footer[off..].starts_with(&kind.end(part).into_bytes()) {
                 return Err(Error::new(ErrorKind::InvalidInput, "Invalid ASCII Armor footer."));
             }
         }
@@ -904,8 +1160,9 @@ fn is_footer(buf: &[u8], reference: &[u8]) -> Option<usize> {
 
 /// Looks for the footer, returning the footer's offset, and the end
 /// of the footer.
-fn find_footer(buf: &[u8], kind: Kind) -> Option<(usize, usize)> {
-    let reference = kind.end().into_bytes();
+fn find_footer(buf: &[u8], kind: Kind, part: Option<(usize, usize)>)
+               -> Option<(usize, usize)> {
+    let reference = kind.end(part).into_bytes();
 
     if buf.len() < reference.len() {
         return None;
@@ -953,7 +1210,8 @@ impl<'a> Read for Reader<'a> {
             // Later, we may have to get some more until we have a
             // multiple of four non-whitespace ASCII characters.
             let mut want = (buf.len() - read + 2) / 3 * 4
-                + self.kind.map(|k| k.footer_max_len()).unwrap_or(46);
+                + self.kind.map(|k| k.footer_max_len(self.part))
+                .unwrap_or(46);
 
             // Keep track of how much we got last time to detect
             // hitting EOF.
@@ -997,8 +1255,10 @@ impl<'a> Read for Reader<'a> {
 
                 // Check if we see the footer.  If so, we're almost done.
                 if let Some(kind) = self.kind {
-                    if let Some((n, end)) = find_footer(&raw, kind) {
-                        self.expect_crc = Reader::finalize(&raw[n..], self.kind)?;
+                    if let Some((n, end)) = find_footer(&raw, kind,
+                                                        self.part) {
+                        self.expect_crc = Reader::finalize(&raw[n..], self.kind,
+                                                           self.part)?;
                         self.finalized = true;
                         match base64::decode_config(&raw[..n], base64::MIME) {
                             Ok(d) => break (end, d),
@@ -1066,6 +1326,114 @@ impl<'a> Read for Reader<'a> {
 
 // XXX: impl BufferedReader for Reader
 
+/// A filter that strips ASCII Armor from a multi-part message.
+///
+/// Legacy software sometimes splits messages into several armored
+/// blocks with header lines of the form `BEGIN PGP MESSAGE, PART
+/// X/Y`.  This reader returns the concatenation of the decoded bodies
+/// of consecutive parts.  The parts must appear in order.  If the
+/// first block is not a part of a multi-part message, this behaves
+/// like a [`Reader`].
+///
+///   [`Reader`]: struct.Reader.html
+///
+/// # Example
+///
+/// ```
+/// # use std::io::Read;
+/// # extern crate sequoia_openpgp as openpgp;
+/// # use openpgp::armor::{MultipartReader, ReaderMode};
+/// # use std::io::{self, Result};
+/// # fn main() { f().unwrap(); }
+/// # fn f() -> Result<()> {
+/// let data =
+///     "-----BEGIN PGP MESSAGE, PART 1/2-----
+///
+///      SGVsbG8g
+///      -----END PGP MESSAGE, PART 1/2-----
+///      -----BEGIN PGP MESSAGE, PART 2/2-----
+///
+///      d29ybGQh
+///      -----END PGP MESSAGE, PART 2/2-----";
+///
+/// let mut reader = MultipartReader::new(io::Cursor::new(&data), None);
+/// let mut content = String::new();
+/// reader.read_to_string(&mut content)?;
+/// assert_eq!(content, "Hello world!");
+/// # Ok(())
+/// # }
+/// ```
+pub struct MultipartReader<'a> {
+    reader: Reader<'a>,
+    initialized: bool,
+}
+
+impl<'a> MultipartReader<'a> {
+    /// Constructs a new filter for the given type of data.
+    ///
+    /// See [`Reader::new`] for a description of `mode`.
+    ///
+    ///   [`Reader::new`]: struct.Reader.html#method.new
+    pub fn new<R, M>(inner: R, mode: M) -> Self
+        where R: 'a + Read,
+              M: Into<Option<ReaderMode>>
+    {
+        MultipartReader {
+            reader: Reader::new(inner, mode),
+            initialized: false,
+        }
+    }
+
+    /// Returns the part that is currently being read.
+    pub fn part(&self) -> Option<(usize, usize)> {
+        self.reader.part()
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> Reader<'a> {
+        self.reader
+    }
+}
+
+impl<'a> Read for MultipartReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if ! self.initialized {
+            self.reader.initialize()?;
+            self.initialized = true;
+            if let Some((x, y)) = self.reader.part() {
+                if x != 1 {
+                    return Err(Error::new(ErrorKind::InvalidInput,
+                                          format!("Expected part 1/{}, \
+                                                   got part {}/{}",
+                                                  y, x, y)));
+                }
+            }
+        }
+
+        loop {
+            let n = self.reader.read(buf)?;
+            if n > 0 || buf.len() == 0 {
+                return Ok(n);
+            }
+
+            // We reached the end of the current block.  If there are
+            // more parts, continue with the next one.
+            let (x, y) = match self.reader.part() {
+                Some((x, y)) if x < y => (x, y),
+                _ => return Ok(0),
+            };
+
+            self.reader.reset();
+            self.reader.initialize()?;
+            if self.reader.part() != Some((x + 1, y)) {
+                return Err(Error::new(ErrorKind::InvalidInput,
+                                      format!("Expected part {}/{}",
+                                              x + 1, y)));
+            }
+        }
+    }
+}
+
 const CRC24_INIT: u32 = 0xB704CE;
 const CRC24_POLY: u32 = 0x1864CFB;
 
@@ -1381,6 +1749,96 @@ mod test {
         }
     }
 
+    #[test]
+    fn line_length() {
+        let payload = (0..1000).map(|i| i as u8).collect::<Vec<u8>>();
+
+        for &cols in &[40, 64, 76, 30] {
+            let mut encoded = Vec::new();
+            Writer::with_line_length(&mut encoded, Kind::File, &[], cols)
+                .unwrap()
+                .write_all(&payload)
+                .unwrap();
+
+            let text = String::from_utf8(encoded.clone()).unwrap();
+            assert!(text.ends_with("-----END PGP ARMORED FILE-----\n"));
+            let body = text.lines()
+                .skip_while(|l| l.len() > 0).skip(1)
+                .take_while(|l| ! l.starts_with("="))
+                .collect::<Vec<_>>();
+            let (last, full) = body.split_last().unwrap();
+            assert!(full.iter().all(|l| l.len() == cols));
+            assert!(last.len() > 0 && last.len() <= cols);
+
+            // The CRC is on a line of its own.
+            assert!(text.lines().any(|l| l.starts_with("=") && l.len() == 5));
+
+            let mut recovered = Vec::new();
+            Reader::new(Cursor::new(&encoded),
+                        ReaderMode::Tolerant(Some(Kind::File)))
+                .read_to_end(&mut recovered)
+                .unwrap();
+            assert_eq!(recovered, payload);
+        }
+
+        assert!(Writer::with_line_length(Vec::new(), Kind::File, &[], 0)
+                .is_err());
+        assert!(Writer::with_line_length(Vec::new(), Kind::File, &[], 256)
+                .is_err());
+    }
+
+    #[test]
+    fn multipart() {
+        use super::MultipartReader;
+
+        let payload = b"Hello world!  This message is split into three parts.";
+        let chunks = payload.chunks(20).collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 3);
+
+        let mut encoded = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            Writer::new_part(&mut encoded, Kind::Message, (i + 1, 3), &[])
+                .unwrap()
+                .write_all(chunk)
+                .unwrap();
+        }
+        assert!(String::from_utf8_lossy(&encoded)
+                .contains("-----END PGP MESSAGE, PART 2/3-----\n"));
+
+        // Reading a single part.
+        let mut r = Reader::new(Cursor::new(&encoded),
+                                ReaderMode::Tolerant(Some(Kind::Message)));
+        let mut dearmored = Vec::new();
+        r.read_to_end(&mut dearmored).unwrap();
+        assert_eq!(r.kind(), Some(Kind::Message));
+        assert_eq!(r.part(), Some((1, 3)));
+        assert_eq!(&dearmored[..], chunks[0]);
+
+        // Reading all of them.
+        let mut r = MultipartReader::new(Cursor::new(&encoded), None);
+        let mut dearmored = Vec::new();
+        r.read_to_end(&mut dearmored).unwrap();
+        assert_eq!(r.part(), Some((3, 3)));
+        assert_eq!(&dearmored[..], &payload[..]);
+
+        // Parts must be in order.
+        let mut encoded = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate().rev() {
+            Writer::new_part(&mut encoded, Kind::Message, (i + 1, 3), &[])
+                .unwrap()
+                .write_all(chunk)
+                .unwrap();
+        }
+        let mut r = MultipartReader::new(Cursor::new(&encoded), None);
+        let mut dearmored = Vec::new();
+        assert!(r.read_to_end(&mut dearmored).is_err());
+
+        // Only messages can be split.
+        assert!(Writer::new_part(Vec::new(), Kind::File, (1, 2), &[]).is_err());
+        assert!(Writer::new_part(Vec::new(), Kind::Message, (3, 2), &[])
+                .is_err());
+    }
+
     quickcheck! {
         fn roundtrip(kind: Kind, payload: Vec<u8>) -> bool {
             if payload.is_empty() {
@@ -614,6 +614,72 @@ impl Arbitrary for CompressionAlgorithm {
         u8::arbitrary(g).into()
     }
 }
+
+/// Compression level.
+///
+/// The level ranges from 0 (no compression) to 9 (best, but slowest
+/// compression).  The default is 6.  Note that bzip2 only knows
+/// three levels, the values are mapped accordingly.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, PartialOrd, Ord)]
+pub struct CompressionLevel(u8);
+
+impl Default for CompressionLevel {
+    fn default() -> Self {
+        CompressionLevel(6)
+    }
+}
+
+impl CompressionLevel {
+    /// Creates a new compression level.
+    ///
+    /// Fails if `level` is not in the range 0 to 9.
+    pub fn new(level: u8) -> Result<CompressionLevel> {
+        if level <= 9 {
+            Ok(CompressionLevel(level))
+        } else {
+            Err(Error::InvalidArgument(
+                format!("compression level out of range: {}", level)).into())
+        }
+    }
+
+    /// No compression.
+    pub fn none() -> CompressionLevel {
+        CompressionLevel(0)
+    }
+
+    /// Fastest compression.
+    pub fn fastest() -> CompressionLevel {
+        CompressionLevel(1)
+    }
+
+    /// Best compression.
+    pub fn best() -> CompressionLevel {
+        CompressionLevel(9)
+    }
+
+    /// Returns the compression level.
+    pub fn level(&self) -> u8 {
+        self.0
+    }
+}
+
+#[cfg(feature = "compression-deflate")]
+impl From<CompressionLevel> for flate2::Compression {
+    fn from(l: CompressionLevel) -> Self {
+        flate2::Compression::new(l.0 as u32)
+    }
+}
+
+#[cfg(feature = "compression-bzip2")]
+impl From<CompressionLevel> for bzip2::Compression {
+    fn from(l: CompressionLevel) -> Self {
+        match l.0 {
+            0...3 => bzip2::Compression::Fastest,
+            4...6 => bzip2::Compression::Default,
+            _ => bzip2::Compression::Best,
+        }
+    }
+}
 
 /// The OpenPGP hash algorithms as defined in [Section 9.4 of RFC 4880].
 ///
@@ -680,7 +746,8 @@ impl FromStr for HashAlgorithm {
             Ok(HashAlgorithm::MD5)
         } else if s == "SHA1" {
             Ok(HashAlgorithm::SHA1)
-        } else if s == "RipeMD160" {
+        } else if s == "RipeMD160" || s == "RIPEMD160" {
+            // The latter is used in armor headers.
             Ok(HashAlgorithm::RipeMD)
         } else if s == "SHA256" {
             Ok(HashAlgorithm::SHA256)
@@ -39,6 +39,38 @@ impl AEADAlgorithm {
         }
     }
 
+    /// Returns the nonce size of the AEAD algorithm.
+    ///
+    /// Unlike [`iv_size`], this also works for algorithms that
+    /// Sequoia does not implement, but whose parameters are defined
+    /// by [Section 5.16 of RFC 4880bis], so that packets using them
+    /// can at least be parsed.
+    ///
+    ///   [`iv_size`]: #method.iv_size
+    ///   [Section 5.16 of RFC 4880bis]: https://tools.ietf.org/html/draft-ietf-openpgp-rfc4880bis-07#section-5.16
+    pub fn nonce_size(&self) -> Result<usize> {
+        use self::AEADAlgorithm::*;
+        match self {
+            &EAX => Ok(16),
+            &OCB => Ok(15),
+            _ => Err(Error::UnsupportedAEADAlgorithm(self.clone()).into()),
+        }
+    }
+
+    /// Returns the authentication tag size of the AEAD algorithm.
+    ///
+    /// Like [`nonce_size`], this also works for algorithms that
+    /// Sequoia does not implement.
+    ///
+    ///   [`nonce_size`]: #method.nonce_size
+    pub fn tag_size(&self) -> Result<usize> {
+        use self::AEADAlgorithm::*;
+        match self {
+            &EAX | &OCB => Ok(16),
+            _ => Err(Error::UnsupportedAEADAlgorithm(self.clone()).into()),
+        }
+    }
+
     /// Creates a nettle context.
     pub fn context(&self, sym_algo: SymmetricAlgorithm, key: &[u8], nonce: &[u8])
                    -> Result<Box<aead::Aead>> {
@@ -797,4 +829,27 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(AEADAlgorithm::EAX.nonce_size().unwrap(), 16);
+        assert_eq!(AEADAlgorithm::EAX.tag_size().unwrap(), 16);
+        assert_eq!(AEADAlgorithm::OCB.nonce_size().unwrap(), 15);
+        assert_eq!(AEADAlgorithm::OCB.tag_size().unwrap(), 16);
+        assert!(! AEADAlgorithm::OCB.is_supported());
+        assert!(AEADAlgorithm::Unknown(42).nonce_size().is_err());
+        assert!(AEADAlgorithm::Private(100).tag_size().is_err());
+
+        for i in 0..=255u8 {
+            let aead = AEADAlgorithm::from(i);
+            if aead.is_supported() {
+                assert_eq!(aead.nonce_size().unwrap(),
+                           aead.iv_size().unwrap());
+                assert_eq!(aead.tag_size().unwrap(),
+                           aead.digest_size().unwrap());
+            } else {
+                assert!(aead.iv_size().is_err());
+            }
+        }
+    }
 }
@@ -3,7 +3,8 @@
 use nettle::{dsa, ecc, ecdsa, ed25519, rsa, Yarrow};
 
 use packet::{self, Key};
-use crypto::SessionKey;
+use crypto::{Rng, SessionKey};
+use crypto::mem::Protected;
 use crypto::mpis::{self, MPI};
 use constants::{Curve, HashAlgorithm};
 
@@ -74,19 +75,21 @@ impl KeyPair {
     }
 }
 
-impl Signer for KeyPair {
-    fn public(&self) -> &Key {
-        &self.public
-    }
-
-    fn sign(&mut self, hash_algo: HashAlgorithm, digest: &[u8])
-            -> Result<mpis::Signature>
+impl KeyPair {
+    /// Creates a signature over the `digest` produced by `hash_algo`
+    /// using the given random number generator.
+    ///
+    /// This is like `Signer::sign`, but DSA and ECDSA signatures
+    /// draw their nonces from `rng`.  EdDSA and RSA signatures are
+    /// deterministic anyway.
+    pub fn sign_with_rng(&mut self, rng: &mut Rng, hash_algo: HashAlgorithm,
+                         digest: &[u8])
+                         -> Result<mpis::Signature>
     {
         use PublicKeyAlgorithm::*;
         use crypto::mpis::PublicKey;
-        use memsec;
 
-        let mut rng = Yarrow::default();
+        let rng = rng.yarrow();
 
         #[allow(deprecated)]
         match (self.public.pk_algo(), self.public.mpis(), &self.secret.mpis())
@@ -112,7 +115,7 @@ impl Signer for KeyPair {
                 //   https://tools.ietf.org/html/rfc4880#section-5.2.2
                 rsa::sign_digest_pkcs1(&public, &secret, digest,
                                        hash_algo.oid()?,
-                                       &mut rng, &mut sig)?;
+                                       &mut *rng, &mut sig)?;
 
                 Ok(mpis::Signature::RSA {
                     s: MPI::new(&sig),
@@ -125,7 +128,8 @@ impl Signer for KeyPair {
                 let params = dsa::Params::new(p.value(), q.value(), g.value());
                 let secret = dsa::PrivateKey::new(x.value());
 
-                let sig = dsa::sign(&params, &secret, digest, &mut rng)?;
+                // Nettle truncates the digest to the bit length of q.
+                let sig = dsa::sign(&params, &secret, digest, &mut *rng)?;
 
                 Ok(mpis::Signature::DSA {
                     r: MPI::new(&sig.r()),
@@ -148,15 +152,11 @@ impl Signer for KeyPair {
                     // secret-dependant branch.
                     let missing = ed25519::ED25519_KEY_SIZE
                         .saturating_sub(scalar.value().len());
-                    let mut sec = [0u8; ed25519::ED25519_KEY_SIZE];
+                    let mut sec: Protected =
+                        vec![0u8; ed25519::ED25519_KEY_SIZE].into();
                     sec[missing..].copy_from_slice(scalar.value());
 
-                    let res = ed25519::sign(public, &sec[..], digest, &mut sig);
-                    unsafe {
-                        memsec::memzero(sec.as_mut_ptr(),
-                                        ed25519::ED25519_KEY_SIZE);
-                    }
-                    res?;
+                    ed25519::sign(public, &sec[..], digest, &mut sig)?;
 
                     Ok(mpis::Signature::EdDSA {
                         r: MPI::new(&sig[..32]),
@@ -186,7 +186,7 @@ impl Signer for KeyPair {
                                 .into()),
                 };
 
-                let sig = ecdsa::sign(&secret, digest, &mut rng);
+                let sig = ecdsa::sign(&secret, digest, &mut *rng);
 
                 Ok(mpis::Signature::ECDSA {
                     r: MPI::new(&sig.r()),
@@ -202,12 +202,24 @@ impl Signer for KeyPair {
     }
 }
 
+impl Signer for KeyPair {
+    fn public(&self) -> &Key {
+        &self.public
+    }
+
+    fn sign(&mut self, hash_algo: HashAlgorithm, digest: &[u8])
+            -> Result<mpis::Signature>
+    {
+        self.sign_with_rng(&mut Rng::new(), hash_algo, digest)
+    }
+}
+
 impl Decryptor for KeyPair {
     fn public(&self) -> &Key {
         &self.public
     }
 
-    /// Creates a signature over the `digest` produced by `hash_algo`.
+    /// Decrypts `ciphertext`, returning the plain session key.
     fn decrypt(&mut self, ciphertext: &mpis::Ciphertext)
                -> Result<SessionKey>
     {
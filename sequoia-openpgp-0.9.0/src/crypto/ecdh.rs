@@ -172,7 +172,6 @@ pub fn encrypt_shared(recipient: &Key, session_key: &SessionKey, VB: MPI,
 pub fn decrypt(recipient: &Key, recipient_sec: &SecretKey,
                ciphertext: &Ciphertext)
                -> Result<SessionKey> {
-    use memsec;
 
     match (recipient.mpis(), recipient_sec, ciphertext) {
         (PublicKey::ECDH { ref curve, ..},
@@ -194,7 +193,8 @@ pub fn decrypt(recipient: &Key, recipient_sec: &SecretKey,
                     // https://lists.gnupg.org/pipermail/gnupg-devel/2018-February/033437.html.
                     let missing = curve25519::CURVE25519_SIZE
                         .saturating_sub(scalar.value().len());
-                    let mut r = [0u8; curve25519::CURVE25519_SIZE];
+                    let mut r: Protected =
+                        vec![0u8; curve25519::CURVE25519_SIZE].into();
 
                     r[missing..].copy_from_slice(scalar.value());
                     r.reverse();
@@ -203,13 +203,8 @@ pub fn decrypt(recipient: &Key, recipient_sec: &SecretKey,
                     // is the recipient's key pair.
                     let mut S: Protected =
                         vec![0; curve25519::CURVE25519_SIZE].into();
-                    let res = curve25519::mul(&mut S, &r[..], V);
-
-                    unsafe {
-                        memsec::memzero(r.as_mut_ptr(),
-                        curve25519::CURVE25519_SIZE);
-                    }
-                    res.expect("buffers are of the wrong size");
+                    curve25519::mul(&mut S, &r[..], V)
+                        .expect("buffers are of the wrong size");
                     S
                 }
 
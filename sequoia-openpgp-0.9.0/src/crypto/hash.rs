@@ -4,9 +4,10 @@ use HashAlgorithm;
 use packet::UserID;
 use packet::UserAttribute;
 use packet::Key;
-use packet::key::Key4;
+use packet::key::{Key4, Key5};
 use packet::Signature;
 use packet::signature::{self, Signature4};
+use packet::signature::subpacket::{SubpacketTag, SubpacketValue};
 use Error;
 use Result;
 use conversions::Time;
@@ -75,6 +76,91 @@ impl HashAlgorithm {
         }
     }
 
+    /// Returns the size of the digest in bytes.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::UnsupportedHashAlgorithm` for private and
+    /// unknown algorithms.
+    pub fn digest_size(self) -> Result<usize> {
+        match self {
+            HashAlgorithm::MD5 => Ok(16),
+            HashAlgorithm::SHA1 => Ok(20),
+            HashAlgorithm::RipeMD => Ok(20),
+            HashAlgorithm::SHA224 => Ok(28),
+            HashAlgorithm::SHA256 => Ok(32),
+            HashAlgorithm::SHA384 => Ok(48),
+            HashAlgorithm::SHA512 => Ok(64),
+            HashAlgorithm::Private(_) | HashAlgorithm::Unknown(_) =>
+                Err(Error::UnsupportedHashAlgorithm(self).into()),
+        }
+    }
+
+    /// Whether this algorithm is considered secure.
+    ///
+    /// MD5 and SHA-1 are broken, and RIPEMD-160 is deprecated by
+    /// [Section 9.4 of RFC 4880bis].  Signatures using these
+    /// algorithms should be rejected.  Private and unknown
+    /// algorithms are not considered secure either.
+    ///
+    ///   [Section 9.4 of RFC 4880bis]: https://tools.ietf.org/html/draft-ietf-openpgp-rfc4880bis-07#section-9.4
+    pub fn is_secure(self) -> bool {
+        match self {
+            HashAlgorithm::SHA224 => true,
+            HashAlgorithm::SHA256 => true,
+            HashAlgorithm::SHA384 => true,
+            HashAlgorithm::SHA512 => true,
+            HashAlgorithm::MD5 => false,
+            HashAlgorithm::SHA1 => false,
+            HashAlgorithm::RipeMD => false,
+            HashAlgorithm::Private(_) => false,
+            HashAlgorithm::Unknown(_) => false,
+        }
+    }
+
+    /// Returns the algorithm's text name.
+    ///
+    /// This is the name used in the `Hash` armor header of
+    /// cleartext signed messages, see [Section 9.4 of RFC 4880].
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::UnsupportedHashAlgorithm` for private and
+    /// unknown algorithms, which don't have a name.
+    ///
+    ///   [Section 9.4 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-9.4
+    pub fn to_name(self) -> Result<&'static str> {
+        match self {
+            HashAlgorithm::MD5 => Ok("MD5"),
+            HashAlgorithm::SHA1 => Ok("SHA1"),
+            HashAlgorithm::RipeMD => Ok("RIPEMD160"),
+            HashAlgorithm::SHA224 => Ok("SHA224"),
+            HashAlgorithm::SHA256 => Ok("SHA256"),
+            HashAlgorithm::SHA384 => Ok("SHA384"),
+            HashAlgorithm::SHA512 => Ok("SHA512"),
+            HashAlgorithm::Private(_) | HashAlgorithm::Unknown(_) =>
+                Err(Error::UnsupportedHashAlgorithm(self).into()),
+        }
+    }
+
+    /// Looks up an algorithm by its text name.
+    ///
+    /// This is the inverse of [`HashAlgorithm::to_name`].  Unlike
+    /// the `FromStr` implementation, the comparison is case
+    /// insensitive.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::InvalidArgument` if the name is not known.
+    ///
+    ///   [`HashAlgorithm::to_name`]: #method.to_name
+    pub fn from_name(name: &str) -> Result<Self> {
+        name.to_uppercase().parse::<HashAlgorithm>().map_err(|_| {
+            Error::InvalidArgument(
+                format!("Unknown hash algorithm: {}", name)).into()
+        })
+    }
+
     /// Creates a new Nettle hash context for this algorith.
     ///
     /// # Errors
@@ -230,7 +316,8 @@ impl Hash for Key4 {
 
         let mut header : Vec<u8> = Vec::with_capacity(9);
 
-        // Tag.  Note: we use this whether
+        // Tag.  Note: we use this whether the key is a primary key
+        // or a subkey, and whether or not it has secret key material.
         header.push(0x99);
 
         // Length (big endian).
@@ -258,10 +345,69 @@ impl Hash for Key4 {
     }
 }
 
+impl Hash for Key5 {
+    /// Update the Hash with a hash of the key.
+    fn hash(&self, hash: &mut Context) {
+        let mpis_len = self.mpis().serialized_len();
+
+        // We hash 15 bytes plus the MPIs.  But, the len doesn't
+        // include the tag (1 byte) or the length (4 bytes).
+        let len = (15 - 5) + mpis_len;
+
+        let mut header : Vec<u8> = Vec::with_capacity(15);
+
+        // Tag.  As for V4 keys, we use this whether the key is a
+        // primary key or a subkey.
+        header.push(0x9a);
+
+        // Length (big endian).
+        header.push(((len >> 24) & 0xFF) as u8);
+        header.push(((len >> 16) & 0xFF) as u8);
+        header.push(((len >> 8) & 0xFF) as u8);
+        header.push((len & 0xFF) as u8);
+
+        // Version.
+        header.push(5);
+
+        // Creation time.
+        let creation_time = self.creation_time().to_pgp()
+            .unwrap_or(0);
+        header.push((creation_time >> 24) as u8);
+        header.push((creation_time >> 16) as u8);
+        header.push((creation_time >> 8) as u8);
+        header.push((creation_time >> 0) as u8);
+
+        // Algorithm.
+        header.push(self.pk_algo().into());
+
+        // Length of the public key material (big endian).
+        header.push(((mpis_len >> 24) & 0xFF) as u8);
+        header.push(((mpis_len >> 16) & 0xFF) as u8);
+        header.push(((mpis_len >> 8) & 0xFF) as u8);
+        header.push((mpis_len & 0xFF) as u8);
+
+        hash.update(&header[..]);
+
+        // MPIs.
+        self.mpis().hash(hash);
+    }
+}
+
+impl Hash for Key {
+    /// Update the Hash with a hash of the key.
+    fn hash(&self, hash: &mut Context) {
+        match self {
+            Key::V4(k) => k.hash(hash),
+            Key::V5(k) => k.hash(hash),
+        }
+    }
+}
+
 impl Hash for Signature {
     /// Adds the `Signature` to the provided hash context.
     fn hash(&self, hash: &mut Context) {
         match self {
+            Signature::V3(sig) => sig.intern.hash(hash),
             Signature::V4(sig) => sig.hash(hash),
         }
     }
@@ -277,6 +423,30 @@ impl Hash for Signature4 {
 impl Hash for signature::Builder {
     /// Adds the `Signature` to the provided hash context.
     fn hash(&self, hash: &mut Context) {
+        if self.version() == 3 {
+            // A version 3 signature only hashes the signature type
+            // and the creation time, see [Section 5.2.4 of RFC 4880].
+            //
+            //   [Section 5.2.4 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.2.4
+            let creation_time = self.hashed_area()
+                .lookup(SubpacketTag::SignatureCreationTime)
+                .and_then(|sb| match sb.value {
+                    SubpacketValue::SignatureCreationTime(t) =>
+                        t.to_pgp().ok(),
+                    _ => None,
+                })
+                .unwrap_or(0);
+
+            let mut header = [0u8; 5];
+            header[0] = self.sigtype().into();
+            header[1] = (creation_time >> 24) as u8;
+            header[2] = (creation_time >> 16) as u8;
+            header[3] = (creation_time >> 8) as u8;
+            header[4] = creation_time as u8;
+            hash.update(&header[..]);
+            return;
+        }
+
         // A version 4 signature packet is laid out as follows:
         //
         //   version - 1 byte                    \
@@ -378,7 +548,15 @@ impl Signature {
         let mut h = sig.hash_algo().context()?;
 
         key.hash(&mut h);
-        userid.hash(&mut h);
+        if sig.version() == 3 {
+            // Version 3 certifications hash the bare user id, see
+            // [Section 5.2.4 of RFC 4880].
+            //
+            //   [Section 5.2.4 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.2.4
+            h.update(userid.value());
+        } else {
+            userid.hash(&mut h);
+        }
         sig.hash(&mut h);
 
         let mut digest = vec![0u8; h.digest_size()];
@@ -397,7 +575,12 @@ impl Signature {
         let mut h = sig.hash_algo().context()?;
 
         key.hash(&mut h);
-        ua.hash(&mut h);
+        if sig.version() == 3 {
+            // Like user ids, see `Signature::userid_binding_hash`.
+            h.update(ua.value());
+        } else {
+            ua.hash(&mut h);
+        }
         sig.hash(&mut h);
 
         let mut digest = vec![0u8; h.digest_size()];
@@ -476,4 +659,51 @@ mod test {
             = check(TPK::from_bytes(::tests::key("dkg.gpg")).unwrap());
         assert!(ua_sigs > 0);
     }
+
+    #[test]
+    fn names() {
+        assert_eq!(HashAlgorithm::from_name("SHA512").unwrap(),
+                   HashAlgorithm::SHA512);
+        assert_eq!(HashAlgorithm::SHA512.to_name().unwrap(), "SHA512");
+        assert_eq!(HashAlgorithm::from_name("sha512").unwrap(),
+                   HashAlgorithm::SHA512);
+        assert_eq!(HashAlgorithm::from_name("RIPEMD160").unwrap(),
+                   HashAlgorithm::RipeMD);
+        assert!(HashAlgorithm::from_name("SHA9000").is_err());
+        assert!(HashAlgorithm::Unknown(42).to_name().is_err());
+        assert!(HashAlgorithm::Private(100).to_name().is_err());
+
+        for i in 0..=255u8 {
+            let algo = HashAlgorithm::from(i);
+            if let Ok(name) = algo.to_name() {
+                assert_eq!(HashAlgorithm::from_name(name).unwrap(), algo);
+                // FromStr agrees.
+                assert_eq!(name.parse::<HashAlgorithm>().unwrap(), algo);
+            }
+        }
+    }
+
+    #[test]
+    fn digest_sizes() {
+        assert_eq!(HashAlgorithm::MD5.digest_size().unwrap(), 16);
+        assert_eq!(HashAlgorithm::SHA1.digest_size().unwrap(), 20);
+        assert_eq!(HashAlgorithm::RipeMD.digest_size().unwrap(), 20);
+        assert_eq!(HashAlgorithm::SHA512.digest_size().unwrap(), 64);
+        assert!(HashAlgorithm::Unknown(42).digest_size().is_err());
+
+        for i in 0..=255u8 {
+            let algo = HashAlgorithm::from(i);
+            if algo.is_supported() {
+                assert_eq!(algo.digest_size().unwrap(),
+                           algo.context().unwrap().digest_size());
+            }
+            if algo.is_secure() {
+                assert!(algo.is_supported());
+                assert!(algo.digest_size().unwrap() >= 28);
+            }
+        }
+        assert!(! HashAlgorithm::MD5.is_secure());
+        assert!(! HashAlgorithm::SHA1.is_secure());
+        assert!(HashAlgorithm::SHA256.is_secure());
+    }
 }
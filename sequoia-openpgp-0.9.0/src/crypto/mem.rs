@@ -54,8 +54,19 @@ impl DerefMut for Protected {
 }
 
 impl From<Vec<u8>> for Protected {
-    fn from(v: Vec<u8>) -> Self {
-        Protected(Pin::new(v.into_boxed_slice()))
+    fn from(mut v: Vec<u8>) -> Self {
+        if v.len() == v.capacity() {
+            return Protected(Pin::new(v.into_boxed_slice()));
+        }
+
+        // Shrinking the vector would reallocate, and leave the
+        // secret in the old allocation.  Copy it, and clear the
+        // original, including any spare capacity.
+        let p = Protected(Pin::new(v[..].to_vec().into_boxed_slice()));
+        unsafe {
+            memsec::memzero(v.as_mut_ptr(), v.capacity());
+        }
+        p
     }
 }
 
@@ -81,11 +92,9 @@ impl Drop for Protected {
 
 impl fmt::Debug for Protected {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if cfg!(debug_assertions) {
-            write!(f, "{:?}", self.0)
-        } else {
-            f.write_str("[<Redacted>]")
-        }
+        // Never reveal the secret, not even in debug builds: Debug
+        // output ends up in logs.
+        f.write_str("[<Redacted>]")
     }
 }
 
@@ -104,3 +113,35 @@ pub fn secure_cmp(a: &[u8], b: &[u8]) -> Ordering {
 
     if ord1 == Ordering::Equal { ord2 } else { ord1 }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protected_eq() {
+        let a: Protected = b"secret"[..].into();
+        let b: Protected = vec![b's', b'e', b'c', b'r', b'e', b't'].into();
+        assert_eq!(a, b);
+        assert_eq!(&a[..], b"secret");
+
+        let c: Protected = b"secreT"[..].into();
+        assert!(a != c);
+        let d: Protected = b"secrets"[..].into();
+        assert!(a != d);
+    }
+
+    #[test]
+    fn protected_cmp() {
+        let v: &[&[u8]] = &[b"", b"a", b"ab", b"b", b"ba", b"bb"];
+        for a in v {
+            for b in v {
+                assert_eq!(secure_cmp(a, b) == Ordering::Equal, a == b);
+            }
+        }
+        // Shorter buffers sort first.
+        assert_eq!(secure_cmp(b"ab", b"b"), Ordering::Greater);
+        assert_eq!(secure_cmp(b"ab", b"aa"), Ordering::Greater);
+        assert_eq!(secure_cmp(b"aa", b"ab"), Ordering::Less);
+    }
+}
@@ -1,12 +1,12 @@
 //! Cryptographic primitives.
 
-use std::io::Read;
+use std::io::{self, Read};
 use std::ops::{Deref, DerefMut};
 use std::fmt;
 
 use nettle::{Random, Yarrow};
 
-use constants::HashAlgorithm;
+use constants::{HashAlgorithm, SymmetricAlgorithm};
 use Result;
 
 pub(crate) mod aead;
@@ -34,9 +34,84 @@ pub fn random<B: AsMut<[u8]>>(mut buf: B) {
     RNG.with(|rng| rng.borrow_mut().random(buf.as_mut()));
 }
 
+/// A cryptographically secure pseudorandom number generator.
+///
+/// Operations that need randomness, like key generation (see, e.g.,
+/// [`Key4::generate_ecc_with_rng`]) and signing (see
+/// [`KeyPair::sign_with_rng`]), have variants that take an `Rng`.
+/// The other variants use a fresh generator that is seeded from the
+/// operating system.
+///
+/// Using [`Rng::from_seed_insecure`], the generator can be seeded
+/// with a fixed value, which makes these operations reproducible.
+/// This is useful for tests, and must not be used for anything else.
+/// Therefore, it is only available if the
+/// `insecure-deterministic-rng` feature is enabled.
+///
+///   [`Key4::generate_ecc_with_rng`]: ../packet/key/struct.Key4.html#method.generate_ecc_with_rng
+///   [`KeyPair::sign_with_rng`]: struct.KeyPair.html#method.sign_with_rng
+///   [`Rng::from_seed_insecure`]: #method.from_seed_insecure
+pub struct Rng(Yarrow);
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng(Yarrow::default())
+    }
+}
+
+impl fmt::Debug for Rng {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Rng")
+    }
+}
+
+impl Rng {
+    /// Creates a generator that is seeded from the operating system.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a generator that is seeded with `seed`.
+    ///
+    /// The generator produces the same output for the same seed,
+    /// so this is only useful for reproducible tests.  Never use it
+    /// to create keys or signatures that are used in practice.
+    ///
+    /// Returns `Error::InvalidArgument` if `seed` is empty.
+    #[cfg(any(test, feature = "insecure-deterministic-rng"))]
+    pub fn from_seed_insecure(seed: &[u8]) -> Result<Self> {
+        if seed.is_empty() {
+            return Err(::Error::InvalidArgument(
+                "The seed must not be empty".into()).into());
+        }
+        Ok(Rng(Yarrow::from_seed(seed)))
+    }
+
+    /// Fills the given buffer with random data.
+    pub fn random<B: AsMut<[u8]>>(&mut self, mut buf: B) {
+        self.0.random(buf.as_mut());
+    }
+
+    /// Returns the underlying nettle generator.
+    pub(crate) fn yarrow(&mut self) -> &mut Yarrow {
+        &mut self.0
+    }
+}
+
+/// Compares two byte slices in constant time.
+///
+/// Returns whether `a` and `b` are equal.  The time taken depends
+/// only on the lengths of the slices, not on their contents.  Use
+/// this to check authentication tags, checksums, and other values
+/// that an attacker must not be able to guess byte by byte.
+pub fn secure_cmp(a: &[u8], b: &[u8]) -> bool {
+    mem::secure_cmp(a, b) == ::std::cmp::Ordering::Equal
+}
+
 /// Holds a session key.
 ///
-/// The session key is cleared when dropped.
+/// The session key is cleared when dropped, and is not revealed by
+/// the `Debug` implementation.
 #[derive(Clone, PartialEq, Eq)]
 pub struct SessionKey(mem::Protected);
 
@@ -48,6 +123,14 @@ impl SessionKey {
         Self(sk)
     }
 
+    /// Creates a new session key for use with `algo`.
+    ///
+    /// The key has the size required by `algo`, and is filled with
+    /// random bytes.  Fails if Sequoia does not support `algo`.
+    pub fn for_algo(algo: SymmetricAlgorithm) -> Result<Self> {
+        Ok(Self::new(algo.key_size()?))
+    }
+
     /// Converts to a buffer for modification.
     pub unsafe fn into_vec(self) -> Vec<u8> {
         self.0.into_vec()
@@ -100,16 +183,68 @@ impl From<&[u8]> for SessionKey {
 
 impl fmt::Debug for SessionKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "SessionKey ({:?})", self.0)
+        f.write_str("SessionKey ([<Redacted>])")
     }
 }
 
 /// Holds a password.
 ///
-/// The password is cleared when dropped.
+/// The password is cleared when dropped, and is not revealed by the
+/// `Debug` implementation.  Use [`Password::map`] to access it.
+///
+///   [`Password::map`]: #method.map
 #[derive(Clone, PartialEq, Eq)]
 pub struct Password(mem::Protected);
 
+impl Password {
+    /// Reads a password from `reader`.
+    ///
+    /// The password extends to the first newline, or the end of the
+    /// input.  The newline, and a carriage return preceding it, are
+    /// not part of the password.  The reader is read one byte at a
+    /// time, so that nothing following the newline is consumed.
+    ///
+    /// The password is only ever stored in memory that is cleared
+    /// when it is no longer needed.  In particular, it never transits
+    /// a `String`.
+    pub fn from_reader(reader: &mut dyn io::Read) -> io::Result<Password> {
+        let mut buffer: mem::Protected = vec![0; 64].into();
+        let mut len = 0;
+        loop {
+            if len == buffer.len() {
+                // Dropping the old buffer clears it.
+                let mut bigger: mem::Protected =
+                    vec![0; 2 * buffer.len()].into();
+                bigger[..len].copy_from_slice(&buffer[..len]);
+                buffer = bigger;
+            }
+
+            match reader.read(&mut buffer[len..len + 1]) {
+                Ok(0) => break,
+                Ok(_) if buffer[len] == b'\n' => break,
+                Ok(_) => len += 1,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        if len > 0 && buffer[len - 1] == b'\r' {
+            len -= 1;
+        }
+        Ok(Password(buffer[..len].into()))
+    }
+
+    /// Calls `fun` with the password.
+    ///
+    /// This makes accesses to the password explicit, and avoids
+    /// copies that are not cleared.
+    pub fn map<F, T>(&self, fun: F) -> T
+        where F: FnOnce(&[u8]) -> T
+    {
+        fun(&self.0)
+    }
+}
+
 impl AsRef<[u8]> for Password {
     fn as_ref(&self) -> &[u8] {
         &self.0
@@ -156,7 +291,7 @@ impl From<&[u8]> for Password {
 
 impl fmt::Debug for Password {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Password ({:?})", self.0)
+        f.write_str("Password ([<Redacted>])")
     }
 }
 
@@ -214,3 +349,96 @@ fn hash_file_test() {
                    &::conversions::to_hex(&digest[..], false));
     }
 }
+
+#[test]
+fn session_key_for_algo_test() {
+    use SymmetricAlgorithm::*;
+
+    for algo in &[TripleDES, CAST5, Blowfish, AES128, AES192, AES256,
+                  Twofish, Camellia128, Camellia192, Camellia256] {
+        let sk = SessionKey::for_algo(*algo).unwrap();
+        assert_eq!(sk.len(), algo.key_size().unwrap());
+    }
+    assert_eq!(SessionKey::for_algo(AES128).unwrap().len(), 16);
+    assert_eq!(SessionKey::for_algo(AES256).unwrap().len(), 32);
+
+    // Two keys are not the same.
+    assert!(SessionKey::for_algo(AES256).unwrap()
+            != SessionKey::for_algo(AES256).unwrap());
+
+    assert!(SessionKey::for_algo(Unencrypted).is_err());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rng_from_seed() {
+        let mut a = Rng::from_seed_insecure(b"seed").unwrap();
+        let mut b = Rng::from_seed_insecure(b"seed").unwrap();
+        let mut c = Rng::from_seed_insecure(b"other seed").unwrap();
+        let (mut x, mut y, mut z) = ([0u8; 32], [0u8; 32], [0u8; 32]);
+        a.random(&mut x[..]);
+        b.random(&mut y[..]);
+        c.random(&mut z[..]);
+        assert_eq!(x, y);
+        assert!(x != z);
+
+        assert!(Rng::from_seed_insecure(b"").is_err());
+    }
+
+    #[test]
+    fn secure_cmp_results() {
+        assert!(secure_cmp(b"", b""));
+        assert!(secure_cmp(b"tag", b"tag"));
+        assert!(! secure_cmp(b"tag", b"taG"));
+        assert!(! secure_cmp(b"tag", b"Tag"));
+        assert!(! secure_cmp(b"tag", b"ta"));
+        assert!(! secure_cmp(b"ta", b"tag"));
+        assert!(! secure_cmp(b"", b"tag"));
+
+        let a = [0x5a; 32];
+        let mut b = a;
+        assert!(secure_cmp(&a, &b));
+        for i in 0..b.len() {
+            b[i] ^= 1;
+            assert!(! secure_cmp(&a, &b));
+            b[i] ^= 1;
+        }
+    }
+
+    #[test]
+    fn password_from_reader() {
+        let mut input = io::Cursor::new(&b"hunter2\r\nnext line"[..]);
+        let password = Password::from_reader(&mut input).unwrap();
+        assert_eq!(password, "hunter2".into());
+        assert_eq!(password.map(|p| p.len()), 7);
+
+        // Nothing after the newline is consumed.
+        let mut rest = Vec::new();
+        input.read_to_end(&mut rest).unwrap();
+        assert_eq!(&rest[..], b"next line");
+
+        // Long passwords without a newline.
+        let long = vec![b'x'; 1000];
+        let password = Password::from_reader(&mut &long[..]).unwrap();
+        assert!(password.map(|p| p == &long[..]));
+
+        let password = Password::from_reader(&mut &b""[..]).unwrap();
+        assert!(password.map(|p| p.is_empty()));
+    }
+
+    #[test]
+    fn debug_redacts() {
+        let password: Password = "hunter2".into();
+        let s = format!("{:?}", password);
+        assert!(! s.contains("104"));
+        assert!(! s.contains("hunter2"));
+
+        let sk: SessionKey = vec![0xaa; 16].into();
+        let s = format!("{:?}", sk);
+        assert!(! s.contains("170"));
+        assert!(! s.to_lowercase().contains("aa"));
+    }
+}
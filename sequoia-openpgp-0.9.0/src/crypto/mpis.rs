@@ -261,13 +261,7 @@ impl ProtectedMPI {
 
 impl fmt::Debug for ProtectedMPI {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if cfg!(debug_assertions) {
-            f.write_fmt(format_args!(
-                "{} bits: {}", self.bits(),
-                ::conversions::to_hex(&*self.value, true)))
-        } else {
-            f.write_str("<Redacted>")
-        }
+        write!(f, "{} bits: <Redacted>", self.bits())
     }
 }
 
@@ -536,40 +530,22 @@ pub enum SecretKey {
 
 impl fmt::Debug for SecretKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if cfg!(debug_assertions) {
-            match self {
-                &SecretKey::RSA{ ref d, ref p, ref q, ref u } =>
-                    write!(f, "RSA {{ d: {:?}, p: {:?}, q: {:?}, u: {:?} }}", d, p, q, u),
-                &SecretKey::DSA{ ref x } =>
-                    write!(f, "DSA {{ x: {:?} }}", x),
-                &SecretKey::Elgamal{ ref x } =>
-                    write!(f, "Elgamal {{ x: {:?} }}", x),
-                &SecretKey::EdDSA{ ref scalar } =>
-                    write!(f, "EdDSA {{ scalar: {:?} }}", scalar),
-                &SecretKey::ECDSA{ ref scalar } =>
-                    write!(f, "ECDSA {{ scalar: {:?} }}", scalar),
-                &SecretKey::ECDH{ ref scalar } =>
-                    write!(f, "ECDH {{ scalar: {:?} }}", scalar),
-                &SecretKey::Unknown{ ref mpis, ref rest } =>
-                    write!(f, "Unknown {{ mips: {:?}, rest: {:?} }}", mpis, rest),
-            }
-        } else {
-            match self {
-                &SecretKey::RSA{ .. } =>
-                    f.write_str("RSA { <Redacted> }"),
-                &SecretKey::DSA{ .. } =>
-                    f.write_str("DSA { <Redacted> }"),
-                &SecretKey::Elgamal{ .. } =>
-                    f.write_str("Elgamal { <Redacted> }"),
-                &SecretKey::EdDSA{ .. } =>
-                    f.write_str("EdDSA { <Redacted> }"),
-                &SecretKey::ECDSA{ .. } =>
-                    f.write_str("ECDSA { <Redacted> }"),
-                &SecretKey::ECDH{ .. } =>
-                    f.write_str("ECDH { <Redacted> }"),
-                &SecretKey::Unknown{ .. } =>
-                    f.write_str("Unknown { <Redacted> }"),
-            }
+        // The secret MPIs are never shown, not even in debug builds.
+        match self {
+            &SecretKey::RSA{ .. } =>
+                f.write_str("RSA { <Redacted> }"),
+            &SecretKey::DSA{ .. } =>
+                f.write_str("DSA { <Redacted> }"),
+            &SecretKey::Elgamal{ .. } =>
+                f.write_str("Elgamal { <Redacted> }"),
+            &SecretKey::EdDSA{ .. } =>
+                f.write_str("EdDSA { <Redacted> }"),
+            &SecretKey::ECDSA{ .. } =>
+                f.write_str("ECDSA { <Redacted> }"),
+            &SecretKey::ECDH{ .. } =>
+                f.write_str("ECDH { <Redacted> }"),
+            &SecretKey::Unknown{ .. } =>
+                f.write_str("Unknown { <Redacted> }"),
         }
     }
 }
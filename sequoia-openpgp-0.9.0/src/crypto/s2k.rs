@@ -13,6 +13,7 @@ use crypto::Password;
 use crypto::SessionKey;
 
 use std::fmt;
+use std::time::{Duration, Instant};
 
 use quickcheck::{Arbitrary, Gen};
 use rand::Rng;
@@ -74,6 +75,83 @@ impl Default for S2K {
 }
 
 impl S2K {
+    /// Returns a *Simple S2K* using `hash`.
+    ///
+    /// This S2K does not use a salt, and should only be used to
+    /// interoperate with legacy implementations.
+    pub fn new_simple(hash: HashAlgorithm) -> Self {
+        S2K::Simple { hash: hash }
+    }
+
+    /// Returns a *Salted S2K* using `hash` and `salt`.
+    pub fn new_salted(hash: HashAlgorithm, salt: [u8; 8]) -> Self {
+        S2K::Salted { hash: hash, salt: salt }
+    }
+
+    /// Returns an *Iterated and Salted S2K* using `hash` and `salt`.
+    ///
+    /// `iterations` is the number of bytes to hash (called the count
+    /// in RFC 4880).  Since not every count can be encoded, it is
+    /// rounded up to the nearest count that can be.  See
+    /// [`S2K::nearest_hash_count()`].  To find a count that takes a
+    /// given amount of time on this machine, use
+    /// [`S2K::calibrate()`].
+    ///
+    /// [`S2K::nearest_hash_count()`]: #method.nearest_hash_count
+    /// [`S2K::calibrate()`]: #method.calibrate
+    pub fn new_iterated(hash: HashAlgorithm, salt: [u8; 8], iterations: u32)
+                        -> Self {
+        S2K::Iterated {
+            hash: hash,
+            salt: salt,
+            hash_bytes: Self::nearest_hash_count(iterations as usize),
+        }
+    }
+
+    /// Returns the number of bytes to hash so that deriving a key
+    /// takes about `target` on this machine.
+    ///
+    /// This measures how fast SHA2-256, which [`S2K::default()`]
+    /// uses, runs, and returns the largest encodable count that fits
+    /// into the time budget, but at least the smallest encodable
+    /// count.  The result can be passed to [`S2K::new_iterated()`].
+    ///
+    /// [`S2K::default()`]: #impl-Default
+    /// [`S2K::new_iterated()`]: #method.new_iterated
+    pub fn calibrate(target: Duration) -> u32 {
+        fn seconds(d: Duration) -> f64 {
+            d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+        }
+
+        const MIN: Duration = Duration::from_millis(10);
+        let password: Password = "calibration"[..].into();
+        let mut hash_bytes = 1 << 16;
+        let elapsed = loop {
+            let s2k = S2K::Iterated {
+                hash: HashAlgorithm::SHA256,
+                salt: [0; 8],
+                hash_bytes: hash_bytes,
+            };
+            let start = Instant::now();
+            let _ = s2k.derive_key(&password, 32);
+            let elapsed = start.elapsed();
+
+            // Make sure the measurement is not dominated by noise.
+            if elapsed >= MIN || hash_bytes >= 0x3e00000 {
+                break elapsed;
+            }
+            hash_bytes *= 2;
+        };
+
+        let estimate =
+            hash_bytes as f64 * seconds(target) / seconds(elapsed).max(1e-9);
+        (0..0x100u32)
+            .map(|coded| Self::decode_count(coded as u8))
+            .filter(|&count| (count as f64) <= estimate)
+            .max()
+            .unwrap_or(Self::decode_count(0))
+    }
+
     /// Convert the string to a key using the S2K's paramters.
     pub fn derive_key(&self, string: &Password, key_size: usize)
     -> Result<SessionKey> {
@@ -88,7 +166,7 @@ impl S2K {
                 let hash_sz = hash.digest_size();
                 let num_contexts = (key_size + hash_sz - 1) / hash_sz;
                 let mut zeros = Vec::with_capacity(num_contexts + 1);
-                let mut ret = vec![0u8; key_size];
+                let mut ret: SessionKey = vec![0u8; key_size].into();
 
                 for data in ret.chunks_mut(hash_sz) {
                     hash.update(&zeros[..]);
@@ -142,11 +220,10 @@ impl S2K {
                     zeros.push(0);
                 }
 
-                Ok(ret.into())
+                Ok(ret)
             }
-            &S2K::Unknown(u) | &S2K::Private(u) =>
-                Err(Error::MalformedPacket(
-                        format!("Unknown S2K type {:#x}", u)).into()),
+            &S2K::Unknown(_) | &S2K::Private(_) =>
+                Err(Error::UnsupportedS2K(*self).into()),
         }
     }
 
@@ -160,25 +237,14 @@ impl S2K {
 
         match hash_bytes {
             0...1024 => 1024,
-            1025...2048 => hash_bytes as u32,
-            0x3e00001...usize::MAX => 0x3e00000,
+            0x3e00000...usize::MAX => 0x3e00000,
             hash_bytes => {
+                // The count is encoded using the five most
+                // significant bits.  Round up the rest.
                 let hash_bytes = hash_bytes as u32;
                 let msb = 32 - hash_bytes.leading_zeros();
-                let mantissa_mask = 0b1111_000000 << (msb - 11);
-                let tail_mask = (1 << (msb - 11)) - 1;
-                let mantissa = (hash_bytes & mantissa_mask) >> (msb - 5);
-                let exp = if msb < 11 { 0 } else { msb - 11 };
-
-                if hash_bytes & tail_mask != 0 {
-                    if mantissa < 0b1111 {
-                        Self::decode_count((mantissa as u8 + 1) | exp as u8)
-                    } else {
-                        Self::decode_count(mantissa as u8 | (exp as u8 + 1))
-                    }
-                } else {
-                    hash_bytes
-                }
+                let step = 1 << (msb - 5);
+                (hash_bytes + step - 1) & !(step - 1)
             }
         }
      }
@@ -211,12 +277,17 @@ impl S2K {
                     format!("S2K: cannot encode iteration count of {}",
                             hash_bytes)).into());
             }
-            11...32 => {
+            11...26 => {
                 let m = 0b1111_000000 << (msb - 11);
-                let t = 1 << (msb - 11);
+                let t = 1 << (msb - 5);
 
                 (m, t - 1)
             }
+            27...32 => {
+                return Err(Error::InvalidArgument(
+                    format!("S2K: cannot encode iteration count of {}",
+                            hash_bytes)).into());
+            }
             _ => unreachable!()
         };
         let exp = if msb < 11 { 0 } else { msb - 11 };
@@ -469,6 +540,68 @@ mod tests {
         }
     }
 
+    #[test]
+    fn s2k_coded_count_rounding() {
+        // Counts that cannot be encoded are rejected...
+        assert!(S2K::encode_count(1025).is_err());
+        assert!(S2K::encode_count(2049).is_err());
+        assert!(S2K::encode_count(0x3e00000 + (1 << 21)).is_err());
+        assert!(S2K::encode_count(::std::u32::MAX).is_err());
+
+        // ... and rounded up to the next encodable count.
+        assert_eq!(S2K::nearest_hash_count(1025), 1088);
+        assert_eq!(S2K::nearest_hash_count(2047), 2048);
+        assert_eq!(S2K::nearest_hash_count(2049), 2176);
+        assert_eq!(S2K::nearest_hash_count(65011711), 65011712);
+        assert_eq!(S2K::nearest_hash_count(::std::usize::MAX), 0x3e00000);
+        for cc in 0..0x100usize {
+            let count = S2K::decode_count(cc as u8);
+            assert_eq!(S2K::nearest_hash_count(count as usize), count);
+            assert_eq!(S2K::encode_count(S2K::nearest_hash_count(
+                count as usize - 1)).unwrap() as usize, cc);
+        }
+    }
+
+    #[test]
+    fn s2k_constructors() {
+        let salt = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(S2K::new_simple(HashAlgorithm::SHA256),
+                   S2K::Simple { hash: HashAlgorithm::SHA256 });
+        assert_eq!(S2K::new_salted(HashAlgorithm::SHA256, salt),
+                   S2K::Salted { hash: HashAlgorithm::SHA256, salt: salt });
+        assert_eq!(S2K::new_iterated(HashAlgorithm::SHA256, salt, 100000),
+                   S2K::Iterated {
+                       hash: HashAlgorithm::SHA256,
+                       salt: salt,
+                       hash_bytes: 102400,
+                   });
+
+        // The iteration count is always encodable.
+        let s2k = S2K::new_iterated(HashAlgorithm::SHA1, salt, 1025);
+        let mut buf = Vec::new();
+        s2k.serialize(&mut buf).unwrap();
+        assert_eq!(S2K::from_bytes(&buf).unwrap(), s2k);
+
+        let password: Password = "password".into();
+        assert_eq!(s2k.derive_key(&password, 16).unwrap(),
+                   S2K::Iterated {
+                       hash: HashAlgorithm::SHA1,
+                       salt: salt,
+                       hash_bytes: 1088,
+                   }.derive_key(&password, 16).unwrap());
+    }
+
+    #[test]
+    fn s2k_calibrate() {
+        let count = S2K::calibrate(Duration::from_millis(0));
+        assert_eq!(count, 1024);
+
+        let count = S2K::calibrate(Duration::from_millis(50));
+        assert!(count >= 1024);
+        assert_eq!(S2K::decode_count(S2K::encode_count(count).unwrap()),
+                   count);
+    }
+
     quickcheck!{
         fn s2k_coded_count_approx(i: usize) -> bool {
             let approx = S2K::nearest_hash_count(i);
@@ -617,4 +617,23 @@ mod tests {
             assert_eq!(&plaintext[..], &::tests::manifesto()[..]);
         }
     }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(SymmetricAlgorithm::AES256.key_size().unwrap(), 32);
+        assert_eq!(SymmetricAlgorithm::AES256.block_size().unwrap(), 16);
+        assert_eq!(SymmetricAlgorithm::TripleDES.key_size().unwrap(), 24);
+        assert_eq!(SymmetricAlgorithm::TripleDES.block_size().unwrap(), 8);
+
+        // IDEA is not implemented.
+        assert!(! SymmetricAlgorithm::IDEA.is_supported());
+        assert!(SymmetricAlgorithm::IDEA.key_size().is_err());
+        assert!(SymmetricAlgorithm::IDEA.block_size().is_err());
+
+        for i in 0..=255u8 {
+            let algo = SymmetricAlgorithm::from(i);
+            assert_eq!(algo.is_supported(), algo.key_size().is_ok());
+            assert_eq!(algo.is_supported(), algo.block_size().is_ok());
+        }
+    }
 }
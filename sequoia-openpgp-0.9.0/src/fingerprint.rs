@@ -1,5 +1,6 @@
 use std::fmt;
 
+use Error;
 use Fingerprint;
 use KeyID;
 use Result;
@@ -25,6 +26,10 @@ impl Fingerprint {
             let mut fp : [u8; 20] = Default::default();
             fp.copy_from_slice(raw);
             Fingerprint::V4(fp)
+        } else if raw.len() == 32 {
+            let mut fp : [u8; 32] = Default::default();
+            fp.copy_from_slice(raw);
+            Fingerprint::V5(fp)
         } else {
             Fingerprint::Invalid(raw.to_vec().into_boxed_slice())
         }
@@ -32,7 +37,15 @@ impl Fingerprint {
 
     /// Reads a hexadecimal fingerprint.
     ///
-    /// This function ignores whitespace.
+    /// This function ignores whitespace and case, and accepts an
+    /// optional `0x` prefix.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidArgument` if `hex` contains characters
+    /// other than hexadecimal digits and whitespace, or if it
+    /// doesn't encode a version 4 (20 bytes) or version 5 (32 bytes)
+    /// fingerprint.
     ///
     /// # Example
     ///
@@ -45,13 +58,19 @@ impl Fingerprint {
     /// assert_eq!(fp.unwrap().to_hex(), hex);
     /// ```
     pub fn from_hex(hex: &str) -> Result<Fingerprint> {
-        Ok(Fingerprint::from_bytes(&::conversions::from_hex(hex, true)?[..]))
+        let bytes = ::conversions::from_hex(hex, true)?;
+        match bytes.len() {
+            20 | 32 => Ok(Fingerprint::from_bytes(&bytes[..])),
+            n => Err(Error::InvalidArgument(
+                format!("Invalid fingerprint length: {} bytes", n)).into()),
+        }
     }
 
     /// Returns a reference to the raw Fingerprint.
     pub fn as_slice(&self) -> &[u8] {
         match self {
             &Fingerprint::V4(ref fp) => fp,
+            &Fingerprint::V5(ref fp) => fp,
             &Fingerprint::Invalid(ref fp) => fp,
         }
     }
@@ -63,6 +82,22 @@ impl Fingerprint {
         self.convert_to_string(true)
     }
 
+    /// Converts the fingerprint to hexadecimal digits grouped by
+    /// spaces.
+    ///
+    /// This is the format GnuPG uses, e.g.:
+    ///
+    /// ```text
+    /// 8F17 7771 18A3 3DDA 9BA4  8E62 AACB 3243 6300 52D9
+    /// ```
+    ///
+    /// This is the same as [`Fingerprint::to_string`].
+    ///
+    ///   [`Fingerprint::to_string`]: #method.to_string
+    pub fn to_spaced_hex(&self) -> String {
+        self.convert_to_string(true)
+    }
+
     /// Converts the fingerprint to a hexadecimal number.
     pub fn to_hex(&self) -> String {
         self.convert_to_string(false)
@@ -70,17 +105,16 @@ impl Fingerprint {
 
     /// Common code for the above functions.
     fn convert_to_string(&self, pretty: bool) -> String {
-        let raw = match self {
-            &Fingerprint::V4(ref fp) => &fp[..],
-            &Fingerprint::Invalid(ref fp) => &fp[..],
-        };
+        let raw = self.as_slice();
 
-        // We currently only handle V4 fingerprints, which look like:
+        // V4 fingerprints look like:
         //
         //   8F17 7771 18A3 3DDA 9BA4  8E62 AACB 3243 6300 52D9
         //
-        // Since we have no idea how to format an invalid fingerprint,
-        // just format it like a V4 fingerprint and hope for the best.
+        // There is no established format for V5 fingerprints, and we
+        // have no idea how to format an invalid fingerprint, so we
+        // just format them like a V4 fingerprint and hope for the
+        // best.
 
         let mut output = Vec::with_capacity(
             // Each byte results in to hex characters.
@@ -122,16 +156,36 @@ impl Fingerprint {
     }
 
     /// Converts the fingerprint to a key ID.
+    ///
+    /// The key ID of a V4 key consists of the low-order 64 bits of
+    /// its fingerprint, whereas that of a V5 key consists of the
+    /// high-order 64 bits.
     pub fn to_keyid(&self) -> KeyID {
         match self {
             &Fingerprint::V4(ref fp) =>
                 KeyID::from_bytes(&fp[fp.len() - 8..]),
+            &Fingerprint::V5(ref fp) =>
+                KeyID::from_bytes(&fp[..8]),
             &Fingerprint::Invalid(ref fp) => {
                 KeyID::Invalid(fp.clone())
             }
         }
     }
 
+    /// Returns whether `keyid` is this fingerprint's key ID.
+    ///
+    /// This is useful to find the key that issued a signature that
+    /// only names the issuer's key ID.  The wildcard key ID does not
+    /// match any fingerprint; see [`KeyID::is_wildcard`].
+    ///
+    ///   [`KeyID::is_wildcard`]: enum.KeyID.html#method.is_wildcard
+    pub fn matches_keyid(&self, keyid: &KeyID) -> bool {
+        match self {
+            &Fingerprint::Invalid(_) => false,
+            _ => ! keyid.is_wildcard() && &self.to_keyid() == keyid,
+        }
+    }
+
     /// Converts the hex representation of the fingerprint to a phrase in the
     /// ICAO alphabet.
     pub fn to_icao(&self) -> String {
@@ -183,4 +237,38 @@ Charlie Delta Echo Foxtrot Zero One Two Three Four Five Six Seven";
 
         assert_eq!(fpr.to_icao(), expected);
     }
+
+    #[test]
+    fn hex() {
+        let hex = "8F17777118A33DDA9BA48E62AACB3243630052D9";
+        let fp = Fingerprint::from_hex(hex).unwrap();
+        assert_eq!(fp.to_hex(), hex);
+        assert_eq!(fp.to_spaced_hex(),
+                   "8F17 7771 18A3 3DDA 9BA4  8E62 AACB 3243 6300 52D9");
+        assert_eq!(fp.to_keyid(), KeyID::from_hex("AACB3243630052D9").unwrap());
+
+        // Whitespace, case, and a 0x prefix are tolerated.
+        for s in &["0x8F17777118A33DDA9BA48E62AACB3243630052D9",
+                   "8f17777118a33dda9ba48e62aacb3243630052d9",
+                   "8F17 7771 18A3 3DDA 9BA4  8E62 AACB 3243 6300 52D9",
+                   " 0X8f17 7771 18a3 3dda 9ba4 8e62 aacb 3243 6300 52D9\n"] {
+            assert_eq!(Fingerprint::from_hex(s).unwrap(), fp);
+        }
+
+        // Wrong lengths and characters are rejected.
+        assert!(Fingerprint::from_hex("").is_err());
+        assert!(Fingerprint::from_hex("AACB3243630052D9").is_err());
+        assert!(Fingerprint::from_hex(&hex[2..]).is_err());
+        assert!(Fingerprint::from_hex(&format!("{}00", hex)).is_err());
+        assert!(Fingerprint::from_hex(&hex[1..]).is_err());
+        assert!(Fingerprint::from_hex(&hex.replace("8", "G")).is_err());
+
+        // V5 fingerprints.
+        let hex = "0123456789ABCDEF0123456789ABCDEF\
+                   0123456789ABCDEF0123456789ABCDEF";
+        match Fingerprint::from_hex(hex).unwrap() {
+            Fingerprint::V5(_) => (),
+            fp => panic!("Expected a V5 fingerprint, got {:?}", fp),
+        }
+    }
 }
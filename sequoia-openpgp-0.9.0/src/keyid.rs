@@ -83,16 +83,25 @@ impl KeyID {
     }
 
     /// Reads a hex-encoded Key ID.
+    ///
+    /// Like [`Fingerprint::from_hex`], this ignores whitespace and
+    /// case, and accepts an optional `0x` prefix.  If a version 4
+    /// fingerprint is given, it is converted to a key ID.  Other
+    /// lengths, like 32-bit short key IDs, are rejected with
+    /// `Error::InvalidArgument`.
+    ///
+    ///   [`Fingerprint::from_hex`]: enum.Fingerprint.html#method.from_hex
     pub fn from_hex(hex: &str) -> Result<KeyID> {
         let bytes = ::conversions::from_hex(hex, true)?;
 
-        // A KeyID is exactly 8 bytes long.
-        if bytes.len() == 8 {
-            Ok(KeyID::from_bytes(&bytes[..]))
-        } else {
-            // Maybe a fingerprint was given.  Try to parse it and
-            // convert it to a KeyID.
-            Ok(Fingerprint::from_hex(hex)?.to_keyid())
+        match bytes.len() {
+            // A KeyID is exactly 8 bytes long.
+            8 => Ok(KeyID::from_bytes(&bytes[..])),
+            // A fingerprint was given.  Convert it to a KeyID.
+            20 => Ok(Fingerprint::from_bytes(&bytes[..]).to_keyid()),
+            n => Err(Error::InvalidArgument(
+                format!("Expected a key ID or fingerprint, \
+                         got {} bytes", n)).into()),
         }
     }
 
@@ -110,10 +119,24 @@ impl KeyID {
     }
 
     /// Returns true if this is a wild card ID.
+    ///
+    /// A PKESK packet uses the wildcard key ID to hide its recipient.
+    /// To decrypt such a packet, every key has to be tried.
     pub fn is_wildcard(&self) -> bool {
         self.as_slice().iter().all(|b| *b == 0)
     }
 
+    /// Returns whether this is the key ID of `fp`.
+    ///
+    /// This is the same as [`Fingerprint::matches_keyid`].  In
+    /// particular, the wildcard key ID does not alias any
+    /// fingerprint.
+    ///
+    ///   [`Fingerprint::matches_keyid`]: enum.Fingerprint.html#method.matches_keyid
+    pub fn aliases(&self, fp: &Fingerprint) -> bool {
+        fp.matches_keyid(self)
+    }
+
     /// Converts the key ID to its standard representation.
     ///
     /// Returns the fingerprint suitable for human consumption.
@@ -203,8 +226,43 @@ mod test {
         KeyID::from_hex("GB3751F1587DAEF1").unwrap_err();
         KeyID::from_hex("EFB3751F1587DAEF1").unwrap_err();
         KeyID::from_hex("%FB3751F1587DAEF1").unwrap_err();
-        assert_match!(KeyID::Invalid(_) = KeyID::from_hex("587DAEF1").unwrap());
-        assert_match!(KeyID::Invalid(_) =
-                      KeyID::from_hex("0x587DAEF1").unwrap());
+        KeyID::from_hex("587DAEF1").unwrap_err();
+        KeyID::from_hex("0x587DAEF1").unwrap_err();
+
+        // Wrong lengths are rejected.
+        for &len in &[7, 9, 21] {
+            let hex = "AB".repeat(len);
+            assert_match!(Some(&Error::InvalidArgument(_)) =
+                          KeyID::from_hex(&hex).unwrap_err()
+                          .downcast_ref::<Error>());
+        }
+    }
+
+    #[test]
+    fn aliases() {
+        let fp = Fingerprint::from_hex(
+            "39D100AB67D5BD8C04010205FB3751F1587DAEF1").unwrap();
+        let keyid = KeyID::from_hex("FB3751F1587DAEF1").unwrap();
+        assert!(fp.matches_keyid(&keyid));
+        assert!(keyid.aliases(&fp));
+
+        // Only the low-order 64 bits count.
+        let other = KeyID::from_hex("39D100AB67D5BD8C").unwrap();
+        assert!(! fp.matches_keyid(&other));
+        assert!(! other.aliases(&fp));
+        assert!(! KeyID::from_bytes(&[0x58, 0x7D, 0xAE, 0xF1]).aliases(&fp));
+
+        // The wildcard matches nothing, even a fingerprint that
+        // happens to end in zeros.
+        let wildcard = KeyID::wildcard();
+        assert!(wildcard.is_wildcard());
+        assert!(KeyID::from_hex("0000 0000 0000 0000").unwrap().is_wildcard());
+        assert!(! keyid.is_wildcard());
+        assert!(! wildcard.aliases(&fp));
+        let zeros = Fingerprint::from_hex(
+            "39D100AB67D5BD8C040102050000000000000000").unwrap();
+        assert_eq!(zeros.to_keyid(), wildcard);
+        assert!(! zeros.matches_keyid(&wildcard));
+        assert!(! wildcard.aliases(&zeros));
     }
 }
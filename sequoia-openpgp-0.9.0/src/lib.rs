@@ -55,6 +55,9 @@ extern crate flate2;
 #[cfg(feature = "compression-bzip2")]
 extern crate bzip2;
 
+#[cfg(feature = "rayon")]
+extern crate rayon;
+
 #[cfg(test)]
 #[macro_use]
 extern crate quickcheck;
@@ -121,6 +124,7 @@ mod packet_pile;
 pub mod message;
 
 pub mod constants;
+pub mod policy;
 use constants::{
     PublicKeyAlgorithm,
     SymmetricAlgorithm,
@@ -149,6 +153,65 @@ pub type Result<T> = ::std::result::Result<T, failure::Error>;
 
 #[derive(Fail, Debug, Clone)]
 /// Errors returned by this module.
+///
+/// Functions in this crate return a [`failure::Error`], which
+/// usually wraps one of these errors.  To find out what went wrong,
+/// for instance to distinguish a wrong password from a corrupted
+/// key, use `downcast_ref`.  Errors from other crates, like
+/// `std::io::Error`, are passed through unchanged.
+///
+/// Some failure modes are split into several variants:
+///
+///   - A wrong password is reported as `InvalidPassword`.
+///
+///   - An unsupported algorithm is reported using the variant for
+///     the kind of algorithm, i.e., `UnsupportedHashAlgorithm`,
+///     `UnsupportedPublicKeyAlgorithm`, `UnsupportedSymmetricAlgorithm`,
+///     `UnsupportedAEADAlgorithm`, `UnsupportedCompressionAlgorithm`,
+///     `UnsupportedEllipticCurve`, or `UnsupportedS2K`.
+///
+///   - An unsupported packet version is reported as
+///     `UnsupportedPacketVersion`.
+///
+/// This type implements [`failure::Fail`], not `std::error::Error`.
+/// `failure` implements `Fail` for every type implementing
+/// `std::error::Error`, so a type cannot derive `Fail` and implement
+/// `std::error::Error` at the same time.  Use [`Fail::cause`], or
+/// [`failure::Error::iter_chain`] to walk the chain of causes.
+///
+///   [`failure::Error`]: https://docs.rs/failure/0.1/failure/struct.Error.html
+///   [`failure::Fail`]: https://docs.rs/failure/0.1/failure/trait.Fail.html
+///   [`Fail::cause`]: https://docs.rs/failure/0.1/failure/trait.Fail.html#method.cause
+///   [`failure::Error::iter_chain`]: https://docs.rs/failure/0.1/failure/struct.Error.html#method.iter_chain
+///
+/// # Example
+///
+/// ```
+/// # extern crate sequoia_openpgp as openpgp;
+/// # use openpgp::Result;
+/// use openpgp::{Error, TPK};
+/// use openpgp::parse::Parse;
+/// use openpgp::tpk::TPKBuilder;
+///
+/// # fn main() { f().unwrap(); }
+/// # fn f() -> Result<()> {
+/// let (tpk, _) = TPKBuilder::new()
+///     .set_password(Some("streng geheim".into()))
+///     .generate()?;
+/// let mut key = tpk.primary().clone();
+/// let algo = key.pk_algo();
+///
+/// match key.secret_mut().unwrap().decrypt_in_place(algo, &"top secret".into())
+/// {
+///     Ok(()) => unreachable!(),
+///     Err(e) => match e.downcast_ref::<Error>() {
+///         Some(Error::InvalidPassword) => (), // Ask again.
+///         _ => return Err(e), // Something else went wrong.
+///     },
+/// }
+/// # Ok(())
+/// # }
+/// ```
 pub enum Error {
     /// Invalid argument.
     #[fail(display = "Invalid argument: {}", _0)]
@@ -166,6 +229,10 @@ pub enum Error {
     #[fail(display = "Unsupported packet type.  Tag: {}", _0)]
     UnsupportedPacketType(packet::Tag),
 
+    /// Unsupported packet version.
+    #[fail(display = "Unsupported {} packet version: {}", _0, _1)]
+    UnsupportedPacketVersion(packet::Tag, u8),
+
     /// Unsupported hash algorithm identifier.
     #[fail(display = "Unsupported hash algorithm: {}", _0)]
     UnsupportedHashAlgorithm(HashAlgorithm),
@@ -237,6 +304,35 @@ pub enum Error {
     /// Index out of range.
     #[fail(display = "Index out of range")]
     IndexOutOfRange,
+
+    /// The key or TPK has expired.
+    #[fail(display = "Expired")]
+    Expired,
+
+    /// The key or TPK has been revoked.
+    #[fail(display = "Revoked")]
+    Revoked,
+
+    /// An encryption container is missing its MDC packet.
+    #[fail(display = "Missing MDC")]
+    MissingMDC,
+
+    /// Unsupported S2K mechanism.
+    #[fail(display = "Unsupported S2K: {}", _0)]
+    UnsupportedS2K(crypto::s2k::S2K),
+
+    /// A compressed data packet decompresses to more than the
+    /// configured number of bytes.
+    #[fail(display = "Decompression limit of {} bytes exceeded", _0)]
+    DecompressionLimitExceeded(u64),
+
+    /// The operation was rejected by the policy.
+    ///
+    /// See the [`policy`] module.
+    ///
+    ///   [`policy`]: policy/index.html
+    #[fail(display = "Policy violation: {}", _0)]
+    PolicyViolation(String),
 }
 
 /// The OpenPGP packets that Sequoia understands.
@@ -355,6 +451,74 @@ impl Packet {
             &Packet::AED(_) => Some(Tag::AED),
         }
     }
+
+    /// Compares the packets' logical content.
+    ///
+    /// `==` is meant for deduplication, and takes some encoding
+    /// details into account.  This function is meant for comparing
+    /// the output of different implementations, and ignores:
+    ///
+    ///   - The packets' framing, i.e., whether an old or a new format
+    ///     CTB is used, and how the length is encoded (including
+    ///     partial body encoding).  This information is not retained
+    ///     by the parser, and thus never considered.
+    ///
+    ///   - For signatures, the order of the subpackets in the hashed
+    ///     and unhashed areas, and the encoding of the subpackets'
+    ///     lengths.  Subpackets with the same tag must still appear
+    ///     in the same relative order, because the last one takes
+    ///     precedence.  In contrast to `==`, the unhashed subpackets
+    ///     are compared.  The computed hash and the signature level,
+    ///     which are not part of the packet, are ignored.
+    ///
+    ///   - For keys, everything that [`Key::public_cmp`] ignores,
+    ///     except that the secret key material, if any, must match.
+    ///
+    ///   - For unknown packets, the error that caused the packet to
+    ///     be unknown.
+    ///
+    /// Container packets are equal if their own fields are equal,
+    /// and their children are pairwise equal according to this
+    /// function.  All other fields are compared as usual.
+    ///
+    ///   [`Key::public_cmp`]: packet/enum.Key.html#method.public_cmp
+    pub fn semantic_eq(&self, other: &Packet) -> bool {
+        use packet::Key;
+
+        fn key_eq(a: &Key, b: &Key) -> bool {
+            Key::public_cmp(a, b) == std::cmp::Ordering::Equal
+                && a.secret() == b.secret()
+        }
+
+        match (self, other) {
+            (&Packet::Signature(ref a), &Packet::Signature(ref b)) =>
+                a.semantic_eq(b),
+            (&Packet::PublicKey(ref a), &Packet::PublicKey(ref b))
+            | (&Packet::PublicSubkey(ref a), &Packet::PublicSubkey(ref b))
+            | (&Packet::SecretKey(ref a), &Packet::SecretKey(ref b))
+            | (&Packet::SecretSubkey(ref a), &Packet::SecretSubkey(ref b)) =>
+                key_eq(a, b),
+            (a, b) => match (a.children.as_ref(), b.children.as_ref()) {
+                (None, None) => a == b,
+                (Some(ac), Some(bc)) => {
+                    if ac.packets.len() != bc.packets.len()
+                        || ! ac.packets.iter().zip(bc.packets.iter())
+                            .all(|(a, b)| a.semantic_eq(b))
+                    {
+                        return false;
+                    }
+
+                    // Compare the rest of the containers.
+                    let mut a = a.clone();
+                    a.children = None;
+                    let mut b = b.clone();
+                    b.children = None;
+                    a == b
+                },
+                _ => false,
+            },
+        }
+    }
 }
 
 /// A `PacketPile` holds a deserialized sequence of OpenPGP messages.
@@ -376,6 +540,31 @@ pub struct PacketPile {
     top_level: Container,
 }
 
+/// The high-level structure of a packet sequence.
+///
+/// See [`PacketPile::structure`].
+///
+///   [`PacketPile::structure`]: struct.PacketPile.html#method.structure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketPileStructure {
+    /// One or more signatures without the signed data.
+    DetachedSignature,
+    /// An encrypted message.
+    Encrypted,
+    /// A signed message.
+    Signed,
+    /// A compressed message.
+    Compressed,
+    /// A message consisting of literal data.
+    Literal,
+    /// A single TPK.
+    Certificate,
+    /// A sequence of TPKs.
+    Keyring,
+    /// None of the above.
+    Unknown,
+}
+
 /// A transferable public key (TPK).
 ///
 /// A TPK (see [RFC 4880, section 11.1]) can be used to verify
@@ -449,6 +638,9 @@ pub struct TPK {
     // Signatures that we couldn't find a place for.
     pub(crate) // XXX for TSK::serialize()
     bad: Vec<packet::Signature>,
+    // Subkeys that were dropped, because none of their binding
+    // signatures verified.
+    bad_subkeys: Vec<(Fingerprint, tpk::SubkeyError)>,
 }
 
 /// An OpenPGP message.
@@ -469,13 +661,16 @@ pub struct Message {
 ///
 /// A fingerprint uniquely identifies a public key.  For more details
 /// about how a fingerprint is generated, see [Section 12.2 of RFC
-/// 4880].
+/// 4880], and [Section 12.2 of RFC 4880bis] for version 5 keys.
 ///
 ///   [Section 12.2 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-12.2
+///   [Section 12.2 of RFC 4880bis]: https://tools.ietf.org/html/draft-ietf-openpgp-rfc4880bis-07#section-12.2
 #[derive(PartialEq, Eq, Clone, Hash)]
 pub enum Fingerprint {
     /// 20 byte SHA-1 hash.
     V4([u8;20]),
+    /// 32 byte SHA-256 hash.
+    V5([u8;32]),
     /// Used for holding fingerprints that we don't understand.  For
     /// instance, we don't grok v3 fingerprints.  And, it is possible
     /// that the Issuer subpacket contains the wrong number of bytes.
@@ -1,11 +1,13 @@
 //! AEAD encrypted data packets.
 
+use std::io;
 use std::ops::{Deref, DerefMut};
 
 use constants::{
     AEADAlgorithm,
     SymmetricAlgorithm,
 };
+use crypto::{aead, SessionKey};
 use packet::{self, Common};
 use Packet;
 use Error;
@@ -116,6 +118,33 @@ impl AED1 {
     pub fn set_iv(&mut self, iv: Box<[u8]>) -> Box<[u8]> {
         ::std::mem::replace(&mut self.iv, iv)
     }
+
+    /// Decrypts the packet's body using `session_key`.
+    ///
+    /// The body must hold the encrypted chunks, i.e. the packet must
+    /// not have been decrypted by the `PacketParser`.  Returns a
+    /// reader yielding the plaintext.
+    ///
+    /// Every chunk is authenticated before any of its plaintext is
+    /// returned, and the final authentication tag is checked when
+    /// the end of the ciphertext is reached.  If authentication
+    /// fails, the reader returns an error at that point.
+    pub fn decrypt<'a>(&'a self, session_key: &SessionKey)
+                       -> Result<Box<dyn io::Read + 'a>> {
+        let body = self.common.body().ok_or_else(|| {
+            Error::InvalidOperation("No encrypted data".into())
+        })?;
+
+        if self.iv.len() != self.aead.iv_size()? {
+            return Err(Error::MalformedPacket(
+                format!("IV has the wrong size: expected {} bytes, got {}",
+                        self.aead.iv_size()?, self.iv.len())).into());
+        }
+
+        Ok(Box::new(aead::Decryptor::new(
+            1, self.sym_algo, self.aead, self.chunk_size, &self.iv,
+            session_key, io::Cursor::new(body))?))
+    }
 }
 
 impl From<AED1> for Packet {
@@ -160,4 +189,67 @@ mod tests {
         s.set_body(vec![0, 1, 2]);
         assert_eq!(s.body(), Some(&[0, 1, 2][..]));
     }
+
+    #[test]
+    fn decrypt() {
+        use std::io::{Read, Write};
+
+        let sym_algo = SymmetricAlgorithm::AES128;
+        let aead_algo = AEADAlgorithm::EAX;
+        let chunk_size = 64;
+        let digest_size = aead_algo.digest_size().unwrap();
+
+        let sk = SessionKey::new(sym_algo.key_size().unwrap());
+        let mut iv = vec![0; aead_algo.iv_size().unwrap()];
+        ::crypto::random(&mut iv);
+
+        // Three and a half chunks.
+        let plaintext = (0..224).map(|i| i as u8).collect::<Vec<u8>>();
+        let mut ciphertext = Vec::new();
+        {
+            let mut e = aead::Encryptor::new(
+                1, sym_algo, aead_algo, chunk_size, &iv, &sk,
+                &mut ciphertext).unwrap();
+            e.write_all(&plaintext).unwrap();
+            e.finish().unwrap();
+        }
+
+        let mut aed = AED1::new(sym_algo, aead_algo, chunk_size,
+                                iv.into_boxed_slice()).unwrap();
+
+        // No body.
+        assert!(aed.decrypt(&sk).is_err());
+
+        aed.set_body(ciphertext.clone());
+        let mut content = Vec::new();
+        aed.decrypt(&sk).unwrap().read_to_end(&mut content).unwrap();
+        assert_eq!(&content[..], &plaintext[..]);
+
+        // Wrong key.
+        let mut content = Vec::new();
+        assert!(aed.decrypt(&SessionKey::new(16)).unwrap()
+                .read_to_end(&mut content).is_err());
+
+        // Tamper with the second chunk.  The first chunk is still
+        // returned, but the second is not.
+        let mut tampered = ciphertext.clone();
+        tampered[chunk_size + digest_size + 1] ^= 1;
+        aed.set_body(tampered);
+        {
+            let mut r = aed.decrypt(&sk).unwrap();
+            let mut chunk = vec![0; chunk_size];
+            r.read_exact(&mut chunk).unwrap();
+            assert_eq!(&chunk[..], &plaintext[..chunk_size]);
+            assert!(r.read_exact(&mut chunk).is_err());
+        }
+
+        // Tamper with the final authentication tag.
+        let mut truncated = ciphertext.clone();
+        let l = truncated.len();
+        truncated[l - 1] ^= 1;
+        aed.set_body(truncated);
+        let mut content = Vec::new();
+        assert!(aed.decrypt(&sk).unwrap()
+                .read_to_end(&mut content).is_err());
+    }
 }
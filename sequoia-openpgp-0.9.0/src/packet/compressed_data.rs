@@ -1,5 +1,8 @@
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use packet::{self, Common};
 use Packet;
@@ -16,18 +19,39 @@ use constants::CompressionAlgorithm;
 /// of a `CompressedData` packet.
 ///
 /// [Section 5.6 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.6
-#[derive(PartialEq, Eq, Hash, Clone)]
+#[derive(Clone)]
 pub struct CompressedData {
     /// CTB packet header fields.
     pub(crate) common: packet::Common,
     /// Algorithm used to compress the payload.
     algo: CompressionAlgorithm,
+    /// The number of compressed and uncompressed bytes read by the
+    /// parser.
+    sizes: Option<(Arc<AtomicUsize>, Arc<AtomicUsize>)>,
+}
+
+// The sizes are not part of the packet.
+impl PartialEq for CompressedData {
+    fn eq(&self, other: &CompressedData) -> bool {
+        self.common == other.common && self.algo == other.algo
+    }
+}
+
+impl Eq for CompressedData {}
+
+impl Hash for CompressedData {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.common.hash(state);
+        self.algo.hash(state);
+    }
 }
 
 impl fmt::Debug for CompressedData {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("CompressedData")
             .field("algo", &self.algo)
+            .field("compressed_len", &self.compressed_len())
+            .field("uncompressed_len", &self.uncompressed_len())
             .field("children",
                    &self.common.children.as_ref()
                        .map(|c| &c.packets).unwrap_or(&Vec::new()))
@@ -43,9 +67,17 @@ impl CompressedData {
         CompressedData {
             common: Default::default(),
             algo: algo,
+            sizes: None,
         }
     }
 
+    /// Sets the counters that the parser uses to record the size of
+    /// the content.
+    pub(crate) fn set_sizes(&mut self, compressed: Arc<AtomicUsize>,
+                            uncompressed: Arc<AtomicUsize>) {
+        self.sizes = Some((compressed, uncompressed));
+    }
+
     /// Gets the compression algorithm.
     pub fn algorithm(&self) -> CompressionAlgorithm {
         self.algo
@@ -56,6 +88,35 @@ impl CompressedData {
         ::std::mem::replace(&mut self.algo, algo)
     }
 
+    /// Gets the size of the compressed content.
+    ///
+    /// This is the number of bytes that the parser has read from the
+    /// packet's body, excluding the algorithm octet.  It is only
+    /// final once the parser has left the container, e.g., if the
+    /// packet is part of a `PacketPile`.  Together with
+    /// [`uncompressed_len`], this can be used to compute the
+    /// compression ratio.
+    ///
+    /// If the packet was not created by the parser, this returns
+    /// `None`.
+    ///
+    ///   [`uncompressed_len`]: #method.uncompressed_len
+    pub fn compressed_len(&self) -> Option<u64> {
+        self.sizes.as_ref()
+            .map(|&(ref c, _)| c.load(Ordering::Relaxed) as u64)
+    }
+
+    /// Gets the size of the uncompressed content.
+    ///
+    /// This is the number of bytes that the parser has decompressed.
+    /// See [`compressed_len`] for the caveats.
+    ///
+    ///   [`compressed_len`]: #method.compressed_len
+    pub fn uncompressed_len(&self) -> Option<u64> {
+        self.sizes.as_ref()
+            .map(|&(_, ref u)| u.load(Ordering::Relaxed) as u64)
+    }
+
     /// Adds a new packet to the container.
     pub fn push(mut self, packet: Packet) -> Self {
         if self.common.children.is_none() {
@@ -99,3 +160,33 @@ impl<'a> DerefMut for CompressedData {
         &mut self.common
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PacketPile;
+    use parse::Parse;
+
+    #[cfg(feature = "compression-deflate")]
+    #[test]
+    fn sizes() {
+        let data = ::tests::message("compressed-data-algo-1.gpg");
+        let pile = PacketPile::from_bytes(data).unwrap();
+        if let Some(Packet::CompressedData(ref cd)) = pile.path_ref(&[0]) {
+            assert_eq!(cd.algorithm(), CompressionAlgorithm::Zip);
+            // The packet uses an indeterminate length, so the body
+            // is at most everything after the CTB and the algorithm
+            // octet.
+            let compressed = cd.compressed_len().unwrap();
+            assert!(compressed > 0);
+            assert!(compressed <= data.len() as u64 - 2);
+            assert!(cd.uncompressed_len().unwrap() > compressed);
+        } else {
+            panic!("Expected a CompressedData packet");
+        }
+
+        let cd = CompressedData::new(CompressionAlgorithm::Zip);
+        assert_eq!(cd.compressed_len(), None);
+        assert_eq!(cd.uncompressed_len(), None);
+    }
+}
@@ -3,10 +3,11 @@
 use std::fmt;
 use std::mem;
 use std::cmp::Ordering;
+use std::ops::{Deref, DerefMut};
 use time;
 
 use Error;
-use crypto::{self, mem::Protected, mpis, hash::Hash, KeyPair};
+use crypto::{self, mem::Protected, mpis, hash::Hash, KeyPair, Rng};
 use packet::Tag;
 use packet;
 use Packet;
@@ -163,10 +164,9 @@ impl Key4 {
 
     /// Creates a new OpenPGP public key packet for an existing Ed25519 key.
     ///
-    /// The ECDH key will use hash algorithm `hash` and symmetric
-    /// algorithm `sym`.  If one or both are `None` secure defaults
-    /// will be used.  The key will have it's creation date set to
-    /// `ctime` or the current time if `None` is given.
+    /// `public_key` is the 32 byte compressed point.  The key will
+    /// have it's creation date set to `ctime` or the current time if
+    /// `None` is given.
     pub fn import_public_ed25519<T>(public_key: &[u8], ctime: T) -> Result<Self>
         where  T: Into<Option<time::Tm>>
     {
@@ -187,9 +187,8 @@ impl Key4 {
 
     /// Creates a new OpenPGP secret key packet for an existing Ed25519 key.
     ///
-    /// The ECDH key will use hash algorithm `hash` and symmetric
-    /// algorithm `sym`.  If one or both are `None` secure defaults
-    /// will be used.  The key will have it's creation date set to
+    /// `private_key` is the 32 byte secret seed, the public key is
+    /// derived from it.  The key will have it's creation date set to
     /// `ctime` or the current time if `None` is given.
     pub fn import_secret_ed25519<T>(private_key: &[u8], ctime: T)
         -> Result<Self> where T: Into<Option<time::Tm>>
@@ -197,7 +196,7 @@ impl Key4 {
         use nettle::ed25519::{self, ED25519_KEY_SIZE};
 
         let mut public_key = [0x40u8; ED25519_KEY_SIZE + 1];
-        ed25519::public_key(&mut public_key[1..], private_key).unwrap();
+        ed25519::public_key(&mut public_key[1..], private_key)?;
 
         Ok(Key4 {
             common: Default::default(),
@@ -213,6 +212,36 @@ impl Key4 {
         })
     }
 
+    /// Creates a new OpenPGP key packet for an existing Ed25519 key.
+    ///
+    /// `public_key` is the 32 byte compressed point.  If
+    /// `private_key`, the 32 byte secret seed, is given, the public
+    /// key derived from it must match `public_key`, otherwise
+    /// `Error::InvalidArgument` is returned.  The key will have it's
+    /// creation date set to `ctime` or the current time if `None` is
+    /// given.
+    pub fn import_ed25519<T>(public_key: &[u8], private_key: Option<&[u8]>,
+                             ctime: T)
+        -> Result<Self> where T: Into<Option<time::Tm>>
+    {
+        let ctime = ctime.into().unwrap_or(time::now());
+        let public = Self::import_public_ed25519(public_key, ctime)?;
+        let private_key = if let Some(private_key) = private_key {
+            private_key
+        } else {
+            return Ok(public);
+        };
+
+        let key = Self::import_secret_ed25519(private_key, ctime)?;
+        if key.mpis() != public.mpis() {
+            return Err(Error::InvalidArgument(
+                "Ed25519 secret key does not match the public key".into())
+                       .into());
+        }
+
+        Ok(key)
+    }
+
     /// Creates a new OpenPGP public key packet for an existing RSA key.
     ///
     /// The RSA key will use public exponent `e` and modulo `n`. The key will
@@ -233,11 +262,12 @@ impl Key4 {
         })
     }
 
-    /// Creates a new OpenPGP public key packet for an existing RSA key.
+    /// Creates a new OpenPGP secret key packet for an existing RSA key.
     ///
-    /// The RSA key will use public exponent `e` and modulo `n`. The key will
-    /// have it's creation date set to `ctime` or the current time if `None`
-    /// is given.
+    /// The RSA key will use secret exponent `d` and primes `p` and
+    /// `q`.  The public key and the multiplicative inverse are
+    /// derived from them.  The key will have it's creation date set
+    /// to `ctime` or the current time if `None` is given.
     pub fn import_secret_rsa<T>(d: &[u8], p: &[u8], q: &[u8], ctime: T)
         -> Result<Self> where T: Into<Option<time::Tm>>
     {
@@ -264,13 +294,71 @@ impl Key4 {
         })
     }
 
+    /// Creates a new OpenPGP key packet for an existing RSA key.
+    ///
+    /// The RSA key will use modulo `n` and public exponent `e`.  If
+    /// `secret` is given, it holds the secret exponent `d`, the
+    /// primes `p` and `q`, and the multiplicative inverse `u` of `p`
+    /// modulo `q`, where `p` is smaller than `q` (see [Section 5.5.3
+    /// of RFC 4880]).  If the secret key material doesn't match the
+    /// public key, `Error::InvalidArgument` is returned.  The key
+    /// will have it's creation date set to `ctime` or the current
+    /// time if `None` is given.
+    ///
+    ///   [Section 5.5.3 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.5.3
+    pub fn import_rsa<T>(n: &[u8], e: &[u8],
+                         secret: Option<(&[u8], &[u8], &[u8], &[u8])>,
+                         ctime: T)
+        -> Result<Self> where T: Into<Option<time::Tm>>
+    {
+        let ctime = ctime.into().unwrap_or(time::now());
+        let public = Self::import_public_rsa(e, n, ctime)?;
+        let (d, p, q, u) = if let Some(secret) = secret {
+            secret
+        } else {
+            return Ok(public);
+        };
+
+        let key = Self::import_secret_rsa(d, p, q, ctime)?;
+        let secret: SecretKey = mpis::SecretKey::RSA {
+            d: mpis::MPI::new(d).into(),
+            p: mpis::MPI::new(p).into(),
+            q: mpis::MPI::new(q).into(),
+            u: mpis::MPI::new(u).into(),
+        }.into();
+        if key.mpis() != public.mpis() || key.secret() != Some(&secret) {
+            return Err(Error::InvalidArgument(
+                "RSA secret key does not match the public key".into())
+                       .into());
+        }
+
+        Ok(key)
+    }
+
     /// Generates a new RSA key with a public modulos of size `bits`.
+    ///
+    /// Keys smaller than 2048 bits are rejected with
+    /// `Error::InvalidArgument`.
     pub fn generate_rsa(bits: usize) -> Result<Self> {
-        use nettle::{rsa, Yarrow};
+        Self::generate_rsa_with_rng(bits, &mut Rng::new())
+    }
+
+    /// Generates a new RSA key with a public modulos of size `bits`
+    /// using the given random number generator.
+    ///
+    /// See `Key4::generate_rsa` for details.
+    pub fn generate_rsa_with_rng(bits: usize, rng: &mut Rng) -> Result<Self> {
+        use nettle::rsa;
         use crypto::mpis::{self, MPI, PublicKey};
 
-        let mut rng = Yarrow::default();
-        let (public, private) = rsa::generate_keypair(&mut rng, bits as u32)?;
+        if bits < 2048 {
+            return Err(Error::InvalidArgument(
+                format!("RSA key size of {} bits is too small", bits))
+                       .into());
+        }
+
+        let rng = rng.yarrow();
+        let (public, private) = rsa::generate_keypair(&mut *rng, bits as u32)?;
         let (p, q, u) = private.as_rfc4880();
         let public_mpis = PublicKey::RSA {
             e: MPI::new(&*public.e()).into(),
@@ -293,6 +381,16 @@ impl Key4 {
         })
     }
 
+    /// Generates a new EdDSA key over Ed25519 for signing.
+    pub fn generate_ed25519() -> Result<Self> {
+        Self::generate_ecc(true, Curve::Ed25519)
+    }
+
+    /// Generates a new ECDH key over Cv25519 for encryption.
+    pub fn generate_ecdh() -> Result<Self> {
+        Self::generate_ecc(false, Curve::Cv25519)
+    }
+
     /// Generates a new ECC key over `curve`.
     ///
     /// If `for_signing` is false a ECDH key, if it's true either a
@@ -301,8 +399,17 @@ impl Key4 {
     /// `for_signing == false` and `curve == Ed25519`.
     /// signing/encryption
     pub fn generate_ecc(for_signing: bool, curve: Curve) -> Result<Self> {
+        Self::generate_ecc_with_rng(for_signing, curve, &mut Rng::new())
+    }
+
+    /// Generates a new ECC key over `curve` using the given random
+    /// number generator.
+    ///
+    /// See `Key4::generate_ecc` for details.
+    pub fn generate_ecc_with_rng(for_signing: bool, curve: Curve,
+                                 rng: &mut Rng)
+                                 -> Result<Self> {
         use nettle::{
-            Yarrow,
             ed25519, ed25519::ED25519_KEY_SIZE,
             curve25519, curve25519::CURVE25519_SIZE,
             ecc, ecdh, ecdsa,
@@ -312,13 +419,13 @@ impl Key4 {
         use PublicKeyAlgorithm::*;
         use Error;
 
-        let mut rng = Yarrow::default();
+        let rng = rng.yarrow();
 
         let (mpis, secret, pk_algo) = match (curve.clone(), for_signing) {
             (Curve::Ed25519, true) => {
                 let mut public = [0u8; ED25519_KEY_SIZE + 1];
                 let mut private: Protected =
-                    ed25519::private_key(&mut rng).into();
+                    ed25519::private_key(&mut *rng).into();
 
                 public[0] = 0x40;
                 ed25519::public_key(&mut public[1..], &private)?;
@@ -338,7 +445,7 @@ impl Key4 {
             (Curve::Cv25519, false) => {
                 let mut public = [0u8; CURVE25519_SIZE + 1];
                 let mut private: Protected =
-                    curve25519::private_key(&mut rng).into();
+                    curve25519::private_key(&mut *rng).into();
 
                 public[0] = 0x40;
 
@@ -367,17 +474,17 @@ impl Key4 {
                 let (public, private, field_sz) = match curve {
                     Curve::NistP256 => {
                         let (pu, sec) =
-                            ecdsa::generate_keypair::<ecc::Secp256r1, _>(&mut rng)?;
+                            ecdsa::generate_keypair::<ecc::Secp256r1, _>(&mut *rng)?;
                         (pu, sec, 256)
                     }
                     Curve::NistP384 => {
                         let (pu, sec) =
-                            ecdsa::generate_keypair::<ecc::Secp384r1, _>(&mut rng)?;
+                            ecdsa::generate_keypair::<ecc::Secp384r1, _>(&mut *rng)?;
                         (pu, sec, 384)
                     }
                     Curve::NistP521 => {
                         let (pu, sec) =
-                            ecdsa::generate_keypair::<ecc::Secp521r1, _>(&mut rng)?;
+                            ecdsa::generate_keypair::<ecc::Secp521r1, _>(&mut *rng)?;
                         (pu, sec, 521)
                     }
                     _ => unreachable!(),
@@ -400,19 +507,19 @@ impl Key4 {
                     let (private, hash, field_sz) = match curve {
                         Curve::NistP256 => {
                             let pv =
-                                ecc::Scalar::new_random::<ecc::Secp256r1, _>(&mut rng);
+                                ecc::Scalar::new_random::<ecc::Secp256r1, _>(&mut *rng);
 
                             (pv, HashAlgorithm::SHA256, 256)
                         }
                         Curve::NistP384 => {
                             let pv =
-                                ecc::Scalar::new_random::<ecc::Secp384r1, _>(&mut rng);
+                                ecc::Scalar::new_random::<ecc::Secp384r1, _>(&mut *rng);
 
                             (pv, HashAlgorithm::SHA384, 384)
                         }
                         Curve::NistP521 => {
                             let pv =
-                                ecc::Scalar::new_random::<ecc::Secp521r1, _>(&mut rng);
+                                ecc::Scalar::new_random::<ecc::Secp521r1, _>(&mut *rng);
 
                             (pv, HashAlgorithm::SHA512, 521)
                         }
@@ -541,18 +648,21 @@ impl Key4 {
     ///
     /// Fails if the secret key is missing, or encrypted.
     pub fn into_keypair(mut self) -> Result<KeyPair> {
-        use packet::key::SecretKey;
-        let secret = match self.set_secret(None) {
-            Some(SecretKey::Unencrypted(secret)) => secret,
+        let secret = self.take_unencrypted_secret()?;
+        KeyPair::new(self.into(), secret)
+    }
+
+    /// Removes the unencrypted secret key from this packet.
+    fn take_unencrypted_secret(&mut self) -> Result<Unencrypted> {
+        match self.set_secret(None) {
+            Some(SecretKey::Unencrypted(secret)) => Ok(secret),
             Some(SecretKey::Encrypted(_)) =>
-                return Err(Error::InvalidArgument(
+                Err(Error::InvalidArgument(
                     "secret key is encrypted".into()).into()),
             None =>
-                return Err(Error::InvalidArgument(
+                Err(Error::InvalidArgument(
                     "no secret key".into()).into()),
-        };
-
-        KeyPair::new(self.into(), secret)
+        }
     }
 }
 
@@ -562,6 +672,126 @@ impl From<Key4> for super::Key {
     }
 }
 
+/// Holds a version 5 public key or public subkey packet.
+///
+/// Version 5 keys are defined in [Section 5.5.2 of RFC 4880bis].
+/// They differ from version 4 keys in that the public key material
+/// is prefixed with its length in octets, and in that the
+/// fingerprint is computed using SHA-256.  As the public parameters
+/// are otherwise the same, a `Key5` wraps a `Key4`, and derefs to
+/// it.  Note that the `fingerprint` and `keyid` methods of the
+/// wrapped `Key4` do not apply; use the ones on `Key5`.
+///
+/// We do not yet support the secret key material of version 5
+/// keys.
+///
+///   [Section 5.5.2 of RFC 4880bis]: https://tools.ietf.org/html/draft-ietf-openpgp-rfc4880bis-07#section-5.5.2
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub struct Key5 {
+    pub(crate) intern: Key4,
+}
+
+impl fmt::Debug for Key5 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Key5")
+            .field("fingerprint", &self.fingerprint())
+            .field("creation_time",
+                   &format!("{}", self.creation_time().rfc3339()))
+            .field("pk_algo", &self.pk_algo())
+            .field("mpis", self.mpis())
+            .field("secret", &self.secret())
+            .finish()
+    }
+}
+
+impl fmt::Display for Key5 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.fingerprint())
+    }
+}
+
+impl Deref for Key5 {
+    type Target = Key4;
+
+    fn deref(&self) -> &Self::Target {
+        &self.intern
+    }
+}
+
+impl DerefMut for Key5 {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.intern
+    }
+}
+
+impl Key5 {
+    /// Creates a new OpenPGP version 5 public key packet.
+    pub fn new(creation_time: time::Tm, pk_algo: PublicKeyAlgorithm,
+               mpis: mpis::PublicKey)
+               -> Result<Self>
+    {
+        Ok(Key5 {
+            intern: Key4::new(creation_time, pk_algo, mpis, None)?,
+        })
+    }
+
+    /// Compares the public bits of two keys.
+    ///
+    /// See `Key4::public_cmp` for details.
+    pub fn public_cmp(a: &Self, b: &Self) -> Ordering {
+        Key4::public_cmp(&a.intern, &b.intern)
+    }
+
+    /// Computes and returns the key's fingerprint as per Section
+    /// 12.2 of RFC 4880bis.
+    pub fn fingerprint(&self) -> Fingerprint {
+        let mut h = HashAlgorithm::SHA256.context().unwrap();
+
+        self.hash(&mut h);
+
+        let mut digest = vec![0u8; h.digest_size()];
+        h.digest(&mut digest);
+        Fingerprint::from_bytes(digest.as_slice())
+    }
+
+    /// Computes and returns the key's key ID as per Section 12.2 of
+    /// RFC 4880bis.
+    pub fn keyid(&self) -> KeyID {
+        self.fingerprint().to_keyid()
+    }
+
+    /// Convert the `Key` struct to a `Packet`.
+    pub fn into_packet(self, tag: Tag) -> Result<Packet> {
+        match tag {
+            Tag::PublicKey => Ok(Packet::PublicKey(self.into())),
+            Tag::PublicSubkey => Ok(Packet::PublicSubkey(self.into())),
+            Tag::SecretKey => Ok(Packet::SecretKey(self.into())),
+            Tag::SecretSubkey => Ok(Packet::SecretSubkey(self.into())),
+            _ => Err(Error::InvalidArgument(
+                format!("Expected Tag::PublicKey, Tag::PublicSubkey, \
+                         Tag::SecretKey, or Tag::SecretSubkey. \
+                         Got: Tag::{:?}",
+                        tag)).into()),
+        }
+    }
+
+    /// Converts this packet with an unencrypted secret key into a `KeyPair`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the secret key is missing, or encrypted.
+    pub fn into_keypair(mut self) -> Result<KeyPair> {
+        let secret = self.intern.take_unencrypted_secret()?;
+        KeyPair::new(self.into(), secret)
+    }
+}
+
+impl From<Key5> for super::Key {
+    fn from(p: Key5) -> Self {
+        super::Key::V5(p)
+    }
+}
+
 /// Holds the secret potion of a OpenPGP secret key or secret subkey packet.
 ///
 /// This type allows postponing the decryption of the secret key until we need to use it.
@@ -596,6 +826,8 @@ impl SecretKey {
     ///
     /// The SecretKey type does not know what kind of key it is, so
     /// `pk_algo` is needed to parse the correct number of MPIs.
+    ///
+    /// See `Encrypted::decrypt` for the errors returned.
     pub fn decrypt_in_place(&mut self, pk_algo: PublicKeyAlgorithm,
                             password: &Password)
                             -> Result<()> {
@@ -612,18 +844,40 @@ impl SecretKey {
         Ok(())
     }
 
+    /// Returns the key derivation mechanism protecting this secret
+    /// key, if it is encrypted.
+    ///
+    /// This can be used to flag keys protected using weak parameters
+    /// before decrypting them.
+    pub fn s2k(&self) -> Option<&S2K> {
+        match self {
+            SecretKey::Encrypted(ref e) => Some(e.s2k()),
+            SecretKey::Unencrypted(_) => None,
+        }
+    }
+
     /// Encrypts this secret key using `password`.
-    pub fn encrypt_in_place(&mut self, password: &Password) -> Result<()> {
+    ///
+    /// The key is derived from `password` using `s2k`, and the
+    /// secret MPIs are encrypted using `algo`.  Together with
+    /// `decrypt_in_place`, this can be used to change the password
+    /// protecting a key, or to upgrade the protection's parameters.
+    ///
+    /// If the secret key is already encrypted,
+    /// `Error::InvalidOperation` is returned.  It must be decrypted
+    /// first.
+    pub fn encrypt_in_place(&mut self, algo: SymmetricAlgorithm, s2k: S2K,
+                            password: &Password)
+                            -> Result<()> {
         let new = match self {
             SecretKey::Unencrypted(ref u) =>
-                Some(u.encrypt(password)?.into()),
-            SecretKey::Encrypted(_) => None,
+                u.encrypt_with(algo, s2k, password)?.into(),
+            SecretKey::Encrypted(_) =>
+                return Err(Error::InvalidOperation(
+                    "Secret key is already encrypted".into()).into()),
         };
 
-        if let Some(v) = new {
-            *self = v;
-        }
-
+        *self = new;
         Ok(())
     }
 
@@ -656,13 +910,22 @@ impl Unencrypted {
     }
 
     /// Encrypts this secret key using `password`.
+    ///
+    /// This uses the default S2K mechanism and AES256.
     pub fn encrypt(&self, password: &Password)
                    -> Result<Encrypted> {
+        self.encrypt_with(SymmetricAlgorithm::AES256, S2K::default(),
+                          password)
+    }
+
+    /// Encrypts this secret key using `password`, deriving the key
+    /// with `s2k` and encrypting with `algo`.
+    pub fn encrypt_with(&self, algo: SymmetricAlgorithm, s2k: S2K,
+                        password: &Password)
+                        -> Result<Encrypted> {
         use std::io::Write;
         use crypto::symmetric::Encryptor;
 
-        let s2k = S2K::default();
-        let algo = SymmetricAlgorithm::AES256;
         let key = s2k.derive_key(password, algo.key_size()?)?;
 
         // Ciphertext is preceded by a random block.
@@ -718,20 +981,57 @@ impl Encrypted {
     ///
     /// The `Encrypted` key does not know what kind of key it is, so
     /// `pk_algo` is needed to parse the correct number of MPIs.
+    ///
+    /// If the key derivation mechanism is not supported,
+    /// `Error::UnsupportedS2K` is returned (or
+    /// `Error::UnsupportedHashAlgorithm` and
+    /// `Error::UnsupportedSymmetricAlgorithm` if it uses an
+    /// unsupported algorithm).  If the ciphertext is too short to be
+    /// an encrypted key, `Error::MalformedPacket` is returned.  If
+    /// the decrypted MPIs are malformed, are truncated, or their
+    /// checksum is wrong, `Error::InvalidPassword` is returned.  Any
+    /// other error is returned as is.  Note: corrupted ciphertext
+    /// cannot be distinguished from a wrong password.
     pub fn decrypt(&self, pk_algo: PublicKeyAlgorithm, password: &Password)
                    -> Result<Unencrypted> {
-        use std::io::{Cursor, Read};
+        use std::io::{self, Cursor, Read};
         use crypto::symmetric::Decryptor;
 
+        let block_size = self.algo.block_size()?;
         let key = self.s2k.derive_key(password, self.algo.key_size()?)?;
+
+        if self.ciphertext.len() <= block_size {
+            return Err(Error::MalformedPacket(
+                "Encrypted secret key is too short".into()).into());
+        }
+
         let cur = Cursor::new(&self.ciphertext);
         let mut dec = Decryptor::new(self.algo, &key, cur)?;
 
         // Consume the first block.
-        let mut trash = vec![0u8; self.algo.block_size()?];
+        let mut trash = vec![0u8; block_size];
         dec.read_exact(&mut trash)?;
 
-        mpis::SecretKey::parse_chksumd(pk_algo, &mut dec).map(|m| m.into())
+        mpis::SecretKey::parse_chksumd(pk_algo, &mut dec)
+            .map(|m| m.into())
+            .map_err(|e| {
+                // Using the wrong password, we decrypt garbage.  Then,
+                // the checksum is wrong, or the MPIs are malformed or
+                // their lengths run past the end of the ciphertext.
+                let wrong_password = match e.downcast_ref::<Error>() {
+                    Some(&Error::MalformedMPI(_)) => true,
+                    Some(_) => false,
+                    None => e.downcast_ref::<io::Error>()
+                        .map(|e| e.kind() == io::ErrorKind::UnexpectedEof)
+                        .unwrap_or(false),
+                };
+
+                if wrong_password {
+                    Error::InvalidPassword.into()
+                } else {
+                    e
+                }
+            })
     }
 }
 
@@ -767,6 +1067,91 @@ mod tests {
         }
     }
 
+    #[test]
+    fn encrypted_key_s2k() {
+        let mut tpk = TPK::from_bytes(
+            ::tests::key("testy-new-encrypted-with-123.pgp")).unwrap();
+        let pair = tpk.primary_mut();
+        let pk_algo = pair.pk_algo();
+        let secret = pair.secret.as_mut().unwrap();
+
+        // We can inspect the S2K parameters before decrypting.
+        match secret.s2k() {
+            Some(&S2K::Iterated { hash, hash_bytes, .. }) => {
+                assert_eq!(hash, HashAlgorithm::SHA1);
+                assert_eq!(hash_bytes, 29360128);
+            },
+            s2k => panic!("unexpected S2K: {:?}", s2k),
+        }
+
+        // A wrong password.
+        assert_match!(Some(&Error::InvalidPassword) =
+                      secret.decrypt_in_place(pk_algo, &"wrong".into())
+                      .unwrap_err().downcast_ref::<Error>());
+        assert!(secret.is_encrypted());
+
+        // An unsupported S2K.
+        let unsupported = if let SecretKey::Encrypted(ref e) = secret {
+            Encrypted::new(S2K::Private(101), e.algo(),
+                           e.ciphertext().to_vec().into_boxed_slice())
+        } else {
+            unreachable!()
+        };
+        assert_match!(Some(&Error::UnsupportedS2K(S2K::Private(101))) =
+                      unsupported.decrypt(pk_algo, &"123".into())
+                      .unwrap_err().downcast_ref::<Error>());
+
+        // Corrupt data.
+        let corrupt = Encrypted::new(S2K::default(),
+                                     SymmetricAlgorithm::AES128,
+                                     vec![0; 8].into_boxed_slice());
+        assert_match!(Some(&Error::MalformedPacket(_)) =
+                      corrupt.decrypt(pk_algo, &"123".into())
+                      .unwrap_err().downcast_ref::<Error>());
+
+        secret.decrypt_in_place(pk_algo, &"123".into()).unwrap();
+        assert!(! secret.is_encrypted());
+        assert_eq!(secret.s2k(), None);
+    }
+
+    #[test]
+    fn reencrypt_key() {
+        let mut tpk = TPK::from_bytes(
+            ::tests::key("testy-new-encrypted-with-123.pgp")).unwrap();
+        let pair = tpk.primary_mut();
+        let pk_algo = pair.pk_algo();
+        let secret = pair.secret.as_mut().unwrap();
+
+        // Encrypted keys cannot be encrypted again.
+        assert_match!(Some(&Error::InvalidOperation(_)) =
+                      secret.encrypt_in_place(SymmetricAlgorithm::AES256,
+                                              S2K::default(), &"new".into())
+                      .unwrap_err().downcast_ref::<Error>());
+
+        secret.decrypt_in_place(pk_algo, &"123".into()).unwrap();
+        let plain = secret.clone();
+
+        let s2k = S2K::Salted {
+            hash: HashAlgorithm::SHA256,
+            salt: [1, 2, 3, 4, 5, 6, 7, 8],
+        };
+        secret.encrypt_in_place(SymmetricAlgorithm::AES128, s2k.clone(),
+                                &"new".into()).unwrap();
+        assert!(secret.is_encrypted());
+        assert_eq!(secret.s2k(), Some(&s2k));
+        if let SecretKey::Encrypted(ref e) = secret {
+            assert_eq!(e.algo(), SymmetricAlgorithm::AES128);
+        }
+
+        // The old password no longer works.
+        assert_match!(Some(&Error::InvalidPassword) =
+                      secret.decrypt_in_place(pk_algo, &"123".into())
+                      .unwrap_err().downcast_ref::<Error>());
+
+        secret.decrypt_in_place(pk_algo, &"new".into()).unwrap();
+        assert_eq!(*secret, plain);
+    }
+
     #[test]
     fn eq() {
         use constants::Curve::*;
@@ -781,7 +1166,7 @@ mod tests {
             assert_eq!(enc_key, enc_clone);
         }
 
-        for bits in vec![1024, 2048, 3072, 4096] {
+        for bits in vec![2048, 3072, 4096] {
             let key = Key4::generate_rsa(bits).unwrap();
             let clone = key.clone();
             assert_eq!(key, clone);
@@ -797,7 +1182,7 @@ mod tests {
             let enc_key = Key4::generate_ecc(false, cv).unwrap();
 
             vec![sign_key, enc_key]
-        }).chain(vec![1024, 2048, 3072, 4096].into_iter().map(|b| {
+        }).chain(vec![2048, 3072, 4096].into_iter().map(|b| {
             Key4::generate_rsa(b).unwrap()
         }));
 
@@ -844,7 +1229,7 @@ mod tests {
 
         let keys = vec![NistP256, NistP384, NistP521].into_iter().map(|cv| {
             Key4::generate_ecc(false, cv).unwrap()
-        }).chain(vec![1024, 2048, 3072, 4096].into_iter().map(|b| {
+        }).chain(vec![2048, 3072, 4096].into_iter().map(|b| {
             Key4::generate_rsa(b).unwrap()
         }));
 
@@ -868,7 +1253,7 @@ mod tests {
 
         let keys = vec![NistP256, NistP384, NistP521].into_iter().map(|cv| {
             Key4::generate_ecc(false, cv).unwrap()
-        }).chain(vec![1024, 2048, 3072, 4096].into_iter().map(|b| {
+        }).chain(vec![2048, 3072, 4096].into_iter().map(|b| {
             Key4::generate_rsa(b).unwrap()
         }));
 
@@ -879,7 +1264,8 @@ mod tests {
             let mut encrypted_key = key.clone();
 
             encrypted_key.secret_mut().unwrap()
-                .encrypt_in_place(&password).unwrap();
+                .encrypt_in_place(SymmetricAlgorithm::AES256, S2K::default(),
+                                  &password).unwrap();
             assert!(encrypted_key.secret().unwrap().is_encrypted());
 
             encrypted_key.secret_mut().unwrap()
@@ -977,6 +1363,42 @@ mod tests {
         let key: Key = Key4::import_secret_rsa(&d[..], &p[..], &q[..], ctime)
             .unwrap().into();
 
+        // Importing the public and the secret key material at once
+        // yields the same key.
+        let (n, e) = match key.mpis() {
+            &mpis::PublicKey::RSA { ref n, ref e } =>
+                (n.value().to_vec(), e.value().to_vec()),
+            _ => unreachable!(),
+        };
+        let u = match key.secret() {
+            Some(&SecretKey::Unencrypted(ref s)) => match s.mpis() {
+                &mpis::SecretKey::RSA { ref u, .. } => u.value().to_vec(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        let imported: Key = Key4::import_rsa(
+            &n, &e, Some((&d[..], &p[..], &q[..], &u[..])), ctime)
+            .unwrap().into();
+        assert_eq!(imported, key);
+        assert_eq!(imported.secret(), key.secret());
+        let public: Key = Key4::import_rsa(&n, &e, None, ctime)
+            .unwrap().into();
+        assert_eq!(public.fingerprint(), key.fingerprint());
+        assert!(public.secret().is_none());
+
+        // Mismatching secret key material is rejected.
+        assert_match!(Some(&Error::InvalidArgument(_)) =
+                      Key4::import_rsa(&n[1..], &e,
+                                       Some((&d[..], &p[..], &q[..], &u[..])),
+                                       ctime)
+                      .unwrap_err().downcast_ref::<Error>());
+        assert_match!(Some(&Error::InvalidArgument(_)) =
+                      Key4::import_rsa(&n, &e,
+                                       Some((&d[..], &p[..], &q[..], &d[..])),
+                                       ctime)
+                      .unwrap_err().downcast_ref::<Error>());
+
         // PKESK
         let c = b"\x8A\x1A\xD4\x82\x91\x6B\xBF\xA1\x65\xD3\x82\x8C\x97\xAB\xD0\x91\xE4\xB4\xC4\x9D\x08\xD8\x8B\xB7\xE6\x13\x3F\x6F\x52\x14\xED\xC4\x77\xB7\x31\x00\xC1\x43\xF9\x62\x53\xBF\x21\x21\x52\x74\x35\xD8\xC7\xA2\x11\x89\xA5\xD5\x21\x98\x6D\x3C\x9F\xF0\xED\xDB\xD7\x0F\xAC\x3C\x15\x25\x34\x52\xC7\x7C\x82\x07\x5A\x99\xC1\xC6\xF6\xF2\x6D\x46\xC8\x56\x59\xE7\xC6\x34\x0C\xCA\x37\x70\xB4\x97\xDA\x18\x14\xC4\x03\x0A\xCB\xE5\x0C\x41\x43\x61\xBA\x32\xB6\x9A\xF3\xDF\x0C\xB0\xCE\xBD\xFE\x72\x6C\xCC\xC1\xE8\xF0\x05\x97\x61\xEA\x30\x10\xB9\x43\xC4\x9A\x41\xED\x72\x27\xA4\xD5\xE7\x08\x41\x6C\x57\x80\xF3\x64\xF0\x45\x70\x27\x36\xBD\x64\x59\x74\xCF\xCD\x39\xE6\xEB\x7C\x62\xC8\x38\x23\xF8\x4C\xB7\x30\x9F\xF1\x40\x4A\xE9\x72\x66\x99\xF7\x2A\x47\x1C\xE7\x12\x20\x58\xBA\x87\x00\xB8\xFC\x54\xBC\xA5\x1D\x7D\x8B\x50\xA4\x4B\xB3\xD7\x44\xC7\x68\x5E\x2D\xBB\xE9\x6E\xC4\xD0\x31\xB0\xD0\xB6\x02\xD1\x74\x6B\xC9\x3D\x19\x32\x3B\xF1\x0E\x74\xF6\x12\x13\xE6\x40\x8F\xA6\x97\xAD\x83\xB0\x84\xD6\xD9\xE5\x25\x8E\x57\x0B\x7A\x7B\xD0\x5C\x29\x96\xED\x29\xED";
         let ciphertext = Ciphertext::RSA{
@@ -1022,7 +1444,7 @@ mod tests {
         hashed.add(Subpacket::new(SubpacketValue::SignatureCreationTime(ctime), false).unwrap()).unwrap();
         unhashed.add(Subpacket::new(SubpacketValue::Issuer(kid), false).unwrap()).unwrap();
 
-        eprintln!("fpr: {}", key.fingerprint());
+        assert_eq!(key.fingerprint(), fpr);
         let sig = Signature4::new(SignatureType::Binary, PublicKeyAlgorithm::EdDSA,
                                   HashAlgorithm::SHA256, hashed, unhashed,
                                   [0xa7,0x19],
@@ -1032,6 +1454,106 @@ mod tests {
         assert_eq!(sig.verify_message(&key, b"Hello, World\n").ok(), Some(true));
     }
 
+    #[test]
+    fn generate() {
+        use constants::SignatureType;
+        use crypto::SessionKey;
+        use packet::signature;
+
+        assert_match!(Some(&Error::InvalidArgument(_)) =
+                      Key4::generate_rsa(1024).unwrap_err()
+                      .downcast_ref::<Error>());
+
+        let msg = b"Hello, World\n";
+        for key in vec![Key4::generate_ed25519().unwrap(),
+                        Key4::generate_rsa(2048).unwrap()] {
+            let now = time::now().canonicalize();
+            assert!(key.creation_time() <= &now);
+            assert!(*key.creation_time() + time::Duration::minutes(1) > now);
+
+            let key: Key = key.into();
+            let mut keypair = key.clone().into_keypair().unwrap();
+            let sig = signature::Builder::new(SignatureType::Binary)
+                .sign_message(&mut keypair, HashAlgorithm::SHA512, msg)
+                .unwrap();
+            assert_eq!(sig.verify_message(&key, msg).ok(), Some(true));
+            assert_eq!(sig.verify_message(&key, b"Goodbye").ok(),
+                       Some(false));
+        }
+
+        let key = Key4::generate_ed25519().unwrap();
+        assert_eq!(key.pk_algo(), PublicKeyAlgorithm::EdDSA);
+        assert_match!(&mpis::PublicKey::EdDSA { curve: Curve::Ed25519, .. }
+                      = key.mpis());
+
+        let key = Key4::generate_ecdh().unwrap();
+        assert_eq!(key.pk_algo(), PublicKeyAlgorithm::ECDH);
+        assert_match!(&mpis::PublicKey::ECDH { curve: Curve::Cv25519, .. }
+                      = key.mpis());
+
+        let key: Key = key.into();
+        let mut keypair = key.clone().into_keypair().unwrap();
+        let cipher = SymmetricAlgorithm::AES256;
+        let sk = SessionKey::new(cipher.key_size().unwrap());
+        let pkesk = PKESK3::for_recipient(cipher, &sk, &key).unwrap();
+        assert_eq!(pkesk.decrypt(&mut keypair).unwrap(), (cipher, sk));
+    }
+
+    #[test]
+    fn import_ed25519_sec() {
+        use time::{at, Timespec};
+        use constants::SignatureType;
+        use packet::signature;
+
+        // Test vector 1 from RFC 8032, Section 7.1.
+        let ctime = at(Timespec::new(1548249630,0));
+        let secret = b"\x9d\x61\xb1\x9d\xef\xfd\x5a\x60\xba\x84\x4a\xf4\x92\xec\x2c\xc4\x44\x49\xc5\x69\x7b\x32\x69\x19\x70\x3b\xac\x03\x1c\xae\x7f\x60";
+        let public = b"\xd7\x5a\x98\x01\x82\xb1\x0a\xb7\xd5\x4b\xfe\xd3\xc9\x64\x07\x3a\x0e\xe1\x72\xf3\xda\xa6\x23\x25\xaf\x02\x1a\x68\xf7\x07\x51\x1a";
+
+        let key: Key = Key4::import_secret_ed25519(secret, ctime)
+            .unwrap().into();
+        let public_key: Key = Key4::import_public_ed25519(public, ctime)
+            .unwrap().into();
+
+        // The derived public key matches, and so does the
+        // fingerprint.
+        assert_eq!(key.mpis(), public_key.mpis());
+        assert_eq!(key.fingerprint(), public_key.fingerprint());
+        assert_eq!(key.keyid(), public_key.keyid());
+
+        // Sign using the imported secret key, and verify using the
+        // imported public key.
+        let msg = b"Hello, World\n";
+        let mut keypair = key.into_keypair().unwrap();
+        let sig = signature::Builder::new(SignatureType::Binary)
+            .sign_message(&mut keypair, HashAlgorithm::SHA256, msg)
+            .unwrap();
+        assert_eq!(sig.verify_message(&public_key, msg).ok(), Some(true));
+        assert_eq!(sig.verify_message(&public_key, b"Goodbye").ok(),
+                   Some(false));
+
+        // Secret keys of the wrong size are rejected.
+        assert!(Key4::import_secret_ed25519(&secret[1..], ctime).is_err());
+
+        // Importing the public and the secret key at once.
+        let imported: Key =
+            Key4::import_ed25519(public, Some(&secret[..]), ctime)
+            .unwrap().into();
+        assert_eq!(imported.fingerprint(), public_key.fingerprint());
+        assert!(imported.secret().is_some());
+        let imported: Key = Key4::import_ed25519(public, None, ctime)
+            .unwrap().into();
+        assert_eq!(imported, public_key);
+        assert!(imported.secret().is_none());
+
+        // A secret key that doesn't match the public key is rejected.
+        let mut other = secret.to_vec();
+        other[0] ^= 1;
+        assert_match!(Some(&Error::InvalidArgument(_)) =
+                      Key4::import_ed25519(public, Some(&other[..]), ctime)
+                      .unwrap_err().downcast_ref::<Error>());
+    }
+
     #[test]
     fn fingerprint_test() {
         let pile =
@@ -1067,4 +1589,138 @@ mod tests {
         }
         assert!(pki == pks.len() && ski == sks.len());
     }
+
+    #[test]
+    fn fingerprint_v5() {
+        let pile = PacketPile::from_bytes(::tests::key("ed25519-v5.pgp"))
+            .unwrap();
+        let key = match pile.children().next() {
+            Some(Packet::PublicKey(k)) => k.clone(),
+            p => panic!("Expected a public key, got: {:?}", p),
+        };
+
+        assert_eq!(key.version(), 5);
+        let fp = key.fingerprint();
+        assert_eq!(fp.to_hex(),
+                   "6DD5924454F569A09E3A49CBC88FDC2F\
+                    29631FF337A3DABCBCE4146767051346");
+        assert_eq!(Fingerprint::from_hex(&fp.to_hex()).unwrap(), fp);
+        // The key ID is taken from the start of the fingerprint.
+        assert_eq!(key.keyid().to_hex(), "6DD5924454F569A0");
+
+        // The fixture has the same public parameters as this V4
+        // key, yet it is a different key.
+        let v4 = TPK::from_bytes(::tests::key(
+            "emmelie-dorothea-dina-samantha-awina-ed25519.pgp")).unwrap();
+        let v4 = v4.primary();
+        assert_eq!(v4.mpis(), key.mpis());
+        assert_eq!(v4.creation_time(), key.creation_time());
+        assert!(v4.fingerprint() != fp);
+        assert_eq!(Key::public_cmp(v4, &key), Ordering::Less);
+
+        let mut buf = Vec::new();
+        Packet::PublicKey(key).serialize(&mut buf).unwrap();
+        assert_eq!(&buf[..], ::tests::key("ed25519-v5.pgp"));
+    }
+
+    #[test]
+    fn fingerprint_secret_key() {
+        // The secret key material is not part of the fingerprint.
+        let public = PacketPile::from_bytes(::tests::key("testy.pgp"))
+            .unwrap();
+        let secret = PacketPile::from_bytes(::tests::key("testy-private.pgp"))
+            .unwrap();
+
+        let keys = |pile: &PacketPile| pile.descendants()
+            .filter_map(|p| match p {
+                &Packet::PublicKey(ref k) | &Packet::PublicSubkey(ref k)
+                    | &Packet::SecretKey(ref k) | &Packet::SecretSubkey(ref k)
+                    => Some((k.fingerprint(), k.keyid())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let public = keys(&public);
+        let secret = keys(&secret);
+        assert_eq!(public.len(), 2);
+        assert_eq!(public, secret);
+        assert_eq!(public[0].0.to_string(),
+                   "3E88 77C8 7727 4692 9751  89F5 D03F 6F86 5226 FE8B");
+        assert_eq!(public[0].1.to_hex(), "D03F6F865226FE8B");
+    }
+
+    #[test]
+    fn deterministic_rng() {
+        use crypto::{Rng, Signer};
+        use constants::Curve::*;
+
+        let rng = || Rng::from_seed_insecure(b"Hello, world!").unwrap();
+
+        for &(for_signing, ref curve) in &[(true, Ed25519), (false, Cv25519),
+                                           (true, NistP256),
+                                           (false, NistP384)] {
+            let a = Key4::generate_ecc_with_rng(
+                for_signing, curve.clone(), &mut rng()).unwrap();
+            let b = Key4::generate_ecc_with_rng(
+                for_signing, curve.clone(), &mut rng()).unwrap();
+            assert_eq!(a.mpis(), b.mpis());
+            assert_eq!(a.secret(), b.secret());
+
+            let c = Key4::generate_ecc(for_signing, curve.clone()).unwrap();
+            assert!(a.mpis() != c.mpis());
+        }
+
+        // ECDSA signatures use random nonces.
+        let key: Key = Key4::generate_ecc(true, NistP256).unwrap().into();
+        let mut keypair = key.clone().into_keypair().unwrap();
+        let digest = [0x5a; 32];
+        let a = keypair.sign_with_rng(&mut rng(), HashAlgorithm::SHA256,
+                                      &digest).unwrap();
+        let b = keypair.sign_with_rng(&mut rng(), HashAlgorithm::SHA256,
+                                      &digest).unwrap();
+        assert_eq!(a, b);
+        let c = keypair.sign(HashAlgorithm::SHA256, &digest).unwrap();
+        assert!(a != c);
+    }
+
+    #[test]
+    fn debug_redacts_secrets() {
+        use conversions::to_hex;
+
+        let tpk = TPK::from_bytes(::tests::key("testy-private.pgp")).unwrap();
+        let key = tpk.primary();
+        let (d, p, q, u) = match key.secret() {
+            Some(SecretKey::Unencrypted(ref u)) => match u.mpis() {
+                mpis::SecretKey::RSA { ref d, ref p, ref q, ref u } =>
+                    (d.clone(), p.clone(), q.clone(), u.clone()),
+                _ => panic!(),
+            },
+            _ => panic!(),
+        };
+
+        let dumps = vec![
+            format!("{:?}", key),
+            format!("{:#?}", key),
+            format!("{:?}", key.secret()),
+            format!("{:?}", tpk),
+            format!("{:?}", d),
+        ];
+        for dump in dumps {
+            for mpi in &[&d, &p, &q, &u] {
+                for &pretty in &[false, true] {
+                    let secret = to_hex(mpi.value(), pretty);
+                    assert!(! dump.contains(&secret));
+                    assert!(! dump.contains(&secret.to_lowercase()));
+                }
+                // Also check for the debug representation of the
+                // bytes.
+                assert!(! dump.contains(&format!("{:?}", mpi.value())));
+            }
+        }
+
+        let sk = ::crypto::SessionKey::from(vec![0x42; 16]);
+        assert!(! format!("{:?}", sk).contains("66"));
+        let password = ::crypto::Password::from("hunter2");
+        assert!(! format!("{:?}", password).contains("hunter2"));
+    }
 }
@@ -142,6 +142,17 @@ impl Literal {
         self.set_filename_from_bytes(filename.as_bytes())
     }
 
+    /// Returns whether the data is marked "for your eyes only".
+    ///
+    /// This is the case if the filename is `_CONSOLE`, which
+    /// indicates that the data is sensitive and should not be saved
+    /// to disk.  See [Section 5.9 of RFC 4880].
+    ///
+    ///   [Section 5.9 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.9
+    pub fn for_your_eyes_only(&self) -> bool {
+        self.filename() == Some(&b"_CONSOLE"[..])
+    }
+
     /// Gets the literal packet's date field.
     ///
     /// Note: when a literal data packet is protected by a signature,
@@ -205,4 +216,14 @@ mod tests {
             true
         }
     }
+
+    #[test]
+    fn for_your_eyes_only() {
+        let mut l = Literal::new(DataFormat::Text);
+        assert!(! l.for_your_eyes_only());
+        l.set_filename("_CONSOLE").unwrap();
+        assert!(l.for_your_eyes_only());
+        l.set_filename("_CONSOLE.txt").unwrap();
+        assert!(! l.for_your_eyes_only());
+    }
 }
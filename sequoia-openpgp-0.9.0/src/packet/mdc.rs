@@ -39,13 +39,16 @@ impl MDC {
     }
 
     /// Returns whether the data protected by the MDC is valid.
+    ///
+    /// The hash stored in the packet is compared in constant time to
+    /// the hash computed over the decrypted plaintext.
     pub fn valid(&self) -> bool {
         if self.hash == [ 0; 20 ] {
             // If the computed_hash and hash are uninitialized, then
             // return false.
             false
         } else {
-            self.computed_hash == self.hash
+            crypto::secure_cmp(&self.computed_hash[..], &self.hash[..])
         }
     }
 }
@@ -75,3 +78,30 @@ impl From<crypto::hash::Context> for MDC {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use constants::HashAlgorithm;
+
+    #[test]
+    fn valid() {
+        let mut ctx = HashAlgorithm::SHA1.context().unwrap();
+        ctx.update(b"plaintext");
+        let mut hash: [u8; 20] = Default::default();
+        ctx.digest(&mut hash[..]);
+
+        // Valid.
+        assert!(MDC::new(hash, hash).valid());
+
+        // Tampered.
+        let mut tampered = hash;
+        tampered[19] ^= 1;
+        assert!(! MDC::new(tampered, hash).valid());
+        assert!(! MDC::new(hash, tampered).valid());
+
+        // Missing, i.e. never computed.
+        assert!(! MDC::from(hash).valid());
+        assert!(! MDC::new([0; 20], [0; 20]).valid());
+    }
+}
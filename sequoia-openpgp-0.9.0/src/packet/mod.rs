@@ -5,6 +5,7 @@
 //!   [Section 4 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-4
 
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::ops::{Deref, DerefMut};
 use std::slice;
 use std::vec;
@@ -12,6 +13,8 @@ use std::io;
 
 use Result;
 use Packet;
+use Fingerprint;
+use KeyID;
 
 pub mod prelude;
 
@@ -32,7 +35,7 @@ pub mod one_pass_sig;
 pub mod key;
 mod marker;
 pub use self::marker::Marker;
-mod trust;
+pub mod trust;
 pub use self::trust::Trust;
 mod userid;
 pub use self::userid::UserID;
@@ -62,6 +65,8 @@ impl<'a> Deref for Packet {
     fn deref(&self) -> &Self::Target {
         match self {
             &Packet::Unknown(ref packet) => &packet.common,
+            &Packet::Signature(Signature::V3(ref packet)) =>
+                &packet.intern.common,
             &Packet::Signature(Signature::V4(ref packet)) => &packet.common,
             &Packet::OnePassSig(ref packet) => &packet.common,
             &Packet::PublicKey(ref packet) => &packet.common,
@@ -88,6 +93,8 @@ impl<'a> DerefMut for Packet {
     fn deref_mut(&mut self) -> &mut Common {
         match self {
             &mut Packet::Unknown(ref mut packet) => &mut packet.common,
+            &mut Packet::Signature(Signature::V3(ref mut packet)) =>
+                &mut packet.intern.common,
             &mut Packet::Signature(Signature::V4(ref mut packet)) =>
                 &mut packet.common,
             &mut Packet::OnePassSig(ref mut packet) => &mut packet.common,
@@ -230,7 +237,7 @@ fn body_length_old_format() {
 }
 
 /// Fields used by multiple packet types.
-#[derive(PartialEq, Eq, Hash, Clone)]
+#[derive(Clone)]
 pub struct Common {
     /// Used by container packets (such as the encryption and
     /// compression packets) to reference their immediate children.
@@ -291,6 +298,32 @@ pub struct Common {
     /// this is not the packet's entire content; it is just the unread
     /// content.
     pub body: Option<Vec<u8>>,
+
+    /// The packet's original encoding, including its header.
+    ///
+    /// This is only recorded if requested using
+    /// [`PacketParserBuilder::preserve_encoding`], and is used by
+    /// [`Packet::serialize_exact`].
+    ///
+    ///   [`PacketParserBuilder::preserve_encoding`]: ../parse/struct.PacketParserBuilder.html#method.preserve_encoding
+    ///   [`Packet::serialize_exact`]: ../enum.Packet.html#method.serialize_exact
+    pub(crate) original_encoding: Option<Vec<u8>>,
+}
+
+// The original encoding is not part of the packet's content.
+impl PartialEq for Common {
+    fn eq(&self, other: &Common) -> bool {
+        self.children == other.children && self.body == other.body
+    }
+}
+
+impl Eq for Common {}
+
+impl Hash for Common {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.children.hash(state);
+        self.body.hash(state);
+    }
 }
 
 impl fmt::Debug for Common {
@@ -299,6 +332,8 @@ impl fmt::Debug for Common {
             .field("children", &self.children)
             .field("body (bytes)",
                    &self.body.as_ref().map(|body| body.len()))
+            .field("original_encoding (bytes)",
+                   &self.original_encoding.as_ref().map(|o| o.len()))
             .finish()
     }
 }
@@ -308,6 +343,7 @@ impl Default for Common {
         Common {
             children: None,
             body: None,
+            original_encoding: None,
         }
     }
 }
@@ -604,6 +640,8 @@ fn packet_path_iter() {
 ///   [Section 5.2 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.2
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum Signature {
+    /// Signature packet version 3.
+    V3(self::signature::Signature3),
     /// Signature packet version 4.
     V4(self::signature::Signature4),
 }
@@ -612,6 +650,7 @@ impl Signature {
     /// Gets the version.
     pub fn version(&self) -> u8 {
         match self {
+            &Signature::V3(_) => 3,
             &Signature::V4(_) => 4,
         }
     }
@@ -623,21 +662,25 @@ impl From<Signature> for Packet {
     }
 }
 
-// Trivial forwarder for singleton enum.
+// A version 3 signature is a version 4 signature in disguise, see
+// `Signature3`.
 impl Deref for Signature {
     type Target = signature::Signature4;
 
     fn deref(&self) -> &Self::Target {
         match self {
+            Signature::V3(sig) => &sig.intern,
             Signature::V4(sig) => sig,
         }
     }
 }
 
-// Trivial forwarder for singleton enum.
+// Changes to a version 3 signature that cannot be expressed in a
+// version 3 packet are caught when serializing it.
 impl DerefMut for Signature {
     fn deref_mut(&mut self) -> &mut Self::Target {
         match self {
+            Signature::V3(ref mut sig) => &mut sig.intern,
             Signature::V4(ref mut sig) => sig,
         }
     }
@@ -776,12 +819,15 @@ impl From<SKESK> for Packet {
 pub enum Key {
     /// Key packet version 4.
     V4(self::key::Key4),
+    /// Key packet version 5.
+    V5(self::key::Key5),
 }
 
 impl fmt::Display for Key {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Key::V4(k) => k.fmt(f),
+            Key::V5(k) => k.fmt(f),
         }
     }
 }
@@ -791,9 +837,27 @@ impl Key {
     pub fn version(&self) -> u8 {
         match self {
             Key::V4(_) => 4,
+            Key::V5(_) => 5,
         }
     }
 
+    /// Computes and returns the key's fingerprint.
+    ///
+    /// Version 4 fingerprints are computed as per Section 12.2 of
+    /// RFC 4880, version 5 fingerprints as per Section 12.2 of RFC
+    /// 4880bis.
+    pub fn fingerprint(&self) -> Fingerprint {
+        match self {
+            Key::V4(k) => k.fingerprint(),
+            Key::V5(k) => k.fingerprint(),
+        }
+    }
+
+    /// Computes and returns the key's key ID.
+    pub fn keyid(&self) -> KeyID {
+        self.fingerprint().to_keyid()
+    }
+
     /// Compares the public bits of two keys.
     ///
     /// This returns Ordering::Equal if the public MPIs, version,
@@ -803,6 +867,8 @@ impl Key {
     pub fn public_cmp(a: &Self, b: &Self) -> ::std::cmp::Ordering {
         match (a, b) {
             (Key::V4(a), Key::V4(b)) => self::key::Key4::public_cmp(a, b),
+            (Key::V5(a), Key::V5(b)) => self::key::Key5::public_cmp(a, b),
+            (a, b) => a.version().cmp(&b.version()),
         }
     }
 
@@ -815,6 +881,7 @@ impl Key {
     pub fn into_keypair(self) -> Result<::crypto::KeyPair> {
         match self {
             Key::V4(p) => p.into_keypair(),
+            Key::V5(p) => p.into_keypair(),
         }
     }
 
@@ -822,26 +889,29 @@ impl Key {
     pub fn into_packet(self, tag: Tag) -> Result<Packet> {
         match self {
             Key::V4(p) => p.into_packet(tag),
+            Key::V5(p) => p.into_packet(tag),
         }
     }
 }
 
-// Trivial forwarder for singleton enum.
+// A `Key5` is a `Key4` with a different fingerprint, which `Key`
+// handles itself.
 impl Deref for Key {
     type Target = self::key::Key4;
 
     fn deref(&self) -> &Self::Target {
         match self {
             Key::V4(ref p) => p,
+            Key::V5(ref p) => &p.intern,
         }
     }
 }
 
-// Trivial forwarder for singleton enum.
 impl DerefMut for Key {
     fn deref_mut(&mut self) -> &mut Self::Target {
         match self {
             Key::V4(ref mut p) => p,
+            Key::V5(ref mut p) => &mut p.intern,
         }
     }
 }
@@ -119,6 +119,10 @@ impl OnePassSig3 {
     }
 
     /// Gets the last flag.
+    ///
+    /// If set, the signature covers the data that follows, including
+    /// any nested signed messages.  Otherwise, the next one-pass
+    /// signature packet is for a signature over the same data.
     pub fn last(&self) -> bool {
         self.last > 0
     }
@@ -195,4 +199,52 @@ mod tests {
             true
         }
     }
+
+    #[test]
+    fn linkage() {
+        use PacketPile;
+
+        // The one-pass signature packets are followed by the signed
+        // data, and then by the signatures in reverse order.
+        let check = |name, lasts: &[bool], issuers: &[&str]| {
+            let pile = PacketPile::from_bytes(::tests::message(name))
+                .unwrap();
+            let opss = pile.children().filter_map(|p| match p {
+                Packet::OnePassSig(ops) => Some(ops),
+                _ => None,
+            }).collect::<Vec<_>>();
+            let sigs = pile.children().filter_map(|p| match p {
+                Packet::Signature(sig) => Some(sig),
+                _ => None,
+            }).collect::<Vec<_>>();
+            assert_eq!(opss.len(), sigs.len());
+
+            for (i, ops) in opss.iter().enumerate() {
+                assert_eq!(ops.last(), lasts[i]);
+                assert_eq!(ops.issuer(), &KeyID::from_hex(issuers[i]).unwrap());
+
+                let sig = sigs[sigs.len() - 1 - i];
+                assert_eq!(ops.sigtype(), sig.sigtype());
+                assert_eq!(ops.hash_algo(), sig.hash_algo());
+                assert_eq!(ops.pk_algo(), sig.pk_algo());
+                assert_eq!(Some(ops.issuer().clone()), sig.issuer());
+            }
+        };
+
+        // Two signatures over the same data: only the second one-pass
+        // signature packet is marked as the last one.
+        check("signed-twice-by-ed25519.pgp", &[false, true],
+              &["069C0C348DD82C19", "069C0C348DD82C19"]);
+
+        // A notarized message: the outer signature covers the inner
+        // signed message.  Both are marked as the last one.
+        check("signed-1-notarized-by-ed25519.pgp", &[true, true],
+              &["069C0C348DD82C19", "7223B56678E02528"]);
+
+        let mut ops = OnePassSig3::new(SignatureType::Binary);
+        assert!(! ops.last());
+        assert_eq!(ops.set_last(true), false);
+        assert!(ops.last());
+        assert_eq!(ops.last_raw(), 1);
+    }
 }
@@ -55,13 +55,23 @@ impl PKESK3 {
     /// Creates a new PKESK3 packet for the given recipent.
     ///
     /// The given symmetric algorithm must match the algorithm that is
-    /// used to encrypt the payload.
+    /// used to encrypt the payload, and the session key must have
+    /// the algorithm's key size.  RSA keys use PKCS#1 v1.5, ECDH
+    /// keys use the KDF and AES key wrap of [RFC 6637].
+    ///
+    ///   [RFC 6637]: https://tools.ietf.org/html/rfc6637#section-8
     pub fn for_recipient(algo: SymmetricAlgorithm,
                          session_key: &SessionKey, recipient: &Key)
                          -> Result<PKESK3> {
         use PublicKeyAlgorithm::*;
         let mut rng = Yarrow::default();
 
+        if session_key.len() != algo.key_size()? {
+            return Err(Error::InvalidArgument(
+                format!("Session key has {} bytes, {} requires {}",
+                        session_key.len(), algo, algo.key_size()?)).into());
+        }
+
         // We need to prefix the cipher specifier to the session key,
         // and a two-octet checksum.
         let mut psk = Vec::with_capacity(1 + session_key.len() + 2);
@@ -113,7 +123,31 @@ impl PKESK3 {
         })
     }
 
+    /// Creates a new PKESK3 packet for the given recipient without
+    /// revealing the recipient.
+    ///
+    /// This is like [`PKESK3::for_recipient`], but the recipient's
+    /// key ID is replaced by the wildcard key ID.  This hides who
+    /// the message is for, at the cost of making the recipient try
+    /// all of their keys to decrypt it.  See [Section 5.1 of RFC
+    /// 4880].
+    ///
+    ///   [`PKESK3::for_recipient`]: #method.for_recipient
+    ///   [Section 5.1 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.1
+    pub fn for_hidden_recipient(algo: SymmetricAlgorithm,
+                                session_key: &SessionKey, recipient: &Key)
+                                -> Result<PKESK3> {
+        let mut pkesk = Self::for_recipient(algo, session_key, recipient)?;
+        pkesk.recipient = KeyID::wildcard();
+        Ok(pkesk)
+    }
+
     /// Gets the recipient.
+    ///
+    /// If the recipient is hidden, this is the wildcard key ID.  See
+    /// [`KeyID::is_wildcard`].
+    ///
+    ///   [`KeyID::is_wildcard`]: ../../enum.KeyID.html#method.is_wildcard
     pub fn recipient(&self) -> &KeyID {
         &self.recipient
     }
@@ -145,31 +179,36 @@ impl PKESK3 {
 
     /// Decrypts the ESK and returns the session key and symmetric algorithm
     /// used to encrypt the following payload.
+    ///
+    /// The two-octet checksum over the session key is verified.  If
+    /// the decrypted data is not a well-formed session key, the same
+    /// error is returned regardless of what is wrong with it, so
+    /// that callers cannot be used as a padding oracle.
     pub fn decrypt(&self, decryptor: &mut Decryptor)
         -> Result<(SymmetricAlgorithm, SessionKey)>
     {
         let plain = decryptor.decrypt(&self.esk)?;
-        let key_rgn = 1..(plain.len() - 2);
-        let sym_algo: SymmetricAlgorithm = plain[0].into();
-        let mut key: SessionKey = vec![0u8; sym_algo.key_size()?].into();
-
-        if key_rgn.len() != sym_algo.key_size()? {
+        if plain.len() < 3 {
             return Err(Error::MalformedPacket(
-                format!("session key has the wrong size")).into());
+                "Invalid session key".into()).into());
         }
 
-        key.copy_from_slice(&plain[key_rgn]);
+        let key_rgn = 1..(plain.len() - 2);
+        let sym_algo: SymmetricAlgorithm = plain[0].into();
+        let size_ok = sym_algo.key_size()
+            .map(|size| size == key_rgn.len())
+            .unwrap_or(false);
 
-        let our_checksum
-            = key.iter().map(|&x| x as usize).sum::<usize>() & 0xffff;
+        let our_checksum = plain[key_rgn.clone()].iter()
+            .map(|&x| x as usize).sum::<usize>() & 0xffff;
         let their_checksum = (plain[plain.len() - 2] as usize) << 8
             | (plain[plain.len() - 1] as usize);
 
-        if their_checksum == our_checksum {
-            Ok((sym_algo, key))
+        // Evaluate both conditions to not leak which one failed.
+        if size_ok & (their_checksum == our_checksum) {
+            Ok((sym_algo, Vec::from(&plain[key_rgn]).into()))
         } else {
-            Err(Error::MalformedPacket(format!("key checksum wrong"))
-                .into())
+            Err(Error::MalformedPacket("Invalid session key".into()).into())
         }
     }
 }
@@ -237,6 +276,46 @@ mod tests {
         }
     }
 
+    #[test]
+    fn decrypt_custom_decryptor() {
+        // A Decryptor that does not expose the secret key, like a
+        // smartcard would.
+        struct Token {
+            keypair: ::crypto::KeyPair,
+            operations: usize,
+        }
+
+        impl Decryptor for Token {
+            fn public(&self) -> &::packet::Key {
+                self.keypair.public()
+            }
+
+            fn decrypt(&mut self, ciphertext: &Ciphertext)
+                       -> Result<SessionKey> {
+                self.operations += 1;
+                self.keypair.decrypt(ciphertext)
+            }
+        }
+
+        let tpk = TPK::from_bytes(
+            ::tests::key("testy-private.pgp")).unwrap();
+        let pile = PacketPile::from_bytes(
+            ::tests::message("encrypted-to-testy.gpg")).unwrap();
+        let keypair =
+            tpk.subkeys().next().unwrap()
+            .subkey().clone().into_keypair().unwrap();
+        let mut reference = keypair.clone();
+        let mut token = Token { keypair: keypair, operations: 0 };
+
+        if let Some(Packet::PKESK(ref pkesk)) = pile.descendants().next() {
+            let plain = pkesk.decrypt(&mut token).unwrap();
+            assert_eq!(token.operations, 1);
+            assert_eq!(plain, pkesk.decrypt(&mut reference).unwrap());
+        } else {
+            panic!("message is not a PKESK packet");
+        }
+    }
+
     #[test]
     fn decrypt_ecdh_cv25519() {
         let tpk = TPK::from_bytes(
@@ -258,6 +337,48 @@ mod tests {
         }
     }
 
+    #[test]
+    fn decrypt_invalid_session_key() {
+        let tpk = TPK::from_bytes(
+            ::tests::key("testy-new-private.pgp")).unwrap();
+        let subkey = tpk.subkeys().next().unwrap().subkey();
+        let mut keypair = subkey.clone().into_keypair().unwrap();
+
+        let algo = SymmetricAlgorithm::AES256;
+        let sk = vec![0x42u8; 32];
+        let checksum = 32 * 0x42;
+        let good = |psk: &[u8]| {
+            PKESK3::new(subkey.keyid(), subkey.pk_algo(),
+                        ecdh::encrypt(subkey, &psk.to_vec().into())
+                        .unwrap())
+                .unwrap()
+        };
+
+        let mut psk = vec![algo.into()];
+        psk.extend_from_slice(&sk);
+        psk.push((checksum >> 8) as u8);
+        psk.push(checksum as u8);
+        let (a, k) = good(&psk[..]).decrypt(&mut keypair).unwrap();
+        assert_eq!(a, algo);
+        assert_eq!(&k[..], &sk[..]);
+
+        // Wrong checksum.
+        let mut bad_checksum = psk.clone();
+        *bad_checksum.last_mut().unwrap() ^= 1;
+        // Wrong key size.
+        let mut bad_size = psk.clone();
+        bad_size.remove(1);
+        // Too short to hold anything.
+        let too_short = vec![algo.into(), 0];
+
+        let errors = [bad_checksum, bad_size, too_short].iter()
+            .map(|psk| good(&psk[..]).decrypt(&mut keypair).unwrap_err()
+                 .to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(errors[0], errors[1]);
+        assert_eq!(errors[0], errors[2]);
+    }
+
     #[test]
     fn decrypt_ecdh_nistp256() {
         let tpk = TPK::from_bytes(
@@ -368,4 +489,57 @@ mod tests {
         let mut keypair = key.into_keypair().unwrap();
         pkesk.decrypt(&mut keypair).unwrap();
     }
+
+    #[test]
+    fn hidden_recipient() {
+        let tpk = TPK::from_bytes(
+            ::tests::key("testy-private.pgp")).unwrap();
+        let key = tpk.subkeys().next().unwrap().subkey();
+        let sk = SessionKey::new(32);
+
+        let pkesk = PKESK3::for_hidden_recipient(
+            SymmetricAlgorithm::AES256, &sk, key).unwrap();
+        assert!(pkesk.recipient().is_wildcard());
+        assert_eq!(pkesk.pk_algo(), key.pk_algo());
+
+        // The wildcard survives a round trip.
+        let pkesk = if let Packet::PKESK(::packet::PKESK::V3(p))
+            = Packet::from_bytes(&Packet::PKESK(pkesk.into()).to_vec().unwrap())
+            .unwrap()
+        {
+            p
+        } else {
+            panic!("Expected a PKESK packet");
+        };
+        assert!(pkesk.recipient().is_wildcard());
+
+        let mut keypair = key.clone().into_keypair().unwrap();
+        assert_eq!(pkesk.decrypt(&mut keypair).unwrap(),
+                   (SymmetricAlgorithm::AES256, sk));
+    }
+
+    #[test]
+    fn for_recipient_errors() {
+        let tpk = TPK::from_bytes(
+            ::tests::key("testy-private.pgp")).unwrap();
+        let key = tpk.subkeys().next().unwrap().subkey();
+
+        // The session key must fit the algorithm.
+        let sk = SessionKey::new(16);
+        assert!(PKESK3::for_recipient(
+            SymmetricAlgorithm::AES256, &sk, key).is_err());
+        let pkesk = PKESK3::for_recipient(
+            SymmetricAlgorithm::AES128, &sk, key).unwrap();
+        assert_eq!(pkesk.recipient(), &key.keyid());
+        let mut keypair = key.clone().into_keypair().unwrap();
+        assert_eq!(pkesk.decrypt(&mut keypair).unwrap(),
+                   (SymmetricAlgorithm::AES128, sk.clone()));
+
+        // Signing-only keys can't be encrypted to.
+        let tpk = TPK::from_bytes(
+            ::tests::key("emmelie-dorothea-dina-samantha-awina-ed25519.pgp"))
+            .unwrap();
+        assert!(PKESK3::for_recipient(
+            SymmetricAlgorithm::AES128, &sk, tpk.primary()).is_err());
+    }
 }
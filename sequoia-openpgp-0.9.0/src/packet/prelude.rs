@@ -7,12 +7,14 @@ pub use packet::{
     Unknown,
     Signature,
     signature,
+    signature::Signature3,
     signature::Signature4,
     OnePassSig,
     one_pass_sig::OnePassSig3,
     Key,
     KeyFlags,
     key::Key4,
+    key::Key5,
     key::SecretKey,
     Marker,
     Trust,
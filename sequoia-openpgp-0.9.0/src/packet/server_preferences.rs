@@ -58,8 +58,13 @@ impl KeyServerPreferences {
     }
 
     /// Whether or not keyservers are allowed to modify this key.
+    ///
+    /// If this returns `true`, the key holder requests that only
+    /// they, or a key server administrator, may modify the key as
+    /// stored on the key server.  In particular, key servers should
+    /// not accept third-party certifications for it.
     pub fn no_modify(&self) -> bool {
-        !self.no_modify
+        self.no_modify
     }
 
     /// Sets whether or not keyservers are allowed to modify this key.
@@ -69,6 +74,28 @@ impl KeyServerPreferences {
     }
 }
 
-/// The private component of this key may be in the possession of more
-/// than one person.
+/// The key holder requests that this key only be modified or updated
+/// by the key holder or an administrator of the key server.
 const KEYSERVER_PREFERENCE_NO_MODIFY: u8 = 0x80;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_modify() {
+        assert!(! KeyServerPreferences::default().no_modify());
+        assert!(KeyServerPreferences::new(&[0x80]).no_modify());
+        assert!(! KeyServerPreferences::new(&[0x01]).no_modify());
+        assert!(! KeyServerPreferences::new(&[]).no_modify());
+
+        let p = KeyServerPreferences::default().set_no_modify(true);
+        assert!(p.no_modify());
+        assert_eq!(p.as_vec(), vec![0x80]);
+
+        // Unknown bits are preserved.
+        let p = KeyServerPreferences::new(&[0x81, 0x02]);
+        assert!(p.no_modify());
+        assert_eq!(p.set_no_modify(false).as_vec(), vec![0x01, 0x02]);
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::fmt;
 use std::ops::Deref;
+use time;
 
 use constants::Curve;
 use Error;
@@ -34,6 +35,11 @@ const TRACE : bool = false;
 ///
 /// This is the mutable version of a `Signature4` packet.  To convert
 /// it to one, use `sign_hash(..)`.
+///
+/// When signing, a Signature Creation Time subpacket set to the
+/// current time is added to the hashed area, unless one is already
+/// present.  Likewise, if the signature doesn't name its issuer, an
+/// Issuer subpacket is added to the unhashed area.
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub struct Builder {
     /// Version of the signature packet. Must be 4.
@@ -117,7 +123,7 @@ impl Builder {
     pub fn sign_primary_key_binding(mut self, signer: &mut Signer,
                                     algo: HashAlgorithm)
                                     -> Result<Signature> {
-        self.pk_algo = signer.public().pk_algo();
+        self = self.pre_sign(signer)?;
         self.hash_algo = algo;
         let digest =
             Signature::primary_key_binding_hash(&self, signer.public())?;
@@ -133,7 +139,7 @@ impl Builder {
     pub fn sign_userid_binding(mut self, signer: &mut Signer,
                                key: &Key, userid: &UserID, algo: HashAlgorithm)
                                -> Result<Signature> {
-        self.pk_algo = signer.public().pk_algo();
+        self = self.pre_sign(signer)?;
         self.hash_algo = algo;
         let digest = Signature::userid_binding_hash(&self, key, userid)?;
 
@@ -148,7 +154,7 @@ impl Builder {
     pub fn sign_subkey_binding(mut self, signer: &mut Signer,
                                primary: &Key, subkey: &Key, algo: HashAlgorithm)
                                -> Result<Signature> {
-        self.pk_algo = signer.public().pk_algo();
+        self = self.pre_sign(signer)?;
         self.hash_algo = algo;
         let digest = Signature::subkey_binding_hash(&self, primary, subkey)?;
 
@@ -164,7 +170,7 @@ impl Builder {
                                        key: &Key, ua: &UserAttribute,
                                        algo: HashAlgorithm)
                                        -> Result<Signature> {
-        self.pk_algo = signer.public().pk_algo();
+        self = self.pre_sign(signer)?;
         self.hash_algo = algo;
         let digest =
             Signature::user_attribute_binding_hash(&self, key, ua)?;
@@ -181,7 +187,7 @@ impl Builder {
                      hash_algo: HashAlgorithm, mut hash: hash::Context)
                      -> Result<Signature> {
         // Fill out some fields, then hash the packet.
-        self.pk_algo = signer.public().pk_algo();
+        self = self.pre_sign(signer)?;
         self.hash_algo = hash_algo;
         self.hash(&mut hash);
 
@@ -197,15 +203,18 @@ impl Builder {
     /// The Signature's public-key algorithm field is set to the
     /// algorithm used by `signer`, the hash-algorithm field is set to
     /// `hash_algo`.
+    ///
+    /// If this is a `SignatureType::Text` signature, the message's
+    /// line endings are normalized to `\r\n` before hashing it.
     pub fn sign_message(mut self, signer: &mut Signer,
                      hash_algo: HashAlgorithm, msg: &[u8])
                      -> Result<Signature> {
         // Hash the message
         let mut hash = hash_algo.context()?;
-        hash.update(msg);
+        hash_document(&mut hash, self.sigtype, msg);
 
         // Fill out some fields, then hash the packet.
-        self.pk_algo = signer.public().pk_algo();
+        self = self.pre_sign(signer)?;
         self.hash_algo = hash_algo;
         self.hash(&mut hash);
 
@@ -216,6 +225,38 @@ impl Builder {
         self.sign(signer, digest)
     }
 
+    /// Fills in the fields that every signature needs.
+    ///
+    /// RFC 4880 requires the creation time to be in the hashed area
+    /// (see [Section 5.2.3.4 of RFC 4880]).  The issuer is only added
+    /// to the unhashed area, so that callers that want to hide it
+    /// can still remove it after signing.
+    ///
+    ///   [Section 5.2.3.4 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.2.3.4
+    fn pre_sign(mut self, signer: &Signer) -> Result<Self> {
+        use packet::signature::subpacket::{Subpacket, SubpacketTag,
+                                           SubpacketValue};
+
+        self.pk_algo = signer.public().pk_algo();
+
+        if self.hashed_area.lookup(SubpacketTag::SignatureCreationTime)
+            .is_none()
+        {
+            self = self.set_signature_creation_time(time::now())?;
+        }
+
+        let has_issuer = [SubpacketTag::Issuer,
+                          SubpacketTag::IssuerFingerprint].iter()
+            .any(|&tag| self.hashed_area.lookup(tag).is_some()
+                 || self.unhashed_area.lookup(tag).is_some());
+        if ! has_issuer {
+            self.unhashed_area.add(Subpacket::new(
+                SubpacketValue::Issuer(signer.public().keyid()), false)?)?;
+        }
+
+        Ok(self)
+    }
+
     fn sign(self, signer: &mut Signer, digest: Vec<u8>) -> Result<Signature> {
         let algo = self.hash_algo;
         let mpis = signer.sign(algo, &digest)?;
@@ -231,9 +272,62 @@ impl Builder {
     }
 }
 
+/// Hashes the document `data` for a signature of type `sigtype`.
+///
+/// Text signatures are over the canonical form of the document,
+/// i.e., with `\r\n` line endings (see [Section 5.2.1 of RFC 4880]).
+///
+///   [Section 5.2.1 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.2.1
+fn hash_document(hash: &mut hash::Context, sigtype: SignatureType,
+                 data: &[u8]) {
+    hash_document_part(hash, sigtype, &mut false, data);
+}
+
+/// Hashes a part of the document for a signature of type `sigtype`.
+///
+/// Like `hash_document`, but the document can be hashed in several
+/// parts, e.g. when streaming it.  Existing CRLFs and lone CRs are
+/// hashed as they are, even if a CRLF is split across parts.
+/// `after_cr` is whether the previous part ended in a carriage
+/// return, and is updated.
+pub(crate) fn hash_document_part(hash: &mut hash::Context,
+                                 sigtype: SignatureType,
+                                 after_cr: &mut bool, data: &[u8]) {
+    if sigtype != SignatureType::Text {
+        hash.update(data);
+        return;
+    }
+
+    let mut last = 0;
+    for (i, &c) in data.iter().enumerate() {
+        let prev_cr = if i == 0 {
+            *after_cr
+        } else {
+            data[i - 1] == b'\r'
+        };
+
+        if c == b'\n' && ! prev_cr {
+            hash.update(&data[last..i]);
+            hash.update(b"\r\n");
+            last = i + 1;
+        }
+    }
+    hash.update(&data[last..]);
+
+    if let Some(&c) = data.last() {
+        *after_cr = c == b'\r';
+    }
+}
+
 impl From<Signature> for Builder {
     fn from(sig: Signature) -> Self {
         match sig {
+            Signature::V3(sig) => {
+                // The builder only creates version 4 signatures.
+                let mut builder: Builder = sig.intern.into();
+                builder.version = 4;
+                builder
+            },
             Signature::V4(sig) => sig.into(),
         }
     }
@@ -248,6 +342,7 @@ impl From<Signature4> for Builder {
 impl<'a> From<&'a Signature> for &'a Builder {
     fn from(sig: &'a Signature) -> Self {
         match sig {
+            Signature::V3(ref sig) => (&sig.intern).into(),
             Signature::V4(ref sig) => sig.into(),
         }
     }
@@ -358,6 +453,28 @@ impl PartialEq for Signature4 {
     }
 }
 
+impl Signature4 {
+    /// Compares the signatures' content.
+    ///
+    /// Unlike `==`, this considers the unhashed area, but compares
+    /// both subpacket areas without regard to the order of the
+    /// subpackets or the encoding of their lengths.  See
+    /// [`Packet::semantic_eq`].
+    ///
+    ///   [`Packet::semantic_eq`]: ../../enum.Packet.html#method.semantic_eq
+    pub(crate) fn semantic_eq(&self, other: &Signature4) -> bool {
+        self.fields.version == other.fields.version
+            && self.fields.sigtype == other.fields.sigtype
+            && self.fields.pk_algo == other.fields.pk_algo
+            && self.fields.hash_algo == other.fields.hash_algo
+            && self.fields.hashed_area.semantic_eq(&other.fields.hashed_area)
+            && self.fields.unhashed_area.semantic_eq(
+                &other.fields.unhashed_area)
+            && self.hash_prefix == other.hash_prefix
+            && self.mpis == other.mpis
+    }
+}
+
 impl std::hash::Hash for Signature4 {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         use std::hash::Hash as StdHash;
@@ -455,9 +572,15 @@ impl Signature4 {
     }
 
     /// Gets the issuer.
+    ///
+    /// If the signature does not contain an issuer subpacket, this
+    /// falls back to the key id derived from the issuer fingerprint
+    /// subpacket.
     pub fn get_issuer(&self) -> Option<KeyID> {
         if let Some(id) = self.issuer() {
             Some(id)
+        } else if let Some(fp) = self.issuer_fingerprint() {
+            Some(fp.to_keyid())
         } else {
             None
         }
@@ -480,10 +603,11 @@ impl Signature4 {
             let area = sig.unhashed_area_mut();
             area.clear();
 
-            // First, add an Issuer subpacket derived from information
-            // from the hashed area.
+            // First, add an Issuer subpacket derived from the
+            // issuer information.
             if let Some(issuer) = self.issuer_fingerprint() {
-                // Prefer the IssuerFingerprint.
+                // Prefer the IssuerFingerprint, which we will also
+                // get from the unhashed area if necessary.
                 area.add(Subpacket::new(
                     SubpacketValue::Issuer(issuer.to_keyid()), false).unwrap())
                     .unwrap();
@@ -553,6 +677,9 @@ impl Signature4 {
                 let params = dsa::Params::new(p.value(), q.value(), g.value());
                 let signature = dsa::Signature::new(r.value(), s.value());
 
+                // Nettle truncates the digest to the bit length of q
+                // (see FIPS 186-4, Section 4.6), so we can pass in the
+                // whole digest, even if it is longer than q.
                 Ok(dsa::verify(&params, &key, hash, &signature))
             }
 
@@ -945,6 +1072,8 @@ impl Signature4 {
     /// This function is for short messages, if you want to verify larger files
     /// use `Verifier`.
     ///
+    /// This is the same as `verify_document`.
+    ///
     /// Note: This only verifies the cryptographic signature.
     /// Constraints on the signature, like creation and expiration
     /// time, or signature revocations must be checked by the caller.
@@ -956,6 +1085,33 @@ impl Signature4 {
     /// signing capability, etc.
     pub fn verify_message(&self, signer: &Key, msg: &[u8])
         -> Result<bool>
+    {
+        self.verify_document(signer, msg)
+    }
+
+    /// Verifies the document signature.
+    ///
+    /// `self` is a `SignatureType::Binary` or `SignatureType::Text`
+    /// signature, `signer` is the key that allegedly made the
+    /// signature, and `data` is the signed document.  For text
+    /// signatures, the line endings of `data` are normalized to
+    /// `\r\n`, as required by [Section 5.2.1 of RFC 4880].
+    ///
+    /// The digest is computed using the signature's hash algorithm
+    /// over `data` and the signature's hashed fields.  If the
+    /// signature is well-formed, but doesn't verify, this returns
+    /// `Ok(false)`.  An error is returned if the signature has the
+    /// wrong type, or uses an unsupported algorithm.
+    ///
+    /// Note: This only verifies the cryptographic signature.
+    /// Constraints on the signature, like creation and expiration
+    /// time, or signature revocations must be checked by the caller.
+    /// Use `verify_document_at` to also check that the signature is
+    /// alive at a given time.
+    ///
+    ///   [Section 5.2.1 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.2.1
+    pub fn verify_document(&self, signer: &Key, data: &[u8])
+        -> Result<bool>
     {
         if self.sigtype() != SignatureType::Binary &&
             self.sigtype() != SignatureType::Text {
@@ -966,12 +1122,38 @@ impl Signature4 {
         let mut hash = self.hash_algo().context()?;
         let mut digest = vec![0u8; hash.digest_size()];
 
-        hash.update(msg);
+        hash_document(&mut hash, self.sigtype(), data);
         self.hash(&mut hash);
         hash.digest(&mut digest);
 
         self.verify_hash(signer, self.hash_algo(), &digest[..])
     }
+
+    /// Verifies the document signature relative to time `t`.
+    ///
+    /// This is like `verify_document`, but additionally checks that
+    /// the signature is alive at `t`, i.e. that it was created at or
+    /// before `t`, and has not expired by then (see
+    /// `Signature4::signature_alive_at`).  If `t` is `None`, the
+    /// current time is used.  If the signature is not alive, this
+    /// returns `Ok(false)`.
+    ///
+    /// To check whether a signature was valid when it was made, pass
+    /// its creation time.
+    ///
+    /// Note: This does not check whether `signer` was alive or
+    /// revoked at `t`; use `TPK::alive_at` and `TPK::keys_valid_at`
+    /// for that.
+    pub fn verify_document_at<T>(&self, signer: &Key, data: &[u8], t: T)
+        -> Result<bool>
+        where T: Into<Option<time::Tm>>
+    {
+        let t = t.into().unwrap_or_else(time::now_utc);
+        if ! self.verify_document(signer, data)? {
+            return Ok(false);
+        }
+        Ok(self.signature_alive_at(t))
+    }
 }
 
 impl From<Signature4> for Packet {
@@ -986,6 +1168,76 @@ impl From<Signature4> for super::Signature {
     }
 }
 
+/// Holds a version 3 signature packet.
+///
+/// Version 3 signatures are deprecated (see [Section 5.2.2 of RFC
+/// 4880]), but they are still found on older keys and in archived
+/// messages.  We can parse, verify, and serialize them, but we don't
+/// create new ones.
+///
+/// A version 3 signature carries the creation time and the issuer
+/// in the packet body instead of in subpackets.  To provide the same
+/// interface as for version 4 signatures, a `Signature3` wraps a
+/// `Signature4` whose hashed area holds a Signature Creation Time
+/// subpacket, and whose unhashed area holds an Issuer subpacket.
+/// These areas are not serialized.  Therefore, a `Signature3` only
+/// dereferences to an immutable `Signature4`.  If the areas are
+/// changed via a `Signature`, serializing the signature fails
+/// unless they still only hold these two subpackets.
+///
+///   [Section 5.2.2 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.2.2
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Signature3 {
+    pub(crate) intern: Signature4,
+}
+
+impl Deref for Signature3 {
+    type Target = Signature4;
+
+    fn deref(&self) -> &Self::Target {
+        &self.intern
+    }
+}
+
+impl Signature3 {
+    /// Creates a new version 3 signature packet.
+    pub fn new(sigtype: SignatureType, creation_time: time::Tm,
+               issuer: KeyID, pk_algo: PublicKeyAlgorithm,
+               hash_algo: HashAlgorithm, hash_prefix: [u8; 2],
+               mpis: mpis::Signature) -> Self {
+        use packet::signature::subpacket::{Subpacket, SubpacketValue};
+
+        // These subpackets are tiny, adding them can't fail.
+        let mut hashed_area = SubpacketArea::empty();
+        hashed_area.add(Subpacket::new(
+            SubpacketValue::SignatureCreationTime(creation_time), true)
+                        .unwrap()).unwrap();
+        let mut unhashed_area = SubpacketArea::empty();
+        unhashed_area.add(Subpacket::new(
+            SubpacketValue::Issuer(issuer), false).unwrap()).unwrap();
+
+        let mut intern = Signature4::new(sigtype, pk_algo, hash_algo,
+                                         hashed_area, unhashed_area,
+                                         hash_prefix, mpis);
+        intern.fields.version = 3;
+        Signature3 {
+            intern: intern,
+        }
+    }
+}
+
+impl From<Signature3> for Packet {
+    fn from(s: Signature3) -> Self {
+        Packet::Signature(s.into())
+    }
+}
+
+impl From<Signature3> for super::Signature {
+    fn from(s: Signature3) -> Self {
+        super::Signature::V3(s)
+    }
+}
+
 
 #[cfg(test)]
 mod test {
@@ -1031,6 +1283,23 @@ mod test {
                 data: &"signed-1-dsa.pgp"[..],
                 good: 1,
             },
+            // SHA256 digests truncated to a 160 bit q, and used as-is
+            // with a 256 bit q.
+            Test {
+                key: &"dsa1024.pgp"[..],
+                data: &"signed-1-sha256-dsa1024.pgp"[..],
+                good: 1,
+            },
+            Test {
+                key: &"dsa3072.pgp"[..],
+                data: &"signed-1-sha256-dsa3072.pgp"[..],
+                good: 1,
+            },
+            Test {
+                key: &"dsa1024.pgp"[..],
+                data: &"signed-1-sha256-dsa3072.pgp"[..],
+                good: 0,
+            },
             Test {
                 key: &"erika-corinna-daniela-simone-antonia-nistp256.pgp"[..],
                 data: &"signed-1-ecdsa-nistp256.pgp"[..],
@@ -1190,6 +1459,57 @@ mod test {
         assert!(sig.verify_message(pair.public(), msg).unwrap());
     }
 
+    #[test]
+    fn sign_message_defaults() {
+        use time;
+        use constants::Curve;
+        use Fingerprint;
+        use packet::signature::subpacket::SubpacketTag;
+
+        let key: Key = Key4::generate_ecc(true, Curve::Ed25519)
+            .unwrap().into();
+        let msg = b"Hello, World";
+        let mut pair = key.clone().into_keypair().unwrap();
+
+        // The creation time and the issuer are filled in.
+        let sig = Builder::new(SignatureType::Binary)
+            .sign_message(&mut pair, HashAlgorithm::SHA512, msg).unwrap();
+        assert!(sig.signature_creation_time().is_some());
+        assert_eq!(sig.issuer(), Some(key.keyid()));
+        assert!(sig.hashed_area().lookup(SubpacketTag::Issuer).is_none());
+        assert!(sig.verify_message(&key, msg).unwrap());
+
+        // But they don't override what is already there.
+        let then = time::at_utc(time::Timespec::new(1500000000, 0));
+        let fp = Fingerprint::from_bytes(b"bbbbbbbbbbbbbbbbbbbb");
+        let sig = Builder::new(SignatureType::Binary)
+            .set_signature_creation_time(then).unwrap()
+            .set_issuer_fingerprint(fp.clone()).unwrap()
+            .sign_message(&mut pair, HashAlgorithm::SHA512, msg).unwrap();
+        assert_eq!(sig.signature_creation_time(), Some(then));
+        assert_eq!(sig.issuer_fingerprint(), Some(fp));
+        assert!(sig.issuer().is_none());
+        assert!(sig.verify_message(&key, msg).unwrap());
+    }
+
+    #[test]
+    fn sign_message_dsa() {
+        let tpk = TPK::from_bytes(::tests::key("dsa1024-private.pgp"))
+            .unwrap();
+        let key = tpk.primary();
+        let msg = b"Hello, World";
+
+        // The digests are longer than q, and have to be truncated.
+        for &hash_algo in &[HashAlgorithm::SHA256, HashAlgorithm::SHA512] {
+            let mut pair = key.clone().into_keypair().unwrap();
+            let sig = Builder::new(SignatureType::Binary)
+                .sign_message(&mut pair, hash_algo, msg).unwrap();
+
+            assert!(sig.verify_message(key, msg).unwrap());
+            assert!(! sig.verify_message(key, b"Goodbye").unwrap());
+        }
+    }
+
     #[test]
     fn verify_message() {
         let tpk = TPK::from_bytes(::tests::key(
@@ -1207,6 +1527,128 @@ mod test {
         assert!(sig.verify_message(tpk.primary(), &msg[..]).unwrap());
     }
 
+    #[test]
+    fn verify_v3() {
+        let tpk = TPK::from_bytes(::tests::key("testy.pgp")).unwrap();
+        let msg = ::tests::manifesto();
+        let p = Packet::from_bytes(
+            ::tests::message("a-cypherpunks-manifesto.txt.testy-v3.sig"))
+            .unwrap();
+        let sig = if let Packet::Signature(s) = p {
+            s
+        } else {
+            panic!("Expected a Signature, got: {:?}", p);
+        };
+
+        assert_eq!(sig.version(), 3);
+        assert_eq!(sig.get_issuer(), Some(tpk.keyid()));
+        assert!(sig.verify_document(tpk.primary(), msg).unwrap());
+        assert!(! sig.verify_document(tpk.primary(), b"Hello").unwrap());
+
+        // It roundtrips, but only as long as it only has the
+        // subpackets that a version 3 signature can express.
+        {
+            use packet::signature::subpacket::{Subpacket, SubpacketValue};
+            use serialize::SerializeInto;
+
+            assert_eq!(&sig.to_vec().unwrap()[..],
+                       &::tests::message(
+                           "a-cypherpunks-manifesto.txt.testy-v3.sig")[3..]);
+
+            let mut sig = sig.clone();
+            sig.unhashed_area_mut().add(Subpacket::new(
+                SubpacketValue::Revocable(false), false).unwrap()).unwrap();
+            assert!(sig.to_vec().is_err());
+        }
+
+        // Converting it to a builder yields a version 4 signature.
+        let builder: Builder = sig.into();
+        assert_eq!(builder.version(), 4);
+    }
+
+    #[test]
+    fn verify_document() {
+        use constants::Curve;
+
+        let tpk = TPK::from_bytes(::tests::key(
+                "emmelie-dorothea-dina-samantha-awina-ed25519.pgp")).unwrap();
+        let msg = ::tests::manifesto();
+        let p = Packet::from_bytes(::tests::message(
+            "a-cypherpunks-manifesto.txt.ed25519.textmode.sig")).unwrap();
+        let sig = if let Packet::Signature(s) = p {
+            s
+        } else {
+            panic!("Expected a Signature, got: {:?}", p);
+        };
+        assert_eq!(sig.sigtype(), SignatureType::Text);
+
+        // The manifesto has Unix line endings, which are normalized
+        // before hashing.  So, DOS line endings work, too.
+        let dos = String::from_utf8(msg.to_vec()).unwrap()
+            .replace("\n", "\r\n");
+        assert!(sig.verify_document(tpk.primary(), msg).unwrap());
+        assert!(sig.verify_document(tpk.primary(), dos.as_bytes()).unwrap());
+        assert!(! sig.verify_document(tpk.primary(), b"Hello").unwrap());
+
+        // Roundtrip.
+        let key: Key = Key4::generate_ecc(true, Curve::Ed25519)
+            .unwrap().into();
+        let mut pair = key.clone().into_keypair().unwrap();
+        let sig = Builder::new(SignatureType::Text)
+            .sign_message(&mut pair, HashAlgorithm::SHA256, b"a\nb\n")
+            .unwrap();
+        assert!(sig.verify_document(&key, b"a\r\nb\r\n").unwrap());
+        assert!(! sig.verify_document(&key, b"a\nb").unwrap());
+
+        // Binary signatures are over the data as is.
+        let sig = Builder::new(SignatureType::Binary)
+            .sign_message(&mut pair, HashAlgorithm::SHA256, b"a\nb\n")
+            .unwrap();
+        assert!(sig.verify_document(&key, b"a\nb\n").unwrap());
+        assert!(! sig.verify_document(&key, b"a\r\nb\r\n").unwrap());
+
+        // Only document signatures can be verified this way.
+        let sig = Builder::new(SignatureType::Standalone)
+            .sign_hash(&mut pair, HashAlgorithm::SHA256,
+                       HashAlgorithm::SHA256.context().unwrap()).unwrap();
+        assert!(sig.verify_document(&key, b"").is_err());
+    }
+
+    #[test]
+    fn verify_document_at() {
+        use constants::Curve;
+
+        let key: Key = Key4::generate_ecc(true, Curve::Ed25519)
+            .unwrap().into();
+        let mut pair = key.clone().into_keypair().unwrap();
+
+        // A signature made at `t`, which expires a day later.
+        let t = time::at_utc(time::Timespec::new(1500000000, 0));
+        let sig = Builder::new(SignatureType::Binary)
+            .set_signature_creation_time(t).unwrap()
+            .set_signature_expiration_time(Some(time::Duration::days(1)))
+            .unwrap()
+            .sign_message(&mut pair, HashAlgorithm::SHA256, b"Hello")
+            .unwrap();
+
+        // The signature is valid when it is made, and while it is
+        // alive.
+        assert!(sig.verify_document_at(&key, b"Hello", t).unwrap());
+        assert!(sig.verify_document_at(
+            &key, b"Hello", t + time::Duration::hours(23)).unwrap());
+        assert!(! sig.verify_document_at(&key, b"Hullo", t).unwrap());
+
+        // But not before it was made, nor after it expired.
+        assert!(! sig.verify_document_at(
+            &key, b"Hello", t - time::Duration::seconds(1)).unwrap());
+        assert!(! sig.verify_document_at(
+            &key, b"Hello", t + time::Duration::days(1)).unwrap());
+        assert!(! sig.verify_document_at(&key, b"Hello", None).unwrap());
+
+        // The cryptographic signature is still fine.
+        assert!(sig.verify_document(&key, b"Hello").unwrap());
+    }
+
     #[test]
     fn sign_with_short_ed25519_secret_key() {
         use conversions::Time;
@@ -1263,6 +1705,64 @@ mod test {
         assert_eq!(cert.verify_userid_binding(cert_key1, test2.primary(), uid_binding.userid()).ok(), Some(true));
     }
 
+    #[test]
+    fn unknown_subpackets_roundtrip() {
+        use time;
+        use packet::signature::subpacket::*;
+        use serialize::SerializeInto;
+
+        let key: Key = Key4::generate_ecc(true, Curve::Ed25519)
+            .unwrap().into();
+        let mut pair = key.clone().into_keypair().unwrap();
+        let msg = b"Hello, World";
+
+        let mut builder = Builder::new(SignatureType::Binary);
+        builder.hashed_area_mut().add(Subpacket {
+            critical: false,
+            tag: SubpacketTag::Unknown(42),
+            value: SubpacketValue::Unknown(b"forty-two"),
+        }).unwrap();
+        let mut builder = builder
+            .set_signature_creation_time(time::now()).unwrap()
+            .set_issuer(key.keyid()).unwrap();
+        builder.unhashed_area_mut().add(Subpacket {
+            critical: false,
+            tag: SubpacketTag::Private(101),
+            value: SubpacketValue::Unknown(b"private"),
+        }).unwrap();
+        let sig = builder.sign_message(&mut pair, HashAlgorithm::SHA256, msg)
+            .unwrap();
+
+        // Reserializing the parsed signature is byte-identical.
+        let bytes = Packet::Signature(sig).to_vec().unwrap();
+        let sig = if let Packet::Signature(s) = Packet::from_bytes(&bytes)
+            .unwrap()
+        {
+            s
+        } else {
+            panic!("expected a signature");
+        };
+        assert_eq!(Packet::Signature(sig.clone()).to_vec().unwrap(), bytes);
+
+        // The order of the subpackets is preserved.
+        assert_eq!(sig.hashed_area().iter().map(|(_, _, s)| s.tag)
+                   .collect::<Vec<_>>(),
+                   vec![SubpacketTag::Unknown(42),
+                        SubpacketTag::SignatureCreationTime,
+                        SubpacketTag::Issuer]);
+        assert_eq!(sig.hashed_area()
+                   .lookup(SubpacketTag::Unknown(42)).unwrap().value,
+                   SubpacketValue::Unknown(b"forty-two"));
+        assert_eq!(sig.unhashed_area()
+                   .lookup(SubpacketTag::Private(101)).unwrap().value,
+                   SubpacketValue::Unknown(b"private"));
+
+        // And we can still read the known ones.
+        assert!(sig.signature_creation_time().is_some());
+        assert_eq!(sig.issuer(), Some(key.keyid()));
+        assert!(sig.verify_message(&key, msg).unwrap());
+    }
+
     #[test]
     fn normalize() {
         use Fingerprint;
@@ -1315,4 +1815,78 @@ mod test {
                    Subpacket::new(SubpacketValue::Issuer(keyid.clone()),
                                   false).unwrap());
     }
+
+    #[test]
+    fn semantic_eq() {
+        use packet::signature::subpacket::{Subpacket, SubpacketValue};
+        use serialize::Serialize;
+        use PacketPile;
+
+        // Reverses the order of the subpackets, and uses five-octet
+        // lengths instead of one-octet lengths.
+        fn reencode(area: &SubpacketArea) -> SubpacketArea {
+            let mut subpackets = area.iter().map(|(_, _, s)| {
+                let mut raw = Vec::new();
+                s.serialize(&mut raw).unwrap();
+                assert!(raw[0] < 192);
+                let mut v = vec![0xff, 0, 0, 0];
+                v.extend_from_slice(&raw);
+                v
+            }).collect::<Vec<_>>();
+            subpackets.reverse();
+            SubpacketArea::new(subpackets.concat())
+        }
+
+        let pile = PacketPile::from_bytes(::tests::message(
+            "a-cypherpunks-manifesto.txt.ed25519.sig")).unwrap();
+        let a = if let Some(Packet::Signature(sig)) = pile.path_ref(&[0]) {
+            sig.clone()
+        } else {
+            panic!("Expected a signature");
+        };
+        assert!(a.hashed_area().iter().count() > 1);
+
+        let mut b = a.clone();
+        b.fields.hashed_area = reencode(a.hashed_area());
+        b.fields.unhashed_area = reencode(a.unhashed_area());
+        let a = Packet::Signature(a);
+        let b = Packet::Signature(b);
+        assert!(a != b);
+        assert!(a.semantic_eq(&b));
+        assert!(b.semantic_eq(&a));
+
+        // The unhashed area is considered.
+        let mut c = a.clone();
+        if let Packet::Signature(ref mut sig) = c {
+            sig.unhashed_area_mut().clear();
+        }
+        assert_eq!(a, c);
+        assert!(! a.semantic_eq(&c));
+
+        // The relative order of subpackets with the same tag matters.
+        let t1 = time::at_utc(time::Timespec::new(1000000000, 0));
+        let t2 = time::at_utc(time::Timespec::new(1500000000, 0));
+        let mut x = SubpacketArea::empty();
+        x.add(Subpacket::new(SubpacketValue::SignatureCreationTime(t1),
+                             false).unwrap()).unwrap();
+        x.add(Subpacket::new(SubpacketValue::SignatureCreationTime(t2),
+                             false).unwrap()).unwrap();
+        let y = reencode(&x);
+        assert!(x.semantic_eq(&x));
+        assert!(! x.semantic_eq(&y));
+        assert!(x.semantic_eq(&reencode(&y)));
+
+        // The packet framing is not considered.
+        let tpk = TPK::from_bytes(::tests::key("neal.pgp")).unwrap();
+        let mut body = Vec::new();
+        tpk.primary().serialize(&mut body).unwrap();
+        assert!(body.len() < 0x10000);
+        let mut old = vec![0x99, (body.len() >> 8) as u8, body.len() as u8];
+        old.extend_from_slice(&body);
+        let old = PacketPile::from_bytes(&old).unwrap();
+        let new = Packet::PublicKey(tpk.primary().clone());
+        assert!(old.path_ref(&[0]).unwrap().semantic_eq(&new));
+        assert!(! Packet::PublicSubkey(tpk.primary().clone())
+                .semantic_eq(&new));
+    }
 }
@@ -309,6 +309,10 @@ impl<'a> fmt::Debug for SubpacketRaw<'a> {
 }
 
 /// Subpacket area.
+///
+/// The subpacket area is kept in its original encoding, including
+/// any unknown subpackets, so that parsed signatures are reserialized
+/// byte for byte.  The typed accessors index into it.
 #[derive(Clone, Eq)]
 pub struct SubpacketArea {
     /// Raw, unparsed subpacket data.
@@ -463,6 +467,25 @@ impl SubpacketArea {
     pub fn empty() -> SubpacketArea {
         SubpacketArea::new(Vec::new())
     }
+
+    /// Compares the subpackets, ignoring their order and the
+    /// encoding of their lengths.
+    ///
+    /// Subpackets with the same tag must occur in the same relative
+    /// order, because if a subpacket occurs more than once, the last
+    /// occurrence takes precedence.
+    pub(crate) fn semantic_eq(&self, other: &SubpacketArea) -> bool {
+        fn canonical(area: &SubpacketArea) -> Vec<(u8, bool, &[u8])> {
+            let mut subpackets = area.iter_raw()
+                .map(|(_, _, sb)| (sb.tag.into(), sb.critical, sb.value))
+                .collect::<Vec<_>>();
+            // This is a stable sort.
+            subpackets.sort_by_key(|&(tag, _, _)| tag);
+            subpackets
+        }
+
+        canonical(self) == canonical(other)
+    }
 }
 
 impl SubpacketArea {
@@ -790,23 +813,28 @@ impl<'a> SubpacketValue<'a> {
             Features(f) => f.as_vec().len(),
             SignatureTarget { ref digest, .. } => 1 + 1 + digest.len(),
             EmbeddedSignature(p) => match p {
-                &Packet::Signature(Signature::V4(ref sig)) => {
+                &Packet::Signature(ref sig) => {
                     use serialize::Serialize;
                     let mut w = Vec::new();
                     sig.serialize(&mut w).unwrap();
                     w.len()
                 },
+                // A malformed embedded signature is kept as is.
+                &Packet::Unknown(ref u) => u.body().map(|b| b.len())
+                    .unwrap_or(0),
                 // Bogus.
                 _ => 0,
             },
             IssuerFingerprint(ref fp) => match fp {
                 Fingerprint::V4(_) => 1 + 20,
+                Fingerprint::V5(_) => 1 + 32,
                 // Educated guess for unknown versions.
                 Fingerprint::Invalid(_) => 1 + fp.as_slice().len(),
             },
             PreferredAEADAlgorithms(ref p) => p.len(),
             IntendedRecipient(ref fp) => match fp {
                 Fingerprint::V4(_) => 1 + 20,
+                Fingerprint::V5(_) => 1 + 32,
                 // Educated guess for unknown versions.
                 Fingerprint::Invalid(_) => 1 + fp.as_slice().len(),
             },
@@ -900,6 +928,19 @@ impl<'a> Subpacket<'a> {
     }
 }
 
+/// Parses a fingerprint prefixed with its version.
+///
+/// Returns `None` if the version is unknown, or the fingerprint's
+/// length doesn't match the version.  Then, the subpacket is kept
+/// as is, so that serializing it reproduces the original bytes.
+fn versioned_fingerprint(value: &[u8]) -> Option<Fingerprint> {
+    match (value.get(0), value.len()) {
+        (Some(&4), 21) | (Some(&5), 33) =>
+            Some(Fingerprint::from_bytes(&value[1..])),
+        _ => None,
+    }
+}
+
 fn from_be_u16(value: &[u8]) -> Option<u16> {
     if value.len() >= 2 {
         Some((value[0] as u16) << 8
@@ -1109,38 +1150,18 @@ impl<'a> From<SubpacketRaw<'a>> for Subpacket<'a> {
                 ))
             },
 
-            SubpacketTag::IssuerFingerprint => {
-                let version = raw.value.get(0);
-                if let Some(version) = version {
-                    if *version == 4 {
-                        Some(SubpacketValue::IssuerFingerprint(
-                            Fingerprint::from_bytes(&raw.value[1..])))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            },
+            SubpacketTag::IssuerFingerprint =>
+                versioned_fingerprint(raw.value)
+                .map(SubpacketValue::IssuerFingerprint),
 
             SubpacketTag::PreferredAEADAlgorithms =>
                 // array of one-octet values.
                 Some(SubpacketValue::PreferredAEADAlgorithms(
                     raw.value.iter().map(|o| (*o).into()).collect())),
 
-            SubpacketTag::IntendedRecipient => {
-                let version = raw.value.get(0);
-                if let Some(version) = version {
-                    if *version == 4 {
-                        Some(SubpacketValue::IntendedRecipient(
-                            Fingerprint::from_bytes(&raw.value[1..])))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            },
+            SubpacketTag::IntendedRecipient =>
+                versioned_fingerprint(raw.value)
+                .map(SubpacketValue::IntendedRecipient),
 
             SubpacketTag::Reserved(_)
                     | SubpacketTag::PlaceholderForBackwardCompatibility
@@ -1204,7 +1225,8 @@ impl SubpacketLengthTrait for SubpacketLength {
             sink.write_all(&[(v >> 8) as u8,
                              (v >> 0) as u8])
         } else {
-            sink.write_all(&[(v >> 24) as u8,
+            sink.write_all(&[255,
+                             (v >> 24) as u8,
                              (v >> 16) as u8,
                              (v >> 8) as u8,
                              (v >> 0) as u8])
@@ -1242,9 +1264,11 @@ impl Signature4 {
         }
 
         // There are a couple of subpackets that we are willing to
-        // take from the unhashed area.  The others we ignore
-        // completely.
+        // take from the unhashed area: they are self-authenticating,
+        // i.e., if they are wrong, the signature simply won't
+        // verify.  The others we ignore completely.
         if !(tag == SubpacketTag::Issuer
+             || tag == SubpacketTag::IssuerFingerprint
              || tag == SubpacketTag::EmbeddedSignature) {
             return None;
         }
@@ -1257,6 +1281,8 @@ impl Signature4 {
     /// In general, you only want to do this for NotationData.
     /// Otherwise, taking the last instance of a specified subpacket
     /// is a reasonable approach for dealing with ambiguity.
+    ///
+    /// Only the hashed area is considered.
     fn subpackets<'a>(&'a self, target: SubpacketTag) -> Vec<Subpacket<'a>> {
         let mut result = Vec::new();
 
@@ -1657,7 +1683,8 @@ impl Signature4 {
     /// Returns the value of all Notation Data packets.
     ///
     /// If the subpacket is not present or malformed, this returns
-    /// an empty vector.
+    /// an empty vector.  Notations in the unhashed area are ignored,
+    /// because anyone can add them.
     ///
     /// Note: unlike other subpacket accessor functions, this function
     /// returns all the Notation Data subpackets, not just the last
@@ -1992,6 +2019,9 @@ impl Signature4 {
     /// This is used, for instance, to store a subkey's primary key
     /// binding signature (0x19).
     ///
+    /// Like the Issuer subpacket, this subpacket is also taken from
+    /// the unhashed area, if it is not present in the hashed area.
+    ///
     /// If the subpacket is not present or malformed, this returns
     /// `None`.
     ///
@@ -3241,3 +3271,111 @@ fn subpacket_test_2() {
 //         }
 //     }
 }
+
+#[test]
+fn unhashed_area() {
+    use constants::Curve;
+
+    let hash_algo = HashAlgorithm::SHA512;
+    let hash = hash_algo.context().unwrap();
+    let key: ::packet::Key =
+        ::packet::key::Key4::generate_ecc(true, Curve::Ed25519).unwrap().into();
+    let mut keypair = key.clone().into_keypair().unwrap();
+
+    let now = time::Tm::from_pgp(time::now_utc().to_pgp().unwrap());
+    let then = time::Tm::from_pgp(now.to_pgp().unwrap() - 3600);
+
+    let mut sig = signature::Builder::new(::constants::SignatureType::Binary)
+        .set_signature_creation_time(now).unwrap()
+        .sign_hash(&mut keypair, hash_algo, hash).unwrap();
+    // Drop the Issuer subpacket that was added when signing.
+    sig.unhashed_area_mut().clear();
+    assert_eq!(sig.issuer(), None);
+    assert_eq!(sig.issuer_fingerprint(), None);
+
+    sig.unhashed_area_mut().add(
+        Subpacket::new(SubpacketValue::Issuer(key.keyid()), false)
+            .unwrap()).unwrap();
+    sig.unhashed_area_mut().add(
+        Subpacket::new(SubpacketValue::IssuerFingerprint(key.fingerprint()),
+                       false).unwrap()).unwrap();
+    sig.unhashed_area_mut().add(
+        Subpacket::new(SubpacketValue::SignatureCreationTime(then), false)
+            .unwrap()).unwrap();
+    sig.unhashed_area_mut().add(
+        Subpacket::new(SubpacketValue::KeyFlags(
+            KeyFlags::default().set_sign(true)), false).unwrap()).unwrap();
+
+    // The issuer subpackets are self-authenticating, so they are
+    // taken from the unhashed area.
+    assert_eq!(sig.issuer(), Some(key.keyid()));
+    assert_eq!(sig.issuer_fingerprint(), Some(key.fingerprint()));
+
+    // Everything else has to come from the hashed area.
+    assert_eq!(sig.signature_creation_time(), Some(now));
+    assert!(! sig.key_flags().can_sign());
+}
+
+#[test]
+fn malformed_fingerprints() {
+    // A version 4 issuer fingerprint with the length of a version 5
+    // fingerprint, a truncated version 5 issuer fingerprint, and an
+    // intended recipient with an unknown version.
+    let mut data = Vec::new();
+    data.extend_from_slice(&[1 + 1 + 32, 33, 4]);
+    data.extend_from_slice(&[0xAA; 32]);
+    data.extend_from_slice(&[1 + 1 + 19, 33, 5]);
+    data.extend_from_slice(&[0xBB; 19]);
+    data.extend_from_slice(&[1 + 1 + 20, 35, 6]);
+    data.extend_from_slice(&[0xCC; 20]);
+
+    let area = SubpacketArea::new(data.clone());
+    assert_eq!(area.iter().count(), 3);
+    for (_, _, sb) in area.iter() {
+        match sb.value {
+            SubpacketValue::Invalid(_) => (),
+            ref v => panic!("Expected an invalid subpacket, got {:?}", v),
+        }
+    }
+
+    // They must survive serialization unchanged.
+    let reserialized: SubpacketArea = area.iter().collect();
+    assert_eq!(reserialized.data, data);
+}
+
+#[test]
+fn five_octet_length() {
+    use constants::Curve;
+    use parse::Parse;
+    use serialize::SerializeInto;
+
+    // Subpackets of 16320 octets and more use the five octet length
+    // form, which starts with 0xff.
+    let value = vec![0xaa; 20000];
+    let length: SubpacketLength =
+        1 + 4 + 2 + 2 + "big@example.org".len() as u32 + value.len() as u32;
+    let mut data = Vec::new();
+    length.serialize(&mut data).unwrap();
+    assert_eq!(&data[..], &[0xff, 0x00, 0x00, 0x4e, 0x38]);
+
+    let key: ::packet::Key =
+        ::packet::key::Key4::generate_ecc(true, Curve::Ed25519).unwrap().into();
+    let mut keypair = key.clone().into_keypair().unwrap();
+    let sig = signature::Builder::new(::constants::SignatureType::Binary)
+        .add_notation("big@example.org", &value, None, false).unwrap()
+        .sign_message(&mut keypair, HashAlgorithm::SHA512, b"data")
+        .unwrap();
+    assert_eq!(sig.notation("big@example.org"), vec![&value[..]]);
+
+    // The length survives a round trip.
+    let sig = if let ::Packet::Signature(sig) =
+        ::Packet::from_bytes(&::Packet::Signature(sig).to_vec().unwrap())
+        .unwrap()
+    {
+        sig
+    } else {
+        panic!("Expected a signature");
+    };
+    assert_eq!(sig.notation("big@example.org"), vec![&value[..]]);
+    assert!(sig.verify_message(&key, b"data").unwrap());
+}
@@ -6,11 +6,13 @@
 //!
 //! [Section 5.3 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.3
 
+use std::cmp::Ordering;
 use std::ops::{Deref, DerefMut};
 use quickcheck::{Arbitrary, Gen};
 
 use Result;
 use crypto;
+use crypto::mem::secure_cmp;
 use crypto::s2k::S2K;
 use Error;
 use constants::{
@@ -151,10 +153,16 @@ impl SKESK4 {
             }))
     }
 
-    /// Convert the `SKESK4` struct to a `Packet`.
     /// Derives the key inside this SKESK4 from `password`. Returns a
     /// tuple of the symmetric cipher to use with the key and the key
     /// itself.
+    ///
+    /// If the packet carries an encrypted session key, it is
+    /// decrypted using the key derived from `password`.  Otherwise,
+    /// the derived key is the session key (see [Section 5.3 of RFC
+    /// 4880]).
+    ///
+    ///   [Section 5.3 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.3
     pub fn decrypt(&self, password: &Password)
         -> Result<(SymmetricAlgorithm, SessionKey)>
     {
@@ -166,7 +174,7 @@ impl SKESK4 {
             let blk_sz = self.sym_algo.block_size()?;
             let mut iv = vec![0u8; blk_sz];
             let mut dec  = self.sym_algo.make_decrypt_cfb(&key[..])?;
-            let mut plain = vec![0u8; esk.len()];
+            let mut plain: SessionKey = vec![0u8; esk.len()].into();
             let cipher = &esk[..];
 
             for (pl, ct)
@@ -175,13 +183,15 @@ impl SKESK4 {
                 dec.decrypt(&mut iv[..], pl, ct)?;
             }
 
-            // Get the algorithm from the front.  While doing that,
-            // push and pop a value to overwrite the position formerly
-            // occupied by last byte of the session key.
-            plain.push(0);
-            let sym = SymmetricAlgorithm::from(plain.remove(0));
-            plain.pop();
-            Ok((sym, plain.into()))
+            // Get the algorithm from the front.
+            let sym = SymmetricAlgorithm::from(plain[0]);
+            let key = SessionKey::from(&plain[1..]);
+            if sym.key_size().ok() != Some(key.len()) {
+                return Err(Error::MalformedPacket(
+                    "SKESK4: Session key has the wrong size".into()).into());
+            }
+
+            Ok((sym, key))
         } else {
             // No ESK, we return the derived key.
 
@@ -274,10 +284,23 @@ impl SKESK5 {
     }
 
     /// Creates a new SKESK version 5 packet with the given password.
+    ///
+    /// The key-encryption key is derived from `password` using
+    /// `s2k`.  It is used to encrypt `session_key` using the given
+    /// AEAD algorithm with a fresh random IV.  The session key must
+    /// be of the size required by `cipher`.
     pub fn with_password(cipher: SymmetricAlgorithm,
                          aead: AEADAlgorithm, s2k: S2K,
                          session_key: &SessionKey, password: &Password)
                          -> Result<Self> {
+        if session_key.len() != cipher.key_size()? {
+            return Err(Error::InvalidArgument(
+                format!("Session key has the wrong size for {}: \
+                         expected {} bytes, got {}",
+                        cipher, cipher.key_size()?, session_key.len()))
+                       .into());
+        }
+
         // Derive key and make a cipher.
         let key = s2k.derive_key(password, cipher.key_size()?)?;
         let mut iv = vec![0u8; aead.iv_size()?];
@@ -288,7 +311,8 @@ impl SKESK5 {
         let ad = [0xc3, 5, cipher.into(), aead.into()];
         ctx.update(&ad);
 
-        // We need to prefix the cipher specifier to the session key.
+        // Unlike in version 4, the cipher is not prefixed to the
+        // session key.
         let mut esk = vec![0u8; session_key.len()];
         ctx.encrypt(&mut esk, &session_key);
 
@@ -300,9 +324,13 @@ impl SKESK5 {
                     digest.into_boxed_slice())
     }
 
-    /// Derives the key inside this SKESK4 from `password`. Returns a
+    /// Derives the key inside this SKESK5 from `password`. Returns a
     /// tuple of the symmetric cipher to use with the key and the key
     /// itself.
+    ///
+    /// The encrypted session key is authenticated using the AEAD
+    /// algorithm.  If authentication fails, e.g. because the password
+    /// is wrong, `Error::ManipulatedMessage` is returned.
     pub fn decrypt(&self, password: &Password)
                    -> Result<(SymmetricAlgorithm, SessionKey)> {
         let key = self.s2k().derive_key(password,
@@ -321,7 +349,9 @@ impl SKESK5 {
             cipher.decrypt(&mut plain, esk);
             let plain = SessionKey::from(plain);
             cipher.digest(&mut digest);
-            if &digest[..] == &self.aead_digest[..] {
+            if secure_cmp(&digest[..], &self.aead_digest[..])
+                == Ordering::Equal
+            {
                 Ok((self.symmetric_algo(), plain))
             } else {
                 Err(Error::ManipulatedMessage.into())
@@ -456,4 +486,65 @@ mod test {
         packets[0].serialize(&mut serialized).unwrap();
         assert_eq!(&raw[..], &serialized[..]);
     }
+    #[test]
+    fn skesk4_decrypt() {
+        use constants::HashAlgorithm;
+
+        let password: Password = String::from("password").into();
+        let algo = SymmetricAlgorithm::AES128;
+        let s2k = S2K::Salted { hash: HashAlgorithm::SHA256, salt: [7; 8] };
+
+        // With an encrypted session key.
+        let sk = SessionKey::new(algo.key_size().unwrap());
+        let skesk = SKESK4::with_password(algo, s2k, &sk, &password)
+            .unwrap();
+        assert_eq!(skesk.decrypt(&password).unwrap(), (algo, sk.clone()));
+        // A wrong password yields garbage, which is most likely
+        // rejected, but may not be.
+        if let Ok((a, k)) = skesk.decrypt(&"wrong".into()) {
+            assert!(a != algo || k != sk);
+        }
+
+        // Without one, the derived key is the session key.
+        let skesk = SKESK4::new(algo, s2k, None).unwrap();
+        let (a, k) = skesk.decrypt(&password).unwrap();
+        assert_eq!(a, algo);
+        assert_eq!(k, s2k.derive_key(&password, algo.key_size().unwrap())
+                   .unwrap());
+
+        // But not for the simple S2K, which has no salt.
+        let skesk = SKESK4::new(algo, S2K::Simple { hash: HashAlgorithm::SHA256 },
+                                None).unwrap();
+        assert!(skesk.decrypt(&password).is_err());
+    }
+
+    #[test]
+    fn skesk5_decrypt() {
+        let password: Password = String::from("password").into();
+        let algo = SymmetricAlgorithm::AES128;
+        let sk = SessionKey::new(algo.key_size().unwrap());
+        let skesk = SKESK5::with_password(algo, AEADAlgorithm::EAX,
+                                          Default::default(), &sk, &password)
+            .unwrap();
+        assert_eq!(skesk.decrypt(&password).unwrap(), (algo, sk.clone()));
+        assert_match!(Some(&Error::ManipulatedMessage) =
+                      skesk.decrypt(&"wrong".into()).unwrap_err()
+                      .downcast_ref::<Error>());
+
+        // Round-trip through the wire format.
+        let packet: Packet = skesk.into();
+        let packet = Packet::from_bytes(&packet.to_vec().unwrap()).unwrap();
+        if let Packet::SKESK(SKESK::V5(ref skesk)) = packet {
+            assert_eq!(skesk.aead_algo(), AEADAlgorithm::EAX);
+            assert_eq!(skesk.decrypt(&password).unwrap(), (algo, sk));
+        } else {
+            panic!("expected a SKESK5 packet, got {:?}", packet);
+        }
+
+        // The session key must match the cipher.
+        assert!(SKESK5::with_password(algo, AEADAlgorithm::EAX,
+                                      Default::default(),
+                                      &SessionKey::new(7), &password)
+                .is_err());
+    }
 }
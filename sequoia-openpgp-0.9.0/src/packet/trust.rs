@@ -1,6 +1,18 @@
+//! Trust packets.
+//!
+//! Trust packets are not exported, but are used by some
+//! implementations to cache information in local keyrings.  See
+//! [`GnuPGTrust`] for a decoder of GnuPG's layout.
+//!
+//!   [`GnuPGTrust`]: struct.GnuPGTrust.html
+
 use std::fmt;
 use quickcheck::{Arbitrary, Gen};
+use time;
 
+use Error;
+use Result;
+use conversions::Time;
 use packet;
 use Packet;
 
@@ -44,6 +56,154 @@ impl Trust {
     pub fn value(&self) -> &[u8] {
         self.value.as_slice()
     }
+
+    /// Decodes the trust packet's value using GnuPG's layout.
+    ///
+    /// Note: the content of trust packets is implementation defined.
+    /// This is a best-effort decoder for the non-standard "ring
+    /// trust" packets found in GnuPG's legacy keyrings (e.g.,
+    /// `pubring.gpg`), and fails with `Error::MalformedPacket` if
+    /// the value cannot be decoded.
+    pub fn gnupg(&self) -> Result<GnuPGTrust> {
+        GnuPGTrust::from_bytes(&self.value)
+    }
+}
+
+/// GnuPG's ring trust information.
+///
+/// This is not part of the OpenPGP standard.  GnuPG stores trust
+/// packets after keys, User IDs, and signatures in its legacy
+/// keyrings.  The first octet is a trust value, the second caches
+/// the result of verifying a signature.  Newer versions of GnuPG
+/// append a subtype, and, for keys and User IDs, the origin of the
+/// key and when it was last updated.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct GnuPGTrust {
+    trust_value: u8,
+    sig_cache: Option<u8>,
+    subtype: u8,
+    key_origin: Option<u8>,
+    key_update: Option<u32>,
+    url: Option<String>,
+}
+
+impl GnuPGTrust {
+    /// Subtype of a trust packet following a signature.
+    pub const SUBTYPE_SIG: u8 = 0;
+    /// Subtype of a trust packet following a key.
+    pub const SUBTYPE_KEY: u8 = 1;
+    /// Subtype of a trust packet following a User ID.
+    pub const SUBTYPE_UID: u8 = 2;
+
+    fn from_bytes(value: &[u8]) -> Result<Self> {
+        let mut t = GnuPGTrust {
+            trust_value: 0,
+            sig_cache: None,
+            subtype: Self::SUBTYPE_SIG,
+            key_origin: None,
+            key_update: None,
+            url: None,
+        };
+
+        let mut value = match value.split_first() {
+            Some((&trust_value, rest)) => {
+                t.trust_value = trust_value;
+                rest
+            },
+            None => return Err(Error::MalformedPacket(
+                "Trust packet too short".into()).into()),
+        };
+
+        if let Some((&c, rest)) = value.split_first() {
+            // The signature cache is only meaningful if the trust
+            // value is zero, and GnuPG ignores it if bit 7 is set.
+            if t.trust_value == 0 && c & 0x80 == 0 {
+                t.sig_cache = Some(c);
+            }
+            value = rest;
+        }
+
+        // Newer versions of GnuPG append the magic "gpg" followed by
+        // the subtype.  Older versions write nothing more.
+        if value.len() > 3 && &value[..3] == b"gpg" {
+            t.subtype = value[3];
+            value = &value[4..];
+        } else if ! value.is_empty() {
+            return Err(Error::MalformedPacket(
+                "Trust packet has trailing data without magic".into()).into());
+        }
+
+        if t.subtype == Self::SUBTYPE_KEY || t.subtype == Self::SUBTYPE_UID {
+            if value.len() < 6 {
+                return Err(Error::MalformedPacket(
+                    "Trust packet too short".into()).into());
+            }
+
+            t.key_origin = Some(value[0]);
+            t.key_update = Some(((value[1] as u32) << 24)
+                                | ((value[2] as u32) << 16)
+                                | ((value[3] as u32) << 8)
+                                | (value[4] as u32));
+            let url_len = value[5] as usize;
+            let url = &value[6..];
+            if url_len > 0 && url.len() >= url_len {
+                t.url = Some(String::from_utf8_lossy(&url[..url_len])
+                             .into_owned());
+            }
+        }
+
+        Ok(t)
+    }
+
+    /// Returns the trust value.
+    ///
+    /// Current versions of GnuPG keep the owner trust in the trust
+    /// database, and leave this at zero.
+    pub fn trust_value(&self) -> u8 {
+        self.trust_value
+    }
+
+    /// Returns whether GnuPG checked the preceding signature.
+    pub fn sig_checked(&self) -> bool {
+        self.sig_cache.map(|c| c & 1 != 0).unwrap_or(false)
+    }
+
+    /// Returns whether GnuPG found the preceding signature to be
+    /// valid.
+    ///
+    /// This is only meaningful if `sig_checked` returns true.
+    pub fn sig_valid(&self) -> bool {
+        self.sig_cache.map(|c| c & 2 != 0).unwrap_or(false)
+    }
+
+    /// Returns the subtype.
+    ///
+    /// This is one of `SUBTYPE_SIG`, `SUBTYPE_KEY`, and
+    /// `SUBTYPE_UID`.  Trust packets written by older versions of
+    /// GnuPG do not have a subtype, and are reported as
+    /// `SUBTYPE_SIG`.
+    pub fn subtype(&self) -> u8 {
+        self.subtype
+    }
+
+    /// Returns where the key was obtained from, if known.
+    ///
+    /// GnuPG uses 1 for keyservers, 2 for the preferred keyserver,
+    /// 3 for DANE, 4 for WKD, 5 for URLs, 6 for files, and 7 for the
+    /// user's own keys.
+    pub fn key_origin(&self) -> Option<u8> {
+        self.key_origin
+    }
+
+    /// Returns when the key was last updated, if known.
+    pub fn key_update(&self) -> Option<time::Tm> {
+        self.key_update.map(|t| time::Tm::from_pgp(t))
+    }
+
+    /// Returns the URL the key was obtained from, if known.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_ref().map(|u| u.as_str())
+    }
 }
 
 impl From<Trust> for Packet {
@@ -63,6 +223,7 @@ mod tests {
     use super::*;
     use parse::Parse;
     use serialize::SerializeInto;
+    use PacketPile;
 
     quickcheck! {
         fn roundtrip(p: Trust) -> bool {
@@ -71,4 +232,52 @@ mod tests {
             true
         }
     }
+
+    #[test]
+    fn gnupg() {
+        let pile = PacketPile::from_bytes(
+            ::tests::key("testy-ring-trust.gpg")).unwrap();
+        let trust: Vec<GnuPGTrust> = pile.into_children()
+            .filter_map(|p| if let Packet::Trust(t) = p {
+                Some(t.gnupg().unwrap())
+            } else {
+                None
+            })
+            .collect();
+        assert_eq!(trust.len(), 5);
+
+        // Primary key.
+        assert_eq!(trust[0].trust_value(), 0);
+        assert_eq!(trust[0].subtype(), GnuPGTrust::SUBTYPE_KEY);
+        assert_eq!(trust[0].key_origin(), Some(5));
+        assert_eq!(trust[0].key_update(),
+                   Some(time::Tm::from_pgp(0x5c000000)));
+        assert_eq!(trust[0].url(), Some("https://example.org/testy.asc"));
+
+        // User ID.
+        assert_eq!(trust[1].subtype(), GnuPGTrust::SUBTYPE_UID);
+        assert_eq!(trust[1].key_origin(), Some(7));
+        assert_eq!(trust[1].url(), None);
+
+        // Signatures.
+        for t in &[&trust[2], &trust[4]] {
+            assert_eq!(t.subtype(), GnuPGTrust::SUBTYPE_SIG);
+            assert!(t.sig_checked());
+            assert!(t.sig_valid());
+            assert_eq!(t.key_origin(), None);
+            assert_eq!(t.key_update(), None);
+        }
+
+        // Subkey, written in the old format.
+        assert_eq!(trust[3].subtype(), GnuPGTrust::SUBTYPE_SIG);
+        assert!(! trust[3].sig_checked());
+        assert_eq!(trust[3].key_origin(), None);
+
+        // Malformed values.
+        assert!(Trust::from(vec![]).gnupg().is_err());
+        assert!(Trust::from(b"\x00\x00gpg\x01".to_vec()).gnupg().is_err());
+        assert!(Trust::from(b"\x00\x00gp".to_vec()).gnupg().is_err());
+        assert!(Trust::from(b"\x00\x00abc\x01\x05\x5c\x00\x00\x00\x00"
+                            .to_vec()).gnupg().is_err());
+    }
 }
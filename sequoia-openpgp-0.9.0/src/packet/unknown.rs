@@ -10,7 +10,9 @@ use Packet;
 /// This is used by the parser to hold packets that it doesn't know
 /// how to process rather than abort.
 ///
-/// This packet effectively holds a binary blob.
+/// This packet effectively holds a binary blob.  The parser retains
+/// the packet's tag and its complete body, so serializing an
+/// `Unknown` packet reproduces the original packet.
 #[derive(Debug)]
 pub struct Unknown {
     /// CTB packet header fields.
@@ -70,6 +72,16 @@ impl Unknown {
     /// Gets the unknown packet's error.
     ///
     /// This is the error that caused parsing or processing to abort.
+    /// If the parser doesn't know the packet's tag, this is
+    /// [`Error::UnsupportedPacketType`].  If the packet has an
+    /// unknown version, this is [`Error::UnsupportedPacketVersion`].
+    /// If the packet is malformed, this is usually
+    /// [`Error::MalformedPacket`].  Use `downcast_ref` to
+    /// distinguish between them.
+    ///
+    ///   [`Error::UnsupportedPacketType`]: ../../enum.Error.html#variant.UnsupportedPacketType
+    ///   [`Error::UnsupportedPacketVersion`]: ../../enum.Error.html#variant.UnsupportedPacketVersion
+    ///   [`Error::MalformedPacket`]: ../../enum.Error.html#variant.MalformedPacket
     pub fn error(&self) -> &failure::Error {
         &self.error
     }
@@ -81,7 +93,7 @@ impl Unknown {
         ::std::mem::replace(&mut self.error, error)
     }
 
-    /// Sets the packet's contents.
+    /// Gets the packet's contents.
     ///
     /// This is the raw packet content not include the CTB and length
     /// information, and not encoded using something like OpenPGP's
@@ -105,3 +117,48 @@ impl From<Unknown> for Packet {
         Packet::Unknown(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Error;
+    use PacketPile;
+    use parse::Parse;
+    use serialize::SerializeInto;
+
+    #[test]
+    fn round_trip() {
+        // A packet with an unknown tag.
+        let data = ::tests::message("private-tag.pgp");
+        let pile = PacketPile::from_bytes(data).unwrap();
+        let packets = pile.descendants().collect::<Vec<_>>();
+        assert_eq!(packets.len(), 2);
+        if let &Packet::Unknown(ref u) = packets[0] {
+            assert_eq!(u.tag(), Tag::Private(60));
+            assert_eq!(u.body(), Some(&b"hello"[..]));
+            match u.error().downcast_ref::<Error>() {
+                Some(Error::UnsupportedPacketType(Tag::Private(60))) => (),
+                e => panic!("Unexpected error: {:?}", e),
+            }
+        } else {
+            panic!("Expected an Unknown packet, got: {:?}", packets[0]);
+        }
+        assert_eq!(&pile.to_vec().unwrap()[..], data);
+
+        // A signature packet with an unknown version.  The parser
+        // has already consumed the version when it gives up.
+        let data = [0xc2, 0x06, 0x09, 0x00, 0x01, 0x02, 0x03, 0x04];
+        let pile = PacketPile::from_bytes(&data[..]).unwrap();
+        if let Some(&Packet::Unknown(ref u)) = pile.path_ref(&[0]) {
+            assert_eq!(u.tag(), Tag::Signature);
+            assert_eq!(u.body(), Some(&data[2..]));
+            match u.error().downcast_ref::<Error>() {
+                Some(Error::UnsupportedPacketVersion(Tag::Signature, 9)) => (),
+                e => panic!("Unexpected error: {:?}", e),
+            }
+        } else {
+            panic!("Expected an Unknown packet, got: {:?}", pile);
+        }
+        assert_eq!(&pile.to_vec().unwrap()[..], &data[..]);
+    }
+}
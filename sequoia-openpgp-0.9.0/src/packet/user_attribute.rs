@@ -15,6 +15,7 @@ use Result;
 use packet::{
     self,
     BodyLength,
+    signature::subpacket::{SubpacketLength, SubpacketLengthTrait},
 };
 use Packet;
 use serialize::Serialize;
@@ -85,11 +86,44 @@ impl UserAttribute {
     }
 
     /// Iterates over the subpackets.
+    ///
+    /// Subpackets of unknown types are returned as
+    /// `Subpacket::Unknown`.
     pub fn subpackets(&self) -> SubpacketIterator {
         SubpacketIterator {
             reader: buffered_reader::Memory::new(&self.value[..]),
         }
     }
+
+    /// Returns the first JPEG image, if any.
+    ///
+    /// This returns the raw image data without the image header,
+    /// i.e., a JPEG file that can be displayed as is.  Malformed
+    /// image subpackets and images in other formats are skipped.
+    /// Unlike [`subpackets()`], this doesn't copy the data.
+    ///
+    /// [`subpackets()`]: #method.subpackets
+    pub fn image(&self) -> Option<&[u8]> {
+        let mut reader = buffered_reader::Memory::new(&self.value[..]);
+        loop {
+            let length = SubpacketLength::parse(&mut reader).ok()? as usize;
+            let start = self.value.len() - reader.buffer().len();
+            if length > reader.buffer().len() {
+                return None;
+            }
+            reader.consume(length);
+            let raw = &self.value[start..start + length];
+
+            // Type octet, image header (length, version, format),
+            // and reserved octets.
+            if raw.len() >= 17 && raw[0] == 1
+                && &raw[1..5] == &[0x10, 0x00, 0x01, 0x01]
+                && raw[5..17].iter().all(|b| *b == 0)
+            {
+                return Some(&raw[17..]);
+            }
+        }
+    }
 }
 
 impl From<UserAttribute> for Packet {
@@ -115,15 +149,21 @@ pub struct SubpacketIterator<'a> {
 impl<'a> Iterator for SubpacketIterator<'a> {
     type Item = Result<Subpacket>;
     fn next(&mut self) -> Option<Self::Item> {
-        let length = match BodyLength::parse_new_format(&mut self.reader) {
-            Ok(BodyLength::Full(l)) => l,
-            Ok(BodyLength::Partial(_)) | Ok(BodyLength::Indeterminate) =>
-                return Some(Err(Error::MalformedPacket(
-                    "Partial or Indeterminate length of subpacket".into())
-                                .into())),
+        match self.reader.data(1) {
+            Ok(ref d) if d.is_empty() => return None,
+            Ok(_) => (),
+            Err(e) => return Some(Err(e.into())),
+        }
+
+        // User attribute subpackets use the same length encoding as
+        // signature subpackets, which, unlike the new format packet
+        // length, has no partial body lengths.
+        let length = match SubpacketLength::parse(&mut self.reader) {
+            Ok(l) => l,
             Err(e) =>
                 if e.kind() == ::std::io::ErrorKind::UnexpectedEof {
-                    return None;
+                    return Some(Err(Error::MalformedPacket(
+                        "Truncated subpacket length".into()).into()));
                 } else {
                     return Some(Err(e.into()));
                 },
@@ -131,7 +171,13 @@ impl<'a> Iterator for SubpacketIterator<'a> {
 
         let raw = match self.reader.data_consume_hard(length as usize) {
             Ok(r) => &r[..length as usize],
-            Err(e) => return Some(Err(e.into())),
+            Err(e) =>
+                if e.kind() == ::std::io::ErrorKind::UnexpectedEof {
+                    return Some(Err(Error::MalformedPacket(
+                        "Truncated subpacket".into()).into()));
+                } else {
+                    return Some(Err(e.into()));
+                },
         };
 
         if raw.len() == 0 {
@@ -286,4 +332,102 @@ ABQQAQAAAAAAAAAAAAAAAAAAAAD/2gAIAQEAAT8Qf//Z
             panic!("Expected JPEG, got {:?}", &subpackets[0]);
         }
     }
+
+    #[test]
+    fn image_accessor() {
+        use TPK;
+
+        let tpk = TPK::from_bytes(::tests::key("dkg.gpg")).unwrap();
+        let ua = tpk.user_attributes().next().unwrap().user_attribute();
+        let img = ua.image().unwrap();
+        assert_eq!(img.len(), 3515);
+        // JPEG's start of image marker.
+        assert_eq!(&img[..2], &[0xff, 0xd8]);
+
+        match ua.subpackets().next() {
+            Some(Ok(Subpacket::Image(Image::JPEG(ref jpeg)))) =>
+                assert_eq!(&jpeg[..], img),
+            s => panic!("Expected JPEG, got {:?}", s),
+        }
+
+        // Unknown subpackets and other image formats are skipped.
+        fn image(format: u8, data: &[u8]) -> Vec<u8> {
+            let mut s = vec![17 + data.len() as u8, 1, 0x10, 0x00, 0x01,
+                             format];
+            s.extend_from_slice(&[0; 12]);
+            s.extend_from_slice(data);
+            s
+        }
+
+        let mut value = vec![4, 7, 1, 2, 3]; // Unknown subpacket.
+        value.extend_from_slice(&image(100, &[4, 5, 6]));
+        value.extend_from_slice(&image(1, &[0xff, 0xd8, 0xff]));
+        let ua = UserAttribute::from(value);
+        assert_eq!(ua.image(), Some(&[0xff, 0xd8, 0xff][..]));
+        let subpackets = ua.subpackets().collect::<Vec<_>>();
+        assert_eq!(subpackets.len(), 3);
+        match subpackets[0] {
+            Ok(Subpacket::Unknown(7, ref body)) =>
+                assert_eq!(&body[..], &[1, 2, 3]),
+            ref s => panic!("Expected an unknown subpacket, got {:?}", s),
+        }
+        match subpackets[1] {
+            Ok(Subpacket::Image(Image::Private(100, _))) => (),
+            ref s => panic!("Expected a private image, got {:?}", s),
+        }
+
+        let ua = UserAttribute::from(vec![4, 7, 1, 2, 3]);
+        assert_eq!(ua.image(), None);
+        // Truncated.
+        let ua = UserAttribute::from(image(1, &[0xff, 0xd8])[..10].to_vec());
+        assert_eq!(ua.image(), None);
+    }
+
+    #[test]
+    fn subpacket_lengths() {
+        fn image(length: &[u8], data: &[u8]) -> Vec<u8> {
+            let mut ua = length.to_vec();
+            ua.push(1); // Image.
+            ua.extend_from_slice(&[0x10, 0x00, 0x01, 0x01]);
+            ua.extend_from_slice(&[0; 12]);
+            ua.extend_from_slice(data);
+            ua
+        }
+
+        fn check(ua: Vec<u8>, data: &[u8]) {
+            let ua = UserAttribute::from(ua);
+            let subpackets = ua.subpackets().collect::<Vec<_>>();
+            assert_eq!(subpackets.len(), 1);
+            if let Ok(Subpacket::Image(Image::JPEG(ref img))) = subpackets[0] {
+                assert_eq!(&img[..], data);
+            } else {
+                panic!("Expected JPEG, got {:?}", &subpackets[0]);
+            }
+        }
+
+        // One octet length.
+        let data = vec![0xaa; 100];
+        check(image(&[117], &data), &data);
+
+        // Two octet lengths, including those with a first octet that
+        // would denote a partial body length in a packet header.
+        let data = vec![0xaa; 1000];
+        check(image(&[195, 57], &data), &data);
+        let data = vec![0xaa; 9000];
+        check(image(&[226, 121], &data), &data);
+
+        // Five octet length.
+        let data = vec![0xaa; 70000];
+        check(image(&[0xff, 0x00, 0x01, 0x11, 0x81], &data), &data);
+
+        // Truncated length.
+        let ua = UserAttribute::from(vec![0xff, 0x00, 0x01]);
+        let subpackets = ua.subpackets().collect::<Vec<_>>();
+        assert_eq!(subpackets.len(), 1);
+        match subpackets[0] {
+            Err(ref e) => assert_match!(
+                Some(&Error::MalformedPacket(_)) = e.downcast_ref::<Error>()),
+            Ok(ref s) => panic!("Expected an error, got {:?}", s),
+        }
+    }
 }
@@ -12,6 +12,7 @@ use rfc2822::{
 };
 use failure::ResultExt;
 
+use Error;
 use Result;
 use packet;
 use Packet;
@@ -284,6 +285,88 @@ impl UserID {
         Ok(combined.into())
     }
 
+    /// Constructs a User ID from its components.
+    ///
+    /// This assembles the conventional `Name (Comment) <email>` form
+    /// and omits the missing components.  At least one component
+    /// must be given, and none may be empty.
+    ///
+    /// Unlike [`UserID::from_address`], this does not quote or
+    /// escape anything.  Instead, it rejects components that contain
+    /// angle brackets or parentheses, and email addresses that are
+    /// not valid according to RFC 2822.  If an email address is
+    /// given, it also checks that [`UserID::name`],
+    /// [`UserID::comment`], and [`UserID::address`] return the
+    /// components unchanged.
+    ///
+    /// For a bare email address, `UserID::from_address(None, None,
+    /// email)` is equivalent.
+    ///
+    ///   [`UserID::from_address`]: #method.from_address
+    ///   [`UserID::name`]: #method.name
+    ///   [`UserID::comment`]: #method.comment
+    ///   [`UserID::address`]: #method.address
+    ///
+    /// ```
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::packet::UserID;
+    /// assert_eq!(UserID::from_parts(Some("Henry Ford"), Some("CEO"),
+    ///                               Some("henry@ford.com")).unwrap().value(),
+    ///            &b"Henry Ford (CEO) <henry@ford.com>"[..]);
+    /// assert!(UserID::from_parts(Some("Henry <Ford>"), None,
+    ///                            Some("henry@ford.com")).is_err());
+    /// ```
+    pub fn from_parts(name: Option<&str>, comment: Option<&str>,
+                      email: Option<&str>)
+        -> Result<Self>
+    {
+        for (what, component) in [("name", name), ("comment", comment),
+                                  ("email address", email)].iter() {
+            match component {
+                Some(c) if c.is_empty() =>
+                    return Err(Error::InvalidArgument(
+                        format!("Empty {}", what)).into()),
+                Some(c) if c.contains(|c| "<>()".contains(c)) =>
+                    return Err(Error::InvalidArgument(
+                        format!("Invalid {}: {:?}", what, c)).into()),
+                _ => (),
+            }
+        }
+
+        let mut parts = Vec::new();
+        if let Some(name) = name {
+            parts.push(name.to_string());
+        }
+        if let Some(comment) = comment {
+            parts.push(format!("({})", comment));
+        }
+        if let Some(email) = email {
+            AddrSpec::parse(email)
+                .context(format!("Invalid address: {:?}", email))?;
+            parts.push(format!("<{}>", email));
+        }
+        if parts.is_empty() {
+            return Err(Error::InvalidArgument(
+                "At least one component is required".into()).into());
+        }
+
+        let userid = UserID::from(parts.join(" "));
+
+        // Without an email address, the User ID is not a name-addr,
+        // and the parsing accessors return nothing useful.
+        if email.is_some() {
+            if userid.name()?.as_ref().map(|s| &s[..]) != name
+                || userid.comment()?.as_ref().map(|s| &s[..]) != comment
+                || userid.address()?.as_ref().map(|s| &s[..]) != email
+            {
+                return Err(Error::InvalidArgument(
+                    format!("{:?} does not round-trip", userid)).into());
+            }
+        }
+
+        Ok(userid)
+    }
+
     /// Gets the user ID packet's value.
     pub fn value(&self) -> &[u8] {
         self.value.as_slice()
@@ -460,6 +543,31 @@ impl UserID {
             }
         }
     }
+
+    /// Returns the normalized email address, if any.
+    ///
+    /// This is a convenience function for matching User IDs against
+    /// recipients.  It is like [`UserID::address_normalized`], but
+    /// returns `None` instead of an error if the User ID is not
+    /// valid UTF-8 or can't be parsed, or if the address is missing
+    /// or is not a valid email address (e.g., a URI).
+    ///
+    /// Both the conventional `Name (Comment) <email>` form and bare
+    /// email addresses are recognized.
+    ///
+    ///   [`UserID::address_normalized`]: #method.address_normalized
+    ///
+    /// ```
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::packet::UserID;
+    /// assert_eq!(UserID::from("Henry Ford (CEO) <Henry@Ford.com>").email(),
+    ///            Some("henry@ford.com".into()));
+    /// assert_eq!(UserID::from("NAS <ssh://nas.example.org>").email(),
+    ///            None);
+    /// ```
+    pub fn email(&self) -> Option<String> {
+        self.address_normalized().unwrap_or(None)
+    }
 }
 
 impl From<UserID> for Packet {
@@ -611,6 +719,90 @@ mod tests {
         c("hANS@bücher.tld", "hans@xn--bcher-kva.tld");
     }
 
+    #[test]
+    fn email() {
+        fn c(value: &[u8], expected: Option<&str>) {
+            let u = UserID::from(value);
+            assert_eq!(u.email(), expected.map(|s| s.to_string()),
+                       "{:?}", u);
+        }
+
+        c(b"Henry Ford (CEO) <henry@ford.com>", Some("henry@ford.com"));
+        c(b"Aldous L. Huxley <Huxley@Old-World.org>",
+          Some("huxley@old-world.org"));
+        // Bare email address.
+        c(b"Huxley@Old-World.org", Some("huxley@old-world.org"));
+        c(b"<huxley@old-world.org>", Some("huxley@old-world.org"));
+        c(b"hans@b\xc3\xbccher.tld", Some("hans@xn--bcher-kva.tld"));
+        // Parentheses in a quoted display name are not a comment.
+        c(b"\"Smith (Jr.), John\" <john@example.org>",
+          Some("john@example.org"));
+        c(b"\"<loki@bar.com>\" <foo@bar.com>", Some("foo@bar.com"));
+
+        // No email address.
+        c(b"Henry Ford", None);
+        c(b"NAS <ssh://nas.example.org>", None);
+        c(b"<huxley@@old-world.org>", None);
+        c(b"", None);
+        // Invalid UTF-8.
+        c(b"Henry Ford <henry@ford.com\xff>", None);
+
+        let u = UserID::from("\"Smith (Jr.), John\" <john@example.org>");
+        assert_eq!(u.name().unwrap(), Some("Smith (Jr.), John".into()));
+        assert_eq!(u.comment().unwrap(), None);
+    }
+
+    #[test]
+    fn from_parts() {
+        fn c(name: Option<&str>, comment: Option<&str>, email: Option<&str>,
+             expected: &str) {
+            let u = UserID::from_parts(name, comment, email).unwrap();
+            assert_eq!(u.value(), expected.as_bytes());
+
+            // Round trip.
+            let u = UserID::from(expected);
+            if email.is_some() {
+                assert_eq!(u.name().unwrap().as_ref().map(|s| &s[..]), name);
+                assert_eq!(u.comment().unwrap().as_ref().map(|s| &s[..]),
+                           comment);
+                assert_eq!(u.address().unwrap().as_ref().map(|s| &s[..]),
+                           email);
+                assert_eq!(u.email(), email.map(|s| s.to_lowercase()));
+            }
+        }
+
+        c(Some("Henry Ford"), Some("CEO"), Some("henry@ford.com"),
+          "Henry Ford (CEO) <henry@ford.com>");
+        c(Some("Aldous L. Huxley"), None, Some("huxley@old-world.org"),
+          "Aldous L. Huxley <huxley@old-world.org>");
+        c(None, Some("work"), Some("huxley@old-world.org"),
+          "(work) <huxley@old-world.org>");
+        c(None, None, Some("huxley@old-world.org"),
+          "<huxley@old-world.org>");
+        c(Some("Henry Ford"), None, None, "Henry Ford");
+        c(Some("Henry Ford"), Some("CEO"), None, "Henry Ford (CEO)");
+
+        // Invalid components.
+        assert!(UserID::from_parts(None, None, None).is_err());
+        assert!(UserID::from_parts(Some(""), None, Some("foo@bar.com"))
+                .is_err());
+        assert!(UserID::from_parts(Some("Foo <Bar>"), None,
+                                   Some("foo@bar.com")).is_err());
+        assert!(UserID::from_parts(Some("Foo (Bar)"), None,
+                                   Some("foo@bar.com")).is_err());
+        assert!(UserID::from_parts(Some("Foo"), Some("(Bar)"),
+                                   Some("foo@bar.com")).is_err());
+        assert!(UserID::from_parts(Some("Foo"), None,
+                                   Some("foo@@bar.com")).is_err());
+        assert!(UserID::from_parts(Some("Foo"), None,
+                                   Some("<foo@bar.com>")).is_err());
+        assert!(UserID::from_parts(Some("Foo"), None,
+                                   Some("ssh://bar.com")).is_err());
+        // Would not round-trip: the quotes disappear.
+        assert!(UserID::from_parts(Some("Thomas \"Tomakin\""), None,
+                                   Some("thomas@clh.co.uk")).is_err());
+    }
+
     #[test]
     fn from_address() {
         assert_eq!(UserID::from_address(None, None, "foo@bar.com")
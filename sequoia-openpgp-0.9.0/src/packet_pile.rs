@@ -8,13 +8,19 @@ use buffered_reader::BufferedReader;
 
 use Result;
 use Error;
+use armor;
 use Packet;
 use packet::{Container, PacketIter};
 use PacketPile;
+use PacketPileStructure;
 use parse::PacketParserResult;
 use parse::PacketParserBuilder;
 use parse::Parse;
 use parse::Cookie;
+use serialize::Serialize;
+use packet::Tag;
+use message::{MessageValidator, Token};
+use tpk::{KeyringValidator, TPKValidator};
 
 
 impl fmt::Debug for PacketPile {
@@ -62,6 +68,17 @@ impl<'a> Parse<'a, PacketPile> for PacketPile {
             data, Cookie::default());
         PacketPile::from_buffered_reader(Box::new(bio))
     }
+
+    /// Deserializes the OpenPGP message stored in a `BufferedReader`
+    /// object.
+    ///
+    /// See `from_reader` for more details and caveats.
+    fn from_buffered_reader(bio: Box<'a + BufferedReader<Cookie>>)
+            -> Result<PacketPile> {
+        PacketParserBuilder::from_buffered_reader(bio)?
+            .buffer_unread_content()
+            .into_packet_pile()
+    }
 }
 
 impl From<Vec<Packet>> for PacketPile {
@@ -77,6 +94,91 @@ impl From<Packet> for PacketPile {
 }
 
 impl PacketPile {
+    /// Classifies the packet sequence.
+    ///
+    /// This checks the top-level packets against the grammars of
+    /// OpenPGP messages, TPKs and keyrings, and returns what kind of
+    /// data the packet pile holds.  This makes it easy to reject
+    /// unexpected input, e.g., to refuse to decrypt a detached
+    /// signature.
+    ///
+    /// Only the top-level packets are considered.  For instance, a
+    /// compressed message is `PacketPileStructure::Compressed` even if
+    /// the compressed data packet contains a signed message.  If you
+    /// need to validate the whole message, use
+    /// [`Message::from_packet_pile`].
+    ///
+    ///   [`Message::from_packet_pile`]: struct.Message.html#method.from_packet_pile
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::{Result, PacketPile, PacketPileStructure};
+    /// # use openpgp::parse::Parse;
+    /// # f(include_bytes!("../tests/data/messages/sig.gpg"));
+    /// #
+    /// # fn f(data: &[u8]) -> Result<()> {
+    /// let pile = PacketPile::from_bytes(data)?;
+    /// if pile.structure() == PacketPileStructure::DetachedSignature {
+    ///     // There is nothing to decrypt.
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn structure(&self) -> PacketPileStructure {
+        let tags = self.children().map(|p| p.tag()).collect::<Vec<_>>();
+        if tags.is_empty() {
+            return PacketPileStructure::Unknown;
+        }
+
+        let mut tpk = TPKValidator::new();
+        let mut keyring = KeyringValidator::new();
+        for &tag in tags.iter() {
+            tpk.push(tag);
+            keyring.push(tag);
+        }
+        tpk.finish();
+        keyring.finish();
+        if tpk.is_tpk() {
+            return PacketPileStructure::Certificate;
+        }
+        if keyring.is_keyring() {
+            return PacketPileStructure::Keyring;
+        }
+
+        if tags.iter().all(|&tag| tag == Tag::Signature) {
+            return PacketPileStructure::DetachedSignature;
+        }
+
+        // Check the message grammar.  We don't look into the
+        // containers, but treat their content as opaque.
+        let mut message = MessageValidator::new();
+        for (i, &tag) in tags.iter().enumerate() {
+            message.push(tag, &[i]);
+            match tag {
+                Tag::CompressedData | Tag::SEIP | Tag::AED =>
+                    message.push_token(Token::OpaqueContent, &[i, 0]),
+                _ => (),
+            }
+        }
+        message.finish();
+        if ! message.is_message() {
+            return PacketPileStructure::Unknown;
+        }
+
+        match tags.iter().find(|&&tag| tag != Tag::Marker) {
+            Some(Tag::PKESK) | Some(Tag::SKESK) | Some(Tag::SEIP)
+                | Some(Tag::AED) =>
+                PacketPileStructure::Encrypted,
+            Some(Tag::OnePassSig) | Some(Tag::Signature) =>
+                PacketPileStructure::Signed,
+            Some(Tag::CompressedData) => PacketPileStructure::Compressed,
+            Some(Tag::Literal) => PacketPileStructure::Literal,
+            _ => PacketPileStructure::Unknown,
+        }
+    }
+
     /// Pretty prints the message to stderr.
     ///
     /// This function is primarily intended for debugging purposes.
@@ -271,12 +373,25 @@ impl PacketPile {
         self.top_level.into_children()
     }
 
-
-    pub(crate) fn from_buffered_reader<'a>(bio: Box<'a + BufferedReader<Cookie>>)
-            -> Result<PacketPile> {
-        PacketParserBuilder::from_buffered_reader(bio)?
-            .buffer_unread_content()
-            .into_packet_pile()
+    /// Writes an ASCII armored version of the `PacketPile` to `o`.
+    ///
+    /// Before anything is written, the top-level packets are checked
+    /// against the declared `kind`.  For instance, key packets may
+    /// not be armored as `Kind::Message`, and a `Kind::PublicKey`
+    /// block must start with a public key packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidArgument` if the packets do not match
+    /// `kind`.
+    pub fn serialize_armored(&self, o: &mut dyn io::Write, kind: armor::Kind)
+                             -> Result<()> {
+        kind.check_packets(self.children())?;
+
+        let mut w = armor::Writer::new(o, kind, &[])?;
+        self.serialize(&mut w)?;
+        w.finalize()?;
+        Ok(())
     }
 
     /// Reads all of the packets from a `PacketParser`, and turns them
@@ -418,6 +533,27 @@ mod test {
     use packet::Tag;
     use parse::{Parse, PacketParser};
 
+    #[test]
+    fn serialize_armored_kind_mismatch() {
+        let pile = PacketPile::from_bytes(::tests::key("public-key.gpg"))
+            .unwrap();
+
+        // Key packets are not a message.
+        let mut buf = Vec::new();
+        assert!(pile.serialize_armored(&mut buf, armor::Kind::Message)
+                .is_err());
+        assert!(pile.serialize_armored(&mut buf, armor::Kind::Signature)
+                .is_err());
+        assert!(pile.serialize_armored(&mut buf, armor::Kind::SecretKey)
+                .is_err());
+        // The guard fires before anything is written.
+        assert!(buf.is_empty());
+
+        pile.serialize_armored(&mut buf, armor::Kind::PublicKey).unwrap();
+        let pile2 = PacketPile::from_bytes(&buf).unwrap();
+        assert_eq!(pile, pile2);
+    }
+
     #[test]
     fn deserialize_test_1 () {
         // XXX: This test should be more thorough.  Right now, we mostly
@@ -853,4 +989,46 @@ mod test {
         assert!(pile.replace(&[ 0, 0 ], 0, Vec::new()).is_ok());
         assert!(pile.replace(&[ 0, 1 ], 0, Vec::new()).is_err());
     }
+
+    #[test]
+    fn from_buffered_reader() {
+        use TPK;
+        use parse::Cookie;
+
+        let data = ::tests::key("testy.pgp");
+
+        let bio = buffered_reader::Memory::with_cookie(
+            data, Cookie::default());
+        let pile = PacketPile::from_buffered_reader(Box::new(bio)).unwrap();
+        assert_eq!(pile, PacketPile::from_bytes(data).unwrap());
+
+        let bio = buffered_reader::Generic::with_cookie(
+            data, None, Cookie::default());
+        let tpk = TPK::from_buffered_reader(Box::new(bio)).unwrap();
+        assert_eq!(tpk, TPK::from_bytes(data).unwrap());
+    }
+
+    #[test]
+    fn structure() {
+        let structure = |data: &[u8]| {
+            PacketPile::from_bytes(data).unwrap().structure()
+        };
+
+        assert_eq!(structure(::tests::message("sig.gpg")),
+                   PacketPileStructure::DetachedSignature);
+        assert_eq!(structure(::tests::message("encrypted-to-testy.gpg")),
+                   PacketPileStructure::Encrypted);
+        assert_eq!(structure(::tests::message("signed-1.gpg")),
+                   PacketPileStructure::Signed);
+        assert_eq!(structure(::tests::message("compressed-data-algo-1.gpg")),
+                   PacketPileStructure::Compressed);
+        assert_eq!(structure(::tests::message("literal-mode-b.gpg")),
+                   PacketPileStructure::Literal);
+        assert_eq!(structure(::tests::key("testy.pgp")),
+                   PacketPileStructure::Certificate);
+        assert_eq!(structure(::tests::key("keyring.pgp")),
+                   PacketPileStructure::Keyring);
+        assert_eq!(structure(::tests::message("userid-bare.gpg")),
+                   PacketPileStructure::Unknown);
+    }
 }
@@ -0,0 +1,321 @@
+use std::io::Read;
+use std::str;
+
+use {
+    Error,
+    HashAlgorithm,
+    Packet,
+    PacketPile,
+    Result,
+};
+use armor::{self, BEGIN_SIGNED_MESSAGE, trim_trailing_whitespace};
+use packet::Signature;
+use parse::Parse;
+
+/// The armor header line that terminates the cleartext.
+const BEGIN_SIGNATURE: &[u8] = b"-----BEGIN PGP SIGNATURE-----";
+
+/// A message using the Cleartext Signature Framework.
+///
+/// A cleartext signed message consists of the signed text, which is
+/// readable without any OpenPGP software, followed by an armored
+/// signature block.  See [Section 7 of RFC 4880] for details.  Use
+/// [`ClearSigner`] to create such messages.
+///
+///   [Section 7 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-7
+///   [`ClearSigner`]: ../serialize/stream/struct.ClearSigner.html
+///
+/// # Example
+///
+/// ```
+/// # extern crate sequoia_openpgp as openpgp;
+/// # use openpgp::Result;
+/// use openpgp::parse::{Parse, ClearSigned};
+/// # f().unwrap();
+/// # fn f() -> Result<()> {
+/// # let tpk = openpgp::TPK::from_bytes(include_bytes!(
+/// #     "../../tests/data/keys/emmelie-dorothea-dina-samantha-awina-ed25519.pgp"))?;
+/// # let key = tpk.keys_all().signing_capable().nth(0).unwrap().2;
+/// let message = ClearSigned::from_bytes(include_bytes!(
+///     "../../tests/data/messages/a-cypherpunks-manifesto.txt.ed25519.clearsig"))?;
+///
+/// for sig in message.signatures() {
+///     assert!(sig.verify_message(key, message.text())?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClearSigned {
+    hash_algos: Vec<HashAlgorithm>,
+    text: Vec<u8>,
+    signatures: Vec<Signature>,
+}
+
+impl<'a> Parse<'a, ClearSigned> for ClearSigned {
+    /// Reads a cleartext signed message from the given reader.
+    ///
+    /// Any text in front of the `-----BEGIN PGP SIGNED MESSAGE-----`
+    /// line is ignored.  Note: this buffers the whole message in
+    /// memory.
+    fn from_reader<R: 'a + Read>(mut reader: R) -> Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        ClearSigned::parse(&data)
+    }
+}
+
+impl ClearSigned {
+    /// Returns the hash algorithms announced in the `Hash` armor
+    /// headers.
+    ///
+    /// If there is no `Hash` header, MD5 is assumed, as required by
+    /// the standard.  Every signature uses one of these algorithms.
+    pub fn hash_algos(&self) -> &[HashAlgorithm] {
+        &self.hash_algos
+    }
+
+    /// Returns the signed text.
+    ///
+    /// The dash-escaping is removed.  This is the text that the
+    /// signatures are computed over: trailing whitespace is stripped
+    /// from every line, and lines are separated by CRLF.  The line
+    /// ending preceding the signature block is not part of the text.
+    pub fn text(&self) -> &[u8] {
+        &self.text
+    }
+
+    /// Returns the signatures.
+    pub fn signatures(&self) -> &[Signature] {
+        &self.signatures
+    }
+
+    fn parse(data: &[u8]) -> Result<Self> {
+        let mut lines = Lines::new(data);
+
+        // Skip anything in front of the header line.
+        loop {
+            match lines.next() {
+                Some(line)
+                    if trim_trailing_whitespace(line) == BEGIN_SIGNED_MESSAGE
+                    => break,
+                Some(_) => (),
+                None => return Err(Error::MalformedMessage(
+                    "Missing cleartext signature header line".into())
+                                   .into()),
+            }
+        }
+
+        // The armor headers are terminated by an empty line.
+        let mut hash_algos = Vec::new();
+        loop {
+            let line = match lines.next() {
+                Some(line) => trim_trailing_whitespace(line),
+                None => return Err(Error::MalformedMessage(
+                    "Truncated armor headers".into()).into()),
+            };
+            if line.is_empty() {
+                break;
+            }
+
+            let line = str::from_utf8(line).map_err(|_| {
+                Error::MalformedMessage("Invalid armor header".into())
+            })?;
+            let mut kv = line.splitn(2, ':');
+            let key = kv.next().unwrap_or("");
+            let value = kv.next();
+            match (key, value) {
+                ("Hash", Some(value)) =>
+                    for name in value.split(',') {
+                        let name = name.trim();
+                        let algo = HashAlgorithm::from_name(name).map_err(|_| {
+                            Error::MalformedMessage(
+                                format!("Unknown hash algorithm: {}", name))
+                        })?;
+                        hash_algos.push(algo);
+                    },
+                _ => return Err(Error::MalformedMessage(
+                    format!("Unexpected armor header: {}", line)).into()),
+            }
+        }
+        if hash_algos.is_empty() {
+            hash_algos.push(HashAlgorithm::MD5);
+        }
+
+        // The text is terminated by the signature's header line.
+        let mut text = Vec::new();
+        let mut first = true;
+        let signature_block = loop {
+            let offset = lines.offset();
+            let line = match lines.next() {
+                Some(line) => line,
+                None => return Err(Error::MalformedMessage(
+                    "Missing signature block".into()).into()),
+            };
+            if line.starts_with(BEGIN_SIGNATURE) {
+                break &data[offset..];
+            }
+
+            let line = if line.starts_with(b"- ") {
+                &line[2..]
+            } else {
+                line
+            };
+
+            if ! first {
+                text.extend_from_slice(b"\r\n");
+            }
+            first = false;
+            text.extend_from_slice(trim_trailing_whitespace(line));
+        };
+
+        let mut reader = armor::Reader::from_bytes(
+            signature_block,
+            armor::ReaderMode::Tolerant(Some(armor::Kind::Signature)));
+        let mut signature_block = Vec::new();
+        reader.read_to_end(&mut signature_block)?;
+
+        let mut signatures = Vec::new();
+        for packet in PacketPile::from_bytes(&signature_block)?
+            .into_children()
+        {
+            match packet {
+                Packet::Signature(sig) => signatures.push(sig),
+                p => return Err(Error::MalformedMessage(
+                    format!("Unexpected packet in signature block: {}",
+                            p.tag())).into()),
+            }
+        }
+        if signatures.is_empty() {
+            return Err(Error::MalformedMessage(
+                "No signatures in signature block".into()).into());
+        }
+
+        // The Hash headers must announce the hash algorithms used by
+        // the signatures.
+        for sig in signatures.iter() {
+            if ! hash_algos.contains(&sig.hash_algo()) {
+                return Err(Error::MalformedMessage(
+                    format!("Signature uses hash algorithm {}, which is \
+                             not announced in a Hash header",
+                            sig.hash_algo())).into());
+            }
+        }
+
+        Ok(ClearSigned {
+            hash_algos: hash_algos,
+            text: text,
+            signatures: signatures,
+        })
+    }
+}
+
+/// Splits data into lines, stripping the line endings.
+struct Lines<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Lines<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Lines {
+            data: data,
+            offset: 0,
+        }
+    }
+
+    /// Returns the offset of the next line.
+    fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset == self.data.len() {
+            return None;
+        }
+
+        let rest = &self.data[self.offset..];
+        let (mut line, len) = match rest.iter().position(|&c| c == b'\n') {
+            Some(i) => (&rest[..i], i + 1),
+            None => (rest, rest.len()),
+        };
+        self.offset += len;
+
+        if line.last() == Some(&b'\r') {
+            line = &line[..line.len() - 1];
+        }
+        Some(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TPK;
+
+    #[test]
+    fn gnupg_interop() {
+        let tpk = TPK::from_bytes(::tests::key(
+            "emmelie-dorothea-dina-samantha-awina-ed25519.pgp")).unwrap();
+        let key = tpk.keys_all().signing_capable().nth(0).unwrap().2;
+
+        let message = ClearSigned::from_bytes(::tests::message(
+            "a-cypherpunks-manifesto.txt.ed25519.clearsig")).unwrap();
+        assert_eq!(message.hash_algos(), &[HashAlgorithm::SHA256]);
+
+        // The manifesto ends in a newline, and one line has trailing
+        // whitespace.  Neither is signed.
+        let manifesto = ::tests::manifesto();
+        let lines = manifesto[..manifesto.len() - 1].split(|&c| c == b'\n')
+            .map(|l| trim_trailing_whitespace(l))
+            .collect::<Vec<_>>();
+        assert_eq!(message.text(), &lines.join(&b"\r\n"[..])[..]);
+
+        assert_eq!(message.signatures().len(), 1);
+        let sig = &message.signatures()[0];
+        assert_eq!(sig.sigtype(), ::constants::SignatureType::Text);
+        assert!(sig.verify_message(key, message.text()).unwrap());
+    }
+
+    #[test]
+    fn malformed() {
+        let sig = ::tests::message(
+            "a-cypherpunks-manifesto.txt.ed25519.clearsig");
+        let n = sig.iter().position(|&c| c == b'\n').unwrap() + 1;
+
+        // Unknown armor headers are rejected.
+        let mut m = sig[..n].to_vec();
+        m.extend_from_slice(b"Comment: Not allowed here\n");
+        m.extend_from_slice(&sig[n..]);
+        assert!(ClearSigned::from_bytes(&m).is_err());
+
+        // The hash algorithm must be known.
+        let mut m = sig[..n].to_vec();
+        m.extend_from_slice(b"Hash: SHA9000\n");
+        m.extend_from_slice(&sig[n..]);
+        assert!(ClearSigned::from_bytes(&m).is_err());
+
+        // The signature's hash algorithm must be announced.
+        let m = String::from_utf8(sig.to_vec()).unwrap()
+            .replace("Hash: SHA256", "Hash: SHA512");
+        assert!(ClearSigned::from_bytes(m.as_bytes()).is_err());
+        let m = String::from_utf8(sig.to_vec()).unwrap()
+            .replace("Hash: SHA256\n", "");
+        assert!(ClearSigned::from_bytes(m.as_bytes()).is_err());
+        let m = String::from_utf8(sig.to_vec()).unwrap()
+            .replace("Hash: SHA256", "Hash: SHA512, SHA256");
+        assert!(ClearSigned::from_bytes(m.as_bytes()).is_ok());
+
+        // The signature block is mandatory.
+        let end = sig.windows(BEGIN_SIGNATURE.len())
+            .position(|w| w == BEGIN_SIGNATURE).unwrap();
+        assert!(ClearSigned::from_bytes(&sig[..end]).is_err());
+
+        // As is the header.
+        assert!(ClearSigned::from_bytes(&sig[n..]).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use std::io;
+use std::cmp;
+use std::mem;
+use std::fmt;
+use std::rc::Rc;
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use failure;
+
+use buffered_reader::BufferedReader;
+use buffered_reader::buffered_reader_generic_read_impl;
+
+use Error;
+use parse::Cookie;
+
+/// A shared counter.
+pub(crate) trait Counter {
+    /// Adds `amount` to the counter.
+    fn add(&self, amount: usize);
+
+    /// Returns the counter's value.
+    fn get(&self) -> u64;
+}
+
+impl Counter for Rc<Cell<u64>> {
+    fn add(&self, amount: usize) {
+        self.set(Cell::get(self) + amount as u64);
+    }
+
+    fn get(&self) -> u64 {
+        Cell::get(self)
+    }
+}
+
+impl Counter for Arc<AtomicUsize> {
+    fn add(&self, amount: usize) {
+        self.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.load(Ordering::Relaxed) as u64
+    }
+}
+
+/// Counts the number of bytes consumed from the underlying reader.
+///
+/// The count is shared with the `PacketParserState` so that the
+/// parser can determine the position of top-level packets without
+/// having to find this reader in the `BufferedReader` stack.  It is
+/// also used to record the sizes of `CompressedData` packets.
+///
+/// Optionally, the reader refuses to return more than a given number
+/// of bytes.  This is used to limit the output of decompressors.
+pub(crate) struct CountingReader<R, C = Rc<Cell<u64>>>
+    where R: BufferedReader<Cookie>, C: Counter
+{
+    reader: R,
+    consumed: C,
+    limit: Option<u64>,
+    cookie: Cookie,
+}
+
+impl<R: BufferedReader<Cookie>, C: Counter> fmt::Display
+    for CountingReader<R, C>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CountingReader")
+    }
+}
+
+impl<R: BufferedReader<Cookie>, C: Counter> fmt::Debug
+    for CountingReader<R, C>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CountingReader")
+            .field("consumed", &self.consumed.get())
+            .field("limit", &self.limit)
+            .field("cookie", &self.cookie)
+            .field("reader", &self.reader)
+            .finish()
+    }
+}
+
+impl<R: BufferedReader<Cookie>, C: Counter> CountingReader<R, C> {
+    /// Instantiates a new counting reader.  Consumed data is added
+    /// to `consumed`.
+    pub fn new(reader: R, consumed: C) -> Self {
+        Self::with_cookie(reader, consumed, Cookie::default())
+    }
+
+    /// Like `new`, but sets a cookie.
+    pub fn with_cookie(reader: R, consumed: C, cookie: Cookie) -> Self {
+        CountingReader {
+            reader: reader,
+            consumed: consumed,
+            limit: None,
+            cookie: cookie,
+        }
+    }
+
+    /// Limits the number of bytes that can be read to `limit`.
+    ///
+    /// Trying to read more results in an
+    /// `Error::DecompressionLimitExceeded`.
+    pub fn with_limit(mut self, limit: Option<u64>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    fn count(&self, amount: usize) {
+        self.consumed.add(amount);
+    }
+
+    /// Returns the number of bytes that may still be read, if the
+    /// reader is limited.
+    fn remaining(&self) -> Option<u64> {
+        self.limit.map(|limit| limit.saturating_sub(self.consumed.get()))
+    }
+}
+
+/// Truncates `data` to the `remaining` number of bytes.
+///
+/// If the caller asked for more than `remaining` bytes, and there is
+/// more data, the limit has been exceeded.
+fn apply_limit(data: &[u8], amount: usize, remaining: Option<u64>,
+               limit: Option<u64>)
+               -> io::Result<&[u8]>
+{
+    match remaining {
+        Some(remaining) if data.len() as u64 > remaining => {
+            if amount as u64 > remaining {
+                Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    failure::Error::from(Error::DecompressionLimitExceeded(
+                        limit.unwrap_or(0))).compat()))
+            } else {
+                Ok(&data[..remaining as usize])
+            }
+        },
+        _ => Ok(data),
+    }
+}
+
+impl<R: BufferedReader<Cookie>, C: Counter> io::Read
+    for CountingReader<R, C>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        return buffered_reader_generic_read_impl(self, buf);
+    }
+}
+
+impl<R: BufferedReader<Cookie>, C: Counter>
+        BufferedReader<Cookie> for CountingReader<R, C> {
+    fn buffer(&self) -> &[u8] {
+        let data = self.reader.buffer();
+        match self.remaining() {
+            Some(remaining) if data.len() as u64 > remaining =>
+                &data[..remaining as usize],
+            _ => data,
+        }
+    }
+
+    fn data(&mut self, amount: usize) -> io::Result<&[u8]> {
+        let (remaining, l) = (self.remaining(), self.limit);
+        apply_limit(self.reader.data(amount)?, amount, remaining, l)
+    }
+
+    fn data_hard(&mut self, amount: usize) -> io::Result<&[u8]> {
+        let (remaining, l) = (self.remaining(), self.limit);
+        apply_limit(self.reader.data_hard(amount)?, amount, remaining, l)
+    }
+
+    fn consume(&mut self, amount: usize) -> &[u8] {
+        let available = self.remaining();
+        self.count(amount);
+        let data = self.reader.consume(amount);
+        match available {
+            Some(available) if data.len() as u64 > available =>
+                &data[..available as usize],
+            _ => data,
+        }
+    }
+
+    fn data_consume(&mut self, amount: usize) -> io::Result<&[u8]> {
+        // data_consume may return less than `amount` bytes, in which
+        // case only those are consumed.
+        let (got, available) = {
+            let data = self.data(amount)?;
+            (cmp::min(data.len(), amount), data.len())
+        };
+        self.count(got);
+        let data = self.reader.data_consume(got)?;
+        Ok(&data[..cmp::min(data.len(), available)])
+    }
+
+    fn data_consume_hard(&mut self, amount: usize) -> io::Result<&[u8]> {
+        let available = self.data_hard(amount)?.len();
+        self.count(amount);
+        let data = self.reader.data_consume_hard(amount)?;
+        Ok(&data[..cmp::min(data.len(), available)])
+    }
+
+    fn get_mut(&mut self) -> Option<&mut BufferedReader<Cookie>> {
+        Some(&mut self.reader)
+    }
+
+    fn get_ref(&self) -> Option<&BufferedReader<Cookie>> {
+        Some(&self.reader)
+    }
+
+    fn into_inner<'b>(self: Box<Self>)
+            -> Option<Box<BufferedReader<Cookie> + 'b>>
+            where Self: 'b {
+        Some(Box::new(self.reader))
+    }
+
+    fn cookie_set(&mut self, cookie: Cookie) -> Cookie {
+        mem::replace(&mut self.cookie, cookie)
+    }
+
+    fn cookie_ref(&self) -> &Cookie {
+        &self.cookie
+    }
+
+    fn cookie_mut(&mut self) -> &mut Cookie {
+        &mut self.cookie
+    }
+}
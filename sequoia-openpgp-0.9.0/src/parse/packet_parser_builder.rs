@@ -1,17 +1,25 @@
 use std::io;
+use std::io::Read;
+use std::fmt;
 use std::path::Path;
+use std::rc::Rc;
+use std::cell::RefCell;
 
 use buffered_reader::BufferedReader;
 
+use Error;
 use Result;
 use parse::PacketParserResult;
 use parse::PacketParser;
 use parse::PacketParserEOF;
 use parse::PacketParserState;
 use parse::PacketParserSettings;
+use parse::RecursionFilter;
 use parse::ParserResult;
 use parse::Parse;
 use parse::Cookie;
+use parse::CountingReader;
+use parse::skip_hard;
 use armor;
 use packet;
 
@@ -29,6 +37,113 @@ pub enum Dearmor {
     Auto(armor::ReaderMode),
 }
 
+/// Which packet headers to accept.
+///
+/// See [`PacketParserBuilder::header_policy`].
+///
+///   [`PacketParserBuilder::header_policy`]: struct.PacketParserBuilder.html#method.header_policy
+pub enum HeaderPolicy {
+    /// Rejects any header that [`Header::valid(false)`] rejects.
+    ///
+    /// In particular, packets with unknown or private (experimental)
+    /// tags cause the parser to fail.
+    ///
+    ///   [`Header::valid(false)`]: ../packet/header/struct.Header.html#method.valid
+    Strict,
+    /// Accepts packets with unknown or private (experimental) tags.
+    ///
+    /// These packets, and packets whose headers are otherwise
+    /// invalid, are returned as `Unknown` packets.  This is the
+    /// default.
+    Lenient,
+    /// Like `Lenient`, but the parser fails if the callback returns
+    /// false for a header.
+    Custom(Box<dyn Fn(&packet::Header) -> bool>),
+}
+
+impl Default for HeaderPolicy {
+    fn default() -> Self {
+        HeaderPolicy::Lenient
+    }
+}
+
+impl fmt::Debug for HeaderPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderPolicy::Strict => f.write_str("Strict"),
+            HeaderPolicy::Lenient => f.write_str("Lenient"),
+            HeaderPolicy::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+impl HeaderPolicy {
+    /// Returns an error if the policy rejects the header.
+    ///
+    /// Syntax errors that `Header::valid(true)` detects are not
+    /// considered by the lenient policies; the parser turns the
+    /// packets into `Unknown` packets.
+    pub(crate) fn check(&self, header: &packet::Header) -> Result<()> {
+        match self {
+            HeaderPolicy::Strict => header.valid(false),
+            HeaderPolicy::Lenient => Ok(()),
+            HeaderPolicy::Custom(accept) => if accept(header) {
+                Ok(())
+            } else {
+                Err(Error::MalformedPacket(
+                    format!("{} packet rejected by the header policy",
+                            header.ctb.tag)).into())
+            },
+        }
+    }
+}
+
+/// What to do if a packet can't be parsed.
+///
+/// See [`PacketParserBuilder::on_error`].
+///
+///   [`PacketParserBuilder::on_error`]: struct.PacketParserBuilder.html#method.on_error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Returns the error.
+    ///
+    /// This is the default.
+    FailFast,
+    /// Returns top-level packets that can't be parsed as `Unknown`
+    /// packets, and continues with the next packet.
+    SkipPacket,
+}
+
+impl Default for ErrorPolicy {
+    fn default() -> Self {
+        ErrorPolicy::FailFast
+    }
+}
+
+/// Records the armor headers once the armor reader consumed them.
+///
+/// The armor reader parses the headers when it is first read from.
+/// Doing that eagerly in `PacketParserBuilder::finalize` would
+/// either hide errors, or report them differently than reading
+/// does.
+struct ArmorHeaderRecorder<'a> {
+    reader: armor::Reader<'a>,
+    headers: Rc<RefCell<Vec<(String, String)>>>,
+    recorded: bool,
+}
+
+impl<'a> io::Read for ArmorHeaderRecorder<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let amount = self.reader.read(buf)?;
+        if ! self.recorded {
+            // The read succeeded, so the headers have been parsed.
+            self.recorded = true;
+            *self.headers.borrow_mut() = self.reader.headers()?.to_vec();
+        }
+        Ok(amount)
+    }
+}
+
 /// A builder for configuring a `PacketParser`.
 ///
 /// Since the default settings are usually appropriate, this mechanism
@@ -39,6 +154,9 @@ pub struct PacketParserBuilder<'a> {
     bio: Box<'a + BufferedReader<Cookie>>,
     dearmor: Dearmor,
     settings: PacketParserSettings,
+    recursion_filter: Option<Box<dyn FnMut(&packet::Header) -> bool>>,
+    header_policy: HeaderPolicy,
+    seek_to: u64,
 }
 
 impl<'a> Parse<'a, PacketParserBuilder<'a>> for PacketParserBuilder<'a> {
@@ -64,23 +182,25 @@ impl<'a> Parse<'a, PacketParserBuilder<'a>> for PacketParserBuilder<'a> {
             Box::new(buffered_reader::Memory::with_cookie(
                 bytes, Cookie::default())))
     }
-}
 
-impl<'a> PacketParserBuilder<'a> {
-    // Creates a `PacketParserBuilder` for an OpenPGP message stored
-    // in a `BufferedReader` object.
-    //
-    // Note: this clears the `level` field of the
-    // `Cookie` cookie.
-    pub(crate) fn from_buffered_reader(mut bio: Box<'a + BufferedReader<Cookie>>)
+    /// Creates a `PacketParserBuilder` for an OpenPGP message stored
+    /// in a `BufferedReader` object.
+    fn from_buffered_reader(mut bio: Box<'a + BufferedReader<Cookie>>)
             -> Result<Self> {
+        // Note: this clears the `level` field of the `Cookie` cookie.
         bio.cookie_mut().level = None;
         Ok(PacketParserBuilder {
             bio: bio,
             dearmor: Dearmor::Auto(Default::default()),
             settings: PacketParserSettings::default(),
+            recursion_filter: None,
+            header_policy: Default::default(),
+            seek_to: 0,
         })
     }
+}
+
+impl<'a> PacketParserBuilder<'a> {
 
     /// Sets the maximum recursion depth.
     ///
@@ -95,6 +215,155 @@ impl<'a> PacketParserBuilder<'a> {
         self
     }
 
+    /// Sets a callback that decides whether to recurse into a
+    /// container.
+    ///
+    /// When `PacketParser::recurse()` is about to descend into a
+    /// container (a compressed data packet, or a decrypted encryption
+    /// container), `filter` is called with the container's header.
+    /// If it returns false, the parser does not recurse, and the
+    /// container's content is handled like that of any other packet,
+    /// i.e., it is either dropped or buffered (see
+    /// [`buffer_unread_content`]).  This allows, for instance, not
+    /// decompressing overly large compressed data packets.
+    ///
+    ///   [`buffer_unread_content`]: #method.buffer_unread_content
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::{Result, Packet};
+    /// # use openpgp::packet::Tag;
+    /// # use openpgp::parse::{
+    /// #     Parse, PacketParserResult, PacketParserBuilder
+    /// # };
+    /// # f(include_bytes!("../../tests/data/messages/compressed-data-algo-1.gpg"));
+    /// #
+    /// # fn f(message_data: &[u8]) -> Result<()> {
+    /// let mut ppr = PacketParserBuilder::from_bytes(message_data)?
+    ///     .recursion_filter(Box::new(|header| {
+    ///         header.ctb.tag != Tag::CompressedData
+    ///     }))
+    ///     .finalize()?;
+    /// while let PacketParserResult::Some(pp) = ppr {
+    ///     // We never see the compressed data packet's children.
+    ///     assert_eq!(pp.recursion_depth(), 0);
+    ///     ppr = pp.recurse()?.1;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn recursion_filter(mut self,
+                            filter: Box<dyn FnMut(&packet::Header) -> bool>)
+                            -> Self {
+        self.recursion_filter = Some(filter);
+        self
+    }
+
+    /// Sets the policy for accepting packet headers.
+    ///
+    /// By default, the parser is lenient: packets with unknown or
+    /// private (experimental) tags are returned as `Unknown` packets.
+    /// Using `HeaderPolicy::Strict`, the parser instead fails when it
+    /// encounters such a packet.  `HeaderPolicy::Custom` allows the
+    /// caller to reject headers using a callback.
+    ///
+    /// The policy is also used when detecting whether the input is
+    /// ASCII armored (see [`Dearmor::Auto`]).
+    ///
+    ///   [`Dearmor::Auto`]: enum.Dearmor.html#variant.Auto
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::Result;
+    /// # use openpgp::packet::Tag;
+    /// # use openpgp::parse::{
+    /// #     Parse, PacketParserResult, PacketParserBuilder, HeaderPolicy
+    /// # };
+    /// # f(include_bytes!("../../tests/data/messages/private-tag.pgp"));
+    /// #
+    /// # fn f(message_data: &[u8]) -> Result<()> {
+    /// // The message starts with a packet using a private tag.
+    /// assert!(PacketParserBuilder::from_bytes(message_data)?
+    ///     .header_policy(HeaderPolicy::Strict)
+    ///     .finalize().is_err());
+    ///
+    /// let ppr = PacketParserBuilder::from_bytes(message_data)?
+    ///     .header_policy(HeaderPolicy::Lenient)
+    ///     .finalize()?;
+    /// if let PacketParserResult::Some(pp) = ppr {
+    ///     assert_eq!(pp.packet.tag(), Tag::Private(60));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn header_policy(mut self, policy: HeaderPolicy) -> Self {
+        self.header_policy = policy;
+        self
+    }
+
+    /// Sets what to do if a packet's body can't be parsed.
+    ///
+    /// Some problems, like packets with unknown versions or invalid
+    /// MPIs, always result in `Unknown` packets.  Other errors, for
+    /// instance unsupported algorithms in the packet's body, cause
+    /// the parser to fail.  Using `ErrorPolicy::SkipPacket`, the
+    /// parser instead returns top-level packets that it fails to
+    /// parse as `Unknown` packets carrying the error and the raw
+    /// body, and then continues with the next packet.  This is
+    /// useful when processing large collections of keys, like key
+    /// server dumps, where a single corrupt packet shouldn't abort
+    /// the whole stream.
+    ///
+    /// The parser uses the packet's length to find the next packet.
+    /// Errors in nested packets, and errors parsing the packet
+    /// headers, are not affected by this setting.  The default is
+    /// `ErrorPolicy::FailFast`.
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.settings.error_policy = policy;
+        self
+    }
+
+    /// Limits the size of decompressed data.
+    ///
+    /// A small compressed data packet can decompress to gigabytes of
+    /// data.  To protect against such decompression bombs, the
+    /// parser returns an error once a compressed data packet's
+    /// content exceeds `limit` bytes.  The limit applies to each
+    /// compressed data packet separately, and it is checked as the
+    /// data is read.  The error is an `io::Error` wrapping
+    /// `Error::DecompressionLimitExceeded`.
+    ///
+    /// By default, there is no limit.
+    pub fn max_decompressed_size(mut self, limit: u64) -> Self {
+        self.settings.max_decompressed_size = Some(limit);
+        self
+    }
+
+    /// Controls whether the packets' original encoding is recorded.
+    ///
+    /// Parsing a packet and serializing it again does not
+    /// necessarily result in the same bytes.  For instance, the
+    /// packet's header may be encoded differently.  When this is
+    /// enabled, the parser remembers each packet's original
+    /// encoding, and [`Packet::serialize_exact`] reproduces it
+    /// byte-for-byte.  This is useful for tools that move packets
+    /// around, but must not alter them.
+    ///
+    /// The original encoding is only recorded for packets whose body
+    /// is completely consumed by the parser, i.e., not for literal
+    /// data packets and containers, and not for packets using the
+    /// partial body encoding.  The default is not to record it.
+    ///
+    ///   [`Packet::serialize_exact`]: ../enum.Packet.html#method.serialize_exact
+    pub fn preserve_encoding(mut self, enable: bool) -> Self {
+        self.settings.preserve_encoding = enable;
+        self
+    }
+
     /// Causes `PacketParser::finish()` to buffer any unread content.
     ///
     /// The unread content is stored in the `Packet::content` Option.
@@ -118,12 +387,88 @@ impl<'a> PacketParserBuilder<'a> {
         self
     }
 
+    /// Controls whether SEIP containers lacking an MDC packet are
+    /// accepted.
+    ///
+    /// A SEIP container without an MDC packet is not integrity
+    /// protected.  Stripping the MDC is a known downgrade attack, so
+    /// by default the `PacketParser` fails with `Error::MissingMDC`
+    /// when it leaves such a container.  Only enable this to read
+    /// legacy data, and be aware that the data may have been
+    /// manipulated.
+    ///
+    /// Note: in this mode, the parser cannot hold back the trailing
+    /// MDC packet while it reads the container's content.  Hence,
+    /// SEIP containers whose last packet before the MDC uses an
+    /// indeterminate length encoding, like the compressed data
+    /// packets generated by GnuPG, cannot be parsed, even if they
+    /// are integrity protected.  Containers whose packets use
+    /// definite lengths are parsed as usual, and their MDC is
+    /// checked.
+    pub fn allow_missing_mdc(mut self, enable: bool) -> Self {
+        self.settings.allow_missing_mdc = enable;
+        self
+    }
+
     /// How to treat the input stream.
     pub fn dearmor(mut self, mode: Dearmor) -> Self {
         self.dearmor = mode;
         self
     }
 
+    /// Starts parsing at the given offset.
+    ///
+    /// `offset` must be the start of a top-level packet, as returned
+    /// by [`PacketParser::position`].  This allows resuming parsing
+    /// after an interruption without having to parse the preceding
+    /// packets.  Note: the skipped data is still read and discarded.
+    ///
+    /// If `offset` is not the start of a plausible packet, or is
+    /// beyond the end of the input, `finalize` returns
+    /// `Error::InvalidArgument`.
+    ///
+    ///   [`PacketParser::position`]: struct.PacketParser.html#method.position
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::Result;
+    /// # use openpgp::packet::Tag;
+    /// # use openpgp::parse::{
+    /// #     Parse, PacketParserResult, PacketParser, PacketParserBuilder
+    /// # };
+    /// # f(include_bytes!("../../tests/data/keys/testy.pgp"));
+    /// #
+    /// # fn f(data: &[u8]) -> Result<()> {
+    /// // Remember where the first subkey starts.
+    /// let mut checkpoint = None;
+    /// let mut ppr = PacketParser::from_bytes(data)?;
+    /// while let PacketParserResult::Some(pp) = ppr {
+    ///     if pp.packet.tag() == Tag::PublicSubkey {
+    ///         checkpoint = Some(pp.position());
+    ///         break;
+    ///     }
+    ///     ppr = pp.next()?.1;
+    /// }
+    ///
+    /// // Resume parsing there.
+    /// let ppr = PacketParserBuilder::from_bytes(data)?
+    ///     .seek_to(checkpoint.unwrap())
+    ///     .finalize()?;
+    /// if let PacketParserResult::Some(pp) = ppr {
+    ///     assert_eq!(pp.packet.tag(), Tag::PublicSubkey);
+    /// } else {
+    ///     unreachable!();
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn seek_to(mut self, offset: u64) -> Self {
+        self.seek_to = offset;
+        self
+    }
+
     /// Finishes configuring the `PacketParser` and returns an
     /// `Option<PacketParser>`.
     ///
@@ -147,7 +492,9 @@ impl<'a> PacketParserBuilder<'a> {
         -> Result<PacketParserResult<'a>>
         where Self: 'a
     {
-        let state = PacketParserState::new(self.settings);
+        let mut state = PacketParserState::new(self.settings);
+        state.recursion_filter = self.recursion_filter.map(RecursionFilter);
+        state.header_policy = self.header_policy;
 
         let dearmor_mode = match self.dearmor {
             Dearmor::Enabled(mode) => Some(mode),
@@ -158,7 +505,9 @@ impl<'a> PacketParserBuilder<'a> {
                 let header = packet::Header::parse(&mut reader);
                 self.bio = Box::new(reader).into_inner().unwrap();
                 if let Ok(header) = header {
-                    if let Err(_) = header.valid(false) {
+                    if let Err(_) = header.valid(true)
+                        .and_then(|_| state.header_policy.check(&header))
+                    {
                         // Invalid header: better try an ASCII armor
                         // decoder.
                         Some(mode)
@@ -173,23 +522,72 @@ impl<'a> PacketParserBuilder<'a> {
             }
         };
 
+        // The armor headers are only known once the first packet's
+        // header has been read.
+        let mut armor_headers = None;
         if let Some(mode) = dearmor_mode {
+            let reader =
+                armor::Reader::from_buffered_reader(self.bio, Some(mode));
+            let headers = Rc::new(RefCell::new(Vec::new()));
+            armor_headers = Some(headers.clone());
+
             self.bio = Box::new(buffered_reader::Generic::with_cookie(
-                armor::Reader::from_buffered_reader(self.bio, Some(mode)),
+                ArmorHeaderRecorder {
+                    reader: reader,
+                    headers: headers,
+                    recorded: false,
+                },
                 None,
                 Default::default()));
         }
 
+        let mut bio : Box<BufferedReader<Cookie> + 'a> =
+            Box::new(CountingReader::new(self.bio, state.consumed.clone()));
+
+        if self.seek_to > 0 {
+            skip_hard(bio.as_mut(), self.seek_to).map_err(|_| {
+                Error::InvalidArgument(
+                    format!("Offset {} is beyond the end of the input",
+                            self.seek_to))
+            })?;
+
+            // Seeking to the end of the input is fine, otherwise there
+            // must be a packet.
+            let mut reader = buffered_reader::Dup::with_cookie(
+                bio, Cookie::default());
+            let plausible = reader.data(1)?.is_empty()
+                || packet::Header::parse(&mut reader)
+                .and_then(|header| {
+                    header.valid(false)?;
+                    PacketParser::plausible(&mut reader, &header)
+                })
+                .is_ok();
+            bio = Box::new(reader).into_inner().unwrap();
+            if ! plausible {
+                return Err(Error::InvalidArgument(
+                    format!("Offset {} is not the start of a packet",
+                            self.seek_to)).into());
+            }
+        }
+
         // Parse the first packet.
-        match PacketParser::parse(Box::new(self.bio), state, vec![ 0 ])? {
+        match PacketParser::parse(bio, state, vec![ 0 ])? {
             ParserResult::Success(mut pp) => {
+                if let Some(headers) = armor_headers {
+                    pp.state.armor_headers = headers.borrow().clone();
+                }
+
                 // We successfully parsed the first packet's header.
                 pp.state.message_validator.push(pp.packet.tag(), &[0]);
                 pp.state.keyring_validator.push(pp.packet.tag());
                 pp.state.tpk_validator.push(pp.packet.tag());
                 Ok(PacketParserResult::Some(pp))
             },
-            ParserResult::EOF((_reader, state, _path)) => {
+            ParserResult::EOF((_reader, mut state, _path)) => {
+                if let Some(headers) = armor_headers {
+                    state.armor_headers = headers.borrow().clone();
+                }
+
                 // `bio` is empty.  We're done.
                 Ok(PacketParserResult::EOF(PacketParserEOF::new(state)))
             }
@@ -247,4 +645,265 @@ mod tests {
             .finalize();
         assert_match!(Ok(PacketParserResult::Some(ref _pp)) = ppr);
     }
+
+    #[test]
+    fn armor_headers() {
+        use armor::{Kind, Writer};
+        use constants::DataFormat;
+        use packet::Literal;
+        use serialize::Serialize;
+        use Packet;
+
+        let mut lit = Literal::new(DataFormat::Binary);
+        lit.set_body(b"Hello world.".to_vec());
+
+        let mut buf = Vec::new();
+        {
+            let mut w = Writer::new(&mut buf, Kind::Message,
+                                    &[("Comment", "Hello armor"),
+                                      ("Version", "42")]).unwrap();
+            Packet::Literal(lit).serialize(&mut w).unwrap();
+            w.finalize().unwrap();
+        }
+
+        let mut ppr = PacketParserBuilder::from_bytes(&buf[..]).unwrap()
+            .finalize().unwrap();
+        let mut packets = 0;
+        while let PacketParserResult::Some(pp) = ppr {
+            assert_eq!(pp.armor_headers().len(), 2);
+            packets += 1;
+            ppr = pp.recurse().unwrap().1;
+        }
+        assert_eq!(packets, 1);
+
+        if let PacketParserResult::EOF(eof) = ppr {
+            assert_eq!(eof.armor_headers(),
+                       &[("Comment".into(), "Hello armor".into()),
+                         ("Version".into(), "42".into())]);
+        } else {
+            unreachable!();
+        }
+
+        // Without armor, there are no headers.
+        let mut ppr = PacketParserBuilder::from_bytes(
+            ::tests::message("sig.gpg")).unwrap()
+            .finalize().unwrap();
+        while let PacketParserResult::Some(pp) = ppr {
+            assert_eq!(pp.armor_headers().len(), 0);
+            ppr = pp.recurse().unwrap().1;
+        }
+        if let PacketParserResult::EOF(eof) = ppr {
+            assert_eq!(eof.armor_headers().len(), 0);
+        } else {
+            unreachable!();
+        }
+    }
+
+    #[cfg(feature = "compression-deflate")]
+    #[test]
+    fn recursion_filter() {
+        use Packet;
+        use packet::Tag;
+
+        let msg = ::tests::message("compressed-data-algo-1.gpg");
+
+        // Without a filter, we recurse into the compressed data
+        // packet.
+        let mut ppr = PacketParserBuilder::from_bytes(msg).unwrap()
+            .finalize().unwrap();
+        let mut tags = Vec::new();
+        while let PacketParserResult::Some(pp) = ppr {
+            tags.push((pp.recursion_depth(), pp.packet.tag()));
+            ppr = pp.recurse().unwrap().1;
+        }
+        assert_eq!(tags, vec![(0, Tag::CompressedData), (1, Tag::Literal)]);
+
+        // Veto recursing into compressed data packets.
+        let mut ppr = PacketParserBuilder::from_bytes(msg).unwrap()
+            .buffer_unread_content()
+            .recursion_filter(Box::new(|header| {
+                header.ctb.tag != Tag::CompressedData
+            }))
+            .finalize().unwrap();
+        let mut packets = Vec::new();
+        while let PacketParserResult::Some(pp) = ppr {
+            assert_eq!(pp.recursion_depth(), 0);
+            let (packet, ppr_) = pp.recurse().unwrap();
+            packets.push(packet);
+            ppr = ppr_;
+        }
+        assert_eq!(packets.len(), 1);
+        if let Packet::CompressedData(ref c) = packets[0] {
+            // The content has not been parsed.
+            assert_eq!(c.descendants().count(), 0);
+            assert!(c.body().map(|b| b.len() > 0).unwrap_or(false));
+        } else {
+            panic!("Expected a compressed data packet, got {:?}", packets[0]);
+        }
+    }
+
+    #[test]
+    fn seek_to() {
+        use packet::Tag;
+
+        for name in &["testy.pgp", "testy.asc"] {
+            let data = ::tests::key(name);
+
+            // Record the position of every top-level packet.
+            let mut packets : Vec<(u64, Tag)> = Vec::new();
+            let mut ppr = PacketParser::from_bytes(data).unwrap();
+            while let PacketParserResult::Some(pp) = ppr {
+                packets.push((pp.position(), pp.packet.tag()));
+                ppr = pp.next().unwrap().1;
+            }
+            assert_eq!(packets[0].0, 0);
+            assert!(packets.windows(2).all(|w| w[0].0 < w[1].0));
+
+            for &(position, tag) in packets.iter() {
+                let ppr = PacketParserBuilder::from_bytes(data).unwrap()
+                    .seek_to(position)
+                    .finalize().unwrap();
+                if let PacketParserResult::Some(pp) = ppr {
+                    assert_eq!(pp.packet.tag(), tag);
+                    assert_eq!(pp.position(), position);
+                } else {
+                    panic!("Expected a packet at {}", position);
+                }
+
+                // Seeking into the middle of a packet fails.
+                assert!(PacketParserBuilder::from_bytes(data).unwrap()
+                        .seek_to(position + 1)
+                        .finalize().is_err());
+            }
+        }
+
+        // Seeking to the end is fine, seeking beyond it is not.
+        let data = ::tests::key("testy.pgp");
+        let ppr = PacketParserBuilder::from_bytes(data).unwrap()
+            .seek_to(data.len() as u64)
+            .finalize().unwrap();
+        assert_match!(PacketParserResult::EOF(_) = ppr);
+        assert!(PacketParserBuilder::from_bytes(data).unwrap()
+                .seek_to(data.len() as u64 + 1)
+                .finalize().is_err());
+    }
+
+    #[test]
+    fn header_policy() {
+        use packet::Tag;
+
+        let msg = ::tests::message("private-tag.pgp");
+
+        // Returns the tags of the packets, or an error.
+        let tags = |policy| -> Result<Vec<Tag>> {
+            let mut tags = Vec::new();
+            let mut ppr = PacketParserBuilder::from_bytes(msg)?
+                .header_policy(policy)
+                .finalize()?;
+            while let PacketParserResult::Some(pp) = ppr {
+                tags.push(pp.packet.tag());
+                ppr = pp.next()?.1;
+            }
+            Ok(tags)
+        };
+
+        // By default, the private packet is returned as an Unknown
+        // packet.
+        let mut ppr = PacketParser::from_bytes(msg).unwrap();
+        if let PacketParserResult::Some(pp) = ppr {
+            assert_match!(::Packet::Unknown(_) = pp.packet);
+            ppr = pp.next().unwrap().1;
+        }
+        assert_match!(PacketParserResult::Some(_) = ppr);
+
+        assert_eq!(tags(HeaderPolicy::Lenient).unwrap(),
+                   vec![ Tag::Private(60), Tag::Literal ]);
+        assert!(tags(HeaderPolicy::Strict).is_err());
+
+        // The callback decides.
+        assert_eq!(tags(HeaderPolicy::Custom(Box::new(|header| {
+            header.ctb.tag != Tag::Private(61)
+        }))).unwrap(), vec![ Tag::Private(60), Tag::Literal ]);
+        assert!(tags(HeaderPolicy::Custom(Box::new(|header| {
+            header.ctb.tag != Tag::Literal
+        }))).is_err());
+        assert!(tags(HeaderPolicy::Custom(Box::new(|header| {
+            header.ctb.tag != Tag::Private(60)
+        }))).is_err());
+
+        // Strict parsing still accepts normal messages.
+        let msg = ::tests::message("literal-mode-t-partial-body.gpg");
+        let ppr = PacketParserBuilder::from_bytes(msg).unwrap()
+            .header_policy(HeaderPolicy::Strict)
+            .finalize().unwrap();
+        assert_match!(PacketParserResult::Some(_) = ppr);
+    }
+
+    #[test]
+    fn on_error() {
+        use packet::Tag;
+
+        // A SKESK packet using an unsupported AEAD algorithm (OCB)
+        // between two user ids.
+        let skesk = [0xc3, 0x05, 0x05, 0x09, 0x02, 0x00, 0x08];
+        let mut msg = vec![0xcd, 0x01, b'a'];
+        msg.extend_from_slice(&skesk);
+        msg.extend_from_slice(&[0xcd, 0x01, b'b']);
+
+        // Returns the tags of the packets, or an error.
+        let tags = |policy| -> Result<Vec<Tag>> {
+            let mut tags = Vec::new();
+            let mut ppr = PacketParserBuilder::from_bytes(&msg)?
+                .on_error(policy)
+                .finalize()?;
+            while let PacketParserResult::Some(pp) = ppr {
+                if let ::Packet::Unknown(ref u) = pp.packet {
+                    assert_eq!(u.body(), Some(&skesk[2..]));
+                    assert_match!(Some(&Error::UnsupportedAEADAlgorithm(_))
+                                  = u.error().downcast_ref());
+                }
+                tags.push(pp.packet.tag());
+                ppr = pp.next()?.1;
+            }
+            Ok(tags)
+        };
+
+        assert!(tags(ErrorPolicy::FailFast).is_err());
+        assert_eq!(tags(ErrorPolicy::SkipPacket).unwrap(),
+                   vec![ Tag::UserID, Tag::SKESK, Tag::UserID ]);
+    }
+
+    #[cfg(feature = "compression-deflate")]
+    #[test]
+    fn max_decompressed_size() {
+        use std::io::Read;
+        use packet::Tag;
+
+        // A message containing 1 MiB of zeros, compressed to a
+        // little more than 1 KiB.
+        let msg = ::tests::message("compressed-zeros.gpg");
+
+        // Returns the length of the literal data, or an error.
+        let read = |limit| -> Result<usize> {
+            let mut ppr = PacketParserBuilder::from_bytes(msg)?
+                .max_decompressed_size(limit)
+                .finalize()?;
+            let mut len = None;
+            while let PacketParserResult::Some(mut pp) = ppr {
+                if pp.packet.tag() == Tag::Literal {
+                    let mut body = Vec::new();
+                    pp.read_to_end(&mut body)?;
+                    len = Some(body.len());
+                }
+                ppr = pp.recurse()?.1;
+            }
+            Ok(len.unwrap())
+        };
+
+        assert_eq!(read(2 * 1024 * 1024).unwrap(), 1024 * 1024);
+
+        let err = read(64 * 1024).unwrap_err();
+        assert!(err.to_string()
+                .contains("Decompression limit of 65536 bytes exceeded"));
+    }
 }
@@ -113,6 +113,13 @@ impl<'a> Parse<'a, PacketPileParser<'a>> for PacketPileParser<'a> {
             data, Cookie::default()));
         PacketPileParser::from_buffered_reader(bio)
     }
+
+    /// Creates a `PacketPileParser` to parse the OpenPGP message stored
+    /// in the `BufferedReader` object.
+    fn from_buffered_reader(bio: Box<BufferedReader<Cookie> + 'a>)
+            -> Result<PacketPileParser<'a>> {
+        Self::from_packet_parser(PacketParser::from_buffered_reader(bio)?)
+    }
 }
 
 impl<'a> PacketPileParser<'a> {
@@ -127,13 +134,6 @@ impl<'a> PacketPileParser<'a> {
         })
     }
 
-    /// Creates a `PacketPileParser` to parse the OpenPGP message stored
-    /// in the `BufferedReader` object.
-    pub(crate) fn from_buffered_reader(bio: Box<BufferedReader<Cookie> + 'a>)
-            -> Result<PacketPileParser<'a>> {
-        Self::from_packet_parser(PacketParser::from_buffered_reader(bio)?)
-    }
-
     /// Inserts the next packet into the `PacketPile`.
     fn insert_packet(&mut self, packet: Packet, position: isize) {
         // Find the right container.
@@ -7,6 +7,10 @@ use std::str;
 use std::mem;
 use std::fmt;
 use std::path::Path;
+use std::rc::Rc;
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
 use time;
 use failure;
 
@@ -20,7 +24,7 @@ use {
     crypto::s2k::S2K,
     Error,
     Header,
-    packet::signature::Signature4,
+    packet::signature::{Signature3, Signature4},
     packet::prelude::*,
     Packet,
     KeyID,
@@ -51,8 +55,19 @@ pub use self::packet_pile_parser::PacketPileParser;
 mod hashed_reader;
 pub(crate) use self::hashed_reader::HashedReader;
 
+mod counting_reader;
+use self::counting_reader::CountingReader;
+
 mod packet_parser_builder;
-pub use self::packet_parser_builder::{Dearmor, PacketParserBuilder};
+pub use self::packet_parser_builder::{
+    Dearmor,
+    ErrorPolicy,
+    HeaderPolicy,
+    PacketParserBuilder,
+};
+
+mod cleartext;
+pub use self::cleartext::ClearSigned;
 
 pub mod map;
 mod mpis;
@@ -91,6 +106,22 @@ pub trait Parse<'a, T> {
     {
         Self::from_reader(io::Cursor::new(data))
     }
+
+    /// Reads from the given `BufferedReader`.
+    ///
+    /// This is useful if the data is already available via a
+    /// `BufferedReader`, e.g. a decompressor, as it avoids adding
+    /// another buffering layer.
+    ///
+    /// The default implementation just uses [`from_reader(..)`], but
+    /// implementations can provide their own specialized version.
+    ///
+    /// [`from_reader(..)`]: #tymethod.from_reader
+    fn from_buffered_reader(bio: Box<dyn BufferedReader<Cookie> + 'a>)
+                            -> Result<T>
+    {
+        Self::from_reader(bio)
+    }
 }
 
 macro_rules! impl_parse_generic_packet {
@@ -188,6 +219,17 @@ macro_rules! make_php_try {
                             Err(e) => e,
                         };
 
+                        // If requested, turn top-level packets that
+                        // we fail to parse into Unknown packets.  I/O
+                        // errors are not the packet's fault.
+                        if $parser.path.len() == 1
+                            && $parser.state.settings.error_policy
+                                == ErrorPolicy::SkipPacket
+                            && e.downcast_ref::<io::Error>().is_none()
+                        {
+                            return $parser.error(e);
+                        }
+
                         Err(e)
                     },
                 }?
@@ -259,7 +301,7 @@ impl<'a> PacketHeaderParser<'a> {
     // Only call this function if the packet's header has been
     // completely and correctly parsed.  If a failure occurs while
     // parsing the header, use `fail()` instead.
-    fn ok(mut self, packet: Packet) -> Result<PacketParser<'a>> {
+    fn ok(mut self, mut packet: Packet) -> Result<PacketParser<'a>> {
         let total_out = self.reader.total_out();
 
         let mut reader = if self.state.settings.map {
@@ -292,6 +334,23 @@ impl<'a> PacketHeaderParser<'a> {
             Box::new(self.reader).into_inner().unwrap()
         };
 
+        // If the parser consumed the whole body, the packet's
+        // original encoding is the header followed by the data we
+        // read.  This doesn't work for partial body encoded packets,
+        // because the chunk headers have already been stripped.
+        if self.state.settings.preserve_encoding {
+            let partial = if let BodyLength::Partial(_) = self.header.length {
+                true
+            } else {
+                false
+            };
+            if ! partial && reader.data(total_out + 1)?.len() == total_out {
+                let mut encoding = self.header_bytes.clone();
+                encoding.extend_from_slice(&reader.buffer()[..total_out]);
+                packet.original_encoding = Some(encoding);
+            }
+        }
+
         // We know the data has been read, so this cannot fail.
         reader.data_consume_hard(total_out).unwrap();
 
@@ -302,6 +361,7 @@ impl<'a> PacketHeaderParser<'a> {
             last_path: vec![],
             reader: reader,
             content_was_read: false,
+            body_bytes_read: total_out as u64,
             decrypted: true,
             finished: false,
             map: self.map,
@@ -319,6 +379,13 @@ impl<'a> PacketHeaderParser<'a> {
         Unknown::parse(self, error)
     }
 
+    // The packet has a version that we don't understand.  Aborts and
+    // returns an Unknown packet instead.
+    fn unsupported_version(self, version: u8) -> Result<PacketParser<'a>> {
+        let tag = self.header.ctb.tag;
+        self.error(Error::UnsupportedPacketVersion(tag, version).into())
+    }
+
     fn field(&mut self, name: &'static str, size: usize) {
         if let Some(ref mut map) = self.map {
             map.add(name, size)
@@ -378,8 +445,16 @@ enum Hashing {
 }
 
 
+/// State associated with a `BufferedReader` used by the parser.
+///
+/// This is used as the cookie of [`BufferedReader`]s passed to
+/// [`Parse::from_buffered_reader`].  Use `Cookie::default()` to
+/// create one.
+///
+///   [`BufferedReader`]: ../../buffered_reader/trait.BufferedReader.html
+///   [`Parse::from_buffered_reader`]: trait.Parse.html#method.from_buffered_reader
 #[derive(Debug)]
-pub(crate) struct Cookie {
+pub struct Cookie {
     // `BufferedReader`s managed by a `PacketParser` have
     // `Some(level)`; an external `BufferedReader` (i.e., the
     // underlying `BufferedReader`) has no level.
@@ -633,6 +708,18 @@ impl Cookie {
     }
 }
 
+// Consumes `amount` bytes without buffering all of them at once.
+fn skip_hard<C>(reader: &mut BufferedReader<C>, mut amount: u64)
+    -> io::Result<()>
+{
+    while amount > 0 {
+        let chunk = cmp::min(amount, 8 * 1024) as usize;
+        reader.data_consume_hard(chunk)?;
+        amount -= chunk as u64;
+    }
+    Ok(())
+}
+
 // Pops readers from a buffered reader stack at the specified level.
 fn buffered_reader_stack_pop<'a>(
     mut reader: Box<BufferedReader<Cookie> + 'a>, depth: isize)
@@ -707,6 +794,19 @@ struct PacketParserSettings {
 
     // Whether or not to create a map.
     map: bool,
+
+    // Whether to accept SEIP containers that lack an MDC packet.
+    allow_missing_mdc: bool,
+
+    // What to do if a top-level packet's body can't be parsed.
+    error_policy: ErrorPolicy,
+
+    // The maximum number of bytes a compressed data packet may
+    // decompress to.
+    max_decompressed_size: Option<u64>,
+
+    // Whether to record the packets' original encoding.
+    preserve_encoding: bool,
 }
 
 // The default `PacketParser` settings.
@@ -716,6 +816,10 @@ impl Default for PacketParserSettings {
             max_recursion_depth: MAX_RECURSION_DEPTH,
             buffer_unread_content: false,
             map: false,
+            allow_missing_mdc: false,
+            error_policy: ErrorPolicy::FailFast,
+            max_decompressed_size: None,
+            preserve_encoding: false,
         }
     }
 }
@@ -792,10 +896,20 @@ impl<'a> Parse<'a, Header> for Header {
 
 impl Unknown {
     /// Parses the body of any packet and returns an Unknown.
-    fn parse<'a>(php: PacketHeaderParser<'a>, error: failure::Error)
+    fn parse<'a>(mut php: PacketHeaderParser<'a>, error: failure::Error)
                  -> Result<PacketParser<'a>>
     {
         let tag = php.header.ctb.tag;
+
+        // The parser may have given up after consuming some of the
+        // packet (e.g., the version).  Rewind so that the body
+        // includes those bytes, and the packet can be serialized
+        // without loss.
+        php.reader.rewind();
+        if php.map.is_some() {
+            php.map = Some(map::Map::new(php.header_bytes.clone()));
+        }
+
         php.ok(Packet::Unknown(Unknown::new(tag, error)))
             .map(|pp| pp.set_decrypted(false))
     }
@@ -854,10 +968,11 @@ impl Signature {
         let version = php_try!(php.parse_u8("version"));
 
         match version {
+            3 => Signature3::parse(php),
             4 => Signature4::parse(php),
             _ => {
                 t!("Ignoring version {} packet.", version);
-                php.fail("unknown version")
+                php.unsupported_version(version)
             },
         }
     }
@@ -865,7 +980,94 @@ impl Signature {
     /// Returns whether the data appears to be a signature (no promises).
     fn plausible(bio: &mut buffered_reader::Dup<Cookie>, header: &Header)
                  -> Result<()> {
-        Signature4::plausible(bio, header)
+        if bio.data(1)?.get(0) == Some(&3) {
+            Signature3::plausible(bio, header)
+        } else {
+            Signature4::plausible(bio, header)
+        }
+    }
+}
+
+impl Signature3 {
+    // Parses a version 3 signature packet.
+    fn parse<'a>(mut php: PacketHeaderParser<'a>)
+        -> Result<PacketParser<'a>>
+    {
+        let indent = php.recursion_depth();
+
+        make_php_try!(php);
+
+        let hashed_len = php_try!(php.parse_u8("hashed_len"));
+        if hashed_len != 5 {
+            return php.fail("invalid length of hashed material");
+        }
+        let sigtype = php_try!(php.parse_u8("sigtype"));
+        let creation_time = php_try!(php.parse_be_u32("creation_time"));
+        let issuer = php_try!(php.parse_bytes("issuer", 8));
+        let pk_algo: PublicKeyAlgorithm = php_try!(php.parse_u8("pk_algo")).into();
+        let hash_algo = php_try!(php.parse_u8("hash_algo"));
+        let hash_prefix1 = php_try!(php.parse_u8("hash_prefix1"));
+        let hash_prefix2 = php_try!(php.parse_u8("hash_prefix2"));
+        if ! pk_algo.can_sign() {
+            return php.fail("not a signature algorithm");
+        }
+        let mpis = php_try!(
+            crypto::mpis::Signature::_parse(pk_algo, &mut php));
+
+        let hash_algo = hash_algo.into();
+        let pp = php.ok(Packet::Signature(Signature3::new(
+            sigtype.into(), time::Tm::from_pgp(creation_time),
+            KeyID::from_bytes(&issuer[..]), pk_algo, hash_algo,
+            [hash_prefix1, hash_prefix2],
+            mpis).into()))?;
+
+        Signature4::parse_finish(indent, pp, hash_algo)
+    }
+
+    /// Returns whether the data appears to be a signature (no promises).
+    fn plausible(bio: &mut buffered_reader::Dup<Cookie>, header: &Header) -> Result<()> {
+        // The absolute minimum size for the header is 19 bytes (this
+        // doesn't include the signature MPIs).
+
+        if let BodyLength::Full(len) = header.length {
+            if len < 19 {
+                // Much too short.
+                return Err(
+                    Error::MalformedPacket("Packet too short".into()).into());
+            }
+        } else {
+            return Err(
+                Error::MalformedPacket(
+                    format!("Unexpected body length encoding: {:?}",
+                            header.length)
+                        .into()).into());
+        }
+
+        // Make sure we have a minimum header.
+        let data = bio.data(19)?;
+        if data.len() < 19 {
+            return Err(
+                Error::MalformedPacket("Short read".into()).into());
+        }
+
+        // Assume unknown == bad.
+        let version = data[0];
+        let hashed_len = data[1];
+        let sigtype : SignatureType = data[2].into();
+        let pk_algo : PublicKeyAlgorithm = data[15].into();
+        let hash_algo : HashAlgorithm = data[16].into();
+
+        if version == 3
+            && hashed_len == 5
+            && !destructures_to!(SignatureType::Unknown(_) = sigtype)
+            && !destructures_to!(PublicKeyAlgorithm::Unknown(_) = pk_algo)
+            && !destructures_to!(HashAlgorithm::Unknown(_) = hash_algo)
+        {
+            Ok(())
+        } else {
+            Err(Error::MalformedPacket("Invalid or unsupported data".into())
+                .into())
+        }
     }
 }
 
@@ -875,7 +1077,6 @@ impl Signature4 {
         -> Result<PacketParser<'a>>
     {
         let indent = php.recursion_depth();
-        tracer!(TRACE, "Signature4::parse", indent);
 
         make_php_try!(php);
 
@@ -899,13 +1100,26 @@ impl Signature4 {
             crypto::mpis::Signature::_parse(pk_algo, &mut php));
 
         let hash_algo = hash_algo.into();
-        let mut pp = php.ok(Packet::Signature(Signature4::new(
+        let pp = php.ok(Packet::Signature(Signature4::new(
             sigtype.into(), pk_algo.into(), hash_algo,
             SubpacketArea::new(hashed_area),
             SubpacketArea::new(unhashed_area),
             [hash_prefix1, hash_prefix2],
             mpis).into()))?;
 
+        Signature4::parse_finish(indent, pp, hash_algo)
+    }
+
+    // Finishes parsing a signature packet.
+    //
+    // This locates the corresponding HashedReader, and stores the
+    // computed hash in the signature.
+    fn parse_finish<'a>(indent: isize, mut pp: PacketParser<'a>,
+                        hash_algo: HashAlgorithm)
+        -> Result<PacketParser<'a>>
+    {
+        tracer!(TRACE, "Signature4::parse_finish", indent);
+
         // Locate the corresponding HashedReader and extract the
         // computed hash.
         let mut computed_hash = None;
@@ -1035,6 +1249,33 @@ fn signature_parser_test () {
     }
 }
 
+#[test]
+fn signature_v3_parser_test () {
+    use serialize::SerializeInto;
+
+    let data = ::tests::message("a-cypherpunks-manifesto.txt.testy-v3.sig");
+
+    let pp = PacketParser::from_bytes(data).unwrap().unwrap();
+    assert_eq!(pp.header.length, BodyLength::Full(277));
+    if let Packet::Signature(ref p) = pp.packet {
+        assert_eq!(p.version(), 3);
+        assert_eq!(p.sigtype(), SignatureType::Binary);
+        assert_eq!(p.pk_algo(), PublicKeyAlgorithm::RSAEncryptSign);
+        assert_eq!(p.hash_algo(), HashAlgorithm::SHA256);
+        assert_eq!(p.signature_creation_time(),
+                   Some(time::Tm::from_pgp(1560281088)));
+        assert_eq!(p.issuer(),
+                   Some(KeyID::from_hex("D03F6F865226FE8B").unwrap()));
+        assert_eq!(p.hash_prefix(), &[0xabu8, 0x30]);
+        assert_eq!(p.mpis().serialized_len(), 258);
+
+        // Reserializing yields the version 3 layout again.
+        assert_eq!(&p.to_vec().unwrap()[..], &data[3..]);
+    } else {
+        panic!("Wrong packet!");
+    }
+}
+
 impl OnePassSig {
     fn parse<'a>(php: PacketHeaderParser<'a>)
         -> Result<PacketParser<'a>>
@@ -1059,7 +1300,7 @@ impl OnePassSig3 {
             t!("Ignoring version {} packet", version);
 
             // Unknown version.  Return an unknown packet.
-            return php.fail("unknown version");
+            return php.unsupported_version(version);
         }
 
         let sigtype = php_try!(php.parse_u8("sigtype"));
@@ -1304,7 +1545,8 @@ impl Key {
 
         match version {
             4 => Key4::parse(php),
-            _ => php.fail("unknown version"),
+            5 => Key5::parse(php),
+            _ => php.unsupported_version(version),
         }
     }
 
@@ -1438,7 +1680,7 @@ impl Key4 {
         let version = data[0];
         let pk_algo : PublicKeyAlgorithm = data[5].into();
 
-        if version == 4
+        if (version == 4 || version == 5)
             && !destructures_to!(PublicKeyAlgorithm::Unknown(_) = pk_algo)
         {
             Ok(())
@@ -1449,6 +1691,47 @@ impl Key4 {
     }
 }
 
+impl Key5 {
+    /// Parses the body of a version 5 public key or public subkey
+    /// packet.
+    ///
+    /// Version 5 secret keys are not supported, and are returned as
+    /// `Unknown` packets.
+    fn parse<'a>(mut php: PacketHeaderParser<'a>) -> Result<PacketParser<'a>> {
+        make_php_try!(php);
+        let tag = php.header.ctb.tag;
+        assert!(tag == Tag::Reserved
+                || tag == Tag::PublicKey
+                || tag == Tag::PublicSubkey
+                || tag == Tag::SecretKey
+                || tag == Tag::SecretSubkey);
+        if tag == Tag::SecretKey || tag == Tag::SecretSubkey {
+            return php.fail("unsupported v5 secret key");
+        }
+
+        let creation_time = php_try!(php.parse_be_u32("creation_time"));
+        let pk_algo: PublicKeyAlgorithm = php_try!(php.parse_u8("pk_algo")).into();
+        let public_len = php_try!(php.parse_be_u32("public_len"));
+        let mpis = php_try!(PublicKey::_parse(pk_algo, &mut php));
+        if mpis.serialized_len() != public_len as usize {
+            return php.error(Error::MalformedPacket(
+                format!("Public key material is {} octets, expected {}",
+                        mpis.serialized_len(), public_len)).into());
+        }
+
+        let key = php_try!(Key5::new(time::Tm::from_pgp(creation_time),
+                                     pk_algo, mpis));
+
+        php.ok(match tag {
+            // For the benefit of Key::from_bytes.
+            Tag::Reserved => Packet::PublicKey(key.into()),
+            Tag::PublicKey => Packet::PublicKey(key.into()),
+            Tag::PublicSubkey => Packet::PublicSubkey(key.into()),
+            _ => unreachable!(),
+        })
+    }
+}
+
 impl<'a> Parse<'a, Key> for Key {
     fn from_reader<R: 'a + Read>(reader: R) -> Result<Self> {
         let bio = buffered_reader::Generic::with_cookie(
@@ -1622,28 +1905,27 @@ impl CompressedData {
         let algo: CompressionAlgorithm =
             php_try!(php.parse_u8("algo")).into();
 
-        #[allow(unreachable_patterns)]
-        match algo {
-            CompressionAlgorithm::Uncompressed => (),
-            #[cfg(feature = "compression-deflate")]
-            CompressionAlgorithm::Zip
-                | CompressionAlgorithm::Zlib => (),
-            #[cfg(feature = "compression-bzip2")]
-            CompressionAlgorithm::BZip2 => (),
-            CompressionAlgorithm::Unknown(_)
-            | CompressionAlgorithm::Private(_) =>
-                return php.fail("unknown compression algorithm"),
-            _ =>
-                return php.fail("unsupported compression algorithm"),
+        if ! algo.is_supported() {
+            return php.error(
+                Error::UnsupportedCompressionAlgorithm(algo).into());
         }
 
         let recursion_depth = php.recursion_depth();
-        let mut pp = php.ok(Packet::CompressedData(CompressedData::new(algo)))?;
+        let max_decompressed_size =
+            php.state.settings.max_decompressed_size;
+        let compressed = Arc::new(AtomicUsize::new(0));
+        let uncompressed = Arc::new(AtomicUsize::new(0));
+        let mut cd = CompressedData::new(algo);
+        cd.set_sizes(compressed.clone(), uncompressed.clone());
+        let mut pp = php.ok(Packet::CompressedData(cd))?;
 
         t!("Pushing a decompressor for {}, recursion depth = {:?}.",
            algo, recursion_depth);
 
         let reader = pp.take_reader();
+        let reader: Box<BufferedReader<Cookie> + 'a> =
+            Box::new(CountingReader::with_cookie(
+                reader, compressed, Cookie::new(recursion_depth)));
         let reader = match algo {
             CompressionAlgorithm::Uncompressed => {
                 if TRACE {
@@ -1668,6 +1950,9 @@ impl CompressedData {
                     reader, Cookie::new(recursion_depth))),
             _ => unreachable!(), // Validated above.
         };
+        let reader = Box::new(CountingReader::with_cookie(
+            reader, uncompressed, Cookie::new(recursion_depth))
+                              .with_limit(max_decompressed_size));
         pp.set_reader(reader);
 
         Ok(pp)
@@ -1728,6 +2013,30 @@ fn compressed_data_parser_test () {
     }
 }
 
+#[test]
+fn compressed_data_unsupported_algo() {
+    fn check(bytes: &[u8], expected: CompressionAlgorithm) {
+        let pp = PacketParser::from_bytes(bytes).unwrap().unwrap();
+        if let Packet::Unknown(ref u) = pp.packet {
+            assert_eq!(u.tag(), Tag::CompressedData);
+            match u.error().downcast_ref::<Error>() {
+                Some(&Error::UnsupportedCompressionAlgorithm(a)) =>
+                    assert_eq!(a, expected),
+                e => panic!("Unexpected error: {:?}", e),
+            }
+        } else {
+            panic!("Expected an Unknown packet, got {:?}", pp.packet);
+        }
+    }
+
+    // A private algorithm is never supported.
+    check(b"\xc8\x01\x6e", CompressionAlgorithm::Private(110));
+
+    #[cfg(not(feature = "compression-bzip2"))]
+    check(::tests::message("compressed-data-algo-3.gpg"),
+          CompressionAlgorithm::BZip2);
+}
+
 impl SKESK {
     /// Parses the body of an SK-ESK packet.
     fn parse<'a>(mut php: PacketHeaderParser<'a>) -> Result<PacketParser<'a>> {
@@ -1752,8 +2061,11 @@ impl SKESK {
                 let aead_algo: AEADAlgorithm =
                     php_try!(php.parse_u8("aead_algo")).into();
                 let s2k = php_try!(S2K::parse(&mut php));
-                let iv_size = php_try!(aead_algo.iv_size());
-                let digest_size = php_try!(aead_algo.digest_size());
+                // Use the sizes defined by the standard so that we
+                // can parse packets using algorithms that we don't
+                // implement.
+                let iv_size = php_try!(aead_algo.nonce_size());
+                let digest_size = php_try!(aead_algo.tag_size());
                 let aead_iv = php_try!(php.parse_bytes("aead_iv", iv_size));
 
                 // The rest of the packet is the ESK, and the AEAD
@@ -1779,7 +2091,7 @@ impl SKESK {
 
             _ => {
                 // We only support version 4 and 5 SKESK packets.
-                return php.fail("unknown version");
+                return php.unsupported_version(version);
             }
         };
 
@@ -1844,7 +2156,7 @@ impl SEIP {
         make_php_try!(php);
         let version = php_try!(php.parse_u8("version"));
         if version != 1 {
-            return php.fail("unknown version");
+            return php.unsupported_version(version);
         }
 
         php.ok(SEIP1::new().into())
@@ -1910,7 +2222,7 @@ impl AED {
 
         match version {
             1 => AED1::parse(php),
-            _ => php.fail("unknown version"),
+            _ => php.unsupported_version(version),
         }
     }
 }
@@ -1928,7 +2240,7 @@ impl AED1 {
         let chunk_size: usize =
             1 << (php_try!(php.parse_u8("chunk_size")) as usize + 6);
 
-        let iv_size = php_try!(aead.iv_size());
+        let iv_size = php_try!(aead.nonce_size());
         let iv = php_try!(php.parse_bytes("iv", iv_size));
 
         let aed = php_try!(Self::new(
@@ -2014,7 +2326,7 @@ impl PKESK {
         let version = php_try!(php.parse_u8("version"));
         match version {
             3 => PKESK3::parse(php),
-            _ => php.fail("unknown version"),
+            _ => php.unsupported_version(version),
         }
     }
 }
@@ -2094,6 +2406,33 @@ struct PacketParserState {
 
     // Whether this is the first packet in the packet sequence.
     first_packet: bool,
+
+    // Decides whether to recurse into a container.
+    recursion_filter: Option<RecursionFilter>,
+
+    /// The armor headers, if the packet sequence was ASCII armored.
+    armor_headers: Vec<(String, String)>,
+
+    /// The decrypted SEIP containers we are currently in, with
+    /// their recursion depth and whether we saw their MDC packet.
+    open_seips: Vec<(isize, bool)>,
+
+    /// The number of bytes consumed from the (dearmored) input.
+    ///
+    /// This is updated by a `CountingReader` at the bottom of the
+    /// `BufferedReader` stack.
+    consumed: Rc<Cell<u64>>,
+
+    /// The offset of the current top-level packet.
+    position: u64,
+
+    /// If set, packets with other tags are skipped.
+    ///
+    /// See `PacketParser::next_skipping`.
+    wanted_tags: Option<Vec<Tag>>,
+
+    /// Which packet headers to accept.
+    header_policy: HeaderPolicy,
 }
 
 impl PacketParserState {
@@ -2104,10 +2443,28 @@ impl PacketParserState {
             keyring_validator: Default::default(),
             tpk_validator: Default::default(),
             first_packet: true,
+            recursion_filter: None,
+            armor_headers: Vec::new(),
+            open_seips: Vec::new(),
+            consumed: Rc::new(Cell::new(0)),
+            position: 0,
+            wanted_tags: None,
+            header_policy: Default::default(),
         }
     }
 }
 
+/// A callback that is consulted before recursing into a container.
+///
+/// See `PacketParserBuilder::recursion_filter`.
+pub(crate) struct RecursionFilter(pub(crate) Box<dyn FnMut(&Header) -> bool>);
+
+impl fmt::Debug for RecursionFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("RecursionFilter")
+    }
+}
+
 /// A low-level OpenPGP message parser.
 ///
 /// A `PacketParser` provides a low-level, iterator-like interface to
@@ -2176,6 +2533,10 @@ pub struct PacketParser<'a> {
     // can't recurse, because we're missing some of the packet!
     content_was_read: bool,
 
+    // The number of bytes of the packet's body that have been
+    // consumed, including those consumed while parsing the packet.
+    body_bytes_read: u64,
+
     // Whether PacketParser::finish has been called.
     finished: bool,
 
@@ -2203,6 +2564,7 @@ impl<'a> std::fmt::Debug for PacketParser<'a> {
             .field("last_path", &self.last_path)
             .field("decrypted", &self.decrypted)
             .field("content_was_read", &self.content_was_read)
+            .field("body_bytes_read", &self.body_bytes_read)
             .field("settings", &self.state.settings)
             .field("map", &self.map)
             .finish()
@@ -2277,6 +2639,17 @@ impl PacketParserEOF {
         }
     }
 
+    /// Returns the armor headers.
+    ///
+    /// If the packet sequence was ASCII armored and the armor was
+    /// stripped by the `PacketParser` (see
+    /// `PacketParserBuilder::dearmor`), this returns the key-value
+    /// pairs from the armor header, e.g. `Comment` or `Version`.
+    /// Otherwise, an empty slice is returned.
+    pub fn armor_headers(&self) -> &[(String, String)] {
+        &self.state.armor_headers[..]
+    }
+
     /// Returns the path of the last packet.
     pub fn last_path(&self) -> &[usize] {
         &self.last_path[..]
@@ -2439,18 +2812,19 @@ impl<'a> Parse<'a, PacketParserResult<'a>> for PacketParser<'a> {
             -> Result<PacketParserResult<'a>> {
         PacketParserBuilder::from_bytes(bytes)?.finalize()
     }
-}
 
-impl <'a> PacketParser<'a> {
     /// Starts parsing an OpenPGP message stored in a `BufferedReader`
     /// object.
     ///
     /// This function returns a `PacketParser` for the first packet in
     /// the stream.
-    pub(crate) fn from_buffered_reader(bio: Box<BufferedReader<Cookie> + 'a>)
+    fn from_buffered_reader(bio: Box<BufferedReader<Cookie> + 'a>)
             -> Result<PacketParserResult<'a>> {
         PacketParserBuilder::from_buffered_reader(bio)?.finalize()
     }
+}
+
+impl <'a> PacketParser<'a> {
 
     /// Returns the reader stack, replacing it with a
     /// `buffered_reader::EOF` reader.
@@ -2490,6 +2864,61 @@ impl <'a> PacketParser<'a> {
         self.decrypted
     }
 
+    /// Returns the number of bytes of the packet's body that have
+    /// been read.
+    ///
+    /// This includes the bytes that the parser consumed while parsing
+    /// the packet's fields, e.g., a literal data packet's file name,
+    /// as well as any content read by the caller.  For containers
+    /// whose content is decompressed or decrypted, the content is
+    /// counted after it has been decompressed or decrypted.
+    pub fn bytes_read(&self) -> u64 {
+        self.body_bytes_read
+    }
+
+    /// Returns the number of bytes of the packet's body that have not
+    /// yet been read.
+    ///
+    /// This is only known if the packet's length is
+    /// `BodyLength::Full`.  For packets using a partial body encoding
+    /// or an indeterminate length, and for containers whose content
+    /// is decompressed or decrypted, this returns `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::{Result, Packet};
+    /// # use openpgp::parse::{Parse, PacketParserResult, PacketParser};
+    /// # f(include_bytes!("../../tests/data/keys/testy.pgp"));
+    /// #
+    /// # fn f(data: &[u8]) -> Result<()> {
+    /// let mut ppr = PacketParser::from_bytes(data)?;
+    /// while let PacketParserResult::Some(pp) = ppr {
+    ///     if let Packet::UserID(_) = pp.packet {
+    ///         // The parser consumed the whole body.
+    ///         assert_eq!(pp.bytes_remaining(), Some(0));
+    ///     }
+    ///     ppr = pp.recurse()?.1;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn bytes_remaining(&self) -> Option<u64> {
+        match self.packet {
+            Packet::CompressedData(_) => return None,
+            Packet::SEIP(_) | Packet::AED(_) if self.decrypted =>
+                return None,
+            _ => (),
+        }
+
+        if let BodyLength::Full(len) = self.header.length {
+            Some((len as u64).saturating_sub(self.body_bytes_read))
+        } else {
+            None
+        }
+    }
+
     /// Returns the path of the last packet.
     pub fn last_path(&self) -> &[usize] {
         &self.last_path[..]
@@ -2500,6 +2929,28 @@ impl <'a> PacketParser<'a> {
         &self.path[..]
     }
 
+    /// Returns the byte offset of the current top-level packet.
+    ///
+    /// The offset is relative to the start of the input, or, if the
+    /// input is ASCII armored, the start of the decoded data.  If the
+    /// current packet is inside a container, this returns the offset
+    /// of the top-level container.
+    ///
+    /// The offset can be passed to [`PacketParserBuilder::seek_to`]
+    /// to resume parsing at this packet.
+    ///
+    ///   [`PacketParserBuilder::seek_to`]: struct.PacketParserBuilder.html#method.seek_to
+    pub fn position(&self) -> u64 {
+        self.state.position
+    }
+
+    /// Returns the armor headers.
+    ///
+    /// See `PacketParserEOF::armor_headers`.
+    pub fn armor_headers(&self) -> &[(String, String)] {
+        &self.state.armor_headers[..]
+    }
+
     /// The current packet's recursion depth.
     ///
     /// A top-level packet has a recursion depth of 0.  Packets in a
@@ -2571,6 +3022,63 @@ impl <'a> PacketParser<'a> {
         }
     }
 
+    /// Returns whether the packets seen so far could form an OpenPGP
+    /// Message.
+    ///
+    /// The current packet is included.  Since more packets may
+    /// follow, this never returns `MessageValidity::Message`: either
+    /// the packet sequence is a valid prefix of a message
+    /// (`MessageValidity::MessagePrefix`), or it definitely isn't a
+    /// message (`MessageValidity::Error`).  This allows streaming
+    /// consumers to bail out early.  At the end of the packet
+    /// sequence, use [`PacketParserEOF::is_message`].
+    ///
+    ///   [`PacketParserEOF::is_message`]: struct.PacketParserEOF.html#method.is_message
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::Result;
+    /// # use openpgp::parse::{Parse, PacketParserResult, PacketParser};
+    /// # f(include_bytes!("../../tests/data/keys/testy.pgp"));
+    /// #
+    /// # fn f(data: &[u8]) -> Result<()> {
+    /// let mut ppr = PacketParser::from_bytes(data)?;
+    /// while let PacketParserResult::Some(pp) = ppr {
+    ///     if pp.message_validity().is_err() {
+    ///         // A TPK is not a message.
+    ///         break;
+    ///     }
+    ///     ppr = pp.recurse()?.1;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn message_validity(&self) -> ::message::MessageValidity {
+        self.state.message_validator.check()
+    }
+
+    /// Returns whether the packets seen so far could form an OpenPGP
+    /// keyring.
+    ///
+    /// See [`PacketParser::message_validity`] for details.
+    ///
+    ///   [`PacketParser::message_validity`]: #method.message_validity
+    pub fn keyring_validity(&self) -> ::tpk::KeyringValidity {
+        self.state.keyring_validator.check()
+    }
+
+    /// Returns whether the packets seen so far could form an OpenPGP
+    /// TPK.
+    ///
+    /// See [`PacketParser::message_validity`] for details.
+    ///
+    ///   [`PacketParser::message_validity`]: #method.message_validity
+    pub fn tpk_validity(&self) -> ::tpk::TPKValidity {
+        self.state.tpk_validator.check()
+    }
+
     /// Returns Ok if the data appears to be a legal packet.
     ///
     /// This is just a heuristic.  It can be used for recovering from
@@ -2628,8 +3136,8 @@ impl <'a> PacketParser<'a> {
     /// stream.  If there are no packets left, this function returns
     /// `bio`.
     fn parse(mut bio: Box<BufferedReader<Cookie> + 'a>,
-             state: PacketParserState,
-             path: Vec<usize>)
+             mut state: PacketParserState,
+             mut path: Vec<usize>)
         -> Result<ParserResult<'a>>
     {
         assert!(path.len() > 0);
@@ -2640,6 +3148,20 @@ impl <'a> PacketParser<'a> {
 
         let recursion_depth = path.len() as isize - 1;
 
+        if let Some(wanted_tags) = state.wanted_tags.take() {
+            let r = Self::skip_unwanted(&mut bio, &mut state, &mut path,
+                                        &wanted_tags);
+            state.wanted_tags = Some(wanted_tags);
+            r?;
+        }
+
+        // At the top level, `bio` is the `CountingReader` (see
+        // `PacketParserBuilder::finalize`), so this is the start of
+        // the packet.
+        if recursion_depth == 0 {
+            state.position = state.consumed.get();
+        }
+
         // When header encounters an EOF, it returns an error.  But,
         // we want to return None.  Try a one byte read.
         if bio.data(1)?.len() == 0 {
@@ -2721,6 +3243,11 @@ impl <'a> PacketParser<'a> {
 
         let tag = header.ctb.tag;
 
+        // Junk is always turned into an Unknown packet.
+        if skip == 0 {
+            state.header_policy.check(&header)?;
+        }
+
         // A buffered_reader::Dup always has an inner.
         let mut bio = Box::new(bio).into_inner().unwrap();
 
@@ -2940,6 +3467,7 @@ impl <'a> PacketParser<'a> {
                             reader_, recursion_depth - 1)?;
                         fake_eof = fake_eof_;
                         if ! fake_eof {
+                            self.check_mdc(recursion_depth - 1)?;
                             self.path.pop().unwrap();
                             *self.path.last_mut()
                                 .expect("A path is never empty") += 1;
@@ -2952,6 +3480,7 @@ impl <'a> PacketParser<'a> {
                     pp.state.message_validator.push(pp.packet.tag(), &path);
                     pp.state.keyring_validator.push(pp.packet.tag());
                     pp.state.tpk_validator.push(pp.packet.tag());
+                    pp.saw_mdc_maybe();
 
                     pp.last_path = self.last_path;
 
@@ -2961,6 +3490,141 @@ impl <'a> PacketParser<'a> {
         }
     }
 
+    /// Like `next()`, but skips packets whose tag is not in `tags`.
+    ///
+    /// The returned `PacketParserResult` is positioned on the next
+    /// packet at the current or a lower recursion depth whose tag is
+    /// in `tags`.  Packets that are skipped are not parsed, and their
+    /// bodies are neither buffered nor hashed; they are simply
+    /// consumed.  This makes scanning a large input for a few kinds
+    /// of packets, say, signatures, much cheaper.  Note: packets using
+    /// a partial or an indeterminate body length are not skipped,
+    /// because their length is not known in advance.
+    ///
+    /// Since skipped packets are not hashed, this should not be used
+    /// when checking signatures over the skipped data.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::Result;
+    /// # use openpgp::packet::Tag;
+    /// # use openpgp::parse::{Parse, PacketParserResult, PacketParser};
+    /// # f(include_bytes!("../../tests/data/keys/testy.pgp"));
+    /// #
+    /// # fn f(data: &[u8]) -> Result<()> {
+    /// let mut ppr = PacketParser::from_bytes(data)?;
+    /// // Skip the primary key.
+    /// if let PacketParserResult::Some(pp) = ppr {
+    ///     ppr = pp.next_skipping(&[Tag::Signature])?.1;
+    /// }
+    /// while let PacketParserResult::Some(pp) = ppr {
+    ///     assert_eq!(pp.packet.tag(), Tag::Signature);
+    ///     ppr = pp.next_skipping(&[Tag::Signature])?.1;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn next_skipping(mut self, tags: &[Tag])
+        -> Result<(Packet, PacketParserResult<'a>)>
+    {
+        self.state.wanted_tags = Some(tags.to_vec());
+        let (packet, mut ppr) = self.next()?;
+        match ppr {
+            PacketParserResult::Some(ref mut pp) =>
+                pp.state.wanted_tags = None,
+            PacketParserResult::EOF(ref mut eof) =>
+                eof.state.wanted_tags = None,
+        }
+        Ok((packet, ppr))
+    }
+
+    /// Skips the packets whose tags are not in `wanted_tags`.
+    ///
+    /// Only packets with a full body length are skipped.  `path` is
+    /// updated to point to the first packet that is not skipped.
+    fn skip_unwanted(bio: &mut Box<BufferedReader<Cookie> + 'a>,
+                     state: &mut PacketParserState,
+                     path: &mut Vec<usize>,
+                     wanted_tags: &[Tag])
+        -> Result<()>
+    {
+        loop {
+            if bio.data(1)?.len() == 0 {
+                return Ok(());
+            }
+
+            let mut dup = buffered_reader::Dup::with_cookie(
+                mem::replace(bio, Box::new(buffered_reader::EOF::with_cookie(
+                    Default::default()))),
+                Cookie::default());
+            let header = Header::parse(&mut dup);
+            let header_len = dup.total_out();
+            *bio = Box::new(dup).into_inner().unwrap();
+
+            // Let the parser deal with anything out of the ordinary.
+            let header = match header {
+                Ok(header) => header,
+                Err(_) => return Ok(()),
+            };
+            let tag = header.ctb.tag;
+            if header.valid(false).is_err() || wanted_tags.contains(&tag) {
+                return Ok(());
+            }
+            let body_len = match header.length {
+                BodyLength::Full(len) => len,
+                _ => return Ok(()),
+            };
+
+            skip_hard(bio.as_mut(), header_len as u64 + body_len as u64)?;
+
+            state.message_validator.push(tag, path);
+            state.keyring_validator.push(tag);
+            state.tpk_validator.push(tag);
+            state.first_packet = false;
+            *path.last_mut().expect("A path is never empty") += 1;
+        }
+    }
+
+    /// Records that the current packet is the MDC of the innermost
+    /// SEIP container, if it is.
+    fn saw_mdc_maybe(&mut self) {
+        if let Packet::MDC(_) = self.packet {
+            let depth = self.recursion_depth();
+            if let Some(seip) = self.state.open_seips.last_mut() {
+                if seip.0 + 1 == depth {
+                    seip.1 = true;
+                }
+            }
+        }
+    }
+
+    /// Checks that the container at `depth`, which we are about to
+    /// leave, had an MDC packet if it is a SEIP container.
+    ///
+    /// A SEIP container without an MDC packet is an integrity
+    /// downgrade, and is rejected unless the user explicitly opted in
+    /// using `PacketParserBuilder::allow_missing_mdc`.
+    fn check_mdc(&mut self, depth: isize) -> Result<()> {
+        let saw_mdc = match self.state.open_seips.last() {
+            Some(&(d, saw_mdc)) if d == depth => saw_mdc,
+            _ => return Ok(()),
+        };
+        self.state.open_seips.pop();
+
+        if ! saw_mdc {
+            if self.state.settings.allow_missing_mdc {
+                // Fabricate the MDC so that the message still
+                // validates.
+                self.state.message_validator.push(Tag::MDC, &self.path);
+            } else {
+                return Err(Error::MissingMDC.into());
+            }
+        }
+        Ok(())
+    }
+
     /// Finishes parsing the current packet and starts parsing the
     /// next one, recursing if possible.
     ///
@@ -2976,7 +3640,7 @@ impl <'a> PacketParser<'a> {
     /// than one level at a time.
     ///
     ///   [`next()`]: #method.next
-    pub fn recurse(self) -> Result<(Packet, PacketParserResult<'a>)> {
+    pub fn recurse(mut self) -> Result<(Packet, PacketParserResult<'a>)> {
         let indent = self.recursion_depth();
         tracer!(TRACE, "PacketParser::recurse", indent);
         t!("({:?}, path: {:?}, level: {:?})",
@@ -3001,12 +3665,26 @@ impl <'a> PacketParser<'a> {
                         already read.",
                        self.packet.tag());
 
+                    // Drop through.
+                } else if ! self.state.recursion_filter.as_mut()
+                    .map(|filter| (filter.0)(&self.header))
+                    .unwrap_or(true)
+                {
+                    t!("Not recursing into the {:?} packet, vetoed by the \
+                        recursion filter.",
+                       self.packet.tag());
+
                     // Drop through.
                 } else {
                     let mut last_path = self.last_path;
                     last_path.clear();
                     last_path.extend_from_slice(&self.path[..]);
 
+                    if let Packet::SEIP(_) = self.packet {
+                        let depth = self.recursion_depth();
+                        self.state.open_seips.push((depth, false));
+                    }
+
                     let mut path = self.path;
                     path.push(0);
 
@@ -3021,6 +3699,7 @@ impl <'a> PacketParser<'a> {
                                 pp.packet.tag(), &path);
                             pp.state.keyring_validator.push(pp.packet.tag());
                             pp.state.tpk_validator.push(pp.packet.tag());
+                            pp.saw_mdc_maybe();
 
                             pp.last_path = last_path;
 
@@ -3107,6 +3786,56 @@ impl <'a> PacketParser<'a> {
         }
     }
 
+    /// Reads the current packet and all following packets into a
+    /// `PacketPile`.
+    ///
+    /// This allows switching from streaming to random access in the
+    /// middle of a message.  The packets' contents are buffered, and
+    /// container packets are recursed into, as far as the
+    /// `PacketParserBuilder`'s [`max_recursion_depth`] permits.
+    /// Packets that have already been returned by the parser are
+    /// not part of the `PacketPile`.
+    ///
+    ///   [`max_recursion_depth`]: struct.PacketParserBuilder.html#method.max_recursion_depth
+    ///
+    /// # Errors
+    ///
+    /// The current packet must be a top-level packet.  If it is
+    /// inside a container, this returns `Error::InvalidOperation`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::Result;
+    /// # use openpgp::parse::{Parse, PacketParserResult, PacketParser};
+    /// # f(include_bytes!("../../tests/data/keys/testy.pgp")).unwrap();
+    /// #
+    /// # fn f(message_data: &[u8]) -> Result<()> {
+    /// let ppr = PacketParser::from_bytes(message_data)?;
+    /// if let PacketParserResult::Some(pp) = ppr {
+    ///     // Inspect the first packet, then read the rest of the
+    ///     // message into memory.
+    ///     assert_eq!(pp.packet.tag(), openpgp::packet::Tag::PublicKey);
+    ///     let (_, ppr) = pp.next()?;
+    ///     if let PacketParserResult::Some(pp) = ppr {
+    ///         let pile = pp.into_packet_pile()?;
+    ///         assert_eq!(pile.children().count(), 4);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_packet_pile(mut self) -> Result<::PacketPile> {
+        if self.recursion_depth() != 0 {
+            return Err(Error::InvalidOperation(
+                "Not at a top-level packet".into()).into());
+        }
+
+        self.state.settings.buffer_unread_content = true;
+        ::PacketPile::from_packet_parser(PacketParserResult::Some(self))
+    }
+
     /// Finishes parsing the current packet.
     ///
     /// By default, this drops any unread content.  Use, for instance,
@@ -3156,6 +3885,37 @@ impl <'a> PacketParser<'a> {
     }
 
     /// Returns a reference to the current packet's header.
+    ///
+    /// The header is available before the packet's body is read.
+    /// This allows, for instance, checking the declared body length
+    /// before deciding whether to buffer the body.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::Result;
+    /// # use openpgp::packet::BodyLength;
+    /// # use openpgp::parse::{Parse, PacketParserResult, PacketParser};
+    /// # f(include_bytes!("../../tests/data/messages/literal-mode-t-partial-body.gpg"));
+    /// #
+    /// # fn f(message_data: &[u8]) -> Result<()> {
+    /// let mut ppr = PacketParser::from_bytes(message_data)?;
+    /// while let PacketParserResult::Some(pp) = ppr {
+    ///     match pp.header().length {
+    ///         BodyLength::Full(len) if len > 1 << 20 =>
+    ///             eprintln!("Large {} packet ({} bytes)",
+    ///                       pp.header().ctb.tag, len),
+    ///         BodyLength::Indeterminate =>
+    ///             eprintln!("{} packet has an indeterminate length",
+    ///                       pp.header().ctb.tag),
+    ///         _ => (),
+    ///     }
+    ///     ppr = pp.recurse()?.1;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn header(&self) -> &Header {
         &self.header
     }
@@ -3215,41 +3975,58 @@ impl<'a> BufferedReader<Cookie> for PacketParser<'a> {
 
     fn consume(&mut self, amount: usize) -> &[u8] {
         self.content_was_read |= amount > 0;
+        self.body_bytes_read += amount as u64;
         self.reader.consume(amount)
     }
 
     fn data_consume(&mut self, amount: usize) -> io::Result<&[u8]> {
         self.content_was_read |= amount > 0;
-        self.reader.data_consume(amount)
+        // data_consume may return less than `amount` bytes, in which
+        // case only those are consumed.
+        let got = cmp::min(self.reader.data(amount)?.len(), amount);
+        self.body_bytes_read += got as u64;
+        self.reader.data_consume(got)
     }
 
     fn data_consume_hard(&mut self, amount: usize) -> io::Result<&[u8]> {
         self.content_was_read |= amount > 0;
-        self.reader.data_consume_hard(amount)
+        let data = self.reader.data_consume_hard(amount)?;
+        self.body_bytes_read += amount as u64;
+        Ok(data)
     }
 
     fn read_be_u16(&mut self) -> io::Result<u16> {
         self.content_was_read = true;
-        self.reader.read_be_u16()
+        let v = self.reader.read_be_u16()?;
+        self.body_bytes_read += 2;
+        Ok(v)
     }
 
     fn read_be_u32(&mut self) -> io::Result<u32> {
         self.content_was_read = true;
-        self.reader.read_be_u32()
+        let v = self.reader.read_be_u32()?;
+        self.body_bytes_read += 4;
+        Ok(v)
     }
 
     fn steal(&mut self, amount: usize) -> io::Result<Vec<u8>> {
         self.content_was_read |= amount > 0;
-        self.reader.steal(amount)
+        let data = self.reader.steal(amount)?;
+        self.body_bytes_read += data.len() as u64;
+        Ok(data)
     }
 
     fn steal_eof(&mut self) -> io::Result<Vec<u8>> {
         self.content_was_read = true;
-        self.reader.steal_eof()
+        let data = self.reader.steal_eof()?;
+        self.body_bytes_read += data.len() as u64;
+        Ok(data)
     }
 
     fn drop_eof(&mut self) -> io::Result<bool> {
         self.content_was_read = true;
+        let len = self.reader.data_eof()?.len();
+        self.body_bytes_read += len as u64;
         self.reader.drop_eof()
     }
 
@@ -3341,6 +4118,42 @@ fn packet_parser_reader_interface() {
     assert!(packet.body.is_none());
 }
 
+// Check that the number of read and remaining bytes is tracked.
+#[test]
+fn packet_parser_bytes_remaining() {
+    use std::io::Read;
+
+    // A literal data packet with an 18 byte body: 12 bytes of
+    // header fields, and 6 bytes of content.
+    let mut pp = PacketParser::from_bytes(
+        ::tests::message("literal-mode-b.gpg")).unwrap().unwrap();
+    assert_eq!(pp.bytes_read(), 12);
+    assert_eq!(pp.bytes_remaining(), Some(6));
+
+    // Read half of the content.
+    let mut buf = [0u8; 3];
+    pp.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"FOO");
+    assert_eq!(pp.bytes_read(), 15);
+    assert_eq!(pp.bytes_remaining(), Some(3));
+
+    // And the rest.
+    let mut rest = Vec::new();
+    pp.read_to_end(&mut rest).unwrap();
+    assert_eq!(&rest[..], b"BAR");
+    assert_eq!(pp.bytes_read(), 18);
+    assert_eq!(pp.bytes_remaining(), Some(0));
+
+    // The length of partial body encoded packets is not known.
+    let mut pp = PacketParser::from_bytes(
+        ::tests::message("literal-mode-t-partial-body.gpg"))
+        .unwrap().unwrap();
+    let mut buf = [0u8; 3];
+    pp.read_exact(&mut buf).unwrap();
+    assert!(pp.bytes_read() > 3);
+    assert_eq!(pp.bytes_remaining(), None);
+}
+
 impl<'a> PacketParser<'a> {
     /// Tries to decrypt the current packet.
     ///
@@ -3429,19 +4242,32 @@ impl<'a> PacketParser<'a> {
 
                 // An MDC consists of a 1-byte CTB, a 1-byte length
                 // encoding, and a 20-byte hash.
-                let mut reader = buffered_reader::Reserve::with_cookie(
-                    Box::new(reader), 1 + 1 + 20,
-                    Cookie::new(self.recursion_depth()));
-                reader.cookie_mut().fake_eof = true;
+                //
+                // If the user is willing to accept a missing MDC, we
+                // cannot reserve the data, because it may be part of
+                // the last packet.  This means that in this mode,
+                // messages with compressed data packets using an
+                // indeterminate length encoding cannot be parsed.
+                let mut reader: Box<BufferedReader<Cookie> + 'a> =
+                    if self.state.settings.allow_missing_mdc {
+                        Box::new(reader)
+                    } else {
+                        let mut reader =
+                            buffered_reader::Reserve::with_cookie(
+                                Box::new(reader), 1 + 1 + 20,
+                                Cookie::new(self.recursion_depth()));
+                        reader.cookie_mut().fake_eof = true;
 
-                t!("Pushing buffered_reader::Reserve, level: {}.",
-                   self.recursion_depth());
+                        t!("Pushing buffered_reader::Reserve, level: {}.",
+                           self.recursion_depth());
+                        Box::new(reader)
+                    };
 
                 // Consume the header.  This shouldn't fail, because
                 // it worked when reading the header.
                 reader.data_consume_hard(bl + 2).unwrap();
 
-                self.reader = Box::new(reader);
+                self.reader = reader;
                 self.decrypted = true;
 
                 Ok(())
@@ -3687,6 +4513,141 @@ mod test {
         return ppr;
     }
 
+    #[test]
+    fn missing_mdc() {
+        use std::io::Write;
+        use constants::DataFormat;
+        use crypto::symmetric;
+        use serialize::SerializeInto;
+
+        let algo = SymmetricAlgorithm::AES128;
+        let sk = SessionKey::new(algo.key_size().unwrap());
+
+        // A SEIP packet containing a literal data packet, but no MDC.
+        let mut lit = Literal::new(DataFormat::Binary);
+        lit.set_body(b"Hello world, no MDC here.".to_vec());
+        let lit = Packet::Literal(lit).to_vec().unwrap();
+
+        let bl = algo.block_size().unwrap();
+        let mut plaintext = vec![0u8; bl];
+        ::crypto::random(&mut plaintext);
+        let tail = plaintext[bl - 2..].to_vec();
+        plaintext.extend_from_slice(&tail);
+        plaintext.extend_from_slice(&lit);
+
+        let seip = |plaintext: &[u8]| -> Vec<u8> {
+            let mut body = vec![1];
+            {
+                let mut e = symmetric::Encryptor::new(algo, &sk, &mut body)
+                    .unwrap();
+                e.write_all(plaintext).unwrap();
+            }
+            assert!(body.len() < 192);
+            let mut msg = vec![0xd2, body.len() as u8];
+            msg.extend_from_slice(&body);
+            msg
+        };
+        let msg = seip(&plaintext);
+
+        let parse = |msg: &[u8], allow_missing_mdc| -> Result<Vec<Packet>> {
+            let mut ppr = PacketParserBuilder::from_bytes(msg)?
+                .buffer_unread_content()
+                .allow_missing_mdc(allow_missing_mdc)
+                .finalize()?;
+            let mut packets = Vec::new();
+            while let PacketParserResult::Some(mut pp) = ppr {
+                if let Packet::SEIP(_) = pp.packet {
+                    pp.decrypt(algo, &sk)?;
+                }
+                let (packet, ppr_) = pp.recurse()?;
+                packets.push(packet);
+                ppr = ppr_;
+            }
+            if let PacketParserResult::EOF(eof) = ppr {
+                eof.is_message()?;
+            }
+            Ok(packets)
+        };
+
+        // Rejected by default.
+        assert!(parse(&msg, false).is_err());
+
+        // But accepted if the user opts in.
+        let packets = parse(&msg, true).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].tag(), Tag::SEIP);
+        if let Packet::Literal(ref lit) = packets[1] {
+            assert_eq!(lit.body(), Some(&b"Hello world, no MDC here."[..]));
+        } else {
+            panic!("expected a literal data packet, got {:?}", packets[1]);
+        }
+
+        // Opting in must not break integrity protected messages.
+        plaintext.extend_from_slice(&[0xd3, 0x14]);
+        let mut hash = [0u8; 20];
+        {
+            let mut h = HashAlgorithm::SHA1.context().unwrap();
+            h.update(&plaintext);
+            h.digest(&mut hash);
+        }
+        plaintext.extend_from_slice(&hash);
+        let msg = seip(&plaintext);
+
+        for &allow_missing_mdc in [false, true].iter() {
+            let packets = parse(&msg, allow_missing_mdc).unwrap();
+            assert_eq!(packets.len(), 3);
+            assert_eq!(packets[0].tag(), Tag::SEIP);
+            assert_eq!(packets[1].tag(), Tag::Literal);
+            if let Packet::MDC(ref mdc) = packets[2] {
+                assert!(mdc.valid());
+            } else {
+                panic!("expected an MDC packet, got {:?}", packets[2]);
+            }
+        }
+    }
+
+    #[test]
+    fn unimplemented_aead() {
+        // A SKESK5 and an AED packet using OCB, which we don't
+        // implement, but can parse.
+        assert!(! AEADAlgorithm::OCB.is_supported());
+        let packet = |tag: u8, body: &[u8]| {
+            let mut p = vec![0xc0 | tag, body.len() as u8];
+            p.extend_from_slice(body);
+            p
+        };
+
+        // Version, AES256, OCB, simple S2K using SHA256, the nonce,
+        // the ESK, and the tag.
+        let mut skesk = vec![5, 9, 2, 0, 8];
+        skesk.extend_from_slice(&[0xaa; 15]);
+        skesk.extend_from_slice(&[0xbb; 32]);
+        skesk.extend_from_slice(&[0xcc; 16]);
+        // Version, AES256, OCB, chunk size, the nonce, and some
+        // ciphertext.
+        let mut aed = vec![1, 9, 2, 0];
+        aed.extend_from_slice(&[0xaa; 15]);
+        aed.extend_from_slice(&[0xdd; 40]);
+
+        let mut msg = packet(3, &skesk);
+        msg.extend_from_slice(&packet(20, &aed));
+        let pile = ::PacketPile::from_bytes(&msg).unwrap();
+
+        if let Some(Packet::SKESK(SKESK::V5(ref s))) = pile.path_ref(&[0]) {
+            assert_eq!(s.aead_algo(), AEADAlgorithm::OCB);
+            assert_eq!(s.aead_iv(), &[0xaa; 15][..]);
+            assert_eq!(s.aead_digest(), &[0xcc; 16][..]);
+        } else {
+            panic!("expected a SKESK5 packet, got {:?}", pile.path_ref(&[0]));
+        }
+        if let Some(Packet::AED(AED::V1(ref a))) = pile.path_ref(&[1]) {
+            assert_eq!(a.aead(), AEADAlgorithm::OCB);
+            assert_eq!(a.iv(), &[0xaa; 15][..]);
+        } else {
+            panic!("expected an AED packet, got {:?}", pile.path_ref(&[1]));
+        }
+    }
+
     #[test]
     fn decrypt_test() {
         for test in DECRYPT_TESTS.iter() { for stream in [false, true].iter() {
@@ -3847,6 +4808,51 @@ mod test {
         }
     }
 
+    #[test]
+    fn validity() {
+        // Returns the validities after each packet as (message,
+        // keyring, tpk), where true means the sequence is a valid
+        // prefix.
+        fn validities(data: &[u8]) -> Vec<(bool, bool, bool)> {
+            let mut v = Vec::new();
+            let mut ppr = PacketParser::from_bytes(data).unwrap();
+            while let PacketParserResult::Some(pp) = ppr {
+                let m = pp.message_validity();
+                let k = pp.keyring_validity();
+                let t = pp.tpk_validity();
+                assert!(! m.is_message() && ! k.is_keyring() && ! t.is_tpk());
+                v.push((m.is_message_prefix(), k.is_keyring_prefix(),
+                        t.is_tpk_prefix()));
+                ppr = pp.recurse().unwrap().1;
+            }
+            v
+        }
+
+        // [ Literal ] is a message.
+        assert_eq!(validities(::tests::message("literal-mode-b.gpg")),
+                   vec![ (true, false, false) ]);
+
+        // [ PublicKey, UserID, Signature, ... ] is a TPK.
+        let v = validities(::tests::key("testy.pgp"));
+        assert_eq!(v.len(), 5);
+        assert!(v.iter().all(|&v| v == (false, true, true)));
+
+        // [ Literal, UserID ] is nothing.
+        let mut data = ::tests::message("literal-mode-b.gpg").to_vec();
+        data.extend_from_slice(::tests::message("userid-bare.gpg"));
+        assert_eq!(validities(&data),
+                   vec![ (true, false, false), (false, false, false) ]);
+
+        // [ PublicKey, ..., PublicKey, ... ] is a keyring, but not
+        // a TPK.
+        let mut data = ::tests::key("testy.pgp").to_vec();
+        data.extend_from_slice(::tests::key("testy.pgp"));
+        let v = validities(&data);
+        assert_eq!(v.len(), 10);
+        assert!(v[..5].iter().all(|&v| v == (false, true, true)));
+        assert!(v[5..].iter().all(|&v| v == (false, true, false)));
+    }
+
     // If we don't decrypt the SEIP packet, it shows up as opaque
     // content.
     #[test]
@@ -4023,4 +5029,187 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn zero_length_packets() {
+        let msg = [
+            // A trust packet with an empty body.
+            0xb0, 0x00,
+            // A literal data packet with an empty body.
+            0xcb, 0x06, b'b', 0x00, 0x00, 0x00, 0x00, 0x00,
+            // A marker packet.
+            0xa8, 0x03, b'P', b'G', b'P',
+            // A marker packet with an empty body is malformed.
+            0xa8, 0x00,
+            // And we still get to the last packet.
+            0xb0, 0x01, 0x07,
+        ];
+
+        let mut ppr = PacketParserBuilder::from_bytes(&msg[..]).unwrap()
+            .buffer_unread_content()
+            .finalize().unwrap();
+        let mut packets = Vec::new();
+        while let PacketParserResult::Some(mut pp) = ppr {
+            let mut body = Vec::new();
+            pp.read_to_end(&mut body).unwrap();
+            assert_eq!(body.len(), 0);
+            assert_eq!(pp.buffer_unread_content().unwrap().len(), 0);
+
+            let (packet, tmp) = pp.recurse().unwrap();
+            packets.push(packet);
+            ppr = tmp;
+        }
+        assert_eq!(packets.len(), 5);
+
+        if let &Packet::Trust(ref t) = &packets[0] {
+            assert_eq!(t.value(), &b""[..]);
+        } else {
+            panic!("expected a trust packet, got {:?}", packets[0]);
+        }
+        if let &Packet::Literal(ref l) = &packets[1] {
+            assert_eq!(l.body().unwrap_or(&b""[..]), &b""[..]);
+            assert_eq!(l.filename(), None);
+        } else {
+            panic!("expected a literal data packet, got {:?}", packets[1]);
+        }
+        assert_match!(&Packet::Marker(_) = &packets[2]);
+        if let &Packet::Unknown(ref u) = &packets[3] {
+            assert_eq!(u.tag(), Tag::Marker);
+        } else {
+            panic!("expected an unknown packet, got {:?}", packets[3]);
+        }
+        if let &Packet::Trust(ref t) = &packets[4] {
+            assert_eq!(t.value(), &[7][..]);
+        } else {
+            panic!("expected a trust packet, got {:?}", packets[4]);
+        }
+    }
+
+    #[test]
+    fn next_skipping() {
+        for name in &["testy.pgp", "neal.pgp"] {
+            let data = ::tests::key(name);
+
+            // The signatures, as seen by a normal parse.
+            let mut expected = Vec::new();
+            let mut ppr = PacketParser::from_bytes(data).unwrap();
+            while let PacketParserResult::Some(pp) = ppr {
+                let (packet, ppr_) = pp.next().unwrap();
+                if let Packet::Signature(_) = packet {
+                    expected.push(packet);
+                }
+                ppr = ppr_;
+            }
+            assert!(expected.len() > 0);
+
+            let mut sigs = Vec::new();
+            let mut ppr = PacketParser::from_bytes(data).unwrap();
+            let mut first = true;
+            while let PacketParserResult::Some(pp) = ppr {
+                if ! first {
+                    assert_eq!(pp.packet.tag(), Tag::Signature);
+                }
+                first = false;
+
+                let (packet, ppr_) =
+                    pp.next_skipping(&[Tag::Signature]).unwrap();
+                if let Packet::Signature(_) = packet {
+                    sigs.push(packet);
+                }
+                ppr = ppr_;
+            }
+            assert_eq!(sigs, expected);
+
+            // The skipped packets are still accounted for.
+            if let PacketParserResult::EOF(eof) = ppr {
+                assert!(eof.is_tpk().is_ok());
+            } else {
+                unreachable!();
+            }
+        }
+    }
+
+    #[test]
+    fn header() {
+        let body = b"b\x00\x00\x00\x00\x00hello world";
+
+        // A new format packet with a full length.
+        let mut full = vec![ 0xcb, body.len() as u8 ];
+        full.extend_from_slice(body);
+
+        // An old format packet with an indeterminate length.
+        let mut indeterminate = vec![ 0xaf ];
+        indeterminate.extend_from_slice(body);
+
+        for (data, length, new_format) in vec![
+            (&full[..], BodyLength::Full(body.len() as u32), true),
+            (&indeterminate[..], BodyLength::Indeterminate, false),
+            (::tests::message("literal-mode-t-partial-body.gpg"),
+             BodyLength::Partial(4096), true),
+        ] {
+            let mut ppr = PacketParser::from_bytes(data).unwrap();
+            if let PacketParserResult::Some(ref mut pp) = ppr {
+                // The header is available before the body is read.
+                assert_eq!(pp.header().ctb.tag, Tag::Literal);
+                assert_eq!(pp.header().length, length);
+                match pp.header().ctb {
+                    CTB::New(_) => assert!(new_format),
+                    CTB::Old(_) => assert!(! new_format),
+                }
+
+                assert!(pp.buffer_unread_content().unwrap().len() > 0);
+                assert_eq!(pp.header().length, length);
+            } else {
+                panic!("Expected a literal packet");
+            }
+        }
+    }
+
+    #[test]
+    fn into_packet_pile() {
+        use PacketPile;
+
+        for name in &["neal.pgp", "testy.pgp", "dkg.gpg"] {
+            let data = ::tests::key(name);
+            let reference = PacketPile::from_bytes(data).unwrap();
+
+            // Parse the first packet using the streaming API.
+            let ppr = PacketParser::from_bytes(data).unwrap();
+            let pp = ppr.unwrap();
+            assert_eq!(pp.packet.tag(), Tag::PublicKey);
+            let (packet, ppr) = pp.next().unwrap();
+            assert_eq!(&packet, reference.children().next().unwrap());
+
+            // Then convert the rest.
+            let pile = ppr.unwrap().into_packet_pile().unwrap();
+            assert_eq!(pile.descendants().count(),
+                       reference.descendants().count() - 1);
+            assert_eq!(pile.children().collect::<Vec<_>>(),
+                       reference.children().skip(1).collect::<Vec<_>>());
+        }
+
+        // Containers are recursed into.
+        let data = ::tests::message("compressed-data-algo-1.gpg");
+        let reference = PacketPile::from_bytes(data).unwrap();
+        assert!(reference.descendants().count()
+                > reference.children().count());
+        let pile = PacketParser::from_bytes(data).unwrap().unwrap()
+            .into_packet_pile().unwrap();
+        assert_eq!(pile, reference);
+
+        // But only up to the maximum recursion depth.
+        let pile = PacketParserBuilder::from_bytes(data).unwrap()
+            .max_recursion_depth(0)
+            .finalize().unwrap().unwrap()
+            .into_packet_pile().unwrap();
+        assert_eq!(pile.descendants().count(), reference.children().count());
+        assert!(pile.descendants().all(|p| p.body().is_some()));
+
+        // Converting from inside a container is not possible.
+        let pp = PacketParser::from_bytes(data).unwrap().unwrap();
+        let (_, ppr) = pp.recurse().unwrap();
+        let pp = ppr.unwrap();
+        assert_eq!(pp.recursion_depth(), 1);
+        assert!(pp.into_packet_pile().is_err());
+    }
 }
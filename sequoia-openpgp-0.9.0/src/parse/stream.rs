@@ -16,12 +16,15 @@ use std::path::Path;
 
 use buffered_reader::BufferedReader;
 use {
+    crypto,
     Error,
     Fingerprint,
     constants::{
         AEADAlgorithm,
         CompressionAlgorithm,
         DataFormat,
+        HashAlgorithm,
+        SignatureType,
         SymmetricAlgorithm,
     },
     packet::{
@@ -43,10 +46,12 @@ use {
     packet::Signature,
     TPK,
     crypto::SessionKey,
+    policy::{self, Policy},
     serialize::Serialize,
 };
 use parse::{
     Cookie,
+    Parse,
     PacketParser,
     PacketParserBuilder,
     PacketParserResult,
@@ -77,6 +82,7 @@ const BUFFER_SIZE: usize = 25 * 1024 * 1024;
 /// use std::io::Read;
 /// use openpgp::{KeyID, TPK, Result};
 /// use openpgp::parse::stream::*;
+/// use openpgp::policy::StandardPolicy;
 /// # fn main() { f().unwrap(); }
 /// # fn f() -> Result<()> {
 ///
@@ -101,8 +107,9 @@ const BUFFER_SIZE: usize = 25 * 1024 * 1024;
 ///      =DjuO
 ///      -----END PGP MESSAGE-----";
 ///
+/// let p = &StandardPolicy::new();
 /// let h = Helper {};
-/// let mut v = Verifier::from_bytes(message, h, None)?;
+/// let mut v = Verifier::from_bytes(p, message, h, None)?;
 ///
 /// let mut content = Vec::new();
 /// v.read_to_end(&mut content)
@@ -119,6 +126,7 @@ const BUFFER_SIZE: usize = 25 * 1024 * 1024;
 /// # }
 pub struct Verifier<'a, H: VerificationHelper> {
     helper: H,
+    policy: &'a dyn Policy,
     tpks: Vec<TPK>,
     /// Maps KeyID to tpks[i].keys_all().nth(j).
     keys: HashMap<KeyID, (usize, usize)>,
@@ -148,6 +156,10 @@ pub enum VerificationResult<'a> {
     GoodChecksum(Signature,
                  &'a TPK, &'a Key, Option<&'a Signature>, RevocationStatus<'a>),
     /// Unable to verify the signature because the key is missing.
+    ///
+    /// This is also returned for signatures that carry neither an
+    /// issuer nor an issuer fingerprint subpacket, because there is
+    /// no way to locate the key that made them.
     MissingKey(Signature),
     /// The signature is bad.
     BadChecksum(Signature),
@@ -394,13 +406,16 @@ impl<'a, H: VerificationHelper> Verifier<'a, H> {
     /// Creates a `Verifier` from the given reader.
     ///
     /// Signature verifications are done relative to time `t`, or the
-    /// current time, if `t` is `None`.
-    pub fn from_reader<R, T>(reader: R, helper: H, t: T)
-                          -> Result<Verifier<'a, H>>
+    /// current time, if `t` is `None`.  Signatures violating `policy`
+    /// are considered bad.
+    pub fn from_reader<R, T>(policy: &'a dyn Policy, reader: R, helper: H,
+                             t: T)
+                             -> Result<Verifier<'a, H>>
         where R: io::Read + 'a, T: Into<Option<time::Tm>>
     {
         let t = t.into().unwrap_or_else(time::now_utc);
         Verifier::from_buffered_reader(
+            policy,
             Box::new(buffered_reader::Generic::with_cookie(reader, None,
                                                         Default::default())),
             helper, t)
@@ -409,13 +424,16 @@ impl<'a, H: VerificationHelper> Verifier<'a, H> {
     /// Creates a `Verifier` from the given file.
     ///
     /// Signature verifications are done relative to time `t`, or the
-    /// current time, if `t` is `None`.
-    pub fn from_file<P, T>(path: P, helper: H, t: T) -> Result<Verifier<'a, H>>
+    /// current time, if `t` is `None`.  Signatures violating `policy`
+    /// are considered bad.
+    pub fn from_file<P, T>(policy: &'a dyn Policy, path: P, helper: H, t: T)
+                           -> Result<Verifier<'a, H>>
         where P: AsRef<Path>,
               T: Into<Option<time::Tm>>
     {
         let t = t.into().unwrap_or_else(time::now_utc);
         Verifier::from_buffered_reader(
+            policy,
             Box::new(buffered_reader::File::with_cookie(path,
                                                      Default::default())?),
             helper, t)
@@ -424,13 +442,16 @@ impl<'a, H: VerificationHelper> Verifier<'a, H> {
     /// Creates a `Verifier` from the given buffer.
     ///
     /// Signature verifications are done relative to time `t`, or the
-    /// current time, if `t` is `None`.
-    pub fn from_bytes<T>(bytes: &'a [u8], helper: H, t: T)
+    /// current time, if `t` is `None`.  Signatures violating `policy`
+    /// are considered bad.
+    pub fn from_bytes<T>(policy: &'a dyn Policy, bytes: &'a [u8], helper: H,
+                         t: T)
                          -> Result<Verifier<'a, H>>
         where T: Into<Option<time::Tm>>
     {
         let t = t.into().unwrap_or_else(time::now_utc);
         Verifier::from_buffered_reader(
+            policy,
             Box::new(buffered_reader::Memory::with_cookie(bytes,
                                                        Default::default())),
             helper, t)
@@ -462,8 +483,10 @@ impl<'a, H: VerificationHelper> Verifier<'a, H> {
     /// Creates the `Verifier`, and buffers the data up to `BUFFER_SIZE`.
     ///
     /// Signature verifications are done relative to time `t`, or the
-    /// current time, if `t` is `None`.
-    pub(crate) fn from_buffered_reader(bio: Box<BufferedReader<Cookie> + 'a>,
+    /// current time, if `t` is `None`.  Signatures violating `policy`
+    /// are considered bad.
+    pub(crate) fn from_buffered_reader(policy: &'a dyn Policy,
+                                       bio: Box<BufferedReader<Cookie> + 'a>,
                                        helper: H, t: time::Tm)
                                        -> Result<Verifier<'a, H>>
     {
@@ -471,6 +494,7 @@ impl<'a, H: VerificationHelper> Verifier<'a, H> {
 
         let mut v = Verifier {
             helper: helper,
+            policy: policy,
             tpks: Vec::new(),
             keys: HashMap::new(),
             oppr: None,
@@ -620,6 +644,8 @@ impl<'a, H: VerificationHelper> Verifier<'a, H> {
                                                 .unwrap();
                                             if sig.verify(key).unwrap_or(false)
                                                 && sig.signature_alive_at(self.time)
+                                                && policy::signature_ok(
+                                                    self.policy, &sig, key)
                                             {
                                                 VerificationResult::GoodChecksum
                                                     (sig, tpk, key, binding,
@@ -633,7 +659,7 @@ impl<'a, H: VerificationHelper> Verifier<'a, H> {
                                         }
                                     } else {
                                         // No issuer.
-                                        VerificationResult::BadChecksum(sig)
+                                        VerificationResult::MissingKey(sig)
                                     }
                                 )
                             }
@@ -899,6 +925,7 @@ impl<'a> io::Read for Transformer<'a> {
 /// use std::io::{self, Read};
 /// use openpgp::{KeyID, TPK, Result};
 /// use openpgp::parse::stream::*;
+/// use openpgp::policy::StandardPolicy;
 /// # fn main() { f().unwrap(); }
 /// # fn f() -> Result<()> {
 ///
@@ -923,8 +950,9 @@ impl<'a> io::Read for Transformer<'a> {
 ///      -----END SIGNATURE-----";
 ///
 /// let data = b"Hello World!";
+/// let p = &StandardPolicy::new();
 /// let h = Helper {};
-/// let mut v = DetachedVerifier::from_bytes(signature, data, h, None)?;
+/// let mut v = DetachedVerifier::from_bytes(p, signature, data, h, None)?;
 ///
 /// let mut content = Vec::new();
 /// v.read_to_end(&mut content)
@@ -946,8 +974,10 @@ impl DetachedVerifier {
     /// Creates a `Verifier` from the given readers.
     ///
     /// Signature verifications are done relative to time `t`, or the
-    /// current time, if `t` is `None`.
-    pub fn from_reader<'a, 's, H, R, S, T>(signature_reader: S, reader: R,
+    /// current time, if `t` is `None`.  Signatures violating `policy`
+    /// are considered bad.
+    pub fn from_reader<'a, 's, H, R, S, T>(policy: &'a dyn Policy,
+                                           signature_reader: S, reader: R,
                                            helper: H, t: T)
                                            -> Result<Verifier<'a, H>>
         where R: io::Read + 'a, S: io::Read + 's, H: VerificationHelper,
@@ -955,6 +985,7 @@ impl DetachedVerifier {
     {
         let t = t.into().unwrap_or_else(time::now_utc);
         Self::from_buffered_reader(
+            policy,
             Box::new(buffered_reader::Generic::with_cookie(signature_reader, None,
                                                         Default::default())),
             Box::new(buffered_reader::Generic::new(reader, None)),
@@ -964,8 +995,10 @@ impl DetachedVerifier {
     /// Creates a `Verifier` from the given files.
     ///
     /// Signature verifications are done relative to time `t`, or the
-    /// current time, if `t` is `None`.
-    pub fn from_file<'a, H, P, S, T>(signature_path: S, path: P,
+    /// current time, if `t` is `None`.  Signatures violating `policy`
+    /// are considered bad.
+    pub fn from_file<'a, H, P, S, T>(policy: &'a dyn Policy,
+                                     signature_path: S, path: P,
                                      helper: H, t: T)
                                      -> Result<Verifier<'a, H>>
         where P: AsRef<Path>, S: AsRef<Path>, H: VerificationHelper,
@@ -973,6 +1006,7 @@ impl DetachedVerifier {
     {
         let t = t.into().unwrap_or_else(time::now_utc);
         Self::from_buffered_reader(
+            policy,
             Box::new(buffered_reader::File::with_cookie(signature_path,
                                                      Default::default())?),
             Box::new(buffered_reader::File::open(path)?),
@@ -982,14 +1016,17 @@ impl DetachedVerifier {
     /// Creates a `Verifier` from the given buffers.
     ///
     /// Signature verifications are done relative to time `t`, or the
-    /// current time, if `t` is `None`.
-    pub fn from_bytes<'a, 's, H, T>(signature_bytes: &'s [u8], bytes: &'a [u8],
+    /// current time, if `t` is `None`.  Signatures violating `policy`
+    /// are considered bad.
+    pub fn from_bytes<'a, 's, H, T>(policy: &'a dyn Policy,
+                                    signature_bytes: &'s [u8], bytes: &'a [u8],
                                     helper: H, t: T)
                                     -> Result<Verifier<'a, H>>
         where H: VerificationHelper, T: Into<Option<time::Tm>>
     {
         let t = t.into().unwrap_or_else(time::now_utc);
         Self::from_buffered_reader(
+            policy,
             Box::new(buffered_reader::Memory::with_cookie(signature_bytes,
                                                        Default::default())),
             Box::new(buffered_reader::Memory::new(bytes)),
@@ -999,15 +1036,18 @@ impl DetachedVerifier {
     /// Creates the `Verifier`, and buffers the data up to `BUFFER_SIZE`.
     ///
     /// Signature verifications are done relative to time `t`, or the
-    /// current time, if `t` is `None`.
+    /// current time, if `t` is `None`.  Signatures violating `policy`
+    /// are considered bad.
     pub(crate) fn from_buffered_reader<'a, 's, H>
-        (signature_bio: Box<BufferedReader<Cookie> + 's>,
+        (policy: &'a dyn Policy,
+         signature_bio: Box<BufferedReader<Cookie> + 's>,
          reader: Box<'a + BufferedReader<()>>,
          helper: H, t: time::Tm)
          -> Result<Verifier<'a, H>>
         where H: VerificationHelper
     {
         Verifier::from_buffered_reader(
+            policy,
             Box::new(buffered_reader::Generic::with_cookie(
                 Transformer::new(signature_bio, reader)?,
                 None, Default::default())),
@@ -1015,6 +1055,176 @@ impl DetachedVerifier {
     }
 }
 
+/// Verifies detached signatures over data written to it.
+///
+/// This is the counterpart to [`Signer::detached`]: the signed data
+/// is fed using `io::Write`, e.g. using `io::copy`, and hashed on the
+/// fly.  Unlike [`DetachedVerifier`], the data is not buffered.  Once
+/// all data has been written, [`finalize`] checks every signature
+/// against the given TPKs, and returns a result for each of them.
+/// For text signatures, the line endings are normalized to CRLF
+/// while hashing.
+///
+///   [`Signer::detached`]: ../../serialize/stream/struct.Signer.html#method.detached
+///   [`DetachedVerifier`]: struct.DetachedVerifier.html
+///   [`finalize`]: #method.finalize
+///
+/// # Example
+///
+/// ```
+/// extern crate sequoia_openpgp as openpgp;
+/// use std::io;
+/// use openpgp::TPK;
+/// use openpgp::parse::Parse;
+/// use openpgp::parse::stream::*;
+/// use openpgp::policy::StandardPolicy;
+/// # fn main() { f().unwrap(); }
+/// # fn f() -> openpgp::Result<()> {
+/// # let tpk = TPK::from_bytes(include_bytes!(
+/// #     "../../tests/data/keys/emmelie-dorothea-dina-samantha-awina-ed25519.pgp"))?;
+/// # let signature = include_bytes!(
+/// #     "../../tests/data/messages/a-cypherpunks-manifesto.txt.ed25519.sig");
+/// # let mut data = &include_bytes!(
+/// #     "../../tests/data/messages/a-cypherpunks-manifesto.txt")[..];
+/// let p = &StandardPolicy::new();
+/// let tpks = vec![tpk];
+/// let mut v = DetachedVerifierWriter::from_bytes(p, signature, &tpks, None)?;
+/// io::copy(&mut data, &mut v)?;
+///
+/// for result in v.finalize()? {
+///     if let VerificationResult::GoodChecksum(..) = result {
+///         // The signature is good.
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct DetachedVerifierWriter<'a> {
+    policy: &'a dyn Policy,
+    tpks: &'a [TPK],
+    sigs: Vec<Signature>,
+    // Text signatures are over the canonicalized data, so we need a
+    // context per hash algorithm and signature type.  The flag is
+    // whether the last byte hashed was a carriage return.
+    hashes: HashMap<(HashAlgorithm, SignatureType),
+                    (crypto::hash::Context, bool)>,
+    time: time::Tm,
+}
+
+impl<'a> DetachedVerifierWriter<'a> {
+    /// Creates a `DetachedVerifierWriter` from the given signatures.
+    ///
+    /// The signatures are checked against `tpks`.  Signature
+    /// verifications are done relative to time `t`, or the current
+    /// time, if `t` is `None`.  Signatures violating `policy` are
+    /// considered bad.
+    pub fn from_bytes<T>(policy: &'a dyn Policy, signature_bytes: &[u8],
+                         tpks: &'a [TPK], t: T)
+                         -> Result<Self>
+        where T: Into<Option<time::Tm>>
+    {
+        let mut sigs = Vec::new();
+        let mut hashes = HashMap::new();
+
+        let mut ppr = PacketParser::from_bytes(signature_bytes)?;
+        while let PacketParserResult::Some(pp) = ppr {
+            let (p, ppr_tmp) = pp.recurse()?;
+            ppr = ppr_tmp;
+
+            match p {
+                Packet::Signature(sig) => {
+                    let key = (sig.hash_algo(), sig.sigtype());
+                    if ! hashes.contains_key(&key) {
+                        hashes.insert(key, (key.0.context()?, false));
+                    }
+                    sigs.push(sig);
+                },
+                p => return Err(Error::MalformedMessage(
+                    format!("Expected signature packet, got {:?}", p.tag()))
+                                .into()),
+            }
+        }
+
+        if sigs.is_empty() {
+            return Err(Error::MalformedMessage(
+                "No signatures found".into()).into());
+        }
+
+        Ok(DetachedVerifierWriter {
+            policy: policy,
+            tpks: tpks,
+            sigs: sigs,
+            hashes: hashes,
+            time: t.into().unwrap_or_else(time::now_utc),
+        })
+    }
+
+    /// Checks the signatures over the data written so far.
+    ///
+    /// Returns a result for every signature, in the order they
+    /// appeared.
+    pub fn finalize(self) -> Result<Vec<VerificationResult<'a>>> {
+        use crypto::hash::Hash;
+
+        let mut results = Vec::with_capacity(self.sigs.len());
+        for mut sig in self.sigs.into_iter() {
+            let algo = sig.hash_algo();
+            let mut hash = self.hashes.get(&(algo, sig.sigtype()))
+                .expect("context created for every signature").0.clone();
+            sig.hash(&mut hash);
+            let mut digest = vec![0u8; hash.digest_size()];
+            hash.digest(&mut digest);
+            sig.set_computed_hash(Some((algo, digest)));
+
+            let issuer = if let Some(issuer) = sig.get_issuer() {
+                issuer
+            } else {
+                results.push(VerificationResult::MissingKey(sig));
+                continue;
+            };
+
+            let found = self.tpks.iter().filter_map(|tpk| {
+                tpk.keys_all().find(|&(_, _, key)| key.keyid() == issuer)
+                    .map(|(binding, revocation, key)|
+                         (tpk, binding, revocation, key))
+            }).next();
+
+            results.push(
+                if let Some((tpk, binding, revocation, key)) = found {
+                    if sig.verify(key).unwrap_or(false)
+                        && sig.signature_alive_at(self.time)
+                        && policy::signature_ok(self.policy, &sig, key)
+                    {
+                        VerificationResult::GoodChecksum(
+                            sig, tpk, key, binding, revocation)
+                    } else {
+                        VerificationResult::BadChecksum(sig)
+                    }
+                } else {
+                    VerificationResult::MissingKey(sig)
+                });
+        }
+
+        Ok(results)
+    }
+}
+
+impl<'a> io::Write for DetachedVerifierWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for (&(_, sigtype), &mut (ref mut hash, ref mut after_cr))
+            in self.hashes.iter_mut()
+        {
+            packet::signature::hash_document_part(hash, sigtype, after_cr,
+                                                  buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 /// Decrypts and verifies an encrypted and optionally signed OpenPGP
 /// message.
 ///
@@ -1037,6 +1247,7 @@ impl DetachedVerifier {
 /// use openpgp::constants::SymmetricAlgorithm;
 /// use openpgp::{KeyID, TPK, Result, packet::{Key, PKESK, SKESK}};
 /// use openpgp::parse::stream::*;
+/// use openpgp::policy::StandardPolicy;
 /// # fn main() { f().unwrap(); }
 /// # fn f() -> Result<()> {
 ///
@@ -1070,8 +1281,9 @@ impl DetachedVerifier {
 ///      =OB/8
 ///      -----END PGP MESSAGE-----";
 ///
+/// let p = &StandardPolicy::new();
 /// let h = Helper {};
-/// let mut v = Decryptor::from_bytes(message, h, None)?;
+/// let mut v = Decryptor::from_bytes(p, message, h, None)?;
 ///
 /// let mut content = Vec::new();
 /// v.read_to_end(&mut content)
@@ -1088,6 +1300,7 @@ impl DetachedVerifier {
 /// # }
 pub struct Decryptor<'a, H: VerificationHelper + DecryptionHelper> {
     helper: H,
+    policy: &'a dyn Policy,
     tpks: Vec<TPK>,
     /// Maps KeyID to tpks[i].keys_all().nth(j).
     keys: HashMap<KeyID, (usize, usize)>,
@@ -1134,17 +1347,216 @@ pub trait DecryptionHelper {
         where D: FnMut(SymmetricAlgorithm, &SessionKey) -> Result<()>;
 }
 
+/// The secret used to decrypt a message.
+///
+/// See [`SecretsHelper::decrypted_with`].
+///
+///   [`SecretsHelper::decrypted_with`]: struct.SecretsHelper.html#method.decrypted_with
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecryptedWith {
+    /// A PKESK was decrypted using a secret key.
+    Key {
+        /// The fingerprint of the TPK.
+        tpk: Fingerprint,
+        /// The fingerprint of the (sub)key.
+        key: Fingerprint,
+    },
+    /// An SKESK was decrypted using a password.
+    ///
+    /// This is the index of the password, in the order in which the
+    /// passwords were added.
+    Password(usize),
+}
+
+/// A `DecryptionHelper` using a set of secret keys and passwords.
+///
+/// For every PKESK, the matching key is tried.  If the recipient is
+/// hidden (i.e., the PKESK uses the wildcard key id), all keys are
+/// tried.  Then, every SKESK is tried with every password.  The
+/// first secret that yields the session key is used, and recorded so
+/// that the caller can learn how the message was decrypted.
+///
+/// Signature verification is delegated to the given
+/// `VerificationHelper`.
+///
+/// # Example
+///
+/// ```
+/// # extern crate sequoia_openpgp as openpgp;
+/// # use std::io::Read;
+/// # use openpgp::{KeyID, TPK, Result};
+/// # use openpgp::parse::stream::*;
+/// # use openpgp::policy::StandardPolicy;
+/// # fn main() { f().unwrap(); }
+/// # fn f() -> Result<()> {
+/// struct NoVerification;
+/// impl VerificationHelper for NoVerification {
+///     fn get_public_keys(&mut self, _: &[KeyID]) -> Result<Vec<TPK>> {
+///         Ok(Vec::new())
+///     }
+///     fn check(&mut self, _: &MessageStructure) -> Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// let helper = SecretsHelper::new(NoVerification)
+///     .add_password("123");
+/// let p = &StandardPolicy::new();
+/// let mut decryptor = Decryptor::from_bytes(
+///     p,
+///     include_bytes!("../../tests/data/messages/encrypted-aes256-password-123.gpg"),
+///     helper, None)?;
+///
+/// let mut content = Vec::new();
+/// decryptor.read_to_end(&mut content)?;
+/// assert_eq!(decryptor.helper_ref().decrypted_with(),
+///            Some(&DecryptedWith::Password(0)));
+/// # Ok(())
+/// # }
+/// ```
+pub struct SecretsHelper<V: VerificationHelper> {
+    verification: V,
+    /// Maps the key ids of the keys to the TPK's fingerprint and
+    /// the key pair.
+    keys: HashMap<KeyID, (Fingerprint, crypto::KeyPair)>,
+    passwords: Vec<crypto::Password>,
+    decrypted_with: Option<DecryptedWith>,
+}
+
+impl<V: VerificationHelper> SecretsHelper<V> {
+    /// Returns a new helper that delegates signature verification
+    /// to `verification`.
+    pub fn new(verification: V) -> Self {
+        SecretsHelper {
+            verification: verification,
+            keys: HashMap::new(),
+            passwords: Vec::new(),
+            decrypted_with: None,
+        }
+    }
+
+    /// Adds the TPK's encryption-capable keys.
+    ///
+    /// Only keys with unencrypted secret key material are added.
+    /// Encrypted secret keys must be decrypted first.
+    pub fn add_tpk(mut self, tpk: &TPK) -> Self {
+        let fp = tpk.fingerprint();
+        for (sig, _, key) in tpk.keys_all() {
+            let can_encrypt = sig.map(|s| {
+                s.key_flags().can_encrypt_at_rest()
+                    || s.key_flags().can_encrypt_for_transport()
+            }).unwrap_or(false);
+            if ! can_encrypt {
+                continue;
+            }
+
+            if let Ok(pair) = key.clone().into_keypair() {
+                self.keys.insert(key.keyid(), (fp.clone(), pair));
+            }
+        }
+        self
+    }
+
+    /// Adds a password.
+    pub fn add_password<P: Into<crypto::Password>>(mut self, password: P)
+                                                   -> Self {
+        self.passwords.push(password.into());
+        self
+    }
+
+    /// Returns the secret that was used to decrypt the message.
+    ///
+    /// This is `None` until the message has been decrypted.
+    pub fn decrypted_with(&self) -> Option<&DecryptedWith> {
+        self.decrypted_with.as_ref()
+    }
+
+    /// Returns a reference to the verification helper.
+    pub fn verification_helper_ref(&self) -> &V {
+        &self.verification
+    }
+
+    /// Returns a mutable reference to the verification helper.
+    pub fn verification_helper_mut(&mut self) -> &mut V {
+        &mut self.verification
+    }
+}
+
+impl<V: VerificationHelper> VerificationHelper for SecretsHelper<V> {
+    fn get_public_keys(&mut self, ids: &[KeyID]) -> Result<Vec<TPK>> {
+        self.verification.get_public_keys(ids)
+    }
+
+    fn check(&mut self, structure: &MessageStructure) -> Result<()> {
+        self.verification.check(structure)
+    }
+}
+
+impl<V: VerificationHelper> DecryptionHelper for SecretsHelper<V> {
+    fn decrypt<D>(&mut self, pkesks: &[PKESK], skesks: &[SKESK],
+                  mut decrypt: D) -> Result<Option<Fingerprint>>
+        where D: FnMut(SymmetricAlgorithm, &SessionKey) -> Result<()>
+    {
+        for pkesk in pkesks {
+            let candidates: Vec<KeyID> = if pkesk.recipient().is_wildcard() {
+                self.keys.keys().cloned().collect()
+            } else {
+                vec![ pkesk.recipient().clone() ]
+            };
+
+            for keyid in candidates {
+                let (tpk, key) = if let Some(&mut (ref tpk, ref mut pair))
+                    = self.keys.get_mut(&keyid)
+                {
+                    let result = pkesk.decrypt(pair)
+                        .and_then(|(algo, sk)| decrypt(algo, &sk));
+                    if result.is_err() {
+                        continue;
+                    }
+                    (tpk.clone(), pair.public().fingerprint())
+                } else {
+                    continue;
+                };
+
+                self.decrypted_with = Some(DecryptedWith::Key {
+                    tpk: tpk.clone(),
+                    key: key,
+                });
+                return Ok(Some(tpk));
+            }
+        }
+
+        for (i, password) in self.passwords.iter().enumerate() {
+            for skesk in skesks {
+                if skesk.decrypt(password)
+                    .and_then(|(algo, sk)| decrypt(algo, &sk)).is_ok()
+                {
+                    self.decrypted_with = Some(DecryptedWith::Password(i));
+                    return Ok(None);
+                }
+            }
+        }
+
+        Err(Error::MissingSessionKey(
+            "No key or password decrypted the message".into()).into())
+    }
+}
+
 impl<'a, H: VerificationHelper + DecryptionHelper> Decryptor<'a, H> {
     /// Creates a `Decryptor` from the given reader.
     ///
     /// Signature verifications are done relative to time `t`, or the
-    /// current time, if `t` is `None`.
-    pub fn from_reader<R, T>(reader: R, helper: H, t: T)
-                          -> Result<Decryptor<'a, H>>
+    /// current time, if `t` is `None`.  Signatures violating `policy`
+    /// are considered bad, and messages encrypted using algorithms
+    /// violating it are rejected.
+    pub fn from_reader<R, T>(policy: &'a dyn Policy, reader: R, helper: H,
+                             t: T)
+                             -> Result<Decryptor<'a, H>>
         where R: io::Read + 'a, T: Into<Option<time::Tm>>
     {
         let t = t.into().unwrap_or_else(time::now_utc);
         Decryptor::from_buffered_reader(
+            policy,
             Box::new(buffered_reader::Generic::with_cookie(reader, None,
                                                         Default::default())),
             helper, t)
@@ -1153,13 +1565,17 @@ impl<'a, H: VerificationHelper + DecryptionHelper> Decryptor<'a, H> {
     /// Creates a `Decryptor` from the given file.
     ///
     /// Signature verifications are done relative to time `t`, or the
-    /// current time, if `t` is `None`.
-    pub fn from_file<P, T>(path: P, helper: H, t: T) -> Result<Decryptor<'a, H>>
+    /// current time, if `t` is `None`.  Signatures violating `policy`
+    /// are considered bad, and messages encrypted using algorithms
+    /// violating it are rejected.
+    pub fn from_file<P, T>(policy: &'a dyn Policy, path: P, helper: H, t: T)
+                           -> Result<Decryptor<'a, H>>
         where P: AsRef<Path>,
               T: Into<Option<time::Tm>>
     {
         let t = t.into().unwrap_or_else(time::now_utc);
         Decryptor::from_buffered_reader(
+            policy,
             Box::new(buffered_reader::File::with_cookie(path,
                                                      Default::default())?),
             helper, t)
@@ -1168,13 +1584,17 @@ impl<'a, H: VerificationHelper + DecryptionHelper> Decryptor<'a, H> {
     /// Creates a `Decryptor` from the given buffer.
     ///
     /// Signature verifications are done relative to time `t`, or the
-    /// current time, if `t` is `None`.
-    pub fn from_bytes<T>(bytes: &'a [u8], helper: H, t: T)
+    /// current time, if `t` is `None`.  Signatures violating `policy`
+    /// are considered bad, and messages encrypted using algorithms
+    /// violating it are rejected.
+    pub fn from_bytes<T>(policy: &'a dyn Policy, bytes: &'a [u8], helper: H,
+                         t: T)
                          -> Result<Decryptor<'a, H>>
         where T: Into<Option<time::Tm>>
     {
         let t = t.into().unwrap_or_else(time::now_utc);
         Decryptor::from_buffered_reader(
+            policy,
             Box::new(buffered_reader::Memory::with_cookie(bytes,
                                                        Default::default())),
             helper, t)
@@ -1204,7 +1624,8 @@ impl<'a, H: VerificationHelper + DecryptionHelper> Decryptor<'a, H> {
     }
 
     /// Creates the `Decryptor`, and buffers the data up to `BUFFER_SIZE`.
-    pub(crate) fn from_buffered_reader(bio: Box<BufferedReader<Cookie> + 'a>,
+    pub(crate) fn from_buffered_reader(policy: &'a dyn Policy,
+                                       bio: Box<BufferedReader<Cookie> + 'a>,
                                        helper: H, t: time::Tm)
                                        -> Result<Decryptor<'a, H>>
     {
@@ -1215,6 +1636,7 @@ impl<'a, H: VerificationHelper + DecryptionHelper> Decryptor<'a, H> {
 
         let mut v = Decryptor {
             helper: helper,
+            policy: policy,
             tpks: Vec::new(),
             keys: HashMap::new(),
             oppr: None,
@@ -1242,12 +1664,30 @@ impl<'a, H: VerificationHelper + DecryptionHelper> Decryptor<'a, H> {
                 Packet::SEIP(_) | Packet::AED(_) => {
                     saw_content = true;
 
+                    if let Packet::AED(ref p) = pp.packet {
+                        if ! v.policy.aead_ok(p.aead()) {
+                            return Err(Error::PolicyViolation(
+                                format!("AEAD algorithm {} is not acceptable",
+                                        p.aead())).into());
+                        }
+                    }
+
                     // Get the symmetric algorithm from the decryption
                     // proxy function.  This is necessary because we
                     // cannot get the algorithm from the SEIP packet.
                     let mut sym_algo = None;
-                    {
+                    // The algorithm, if the policy rejected it.
+                    let mut rejected = None;
+                    let identity = {
+                        let policy = v.policy;
                         let decryption_proxy = |algo, secret: &SessionKey| {
+                            if ! policy.symmetric_ok(algo) {
+                                rejected = Some(algo);
+                                return Err(Error::PolicyViolation(
+                                    format!("Symmetric algorithm {} is not \
+                                             acceptable", algo)).into());
+                            }
+
                             let result = pp.decrypt(algo, secret);
                             if let Ok(_) = result {
                                 sym_algo = Some(algo);
@@ -1255,10 +1695,19 @@ impl<'a, H: VerificationHelper + DecryptionHelper> Decryptor<'a, H> {
                             result
                         };
 
-                        v.identity =
-                            v.helper.decrypt(&pkesks[..], &skesks[..],
-                                             decryption_proxy)?;
+                        v.helper.decrypt(&pkesks[..], &skesks[..],
+                                         decryption_proxy)
+                    };
+                    if let Some(algo) = rejected {
+                        if ! pp.decrypted() {
+                            // Report the policy violation instead of
+                            // the helper's generic error.
+                            return Err(Error::PolicyViolation(
+                                format!("Symmetric algorithm {} is not \
+                                         acceptable", algo)).into());
+                        }
                     }
+                    v.identity = identity?;
                     if ! pp.decrypted() {
                         // XXX: That is not quite the right error to return.
                         return Err(
@@ -1403,6 +1852,17 @@ impl<'a, H: VerificationHelper + DecryptionHelper> Decryptor<'a, H> {
                     ppr = ppr_tmp;
                 }
 
+                // Only now can we tell whether the message is
+                // complete.  In particular, an encryption container
+                // that lacks its MDC is only detected here.  Fail
+                // closed: stripping the MDC is a downgrade attack.
+                if let PacketParserResult::EOF(ref eof) = ppr {
+                    if let Err(err) = eof.is_message() {
+                        return Err(err.context(
+                            "Malformed OpenPGP message").into());
+                    }
+                }
+
                 self.verify_signatures()
             } else {
                 self.oppr = Some(PacketParserResult::Some(pp));
@@ -1435,7 +1895,9 @@ impl<'a, H: VerificationHelper + DecryptionHelper> Decryptor<'a, H> {
                                     let (binding, revocation, key)
                                         = tpk.keys_all().nth(*j).unwrap();
                                     if sig.verify(key).unwrap_or(false) &&
-                                        sig.signature_alive_at(self.time)
+                                        sig.signature_alive_at(self.time) &&
+                                        policy::signature_ok(
+                                            self.policy, &sig, key)
                                     {
                                         // Check intended recipients.
                                         if let Some(identity) =
@@ -1472,7 +1934,7 @@ impl<'a, H: VerificationHelper + DecryptionHelper> Decryptor<'a, H> {
                                 }
                             } else {
                                 // No issuer.
-                                VerificationResult::BadChecksum(sig)
+                                VerificationResult::MissingKey(sig)
                             }
                         )
                     }
@@ -1542,6 +2004,7 @@ mod test {
     use failure;
     use super::*;
     use parse::Parse;
+    use policy::{NullPolicy, StandardPolicy};
 
     #[derive(Debug, PartialEq)]
     struct VHelper {
@@ -1617,6 +2080,7 @@ mod test {
 
     #[test]
     fn verifier() {
+        let p = &StandardPolicy::new();
         let keys = [
             "neal.pgp",
             "emmelie-dorothea-dina-samantha-awina-ed25519.pgp"
@@ -1636,7 +2100,7 @@ mod test {
             // Test Verifier.
             let mut h = VHelper::new(0, 0, 0, 0, keys.clone());
             let mut v =
-                match Verifier::from_bytes(::tests::file(f), h,
+                match Verifier::from_bytes(p, ::tests::file(f), h,
                                            ::frozen_time()) {
                     Ok(v) => v,
                     Err(e) => if r.error > 0 || r.unknown > 0 {
@@ -1664,7 +2128,7 @@ mod test {
             // Test Decryptor.
             let mut h = VHelper::new(0, 0, 0, 0, keys.clone());
             let mut v =
-                match Decryptor::from_bytes(::tests::file(f), h,
+                match Decryptor::from_bytes(p, ::tests::file(f), h,
                                             ::frozen_time()) {
                     Ok(v) => v,
                     Err(e) => if r.error > 0 || r.unknown > 0 {
@@ -1695,6 +2159,7 @@ mod test {
     /// VerificationHelper::check().
     #[test]
     fn verifier_levels() {
+        let p = &StandardPolicy::new();
         struct VHelper(());
         impl VerificationHelper for VHelper {
             fn get_public_keys(&mut self, _ids: &[KeyID]) -> Result<Vec<TPK>> {
@@ -1738,12 +2203,14 @@ mod test {
 
         // Test verifier.
         let v = Verifier::from_bytes(
+            p,
             ::tests::message("signed-1-notarized-by-ed25519.pgp"),
             VHelper(()), ::frozen_time()).unwrap();
         assert!(v.message_processed());
 
         // Test decryptor.
         let v = Decryptor::from_bytes(
+            p,
             ::tests::message("signed-1-notarized-by-ed25519.pgp"),
             VHelper(()), ::frozen_time()).unwrap();
         assert!(v.message_processed());
@@ -1751,6 +2218,7 @@ mod test {
 
     #[test]
     fn detached_verifier() {
+        let p = &StandardPolicy::new();
         let keys = [
             "emmelie-dorothea-dina-samantha-awina-ed25519.pgp"
         ].iter()
@@ -1761,6 +2229,7 @@ mod test {
 
         let h = VHelper::new(0, 0, 0, 0, keys.clone());
         let mut v = DetachedVerifier::from_bytes(
+            p,
             ::tests::message("a-cypherpunks-manifesto.txt.ed25519.sig"),
             ::tests::manifesto(),
             h, ::frozen_time()).unwrap();
@@ -1779,6 +2248,7 @@ mod test {
         use std::io::Cursor;
         let h = VHelper::new(0, 0, 0, 0, keys.clone());
         let mut v = DetachedVerifier::from_reader(
+            p,
             Cursor::new(
                 ::tests::message("a-cypherpunks-manifesto.txt.ed25519.sig")),
             Cursor::new(::tests::manifesto()),
@@ -1791,8 +2261,276 @@ mod test {
         assert_eq!(reference, &content[..]);
     }
 
+    #[test]
+    fn detached_verifier_writer() {
+        let p = &StandardPolicy::new();
+        use std::io::Write;
+        use constants::{HashAlgorithm, SignatureType};
+        use packet::signature;
+        use serialize::SerializeInto;
+
+        let tpk = TPK::from_bytes(::tests::key(
+            "emmelie-dorothea-dina-samantha-awina-ed25519.pgp")).unwrap();
+        let tpks = vec![tpk.clone()];
+        let reference = ::tests::manifesto();
+
+        let mut v = DetachedVerifierWriter::from_bytes(
+            p,
+            ::tests::message("a-cypherpunks-manifesto.txt.ed25519.sig"),
+            &tpks, ::frozen_time()).unwrap();
+        v.write_all(&reference[..10]).unwrap();
+        v.write_all(&reference[10..]).unwrap();
+        let results = v.finalize().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_match!(&VerificationResult::GoodChecksum(..) = &results[0]);
+
+        // Different data.
+        let mut v = DetachedVerifierWriter::from_bytes(
+            p,
+            ::tests::message("a-cypherpunks-manifesto.txt.ed25519.sig"),
+            &tpks, ::frozen_time()).unwrap();
+        v.write_all(b"Hello world").unwrap();
+        let results = v.finalize().unwrap();
+        assert_match!(&VerificationResult::BadChecksum(_) = &results[0]);
+
+        // Two signatures over the same data, one of them by a key we
+        // don't have.
+        let other = TPK::from_bytes(::tests::key("testy-new-private.pgp"))
+            .unwrap();
+        let mut pair = other.keys_all().signing_capable().nth(0).unwrap().2
+            .clone().into_keypair().unwrap();
+        let sig = signature::Builder::new(SignatureType::Binary)
+            .set_signature_creation_time(time::now()).unwrap()
+            .set_issuer_fingerprint(pair.public().fingerprint()).unwrap()
+            .sign_message(&mut pair, HashAlgorithm::SHA256, reference)
+            .unwrap();
+        let mut sigs = Packet::from_bytes(
+            ::tests::message("a-cypherpunks-manifesto.txt.ed25519.sig"))
+            .unwrap().to_vec().unwrap();
+        sigs.extend_from_slice(&Packet::Signature(sig).to_vec().unwrap());
+
+        let mut v = DetachedVerifierWriter::from_bytes(p, &sigs, &tpks,
+                                                       ::frozen_time())
+            .unwrap();
+        io::copy(&mut &reference[..], &mut v).unwrap();
+        let results = v.finalize().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_match!(&VerificationResult::GoodChecksum(..) = &results[0]);
+        assert_match!(&VerificationResult::MissingKey(_) = &results[1]);
+
+        // A text and a binary signature over the same data.  The text
+        // signature is over the data with normalized line endings,
+        // even if a CRLF is split across writes.
+        let text = b"one\ntwo\r\nthree\n";
+        let mut sigs = Vec::new();
+        for &sigtype in &[SignatureType::Text, SignatureType::Binary] {
+            let sig = signature::Builder::new(sigtype)
+                .set_signature_creation_time(time::now()).unwrap()
+                .set_issuer_fingerprint(pair.public().fingerprint()).unwrap()
+                .set_issuer(pair.public().keyid()).unwrap()
+                .sign_message(&mut pair, HashAlgorithm::SHA256, text)
+                .unwrap();
+            sigs.extend_from_slice(&Packet::Signature(sig).to_vec().unwrap());
+        }
+
+        let tpks = vec![other.clone()];
+        let mut v = DetachedVerifierWriter::from_bytes(p, &sigs, &tpks, None)
+            .unwrap();
+        v.write_all(&text[..8]).unwrap();
+        v.write_all(&text[8..]).unwrap();
+        let results = v.finalize().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_match!(&VerificationResult::GoodChecksum(..) = &results[0]);
+        assert_match!(&VerificationResult::GoodChecksum(..) = &results[1]);
+
+        // Only the text signature is valid over the canonical form.
+        let mut v = DetachedVerifierWriter::from_bytes(p, &sigs, &tpks, None)
+            .unwrap();
+        v.write_all(b"one\r\ntwo\r\nthree\r\n").unwrap();
+        let results = v.finalize().unwrap();
+        assert_match!(&VerificationResult::GoodChecksum(..) = &results[0]);
+        assert_match!(&VerificationResult::BadChecksum(_) = &results[1]);
+    }
+
+    /// Tests that signatures with only an issuer fingerprint can be
+    /// verified, and that signatures without any issuer information
+    /// are reported as missing a key.
+    #[test]
+    fn verifier_issuer_fingerprint_only() {
+        let p = &StandardPolicy::new();
+        use constants::{HashAlgorithm, SignatureType};
+        use packet::signature;
+        use serialize::SerializeInto;
+
+        #[derive(Default)]
+        struct Counter {
+            good: usize,
+            missing: usize,
+            bad: usize,
+            keys: Vec<TPK>,
+        }
+
+        impl VerificationHelper for Counter {
+            fn get_public_keys(&mut self, _ids: &[KeyID]) -> Result<Vec<TPK>> {
+                Ok(self.keys.clone())
+            }
+
+            fn check(&mut self, structure: &MessageStructure) -> Result<()> {
+                use self::VerificationResult::*;
+                for layer in structure.iter() {
+                    if let MessageLayer::SignatureGroup { ref results } = layer {
+                        for result in results {
+                            match result {
+                                GoodChecksum(..) => self.good += 1,
+                                MissingKey(_) => self.missing += 1,
+                                BadChecksum(_) => self.bad += 1,
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        let tpk = TPK::from_bytes(::tests::key(
+            "emmelie-dorothea-dina-samantha-awina-ed25519-private.pgp")).unwrap();
+        let mut pair = tpk.primary().clone().into_keypair().unwrap();
+        let msg = ::tests::manifesto();
+
+        // Only an issuer fingerprint.
+        let sig = signature::Builder::new(SignatureType::Binary)
+            .set_signature_creation_time(time::now()).unwrap()
+            .set_issuer_fingerprint(tpk.fingerprint()).unwrap()
+            .sign_message(&mut pair, HashAlgorithm::SHA256, msg).unwrap();
+        assert!(sig.issuer().is_none());
+        assert_eq!(sig.get_issuer(), Some(tpk.keyid()));
+        let sig = Packet::Signature(sig).to_vec().unwrap();
+
+        let h = Counter { keys: vec![tpk.clone()], .. Default::default() };
+        let mut v = DetachedVerifier::from_bytes(p, &sig, msg, h, None)
+            .unwrap();
+        let mut content = Vec::new();
+        v.read_to_end(&mut content).unwrap();
+        assert_eq!(msg, &content[..]);
+        let h = v.into_helper();
+        assert_eq!((h.good, h.missing, h.bad), (1, 0, 0));
+
+        // No issuer information at all.  The builder adds an Issuer
+        // subpacket to the unhashed area, so we have to remove it.
+        let mut sig = signature::Builder::new(SignatureType::Binary)
+            .set_signature_creation_time(time::now()).unwrap()
+            .sign_message(&mut pair, HashAlgorithm::SHA256, msg).unwrap();
+        sig.unhashed_area_mut().clear();
+        assert!(sig.get_issuer().is_none());
+        let sig = Packet::Signature(sig).to_vec().unwrap();
+
+        let h = Counter { keys: vec![tpk.clone()], .. Default::default() };
+        let mut v = DetachedVerifier::from_bytes(p, &sig, msg, h, None)
+            .unwrap();
+        let mut content = Vec::new();
+        v.read_to_end(&mut content).unwrap();
+        assert_eq!(msg, &content[..]);
+        let h = v.into_helper();
+        assert_eq!((h.good, h.missing, h.bad), (0, 1, 0));
+    }
+
+    #[test]
+    fn decryptor_mdc() {
+        let p = &StandardPolicy::new();
+        use std::io::Write;
+        use crypto::s2k::S2K;
+        use crypto::symmetric;
+        use packet::skesk::SKESK4;
+        use serialize::SerializeInto;
+
+        struct Helper(SessionKey);
+        impl VerificationHelper for Helper {
+            fn get_public_keys(&mut self, _ids: &[KeyID]) -> Result<Vec<TPK>> {
+                Ok(Vec::new())
+            }
+            fn check(&mut self, _: &MessageStructure) -> Result<()> {
+                Ok(())
+            }
+        }
+        impl DecryptionHelper for Helper {
+            fn decrypt<D>(&mut self, _: &[PKESK], _: &[SKESK], mut decrypt: D)
+                          -> Result<Option<Fingerprint>>
+                where D: FnMut(SymmetricAlgorithm, &SessionKey) -> Result<()>
+            {
+                decrypt(SymmetricAlgorithm::AES128, &self.0)?;
+                Ok(None)
+            }
+        }
+
+        // Encrypts `plaintext` into a SEIP packet, and appends the
+        // given MDC, if any.  The MDC's hash is computed, then
+        // `tamper` is applied to it.
+        fn encrypt(sk: &SessionKey, plaintext: &[u8], mdc: bool,
+                   tamper: bool) -> Vec<u8> {
+            let algo = SymmetricAlgorithm::AES128;
+            let bl = algo.block_size().unwrap();
+            let mut prefix = vec![0u8; bl];
+            ::crypto::random(&mut prefix);
+            let tail = prefix[bl - 2..].to_vec();
+            prefix.extend_from_slice(&tail);
+
+            let mut body = prefix;
+            body.extend_from_slice(plaintext);
+            if mdc {
+                body.extend_from_slice(&[0xd3, 0x14]);
+                let mut ctx = HashAlgorithm::SHA1.context().unwrap();
+                ctx.update(&body);
+                let mut hash = [0u8; 20];
+                ctx.digest(&mut hash[..]);
+                if tamper {
+                    hash[0] ^= 1;
+                }
+                body.extend_from_slice(&hash);
+            }
+
+            let mut ciphertext = vec![1];
+            {
+                let mut e = symmetric::Encryptor::new(algo, sk, &mut ciphertext)
+                    .unwrap();
+                e.write_all(&body).unwrap();
+            }
+
+            assert!(ciphertext.len() < 192);
+            let mut seip = vec![0xd2, ciphertext.len() as u8];
+            seip.extend_from_slice(&ciphertext);
+            seip
+        }
+
+        let algo = SymmetricAlgorithm::AES128;
+        let sk = SessionKey::new(algo.key_size().unwrap());
+        let mut lit = Literal::new(DataFormat::Binary);
+        lit.set_body(b"Hello world.".to_vec());
+        let lit = Packet::Literal(lit).to_vec().unwrap();
+        let skesk: Packet = SKESK4::with_password(
+            algo, S2K::default(), &sk, &"password".into()).unwrap().into();
+        let skesk = skesk.to_vec().unwrap();
+
+        let decrypt = |mdc, tamper| -> Result<Vec<u8>> {
+            let mut msg = skesk.clone();
+            msg.extend_from_slice(&encrypt(&sk, &lit, mdc, tamper));
+            let mut d = Decryptor::from_bytes(p, &msg, Helper(sk.clone()),
+                                              None)?;
+            let mut content = Vec::new();
+            d.read_to_end(&mut content)?;
+            Ok(content)
+        };
+
+        // Valid.
+        assert_eq!(&decrypt(true, false).unwrap()[..], b"Hello world.");
+        // Tampered.
+        assert!(decrypt(true, true).is_err());
+        // Missing.
+        assert!(decrypt(false, false).is_err());
+    }
+
     #[test]
     fn verify_long_message() {
+        let p = &StandardPolicy::new();
         use constants::DataFormat;
         use tpk::{TPKBuilder, CipherSuite};
         use serialize::stream::{LiteralWriter, Signer, Message};
@@ -1819,7 +2557,7 @@ mod test {
 
         // Test Verifier.
         let h = VHelper::new(0, 0, 0, 0, vec![tpk.clone()]);
-        let mut v = Verifier::from_bytes(&buf, h, None).unwrap();
+        let mut v = Verifier::from_bytes(p, &buf, h, None).unwrap();
 
         assert!(!v.message_processed());
         assert!(v.helper_ref().good == 0);
@@ -1842,7 +2580,7 @@ mod test {
         // Try the same, but this time we let .check() fail.
         let h = VHelper::new(0, 0, /* makes check() fail: */ 1, 0,
                              vec![tpk.clone()]);
-        let mut v = Verifier::from_bytes(&buf, h, None).unwrap();
+        let mut v = Verifier::from_bytes(p, &buf, h, None).unwrap();
 
         assert!(!v.message_processed());
         assert!(v.helper_ref().good == 0);
@@ -1866,7 +2604,7 @@ mod test {
 
         // Test Decryptor.
         let h = VHelper::new(0, 0, 0, 0, vec![tpk.clone()]);
-        let mut v = Decryptor::from_bytes(&buf, h, None).unwrap();
+        let mut v = Decryptor::from_bytes(p, &buf, h, None).unwrap();
 
         assert!(!v.message_processed());
         assert!(v.helper_ref().good == 0);
@@ -1889,7 +2627,7 @@ mod test {
         // Try the same, but this time we let .check() fail.
         let h = VHelper::new(0, 0, /* makes check() fail: */ 1, 0,
                              vec![tpk.clone()]);
-        let mut v = Decryptor::from_bytes(&buf, h, None).unwrap();
+        let mut v = Decryptor::from_bytes(p, &buf, h, None).unwrap();
 
         assert!(!v.message_processed());
         assert!(v.helper_ref().good == 0);
@@ -1911,4 +2649,112 @@ mod test {
         assert!(v.helper_ref().unknown == 0);
         assert!(v.helper_ref().error == 0);
     }
+
+    #[test]
+    fn secrets_helper() {
+        let p = &StandardPolicy::new();
+        // Decrypt using a password.  Only the second one is right.
+        let helper = SecretsHelper::new(VHelper::default())
+            .add_password("wrong")
+            .add_password("123");
+        let mut d = Decryptor::from_bytes(
+            p,
+            ::tests::message("encrypted-aes256-password-123.gpg"),
+            helper, None).unwrap();
+        let mut content = Vec::new();
+        d.read_to_end(&mut content).unwrap();
+        assert_eq!(&content[..], ::tests::manifesto());
+        assert_eq!(d.helper_ref().decrypted_with(),
+                   Some(&DecryptedWith::Password(1)));
+
+        // Decrypt using a secret key.
+        let tpk = TPK::from_bytes(
+            ::tests::key("testy-private.pgp")).unwrap();
+        let helper = SecretsHelper::new(VHelper::default())
+            .add_password("123")
+            .add_tpk(&tpk);
+        let mut d = Decryptor::from_bytes(
+            p,
+            ::tests::message("encrypted-to-testy.gpg"),
+            helper, None).unwrap();
+        let mut content = Vec::new();
+        d.read_to_end(&mut content).unwrap();
+        assert!(content.len() > 0);
+        assert_eq!(d.helper_ref().decrypted_with(),
+                   Some(&DecryptedWith::Key {
+                       tpk: tpk.fingerprint(),
+                       key: tpk.subkeys().next().unwrap().subkey()
+                           .fingerprint(),
+                   }));
+
+        // Neither the key nor the password is right.
+        let helper = SecretsHelper::new(VHelper::default())
+            .add_password("123")
+            .add_tpk(&tpk);
+        assert!(Decryptor::from_bytes(
+            p,
+            ::tests::message("encrypted-to-testy-new.pgp"),
+            helper, None).is_err());
+    }
+
+    #[test]
+    fn secrets_helper_hidden_recipient() {
+        let p = &StandardPolicy::new();
+        use PacketPile;
+        use serialize::SerializeInto;
+
+        // Hide the recipient of the message.
+        let mut pile = PacketPile::from_bytes(
+            ::tests::message("encrypted-to-testy.gpg")).unwrap();
+        if let Some(Packet::PKESK(ref mut pkesk)) = pile.path_ref_mut(&[0]) {
+            pkesk.set_recipient(KeyID::wildcard());
+        } else {
+            panic!("Expected a PKESK packet");
+        }
+        let message = pile.to_vec().unwrap();
+
+        // All keys are tried, including ones that don't fit.
+        let decoy = TPK::from_bytes(
+            ::tests::key("testy-new-private.pgp")).unwrap();
+        let tpk = TPK::from_bytes(
+            ::tests::key("testy-private.pgp")).unwrap();
+        let helper = SecretsHelper::new(VHelper::default())
+            .add_tpk(&decoy)
+            .add_tpk(&tpk);
+        let mut d = Decryptor::from_bytes(p, &message, helper, None).unwrap();
+        let mut content = Vec::new();
+        d.read_to_end(&mut content).unwrap();
+        assert!(content.len() > 0);
+        assert_eq!(d.helper_ref().decrypted_with(),
+                   Some(&DecryptedWith::Key {
+                       tpk: tpk.fingerprint(),
+                       key: tpk.subkeys().next().unwrap().subkey()
+                           .fingerprint(),
+                   }));
+    }
+
+    /// Tests that signatures using weak hash algorithms are rejected
+    /// by the standard policy, but not by the null policy.
+    #[test]
+    fn policy_rejects_sha1() {
+        let keys = vec![TPK::from_bytes(::tests::key("neal.pgp")).unwrap()];
+        let msg = ::tests::message("signed-1-sha1-neal.gpg");
+
+        // The signature is considered bad, hence check() fails.
+        let p = &StandardPolicy::new();
+        let h = VHelper::new(0, 0, 0, 0, keys.clone());
+        assert!(Verifier::from_bytes(p, msg, h, ::frozen_time()).is_err());
+        let h = VHelper::new(0, 0, 0, 0, keys.clone());
+        assert!(Decryptor::from_bytes(p, msg, h, ::frozen_time()).is_err());
+
+        let p = &NullPolicy::new();
+        let h = VHelper::new(0, 0, 0, 0, keys.clone());
+        let mut v = Verifier::from_bytes(p, msg, h, ::frozen_time()).unwrap();
+        assert!(v.message_processed());
+        assert_eq!(v.helper_ref().good, 1);
+        assert_eq!(v.helper_ref().bad, 0);
+        let mut content = Vec::new();
+        v.read_to_end(&mut content).unwrap();
+        assert_eq!(::tests::manifesto(), &content[..]);
+    }
 }
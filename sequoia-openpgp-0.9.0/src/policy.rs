@@ -0,0 +1,243 @@
+//! Policies for accepting cryptographic algorithms.
+//!
+//! Not every algorithm that OpenPGP allows is still considered
+//! secure.  For instance, SHA-1 collisions are practical, and a
+//! 1024-bit RSA key can be factored by a determined attacker.  A
+//! [`Policy`] decides which algorithms to trust.  It is passed to the
+//! streaming [`Verifier`], [`DetachedVerifier`],
+//! [`DetachedVerifierWriter`], and [`Decryptor`], which reject
+//! signatures and messages that violate it.
+//!
+//! This module provides two policies: [`StandardPolicy`], which
+//! reflects current best practices, and [`NullPolicy`], which accepts
+//! everything and is mostly useful for testing.  To implement your
+//! own policy, implement the `Policy` trait, and override the methods
+//! you care about.
+//!
+//!   [`Policy`]: trait.Policy.html
+//!   [`StandardPolicy`]: struct.StandardPolicy.html
+//!   [`NullPolicy`]: struct.NullPolicy.html
+//!   [`Verifier`]: ../parse/stream/struct.Verifier.html
+//!   [`DetachedVerifier`]: ../parse/stream/struct.DetachedVerifier.html
+//!   [`DetachedVerifierWriter`]: ../parse/stream/struct.DetachedVerifierWriter.html
+//!   [`Decryptor`]: ../parse/stream/struct.Decryptor.html
+//!
+//! # Example
+//!
+//! A policy that additionally rejects 64-bit block ciphers:
+//!
+//! ```
+//! # extern crate sequoia_openpgp as openpgp;
+//! use openpgp::constants::SymmetricAlgorithm;
+//! use openpgp::policy::{Policy, StandardPolicy};
+//!
+//! #[derive(Debug)]
+//! struct StrictPolicy(StandardPolicy);
+//!
+//! impl Policy for StrictPolicy {
+//!     fn signature_hash_ok(&self, algo: openpgp::constants::HashAlgorithm)
+//!                          -> bool {
+//!         self.0.signature_hash_ok(algo)
+//!     }
+//!
+//!     fn symmetric_ok(&self, algo: SymmetricAlgorithm) -> bool {
+//!         self.0.symmetric_ok(algo)
+//!             && algo.block_size().map(|s| s >= 16).unwrap_or(false)
+//!     }
+//!
+//!     fn public_key_ok(&self, algo: openpgp::constants::PublicKeyAlgorithm,
+//!                      bits: Option<usize>) -> bool {
+//!         self.0.public_key_ok(algo, bits)
+//!     }
+//!
+//!     // Not overriding aead_ok accepts all AEAD algorithms.
+//! }
+//!
+//! let p = StrictPolicy(StandardPolicy::new());
+//! assert!(p.symmetric_ok(SymmetricAlgorithm::AES256));
+//! assert!(! p.symmetric_ok(SymmetricAlgorithm::CAST5));
+//! ```
+
+use std::fmt;
+
+use constants::{
+    AEADAlgorithm,
+    HashAlgorithm,
+    PublicKeyAlgorithm,
+    SymmetricAlgorithm,
+};
+use packet::{Key, Signature};
+
+/// Decides which cryptographic algorithms are acceptable.
+///
+/// Every method has a default implementation that accepts
+/// everything.
+pub trait Policy : fmt::Debug {
+    /// Returns whether signatures using the given hash algorithm are
+    /// acceptable.
+    fn signature_hash_ok(&self, algo: HashAlgorithm) -> bool {
+        let _ = algo;
+        true
+    }
+
+    /// Returns whether messages encrypted using the given symmetric
+    /// algorithm are acceptable.
+    fn symmetric_ok(&self, algo: SymmetricAlgorithm) -> bool {
+        let _ = algo;
+        true
+    }
+
+    /// Returns whether messages encrypted using the given AEAD
+    /// algorithm are acceptable.
+    fn aead_ok(&self, algo: AEADAlgorithm) -> bool {
+        let _ = algo;
+        true
+    }
+
+    /// Returns whether keys using the given public key algorithm are
+    /// acceptable.
+    ///
+    /// `bits` is the size of the key as returned by
+    /// [`PublicKey::bits`], if known.
+    ///
+    ///   [`PublicKey::bits`]: ../crypto/mpis/enum.PublicKey.html#method.bits
+    fn public_key_ok(&self, algo: PublicKeyAlgorithm, bits: Option<usize>)
+                     -> bool {
+        let _ = (algo, bits);
+        true
+    }
+}
+
+/// Returns whether `sig`, made by `key`, is acceptable under `policy`.
+pub(crate) fn signature_ok(policy: &dyn Policy, sig: &Signature, key: &Key)
+                           -> bool {
+    policy.signature_hash_ok(sig.hash_algo())
+        && policy.public_key_ok(key.pk_algo(), key.mpis().bits())
+}
+
+/// The standard policy.
+///
+/// This policy reflects the current consensus on which algorithms
+/// are safe to use:
+///
+///   - Signatures using MD5, SHA-1, or RIPEMD-160 are rejected, see
+///     [`HashAlgorithm::is_secure`].
+///
+///   - RSA, DSA, and Elgamal keys must have at least 2048 bits.  All
+///     standardized elliptic curve algorithms are accepted.
+///
+///   - All standardized symmetric and AEAD algorithms except IDEA
+///     are accepted.  In particular, 64-bit block ciphers are still
+///     accepted so that old messages can be decrypted.  Messages
+///     that are not encrypted, or that use private or unknown
+///     algorithms, are rejected.
+///
+/// The defaults will be updated as algorithms age.
+///
+///   [`HashAlgorithm::is_secure`]: ../constants/enum.HashAlgorithm.html#method.is_secure
+#[derive(Debug, Clone, Default)]
+pub struct StandardPolicy {
+}
+
+impl StandardPolicy {
+    /// Returns a new `StandardPolicy`.
+    pub fn new() -> Self {
+        StandardPolicy {}
+    }
+}
+
+impl Policy for StandardPolicy {
+    fn signature_hash_ok(&self, algo: HashAlgorithm) -> bool {
+        algo.is_secure()
+    }
+
+    fn symmetric_ok(&self, algo: SymmetricAlgorithm) -> bool {
+        use constants::SymmetricAlgorithm::*;
+        match algo {
+            TripleDES | CAST5 | Blowfish | AES128 | AES192 | AES256
+                | Twofish | Camellia128 | Camellia192 | Camellia256
+                => true,
+            Unencrypted | IDEA | Private(_) | Unknown(_)
+                => false,
+        }
+    }
+
+    fn aead_ok(&self, algo: AEADAlgorithm) -> bool {
+        use constants::AEADAlgorithm::*;
+        match algo {
+            EAX | OCB => true,
+            Private(_) | Unknown(_) => false,
+        }
+    }
+
+    fn public_key_ok(&self, algo: PublicKeyAlgorithm, bits: Option<usize>)
+                     -> bool {
+        use constants::PublicKeyAlgorithm::*;
+        #[allow(deprecated)]
+        match algo {
+            RSAEncryptSign | RSAEncrypt | RSASign | DSA | ElgamalEncrypt
+                | ElgamalEncryptSign
+                => bits.map(|b| b >= 2048).unwrap_or(false),
+            ECDH | ECDSA | EdDSA
+                => true,
+            Private(_) | Unknown(_)
+                => false,
+        }
+    }
+}
+
+/// A policy that accepts everything.
+///
+/// This is useful for testing, and for inspecting old data.  Don't
+/// use it to decide whether to trust a signature.
+#[derive(Debug, Clone, Default)]
+pub struct NullPolicy {
+}
+
+impl NullPolicy {
+    /// Returns a new `NullPolicy`.
+    pub fn new() -> Self {
+        NullPolicy {}
+    }
+}
+
+impl Policy for NullPolicy {
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_policy() {
+        let p = StandardPolicy::new();
+        assert!(p.signature_hash_ok(HashAlgorithm::SHA256));
+        assert!(p.signature_hash_ok(HashAlgorithm::SHA512));
+        assert!(! p.signature_hash_ok(HashAlgorithm::SHA1));
+        assert!(! p.signature_hash_ok(HashAlgorithm::MD5));
+
+        assert!(p.symmetric_ok(SymmetricAlgorithm::AES256));
+        assert!(! p.symmetric_ok(SymmetricAlgorithm::Unencrypted));
+        assert!(! p.symmetric_ok(SymmetricAlgorithm::IDEA));
+
+        assert!(p.aead_ok(AEADAlgorithm::EAX));
+        assert!(! p.aead_ok(AEADAlgorithm::Unknown(42)));
+
+        assert!(p.public_key_ok(PublicKeyAlgorithm::RSAEncryptSign,
+                                Some(3072)));
+        assert!(! p.public_key_ok(PublicKeyAlgorithm::RSAEncryptSign,
+                                  Some(1024)));
+        assert!(! p.public_key_ok(PublicKeyAlgorithm::DSA, None));
+        assert!(p.public_key_ok(PublicKeyAlgorithm::EdDSA, Some(256)));
+    }
+
+    #[test]
+    fn null_policy() {
+        let p = NullPolicy::new();
+        assert!(p.signature_hash_ok(HashAlgorithm::MD5));
+        assert!(p.symmetric_ok(SymmetricAlgorithm::Unencrypted));
+        assert!(p.aead_ok(AEADAlgorithm::Unknown(42)));
+        assert!(p.public_key_ok(PublicKeyAlgorithm::RSAEncryptSign,
+                                Some(512)));
+    }
+}
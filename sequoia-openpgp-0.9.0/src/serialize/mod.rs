@@ -23,6 +23,7 @@ use self::partial_body::PartialBodyFilter;
 pub mod writer;
 pub mod stream;
 use crypto::s2k::S2K;
+use parse::Parse;
 use packet::signature::subpacket::{
     Subpacket, SubpacketValue, SubpacketLengthTrait,
 };
@@ -43,7 +44,17 @@ pub trait Serialize {
 
 /// Serializes OpenPGP data structures into pre-allocated buffers.
 pub trait SerializeInto {
-    /// Computes the maximal length of the serialized representation.
+    /// Computes the length of the serialized representation.
+    ///
+    /// For `Packet`s, `PacketPile`s, `Message`s and `TPK`s, this is
+    /// exactly the number of bytes that `Serialize::serialize`
+    /// writes, so it can be used to allocate buffers precisely.
+    ///
+    /// Computing the length of a compressed data packet, or of a
+    /// container that includes one, requires compressing its body,
+    /// which is as expensive as serializing it.  Use `to_vec` instead
+    /// of calling this function and `serialize_into`, because it
+    /// avoids compressing the data twice.
     ///
     /// # Errors
     ///
@@ -57,8 +68,8 @@ pub trait SerializeInto {
     ///
     /// # Errors
     ///
-    /// If the length of the given slice is smaller than the maximal
-    /// length computed by `serialized_len()`, this function returns
+    /// If the length of the given slice is smaller than the length
+    /// computed by `serialized_len()`, this function returns
     /// `Error::InvalidArgument`.
     fn serialize_into(&self, buf: &mut [u8]) -> Result<usize>;
 
@@ -73,8 +84,8 @@ pub trait SerializeInto {
 }
 
 trait NetLength {
-    /// Computes the maximal length of the serialized representation
-    /// without framing.
+    /// Computes the length of the serialized representation without
+    /// framing.
     ///
     /// # Errors
     ///
@@ -82,8 +93,8 @@ trait NetLength {
     /// length.
     fn net_len(&self) -> usize;
 
-    /// Computes the maximal length of the serialized representation
-    /// with framing.
+    /// Computes the length of the serialized representation with
+    /// framing.
     ///
     /// # Errors
     ///
@@ -396,6 +407,7 @@ impl SerializeInto for Fingerprint {
     fn serialized_len(&self) -> usize {
         match self {
             Fingerprint::V4(_) => 20,
+            Fingerprint::V5(_) => 32,
             Fingerprint::Invalid(ref fp) => fp.len(),
         }
     }
@@ -928,6 +940,8 @@ impl<'a> Serialize for SubpacketValue<'a> {
             },
             EmbeddedSignature(ref p) => match p {
                 &Packet::Signature(ref sig) => sig.serialize(o)?,
+                // A malformed embedded signature is kept as is.
+                &Packet::Unknown(ref u) => u.serialize(o)?,
                 _ => return Err(Error::InvalidArgument(
                     format!("Not a signature: {:?}", p)).into()),
             },
@@ -936,6 +950,10 @@ impl<'a> Serialize for SubpacketValue<'a> {
                     o.write_all(&[4])?;
                     o.write_all(fp.as_slice())?;
                 },
+                Fingerprint::V5(_) => {
+                    o.write_all(&[5])?;
+                    o.write_all(fp.as_slice())?;
+                },
                 _ => return Err(Error::InvalidArgument(
                     "Unknown kind of fingerprint".into()).into()),
             }
@@ -948,6 +966,10 @@ impl<'a> Serialize for SubpacketValue<'a> {
                     o.write_all(&[4])?;
                     o.write_all(fp.as_slice())?;
                 },
+                Fingerprint::V5(_) => {
+                    o.write_all(&[5])?;
+                    o.write_all(fp.as_slice())?;
+                },
                 _ => return Err(Error::InvalidArgument(
                     "Unknown kind of fingerprint".into()).into()),
             }
@@ -988,15 +1010,18 @@ impl<'a> SerializeInto for SubpacketValue<'a> {
             SignatureTarget { ref digest, .. } => 2 + digest.len(),
             EmbeddedSignature(ref p) => match p {
                 &Packet::Signature(ref sig) => sig.serialized_len(),
+                &Packet::Unknown(ref u) => u.serialized_len(),
                 _ => 0,
             },
             IssuerFingerprint(ref fp) => match fp {
-                Fingerprint::V4(_) => 1 + fp.serialized_len(),
+                Fingerprint::V4(_) | Fingerprint::V5(_) =>
+                    1 + fp.serialized_len(),
                 _ => 0,
             },
             PreferredAEADAlgorithms(ref p) => p.len(),
             IntendedRecipient(ref fp) => match fp {
-                Fingerprint::V4(_) => 1 + fp.serialized_len(),
+                Fingerprint::V4(_) | Fingerprint::V5(_) =>
+                    1 + fp.serialized_len(),
                 _ => 0,
             },
             Unknown(ref raw) => raw.len(),
@@ -1012,25 +1037,114 @@ impl<'a> SerializeInto for SubpacketValue<'a> {
 impl Serialize for Signature {
     fn serialize(&self, o: &mut dyn std::io::Write) -> Result<()> {
         match self {
+            &Signature::V3(ref s) => s.serialize(o),
             &Signature::V4(ref s) => s.serialize(o),
         }
     }
 }
 
+impl NetLength for Signature {
+    fn net_len(&self) -> usize {
+        match self {
+            &Signature::V3(ref s) => s.net_len(),
+            &Signature::V4(ref s) => s.net_len(),
+        }
+    }
+}
+
 impl SerializeInto for Signature {
     fn serialized_len(&self) -> usize {
         match self {
+            &Signature::V3(ref s) => s.serialized_len(),
             &Signature::V4(ref s) => s.serialized_len(),
         }
     }
 
     fn serialize_into(&self, buf: &mut [u8]) -> Result<usize> {
         match self {
+            &Signature::V3(ref s) => s.serialize_into(buf),
             &Signature::V4(ref s) => s.serialize_into(buf),
         }
     }
 }
 
+impl Serialize for Signature3 {
+    /// Writes a serialized version of the specified `Signature`
+    /// packet to `o`.
+    ///
+    /// Note: this function does not compute the signature (which
+    /// would require access to the private key); it assumes that
+    /// sig.mpis is up to date.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if the signature lacks a
+    /// creation time or an issuer, or if its subpacket areas hold
+    /// anything else, which a version 3 signature cannot express.
+    ///
+    /// [`Error::InvalidArgument`]: ../../enum.Error.html#variant.InvalidArgument
+    fn serialize(&self, o: &mut dyn std::io::Write) -> Result<()> {
+        use packet::signature::subpacket::SubpacketTag;
+
+        if self.hashed_area().iter()
+            .any(|(_, _, sb)| sb.tag != SubpacketTag::SignatureCreationTime)
+            || self.unhashed_area().iter()
+            .any(|(_, _, sb)| sb.tag != SubpacketTag::Issuer)
+        {
+            return Err(Error::InvalidArgument(
+                "Version 3 signatures only have a creation time and \
+                 an issuer".into()).into());
+        }
+
+        let creation_time = self.signature_creation_time()
+            .ok_or_else(|| Error::InvalidArgument(
+                "Version 3 signature without creation time".into()))?;
+        let issuer = self.issuer()
+            .ok_or_else(|| Error::InvalidArgument(
+                "Version 3 signature without issuer".into()))?;
+
+        write_byte(o, 3)?;
+        // The length of the hashed material.
+        write_byte(o, 5)?;
+        write_byte(o, self.sigtype().into())?;
+        write_be_u32(o, creation_time.to_pgp()?)?;
+        o.write_all(issuer.as_slice())?;
+        write_byte(o, self.pk_algo().into())?;
+        write_byte(o, self.hash_algo().into())?;
+
+        write_byte(o, self.hash_prefix()[0])?;
+        write_byte(o, self.hash_prefix()[1])?;
+
+        self.mpis().serialize(o)?;
+
+        Ok(())
+    }
+}
+
+impl NetLength for Signature3 {
+    fn net_len(&self) -> usize {
+        1 // Version.
+            + 1 // Length of the hashed material.
+            + 1 // Signature type.
+            + 4 // Creation time.
+            + 8 // Issuer.
+            + 1 // PK algorithm.
+            + 1 // Hash algorithm.
+            + 2 // Hash prefix.
+            + self.mpis().serialized_len()
+    }
+}
+
+impl SerializeInto for Signature3 {
+    fn serialized_len(&self) -> usize {
+        self.net_len()
+    }
+
+    fn serialize_into(&self, buf: &mut [u8]) -> Result<usize> {
+        generic_serialize_into(self, buf)
+    }
+}
+
 impl Serialize for Signature4 {
     /// Writes a serialized version of the specified `Signature`
     /// packet to `o`.
@@ -1165,14 +1279,24 @@ impl Serialize for Key {
     fn serialize(&self, o: &mut io::Write) -> Result<()> {
         match self {
             &Key::V4(ref p) => p.serialize(o),
+            &Key::V5(ref p) => p.serialize(o),
         }
     }
 }
 
 impl Key {
+    fn serialize_key(&self, o: &mut io::Write, serialize_secrets: bool)
+                     -> Result<()> {
+        match self {
+            &Key::V4(ref p) => p.serialize_key(o, serialize_secrets),
+            &Key::V5(ref p) => p.serialize_key(o, serialize_secrets),
+        }
+    }
+
     fn net_len_key(&self, serialize_secrets: bool) -> usize {
         match self {
             &Key::V4(ref p) => p.net_len_key(serialize_secrets),
+            &Key::V5(ref p) => p.net_len_key(serialize_secrets),
         }
     }
 }
@@ -1181,12 +1305,14 @@ impl SerializeInto for Key {
     fn serialized_len(&self) -> usize {
         match self {
             &Key::V4(ref p) => p.serialized_len(),
+            &Key::V5(ref p) => p.serialized_len(),
         }
     }
 
     fn serialize_into(&self, buf: &mut [u8]) -> Result<usize> {
         match self {
-            &Key::V4(ref p) => p.serialize_into(buf)
+            &Key::V4(ref p) => p.serialize_into(buf),
+            &Key::V5(ref p) => p.serialize_into(buf),
         }
     }
 }
@@ -1270,6 +1396,49 @@ impl SerializeInto for Key4 {
     }
 }
 
+impl Serialize for Key5 {
+    fn serialize(&self, o: &mut io::Write) -> Result<()> {
+        self.serialize_key(o, true)
+    }
+}
+
+impl Key5 {
+    fn serialize_key(&self, o: &mut io::Write, serialize_secrets: bool)
+                     -> Result<()> {
+        if self.secret().is_some() && serialize_secrets {
+            return Err(Error::InvalidOperation(
+                "Serializing the secret key material of version 5 keys \
+                 is not supported".into()).into());
+        }
+
+        write_byte(o, 5)?; // Version.
+        write_be_u32(o, self.creation_time().to_pgp()?)?;
+        write_byte(o, self.pk_algo().into())?;
+        write_be_u32(o, self.mpis().serialized_len() as u32)?;
+        self.mpis().serialize(o)?;
+
+        Ok(())
+    }
+
+    fn net_len_key(&self, _serialize_secrets: bool) -> usize {
+        1 // Version.
+            + 4 // Creation time.
+            + 1 // PK algo.
+            + 4 // Length of the public key material.
+            + self.mpis().serialized_len()
+    }
+}
+
+impl SerializeInto for Key5 {
+    fn serialized_len(&self) -> usize {
+        self.net_len_key(true)
+    }
+
+    fn serialize_into(&self, buf: &mut [u8]) -> Result<usize> {
+        generic_serialize_into(self, buf)
+    }
+}
+
 impl Serialize for Marker {
     fn serialize(&self, o: &mut dyn std::io::Write) -> Result<()> {
         o.write_all(Marker::BODY)?;
@@ -1492,7 +1661,11 @@ impl Serialize for Literal {
 
 impl NetLength for Literal {
     fn net_len(&self) -> usize {
-        1 + (1 + self.filename().map(|f| f.len()).unwrap_or(0)) + 4
+        // serialize_headers truncates the filename to 255 octets.
+        let filename_len = self.filename()
+            .map(|f| cmp::min(f.len(), 255)).unwrap_or(0);
+
+        1 + (1 + filename_len) + 4
             + self.common.body.as_ref().map(|b| b.len()).unwrap_or(0)
     }
 }
@@ -1524,7 +1697,8 @@ impl Serialize for CompressedData {
         }
 
         let o = stream::Message::new(o);
-        let mut o = stream::Compressor::new_naked(o, self.algorithm(), 0)?;
+        let mut o = stream::Compressor::new_naked(o, self.algorithm(),
+                                                    Default::default(), 0)?;
 
         // Serialize the packets.
         if let Some(ref children) = self.common.children {
@@ -1544,30 +1718,23 @@ impl Serialize for CompressedData {
 
 impl NetLength for CompressedData {
     fn net_len(&self) -> usize {
-        let inner_length =
-            self.common.children.as_ref().map(|children| {
-                children.packets.iter().map(|p| p.serialized_len())
-                    .sum()
-            }).unwrap_or(0)
-            + self.common.body.as_ref().map(|body| body.len()).unwrap_or(0);
-
-        // Worst case, the data gets larger.  Account for that.
-        let inner_length = inner_length + cmp::max(inner_length / 2, 128);
-
-        1 // Algorithm.
-            + inner_length // Compressed data.
+        // The size of the compressed data depends on the data being
+        // compressed, so the only way to get the exact length is to
+        // compress it.
+        let mut body = Vec::new();
+        match self.serialize(&mut body) {
+            Ok(()) => body.len(),
+            Err(_) => 0,
+        }
     }
 }
 
 impl SerializeInto for CompressedData {
-    /// Computes the maximal length of the serialized representation.
+    /// Computes the length of the serialized representation.
     ///
-    /// The size of the serialized compressed data packet is tricky to
-    /// predict.  First, it depends on the data being compressed.
-    /// Second, we emit partial body encoded data.
-    ///
-    /// This function tries overestimates the length.  However, it may
-    /// happen that `serialize_into()` fails.
+    /// The size of the serialized compressed data packet depends on
+    /// the data being compressed.  To compute it, this function
+    /// compresses the data, which is expensive.
     ///
     /// # Errors
     ///
@@ -1579,6 +1746,14 @@ impl SerializeInto for CompressedData {
     fn serialize_into(&self, buf: &mut [u8]) -> Result<usize> {
         generic_serialize_into(self, buf)
     }
+
+    fn to_vec(&self) -> Result<Vec<u8>> {
+        // Avoid compressing the data twice, once to compute the
+        // length, and once to serialize it.
+        let mut o = Vec::new();
+        self.serialize(&mut o)?;
+        Ok(o)
+    }
 }
 
 impl Serialize for PKESK {
@@ -1896,9 +2071,10 @@ impl Serialize for Packet {
     fn serialize(&self, o: &mut dyn std::io::Write) -> Result<()> {
         CTB::new(self.tag()).serialize(o)?;
 
-        // Special-case the compressed data packet, because we need
-        // the accurate length, and CompressedData::net_len()
-        // overestimates the size.
+        // Special-case the compressed data packet.  Its length is
+        // only known after compressing the body, so compress it once
+        // here instead of once for CompressedData::net_len() and
+        // again for CompressedData::serialize().
         if let Packet::CompressedData(ref p) = self {
             let mut body = Vec::new();
             p.serialize(&mut body)?;
@@ -1931,6 +2107,62 @@ impl Serialize for Packet {
     }
 }
 
+impl Packet {
+    /// Writes the packet's original encoding to `o`.
+    ///
+    /// If the packet was parsed with
+    /// [`PacketParserBuilder::preserve_encoding`] enabled, this
+    /// writes exactly the bytes that the packet was parsed from,
+    /// including the packet's header.  Otherwise, or if the packet
+    /// was modified after it was parsed, this is equivalent to
+    /// `serialize`.
+    ///
+    ///   [`PacketParserBuilder::preserve_encoding`]: parse/struct.PacketParserBuilder.html#method.preserve_encoding
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::Result;
+    /// # use openpgp::parse::{Parse, PacketParserResult, PacketParserBuilder};
+    /// # f(include_bytes!("../../tests/data/messages/sig.gpg"));
+    /// #
+    /// # fn f(data: &[u8]) -> Result<()> {
+    /// let ppr = PacketParserBuilder::from_bytes(data)?
+    ///     .preserve_encoding(true)
+    ///     .finalize()?;
+    /// if let PacketParserResult::Some(pp) = ppr {
+    ///     let (packet, _) = pp.next()?;
+    ///
+    ///     let mut buf = Vec::new();
+    ///     packet.serialize_exact(&mut buf)?;
+    ///     assert_eq!(&buf[..], data);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serialize_exact(&self, o: &mut dyn std::io::Write) -> Result<()> {
+        let mut canonical = Vec::new();
+        self.serialize(&mut canonical)?;
+
+        if let Some(ref encoding) = self.original_encoding {
+            // Make sure that the original encoding still represents
+            // the packet by comparing the canonical encodings.
+            if let Ok(original) = Packet::from_bytes(&encoding[..]) {
+                let mut original_canonical = Vec::new();
+                original.serialize(&mut original_canonical)?;
+                if original_canonical == canonical {
+                    o.write_all(encoding)?;
+                    return Ok(());
+                }
+            }
+        }
+
+        o.write_all(&canonical)?;
+        Ok(())
+    }
+}
+
 impl NetLength for Packet {
     fn net_len(&self) -> usize {
         match self {
@@ -1957,34 +2189,32 @@ impl NetLength for Packet {
 }
 
 impl SerializeInto for Packet {
+    /// Computes the length of the serialized representation.
+    ///
+    /// This is exactly the number of bytes that `serialize` writes,
+    /// including the packet's header.
     fn serialized_len(&self) -> usize {
-        (match self {
-            &Packet::Unknown(ref p) => p.serialized_len(),
-            &Packet::Signature(ref p) => p.serialized_len(),
-            &Packet::OnePassSig(ref p) => p.serialized_len(),
-            &Packet::PublicKey(ref p) => p.serialized_len(),
-            &Packet::PublicSubkey(ref p) => p.serialized_len(),
-            &Packet::SecretKey(ref p) => p.serialized_len(),
-            &Packet::SecretSubkey(ref p) => p.serialized_len(),
-            &Packet::Marker(ref p) => p.serialized_len(),
-            &Packet::Trust(ref p) => p.serialized_len(),
-            &Packet::UserID(ref p) => p.serialized_len(),
-            &Packet::UserAttribute(ref p) => p.serialized_len(),
-            &Packet::Literal(ref p) => p.serialized_len(),
-            &Packet::CompressedData(ref p) => p.serialized_len(),
-            &Packet::PKESK(ref p) => p.serialized_len(),
-            &Packet::SKESK(ref p) => p.serialized_len(),
-            &Packet::SEIP(ref p) => p.serialized_len(),
-            &Packet::MDC(ref p) => p.serialized_len(),
-            &Packet::AED(ref p) => p.serialized_len(),
-        })
-            + 1 // CTB.
-            + BodyLength::Full(self.net_len() as u32).serialized_len()
+        self.gross_len()
     }
 
     fn serialize_into(&self, buf: &mut [u8]) -> Result<usize> {
         generic_serialize_into(self, buf)
     }
+
+    fn to_vec(&self) -> Result<Vec<u8>> {
+        if let Packet::CompressedData(_) = self {
+            // Avoid compressing the data twice.
+            let mut o = Vec::new();
+            self.serialize(&mut o)?;
+            Ok(o)
+        } else {
+            let mut o = vec![0; self.serialized_len()];
+            let len = self.serialize_into(&mut o[..])?;
+            o.truncate(len);
+            o.shrink_to_fit();
+            Ok(o)
+        }
+    }
 }
 
 /// References packet bodies.
@@ -2074,9 +2304,10 @@ impl<'a> Serialize for PacketRef<'a> {
     fn serialize(&self, o: &mut dyn std::io::Write) -> Result<()> {
         CTB::new(self.tag()).serialize(o)?;
 
-        // Special-case the compressed data packet, because we need
-        // the accurate length, and CompressedData::net_len()
-        // overestimates the size.
+        // Special-case the compressed data packet.  Its length is
+        // only known after compressing the body, so compress it once
+        // here instead of once for CompressedData::net_len() and
+        // again for CompressedData::serialize().
         if let PacketRef::CompressedData(ref p) = self {
             let mut body = Vec::new();
             p.serialize(&mut body)?;
@@ -2135,29 +2366,12 @@ impl<'a> NetLength for PacketRef<'a> {
 }
 
 impl<'a> SerializeInto for PacketRef<'a> {
+    /// Computes the length of the serialized representation.
+    ///
+    /// This is exactly the number of bytes that `serialize` writes,
+    /// including the packet's header.
     fn serialized_len(&self) -> usize {
-        (match self {
-            PacketRef::Unknown(p) => p.serialized_len(),
-            PacketRef::Signature(p) => p.serialized_len(),
-            PacketRef::OnePassSig(p) => p.serialized_len(),
-            PacketRef::PublicKey(p) => p.serialized_len(),
-            PacketRef::PublicSubkey(p) => p.serialized_len(),
-            PacketRef::SecretKey(p) => p.serialized_len(),
-            PacketRef::SecretSubkey(p) => p.serialized_len(),
-            PacketRef::Marker(p) => p.serialized_len(),
-            PacketRef::Trust(p) => p.serialized_len(),
-            PacketRef::UserID(p) => p.serialized_len(),
-            PacketRef::UserAttribute(p) => p.serialized_len(),
-            PacketRef::Literal(p) => p.serialized_len(),
-            PacketRef::CompressedData(p) => p.serialized_len(),
-            PacketRef::PKESK(p) => p.serialized_len(),
-            PacketRef::SKESK(p) => p.serialized_len(),
-            PacketRef::SEIP(p) => p.serialized_len(),
-            PacketRef::MDC(p) => p.serialized_len(),
-            PacketRef::AED(p) => p.serialized_len(),
-        })
-            + 1 // CTB.
-            + BodyLength::Full(self.net_len() as u32).serialized_len()
+        self.gross_len()
     }
 
     fn serialize_into(&self, buf: &mut [u8]) -> Result<usize> {
@@ -2184,6 +2398,14 @@ impl SerializeInto for PacketPile {
     fn serialize_into(&self, buf: &mut [u8]) -> Result<usize> {
         generic_serialize_into(self, buf)
     }
+
+    fn to_vec(&self) -> Result<Vec<u8>> {
+        // Computing the length would compress any compressed data
+        // packets, only to compress them again when serializing.
+        let mut o = Vec::new();
+        self.serialize(&mut o)?;
+        Ok(o)
+    }
 }
 
 impl Serialize for Message {
@@ -2204,6 +2426,11 @@ impl SerializeInto for Message {
         use std::ops::Deref;
         self.deref().serialize_into(buf)
     }
+
+    fn to_vec(&self) -> Result<Vec<u8>> {
+        use std::ops::Deref;
+        self.deref().to_vec()
+    }
 }
 
 impl Serialize for autocrypt::AutocryptHeader {
@@ -2671,4 +2898,147 @@ mod test {
             assert_eq!(&buf[..], &b"\xff\xff\xff\xff\xff"[..]);
         }
     }
+
+    #[cfg(feature = "compression-deflate")]
+    #[test]
+    fn serialized_len_is_exact() {
+        use TPK;
+
+        // Packets of many types, including containers and keys
+        // with secrets.
+        for filename in &["messages/compressed-data-algo-1.gpg",
+                          "messages/signed-1.gpg",
+                          "messages/encrypted-to-testy.gpg",
+                          "messages/literal-mode-b.gpg",
+                          "keys/testy-private.pgp",
+                          "keys/dkg.gpg"] {
+            let pile = PacketPile::from_bytes(
+                ::tests::file(filename)).unwrap();
+            for p in pile.descendants() {
+                let mut buf = Vec::new();
+                p.serialize(&mut buf).unwrap();
+                assert_eq!(p.serialized_len(), buf.len(),
+                           "{}: {:?}", filename, p.tag());
+            }
+
+            let mut buf = Vec::new();
+            pile.serialize(&mut buf).unwrap();
+            assert_eq!(pile.serialized_len(), buf.len());
+        }
+
+        // A TPK's secrets are not serialized.
+        for filename in &["testy-private.pgp", "neal.pgp"] {
+            let tpk = TPK::from_bytes(::tests::key(filename)).unwrap();
+            let mut buf = Vec::new();
+            tpk.serialize(&mut buf).unwrap();
+            assert_eq!(tpk.serialized_len(), buf.len());
+        }
+    }
+
+    #[test]
+    fn serialize_exact() {
+        // The signature uses an old-style CTB, which we don't emit.
+        let data = ::tests::message("sig.gpg");
+
+        let ppr = PacketParserBuilder::from_bytes(data).unwrap()
+            .preserve_encoding(true)
+            .finalize().unwrap();
+        let pile = ppr.unwrap().into_packet_pile().unwrap();
+        let sig = pile.children().next().unwrap();
+        assert_eq!(sig.tag(), Tag::Signature);
+
+        let mut buf = Vec::new();
+        sig.serialize(&mut buf).unwrap();
+        assert!(&buf[..] != data);
+
+        let mut buf = Vec::new();
+        sig.serialize_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], data);
+
+        // Without preserving the encoding, this is just serialize.
+        let sig = Packet::from_bytes(data).unwrap();
+        let mut exact = Vec::new();
+        sig.serialize_exact(&mut exact).unwrap();
+        let mut buf = Vec::new();
+        sig.serialize(&mut buf).unwrap();
+        assert_eq!(exact, buf);
+
+        // Modified packets are serialized normally.
+        let mut sig = pile.into_children().next().unwrap();
+        if let Packet::Signature(Signature::V4(ref mut s)) = sig {
+            s.unhashed_area_mut().add(Subpacket::new(
+                SubpacketValue::Issuer(KeyID::from_hex("AAAAAAAAAAAAAAAA")
+                                       .unwrap()), false).unwrap())
+                .unwrap();
+        } else {
+            panic!("expected a signature");
+        }
+        let mut exact = Vec::new();
+        sig.serialize_exact(&mut exact).unwrap();
+        let mut buf = Vec::new();
+        sig.serialize(&mut buf).unwrap();
+        assert_eq!(exact, buf);
+    }
+
+    #[test]
+    fn secret_keys_unchanged() {
+        use parse::PacketParserResult;
+        use packet::key::SecretKey;
+
+        // The secret key material is kept in protected memory.  Make
+        // sure that this doesn't change its serialized form.
+        for f in &["testy-private.pgp",
+                   "testy-new-private.pgp",
+                   "testy-new-encrypted-with-123.pgp",
+                   "dsa2048-elgamal3072-private.pgp",
+                   "testy-nistp256-private.pgp",
+                   "testy-nistp384-private.pgp",
+                   "testy-nistp521-private.pgp",
+                   "emmelie-dorothea-dina-samantha-awina-ed25519-private.pgp"]
+        {
+            let mut ppr = PacketParserBuilder::from_bytes(::tests::key(f))
+                .unwrap()
+                .preserve_encoding(true)
+                .finalize().unwrap();
+            let mut keys = 0;
+            while let PacketParserResult::Some(pp) = ppr {
+                let (packet, ppr_) = pp.next().unwrap();
+                ppr = ppr_;
+
+                let key = match packet {
+                    Packet::SecretKey(ref k) | Packet::SecretSubkey(ref k) =>
+                        k.clone(),
+                    _ => continue,
+                };
+                keys += 1;
+
+                let original = packet.original_encoding.as_ref().unwrap();
+                let header = Header::from_bytes(&original[..]).unwrap();
+                let body = match header.length {
+                    BodyLength::Full(l) => &original[original.len()
+                                                     - l as usize..],
+                    _ => panic!("{}: unexpected length encoding", f),
+                };
+                assert_eq!(&key.to_vec().unwrap()[..], body,
+                           "{}: key {} changed", f, key.keyid());
+
+                // Decrypting the key must not change the secret key
+                // material's encoding either.
+                let mut key = key;
+                let pk_algo = key.pk_algo();
+                let secret = key.secret_mut().unwrap();
+                if secret.is_encrypted() {
+                    secret.decrypt_in_place(pk_algo, &"123".into()).unwrap();
+                }
+                let plain = key.to_vec().unwrap();
+                if let Some(SecretKey::Unencrypted(_)) = key.secret() {
+                    let k = Key::from_bytes(&plain).unwrap();
+                    assert_eq!(k.to_vec().unwrap(), plain);
+                } else {
+                    panic!("{}: key {} is still encrypted", f, key.keyid());
+                }
+            }
+            assert!(keys > 0, "{}: no secret keys", f);
+        }
+    }
 }
@@ -10,6 +10,7 @@
 //! [encryption example]: struct.Encryptor.html#example
 
 use std::fmt;
+use std::cmp;
 use std::io::{self, Write};
 use std::iter;
 use time;
@@ -24,6 +25,7 @@ use {
     crypto::SessionKey,
     packet::prelude::*,
     packet::signature,
+    packet::signature::subpacket::NotationDataFlags,
     TPK,
 };
 use packet::ctb::CTB;
@@ -36,11 +38,13 @@ use super::{
 use constants::{
     AEADAlgorithm,
     CompressionAlgorithm,
+    CompressionLevel,
     DataFormat,
     SignatureType,
     SymmetricAlgorithm,
 };
 use conversions::Time;
+use armor::{self, BEGIN_SIGNED_MESSAGE, trim_trailing_whitespace};
 
 /// Cookie must be public because the writers are.
 #[doc(hidden)]
@@ -198,15 +202,159 @@ pub struct Signer<'a> {
     // digests.
     inner: Option<writer::BoxStack<'a, Cookie>>,
     signers: Vec<&'a mut dyn crypto::Signer>,
-    intended_recipients: Option<Vec<Fingerprint>>,
     detached: bool,
+    params: SignatureParameters,
     hash: crypto::hash::Context,
+    // Whether the last byte hashed was a carriage return.  Used to
+    // canonicalize line endings across writes in text mode.
+    after_cr: bool,
     cookie: Cookie,
 }
 
+/// The parameters of the signatures emitted by `Signer` and
+/// `DetachedSigner`.
+///
+/// Both signers use this to hash the data and to make the
+/// signatures, so that they emit the same signatures.
+struct SignatureParameters {
+    intended_recipients: Option<Vec<Fingerprint>>,
+    hash_algo: HashAlgorithm,
+    creation_time: Option<time::Tm>,
+    notations: Vec<(String, Vec<u8>, NotationDataFlags)>,
+    text_mode: bool,
+}
+
+impl Default for SignatureParameters {
+    fn default() -> Self {
+        SignatureParameters {
+            intended_recipients: None,
+            hash_algo: HashAlgorithm::SHA512,
+            creation_time: None,
+            notations: Vec::new(),
+            text_mode: false,
+        }
+    }
+}
+
+impl SignatureParameters {
+    fn sigtype(&self) -> SignatureType {
+        if self.text_mode {
+            SignatureType::Text
+        } else {
+            SignatureType::Binary
+        }
+    }
+
+    /// Adds a Notation Data subpacket to every signature.
+    ///
+    /// See [`SignerBuilder::add_notation`].
+    ///
+    ///   [`SignerBuilder::add_notation`]: struct.SignerBuilder.html#method.add_notation
+    fn add_notation(&mut self, name: &str, value: &[u8],
+                    flags: Option<NotationDataFlags>)
+                    -> Result<()>
+    {
+        if ! name.contains('@') {
+            return Err(Error::InvalidArgument(
+                format!("Notation name {:?} lacks a namespace", name)).into());
+        }
+
+        self.notations.push((name.into(), value.to_vec(),
+                             flags.unwrap_or_default()));
+        Ok(())
+    }
+
+    /// Signs `hash` with every signer, and writes the signatures to
+    /// `sink`.
+    fn emit(&self, signers: &mut [&mut dyn crypto::Signer],
+            hash: &crypto::hash::Context, sink: &mut dyn io::Write)
+            -> Result<()>
+    {
+        let creation_time = self.creation_time.clone()
+            .unwrap_or_else(|| time::now().canonicalize());
+
+        // Signatures made before the key existed are bogus.  Check
+        // all keys before emitting anything.
+        for signer in signers.iter() {
+            if creation_time < *signer.public().creation_time() {
+                return Err(Error::InvalidArgument(
+                    format!("Signature creation time predates the \
+                             creation time of key {}",
+                            signer.public().keyid())).into());
+            }
+        }
+
+        for signer in signers.iter_mut() {
+            // Part of the signature packet is hashed in, therefore
+            // we need to clone the hash.
+            let hash = hash.clone();
+
+            // Make and hash a signature packet.
+            let mut sig = signature::Builder::new(self.sigtype())
+                .set_signature_creation_time(creation_time.clone())?
+                .set_issuer_fingerprint(signer.public().fingerprint())?
+                // GnuPG up to (and including) 2.2.8 requires the
+                // Issuer subpacket to be present.
+                .set_issuer(signer.public().keyid())?;
+
+            if let Some(ref ir) = self.intended_recipients {
+                sig = sig.set_intended_recipients(ir.clone())?;
+            }
+
+            for &(ref name, ref value, flags) in self.notations.iter() {
+                sig = sig.add_notation(name, value, flags, false)?;
+            }
+
+            // Compute the signature.
+            let sig = sig.sign_hash(*signer, self.hash_algo, hash)?;
+
+            // And emit the packet.
+            Packet::Signature(sig).serialize(sink)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether `key` can make signatures using `hash_algo`.
+///
+/// RSA signatures embed the digest in the PKCS#1 padding, hence the
+/// modulus must be large enough.  DSA and ECDSA use at most as many
+/// bits of the digest as the group has, and the digest must not be
+/// shorter than that (see [Section 13.6 of RFC 4880] and [Section 6
+/// of RFC 6637]).
+///
+///   [Section 13.6 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-13.6
+///   [Section 6 of RFC 6637]: https://tools.ietf.org/html/rfc6637#section-6
+fn key_can_use_hash(key: &Key, hash_algo: HashAlgorithm) -> bool {
+    use crypto::mpis::PublicKey;
+
+    let digest_size = match hash_algo.digest_size() {
+        Ok(size) => size,
+        Err(_) => return false,
+    };
+
+    match key.mpis() {
+        PublicKey::RSA { ref n, .. } =>
+            // EMSA-PKCS1-v1_5 needs at least 11 octets of padding, and
+            // the DigestInfo prefix takes up to 19 octets.
+            digest_size + 19 + 11 <= (n.bits() + 7) / 8,
+        PublicKey::DSA { ref q, .. } =>
+            digest_size * 8 >= q.bits(),
+        PublicKey::ECDSA { ref curve, .. } =>
+            curve.bits().map(|bits| digest_size * 8 >= cmp::min(bits, 512))
+            .unwrap_or(true),
+        _ => true,
+    }
+}
+
 impl<'a> Signer<'a> {
     /// Creates a signer.
     ///
+    /// `hash_algo` selects the digest algorithm used by all
+    /// `signers`.  It is recorded in the one-pass-signature packets
+    /// as well as in the signature packets.  If `None`, SHA512 is
+    /// used.
+    ///
     /// # Example
     ///
     /// ```
@@ -219,6 +367,7 @@ impl<'a> Signer<'a> {
     /// # use openpgp::crypto::KeyPair;
     /// # use openpgp::parse::Parse;
     /// # use openpgp::parse::stream::*;
+    /// # use openpgp::policy::StandardPolicy;
     /// # let tsk = TPK::from_bytes(include_bytes!(
     /// #     "../../tests/data/keys/testy-new-private.pgp"))
     /// #     .unwrap();
@@ -256,7 +405,8 @@ impl<'a> Signer<'a> {
     ///     }
     /// }
     ///
-    /// let mut verifier = Verifier::from_bytes(&o, Helper(&tpk), None)?;
+    /// let p = &StandardPolicy::new();
+    /// let mut verifier = Verifier::from_bytes(p, &o, Helper(&tpk), None)?;
     ///
     /// let mut message = String::new();
     /// verifier.read_to_string(&mut message)?;
@@ -270,7 +420,11 @@ impl<'a> Signer<'a> {
                   -> Result<writer::Stack<'a, Cookie>>
         where H: Into<Option<HashAlgorithm>>
     {
-        Self::make(inner, signers, None, false, hash_algo)
+        let mut builder = SignerBuilder::new(inner, signers);
+        if let Some(algo) = hash_algo.into() {
+            builder = builder.hash_algo(algo);
+        }
+        builder.finalize()
     }
 
     /// Creates a signer with intended recipients.
@@ -286,9 +440,12 @@ impl<'a> Signer<'a> {
                                        -> Result<writer::Stack<'a, Cookie>>
         where H: Into<Option<HashAlgorithm>>
     {
-        Self::make(inner, signers,
-                   Some(recipients.iter().map(|r| r.fingerprint()).collect()),
-                   false, hash_algo)
+        let mut builder = SignerBuilder::new(inner, signers)
+            .intended_recipients(recipients);
+        if let Some(algo) = hash_algo.into() {
+            builder = builder.hash_algo(algo);
+        }
+        builder.finalize()
     }
 
     /// Creates a signer for a detached signature.
@@ -304,6 +461,7 @@ impl<'a> Signer<'a> {
     /// # use openpgp::crypto::KeyPair;
     /// # use openpgp::parse::Parse;
     /// # use openpgp::parse::stream::*;
+    /// # use openpgp::policy::StandardPolicy;
     /// # let tsk = TPK::from_bytes(include_bytes!(
     /// #     "../../tests/data/keys/testy-new-private.pgp"))
     /// #     .unwrap();
@@ -342,8 +500,9 @@ impl<'a> Signer<'a> {
     ///     }
     /// }
     ///
+    /// let p = &StandardPolicy::new();
     /// let mut verifier =
-    ///     DetachedVerifier::from_bytes(&o, b"Make it so, number one!",
+    ///     DetachedVerifier::from_bytes(p, &o, b"Make it so, number one!",
     ///                                  Helper(&tpk), None)?;
     ///
     /// let mut message = String::new();
@@ -358,18 +517,18 @@ impl<'a> Signer<'a> {
                        -> Result<writer::Stack<'a, Cookie>>
         where H: Into<Option<HashAlgorithm>>
     {
-        Self::make(inner, signers, None, true, hash_algo)
+        let mut builder = SignerBuilder::new(inner, signers).detached();
+        if let Some(algo) = hash_algo.into() {
+            builder = builder.hash_algo(algo);
+        }
+        builder.finalize()
     }
 
-    fn make<H>(inner: writer::Stack<'a, Cookie>,
-               signers: Vec<&'a mut dyn crypto::Signer>,
-               intended_recipients: Option<Vec<Fingerprint>>, detached: bool,
-               hash_algo: H)
-               -> Result<writer::Stack<'a, Cookie>>
-        where H: Into<Option<HashAlgorithm>>
-    {
+    fn make(builder: SignerBuilder<'a>) -> Result<writer::Stack<'a, Cookie>> {
+        let SignerBuilder { inner, signers, detached, params } = builder;
         let mut inner = writer::BoxStack::from(inner);
-        let hash_algo = hash_algo.into().unwrap_or(HashAlgorithm::SHA512);
+        let hash_algo = params.hash_algo;
+        let sigtype = params.sigtype();
 
         if signers.len() == 0 {
             return Err(Error::InvalidArgument(
@@ -381,7 +540,7 @@ impl<'a> Signer<'a> {
             // signature packet.
             for (i, keypair) in signers.iter().enumerate() {
                 let key = keypair.public();
-                let mut ops = OnePassSig3::new(SignatureType::Binary);
+                let mut ops = OnePassSig3::new(sigtype);
                 ops.set_pk_algo(key.pk_algo());
                 ops.set_hash_algo(hash_algo);
                 ops.set_issuer(key.keyid());
@@ -394,9 +553,10 @@ impl<'a> Signer<'a> {
         Ok(writer::Stack::from(Box::new(Signer {
             inner: Some(inner),
             signers: signers,
-            intended_recipients: intended_recipients,
             detached: detached,
+            params: params,
             hash: hash_algo.context()?,
+            after_cr: false,
             cookie: Cookie {
                 level: level,
                 private: Private::Signer,
@@ -404,36 +564,185 @@ impl<'a> Signer<'a> {
         })))
     }
 
+    /// Hashes `data`.
+    ///
+    /// In text mode, line endings are normalized to CRLF.
+    fn hash_data(&mut self, data: &[u8]) {
+        signature::hash_document_part(&mut self.hash, self.params.sigtype(),
+                                      &mut self.after_cr, data);
+    }
+
     fn emit_signatures(&mut self) -> Result<()> {
         if let Some(ref mut sink) = self.inner {
-            // Emit the signatures in reverse, so that the
-            // one-pass-signature and signature packets "bracket" the
-            // message.
-            for signer in self.signers.iter_mut() {
-                // Part of the signature packet is hashed in,
-                // therefore we need to clone the hash.
-                let mut hash = self.hash.clone();
-
-                // Make and hash a signature packet.
-                let mut sig = signature::Builder::new(SignatureType::Binary)
-                    .set_signature_creation_time(time::now().canonicalize())?
-                    .set_issuer_fingerprint(signer.public().fingerprint())?
-                    // GnuPG up to (and including) 2.2.8 requires the
-                    // Issuer subpacket to be present.
-                    .set_issuer(signer.public().keyid())?;
-
-                if let Some(ref ir) = self.intended_recipients {
-                    sig = sig.set_intended_recipients(ir.clone())?;
-                }
+            self.params.emit(&mut self.signers, &self.hash, sink)?;
+        }
+        Ok(())
+    }
+}
 
-                // Compute the signature.
-                let sig = sig.sign_hash(*signer, HashAlgorithm::SHA512, hash)?;
+/// Builds a `Signer`.
+///
+/// This allows configuring options not covered by the constructors
+/// of [`Signer`].
+///
+///   [`Signer`]: struct.Signer.html
+///
+/// # Example
+///
+/// ```
+/// extern crate sequoia_openpgp as openpgp;
+/// extern crate time;
+/// use std::io::Write;
+/// use openpgp::serialize::stream::{Message, SignerBuilder};
+/// # use openpgp::{Result, TPK};
+/// # use openpgp::parse::Parse;
+/// # let tsk = TPK::from_bytes(include_bytes!(
+/// #     "../../tests/data/keys/testy-new-private.pgp"))
+/// #     .unwrap();
+/// # f(tsk).unwrap();
+/// # fn f(tsk: TPK) -> Result<()> {
+/// let mut signing_keypair =
+///     tsk.keys_valid().signing_capable().nth(0).unwrap().2
+///     .clone().into_keypair()?;
+///
+/// let mut o = vec![];
+/// {
+///     let message = Message::new(&mut o);
+///     let mut signer =
+///         SignerBuilder::new(message, vec![&mut signing_keypair])
+///         .detached()
+///         // Pin the creation time, e.g. for reproducible builds.
+///         .creation_time(time::now_utc())
+///         .finalize()?;
+///     signer.write_all(b"Make it so, number one!")?;
+///     signer.finalize()?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct SignerBuilder<'a> {
+    inner: writer::Stack<'a, Cookie>,
+    signers: Vec<&'a mut dyn crypto::Signer>,
+    detached: bool,
+    params: SignatureParameters,
+}
 
-                // And emit the packet.
-                Packet::Signature(sig).serialize(sink)?;
-            }
+impl<'a> SignerBuilder<'a> {
+    /// Starts building a signer using `signers`.
+    pub fn new(inner: writer::Stack<'a, Cookie>,
+               signers: Vec<&'a mut dyn crypto::Signer>)
+               -> Self {
+        SignerBuilder {
+            inner: inner,
+            signers: signers,
+            detached: false,
+            params: Default::default(),
         }
-        Ok(())
+    }
+
+    /// Emits signatures indicating the intended recipients.
+    ///
+    /// See [`Signer::with_intended_recipients`].
+    ///
+    ///   [`Signer::with_intended_recipients`]: struct.Signer.html#method.with_intended_recipients
+    pub fn intended_recipients(mut self, recipients: &[&TPK]) -> Self {
+        self.params.intended_recipients =
+            Some(recipients.iter().map(|r| r.fingerprint()).collect());
+        self
+    }
+
+    /// Creates a detached signature.
+    pub fn detached(mut self) -> Self {
+        self.detached = true;
+        self
+    }
+
+    /// Sets the hash algorithm.
+    ///
+    /// If not set, SHA512 is used.
+    pub fn hash_algo(mut self, algo: HashAlgorithm) -> Self {
+        self.params.hash_algo = algo;
+        self
+    }
+
+    /// Selects a hash algorithm that `recipients` accept.
+    ///
+    /// The strongest hash algorithm that we support, that all
+    /// signing keys can use, and that is among the preferred hash
+    /// algorithms of all recipients (see
+    /// `TPK::preferred_hash_algorithms_at`) is used.  Recipients that
+    /// do not state any preferences are assumed to accept any
+    /// algorithm.  If there is no such algorithm, the strongest
+    /// algorithm that the signing keys can use is used, or SHA512 if
+    /// there is none.
+    pub fn hash_algo_for(mut self, recipients: &[&TPK]) -> Self {
+        let prefs = recipients.iter()
+            .filter_map(|r| r.preferred_hash_algorithms_at(None))
+            .collect::<Vec<_>>();
+
+        let usable = [HashAlgorithm::SHA512, HashAlgorithm::SHA384,
+                      HashAlgorithm::SHA256, HashAlgorithm::SHA224]
+            .iter()
+            .cloned()
+            .filter(|&a| a.is_supported())
+            .filter(|&a| self.signers.iter()
+                    .all(|s| key_can_use_hash(s.public(), a)))
+            .collect::<Vec<_>>();
+
+        let algo = usable.iter()
+            .find(|a| prefs.iter().all(|p| p.contains(*a)))
+            .or(usable.first())
+            .cloned()
+            .unwrap_or(HashAlgorithm::SHA512);
+
+        self.params.hash_algo = algo;
+        self
+    }
+
+    /// Sets the signature creation time.
+    ///
+    /// If not set, the time the signatures are emitted is used.  If
+    /// `creation_time` predates the creation time of any of the
+    /// signing keys, finalizing the signer fails.
+    pub fn creation_time(mut self, creation_time: time::Tm) -> Self {
+        self.params.creation_time = Some(creation_time.canonicalize());
+        self
+    }
+
+    /// Creates text signatures.
+    ///
+    /// Text signatures are computed over the data with line endings
+    /// normalized to CRLF (see [Section 5.2.1 of RFC 4880]), so that
+    /// they verify regardless of the platform's line ending
+    /// convention.  The data itself is written as is.  When
+    /// signing a message, the literal data should be marked as
+    /// `DataFormat::Text`.
+    ///
+    ///   [Section 5.2.1 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.2.1
+    pub fn text_mode(mut self) -> Self {
+        self.params.text_mode = true;
+        self
+    }
+
+    /// Adds a Notation Data subpacket to every emitted signature.
+    ///
+    /// Use `flags` to mark the value as human-readable.  User
+    /// defined notation names have the form `name@domain` (see
+    /// [Section 5.2.3.16 of RFC 4880]); names without an `@` are
+    /// reserved for the IETF, and are rejected.
+    ///
+    ///   [Section 5.2.3.16 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.2.3.16
+    pub fn add_notation<F>(mut self, name: &str, value: &[u8], flags: F)
+                           -> Result<Self>
+        where F: Into<Option<NotationDataFlags>>
+    {
+        self.params.add_notation(name, value, flags.into())?;
+        Ok(self)
+    }
+
+    /// Creates the signer.
+    pub fn finalize(self) -> Result<writer::Stack<'a, Cookie>> {
+        Signer::make(self)
     }
 }
 
@@ -467,28 +776,418 @@ impl<'a> Write for Signer<'a> {
         };
 
         if let Ok(amount) = written {
-            self.hash.update(&buf[..amount]);
+            self.hash_data(&buf[..amount]);
+        }
+
+        written
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner.as_mut() {
+            Some(ref mut w) => w.flush(),
+            // When we are popped off the stack, we have no inner
+            // writer.  Just do nothing.
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> writer::Stackable<'a, Cookie> for Signer<'a> {
+    fn pop(&mut self) -> Result<Option<writer::BoxStack<'a, Cookie>>> {
+        Ok(self.inner.take())
+    }
+    fn mount(&mut self, new: writer::BoxStack<'a, Cookie>) {
+        self.inner = Some(new);
+    }
+    fn inner_mut(&mut self) -> Option<&mut writer::Stackable<'a, Cookie>> {
+        if let Some(ref mut i) = self.inner {
+            Some(i)
+        } else {
+            None
+        }
+    }
+    fn inner_ref(&self) -> Option<&writer::Stackable<'a, Cookie>> {
+        if let Some(ref i) = self.inner {
+            Some(i)
+        } else {
+            None
         }
+    }
+    fn into_inner(mut self: Box<Self>)
+                  -> Result<Option<writer::BoxStack<'a, Cookie>>> {
+        self.emit_signatures()?;
+        Ok(self.inner.take())
+    }
+    fn cookie_set(&mut self, cookie: Cookie) -> Cookie {
+        ::std::mem::replace(&mut self.cookie, cookie)
+    }
+    fn cookie_ref(&self) -> &Cookie {
+        &self.cookie
+    }
+    fn cookie_mut(&mut self) -> &mut Cookie {
+        &mut self.cookie
+    }
+}
+
+/// Creates detached signatures over a series of inputs.
+///
+/// Unlike [`Signer::detached`], which signs a single stream, this
+/// signer can be reused.  Data written to it is hashed until
+/// [`emit`] is called, which writes a signature block for that data,
+/// and starts over.  This is useful to sign a collection of files
+/// without unlocking the keys again for every file.
+///
+/// The signatures are made like the ones emitted by a [`Signer`],
+/// and can be configured in the same way as using a
+/// [`SignerBuilder`].
+///
+///   [`Signer::detached`]: struct.Signer.html#method.detached
+///   [`emit`]: #method.emit
+///   [`Signer`]: struct.Signer.html
+///   [`SignerBuilder`]: struct.SignerBuilder.html
+///
+/// # Example
+///
+/// ```
+/// extern crate sequoia_openpgp as openpgp;
+/// use std::io::Write;
+/// use openpgp::serialize::stream::DetachedSigner;
+/// # use openpgp::{Result, TPK};
+/// # use openpgp::parse::Parse;
+/// # let tsk = TPK::from_bytes(include_bytes!(
+/// #     "../../tests/data/keys/testy-new-private.pgp"))
+/// #     .unwrap();
+/// # f(tsk).unwrap();
+/// # fn f(tsk: TPK) -> Result<()> {
+/// let mut signing_keypair =
+///     tsk.keys_valid().signing_capable().nth(0).unwrap().2
+///     .clone().into_keypair()?;
+///
+/// let mut signer = DetachedSigner::new(vec![&mut signing_keypair], None)?;
+/// let mut signatures = Vec::new();
+/// for file in &[&b"one"[..], b"two", b"three"] {
+///     // In reality, just io::copy() the file to be signed.
+///     signer.write_all(file)?;
+///
+///     let mut sig = Vec::new();
+///     signer.emit(&mut sig)?;
+///     signatures.push(sig);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct DetachedSigner<'a> {
+    signers: Vec<&'a mut dyn crypto::Signer>,
+    params: SignatureParameters,
+    hash: crypto::hash::Context,
+    // Whether the last byte hashed was a carriage return.  Used to
+    // canonicalize line endings across writes in text mode.
+    after_cr: bool,
+}
+
+impl<'a> DetachedSigner<'a> {
+    /// Creates a reusable signer for detached signatures.
+    ///
+    /// If `hash_algo` is `None`, SHA512 is used.
+    pub fn new<H>(signers: Vec<&'a mut dyn crypto::Signer>, hash_algo: H)
+                  -> Result<Self>
+        where H: Into<Option<HashAlgorithm>>
+    {
+        if signers.len() == 0 {
+            return Err(Error::InvalidArgument(
+                "No signing keys given".into()).into());
+        }
+
+        let mut params = SignatureParameters::default();
+        if let Some(algo) = hash_algo.into() {
+            params.hash_algo = algo;
+        }
+        Ok(DetachedSigner {
+            signers: signers,
+            hash: params.hash_algo.context()?,
+            params: params,
+            after_cr: false,
+        })
+    }
+
+    /// Sets the signature creation time.
+    ///
+    /// See [`SignerBuilder::creation_time`].  If not set, the time
+    /// each signature block is emitted is used.
+    ///
+    ///   [`SignerBuilder::creation_time`]: struct.SignerBuilder.html#method.creation_time
+    pub fn creation_time(mut self, creation_time: time::Tm) -> Self {
+        self.params.creation_time = Some(creation_time.canonicalize());
+        self
+    }
+
+    /// Creates text signatures.
+    ///
+    /// See [`SignerBuilder::text_mode`].
+    ///
+    ///   [`SignerBuilder::text_mode`]: struct.SignerBuilder.html#method.text_mode
+    pub fn text_mode(mut self) -> Self {
+        self.params.text_mode = true;
+        self
+    }
+
+    /// Adds a Notation Data subpacket to every emitted signature.
+    ///
+    /// See [`SignerBuilder::add_notation`].
+    ///
+    ///   [`SignerBuilder::add_notation`]: struct.SignerBuilder.html#method.add_notation
+    pub fn add_notation<F>(mut self, name: &str, value: &[u8], flags: F)
+                           -> Result<Self>
+        where F: Into<Option<NotationDataFlags>>
+    {
+        self.params.add_notation(name, value, flags.into())?;
+        Ok(self)
+    }
+
+    /// Writes a signature block over the data written since the
+    /// last call to `emit` to `o`.
+    ///
+    /// Afterwards, the signer is ready to sign the next input.
+    ///
+    /// If an error occurs, the data written so far is not
+    /// discarded, and `emit` can be called again.  Note, however,
+    /// that some of the signatures may already have been written to
+    /// `o`.
+    pub fn emit(&mut self, o: &mut dyn io::Write) -> Result<()> {
+        self.params.emit(&mut self.signers, &self.hash, o)?;
+        self.hash = self.params.hash_algo.context()?;
+        self.after_cr = false;
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Debug for DetachedSigner<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DetachedSigner")
+            .field("hash_algo", &self.params.hash_algo)
+            .finish()
+    }
+}
+
+impl<'a> Write for DetachedSigner<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        signature::hash_document_part(&mut self.hash, self.params.sigtype(),
+                                      &mut self.after_cr, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Creates a cleartext signed message.
+///
+/// The written text is emitted using the Cleartext Signature
+/// Framework (see [Section 7 of RFC 4880]): after a header, the text
+/// is written as is, except that trailing whitespace is removed from
+/// every line, and lines starting with a dash are dash-escaped.  When
+/// the writer is finalized, a text signature over the text is
+/// emitted for every signing key in an armored signature block.
+///
+/// Note that the line ending preceding the signature block is not
+/// part of the signed text, so whether the text ends in a newline
+/// or not is not preserved.  Use [`ClearSigned`] to parse the result.
+///
+/// Unless otherwise specified, SHA512 is used as hash algorithm.
+///
+///   [Section 7 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-7
+///   [`ClearSigned`]: ../../parse/struct.ClearSigned.html
+///
+/// # Example
+///
+/// ```
+/// extern crate sequoia_openpgp as openpgp;
+/// use std::io::Write;
+/// use openpgp::serialize::stream::{Message, ClearSigner};
+/// use openpgp::parse::{Parse, ClearSigned};
+/// # use openpgp::{Result, TPK};
+/// # let tsk = TPK::from_bytes(include_bytes!(
+/// #     "../../tests/data/keys/testy-new-private.pgp"))
+/// #     .unwrap();
+/// # f(tsk).unwrap();
+/// # fn f(tsk: TPK) -> Result<()> {
+/// let key = tsk.keys_valid().signing_capable().nth(0).unwrap().2;
+/// let mut signing_keypair = key.clone().into_keypair()?;
+///
+/// let mut o = vec![];
+/// {
+///     let message = Message::new(&mut o);
+///     let mut signer =
+///         ClearSigner::new(message, vec![&mut signing_keypair], None)?;
+///     signer.write_all(b"Make it so, number one!\n")?;
+///     signer.write_all(b"- Captain Picard\n")?;
+///     signer.finalize()?;
+/// }
+/// assert!(o.starts_with(b"-----BEGIN PGP SIGNED MESSAGE-----\n\
+///                         Hash: SHA512\n\
+///                         \n\
+///                         Make it so, number one!\n\
+///                         - - Captain Picard\n\
+///                         -----BEGIN PGP SIGNATURE-----\n"));
+///
+/// let message = ClearSigned::from_bytes(&o)?;
+/// assert_eq!(message.text(),
+///            &b"Make it so, number one!\r\n- Captain Picard"[..]);
+/// assert!(message.signatures()[0].verify_message(key, message.text())?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct ClearSigner<'a> {
+    // The underlying writer.  We wrap it with `Option` so that we
+    // can `take()` it when we are finalized.
+    inner: Option<writer::BoxStack<'a, Cookie>>,
+    signers: Vec<&'a mut dyn crypto::Signer>,
+    params: SignatureParameters,
+    hash: crypto::hash::Context,
+    // The current, incomplete line.
+    line: Vec<u8>,
+    // Whether we have emitted any lines.
+    have_lines: bool,
+    cookie: Cookie,
+}
+
+impl<'a> ClearSigner<'a> {
+    /// Creates a signer for cleartext signed messages.
+    ///
+    /// `hash_algo` selects the digest algorithm used by all
+    /// `signers`.  If `None`, SHA512 is used.
+    pub fn new<H>(inner: writer::Stack<'a, Cookie>,
+                  signers: Vec<&'a mut dyn crypto::Signer>,
+                  hash_algo: H)
+                  -> Result<writer::Stack<'a, Cookie>>
+        where H: Into<Option<HashAlgorithm>>
+    {
+        let mut inner = writer::BoxStack::from(inner);
+        let mut params = SignatureParameters::default();
+        params.text_mode = true;
+        if let Some(algo) = hash_algo.into() {
+            params.hash_algo = algo;
+        }
+
+        if signers.len() == 0 {
+            return Err(Error::InvalidArgument(
+                "No signing keys given".into()).into());
+        }
+
+        let name = params.hash_algo.to_name()?;
+        inner.write_all(BEGIN_SIGNED_MESSAGE)?;
+        write!(inner, "\nHash: {}\n\n", name)?;
+
+        let level = inner.cookie_ref().level + 1;
+        Ok(writer::Stack::from(Box::new(ClearSigner {
+            inner: Some(inner),
+            signers: signers,
+            hash: params.hash_algo.context()?,
+            params: params,
+            line: Vec::new(),
+            have_lines: false,
+            cookie: Cookie::new(level),
+        })))
+    }
+
+    /// Emits and hashes a line of text.
+    ///
+    /// `line` must not include the line ending.
+    fn emit_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let line = if line.last() == Some(&b'\r') {
+            &line[..line.len() - 1]
+        } else {
+            line
+        };
+        let line = trim_trailing_whitespace(line);
+
+        // The line endings are normalized to CRLF, and the one
+        // preceding the signature block is not hashed.
+        if self.have_lines {
+            self.hash.update(b"\r\n");
+        }
+        self.hash.update(line);
+        self.have_lines = true;
+
+        let inner = self.inner.as_mut().ok_or_else(|| io::Error::new(
+            io::ErrorKind::BrokenPipe, "Writer is finalized"))?;
+        if line.starts_with(b"-") {
+            inner.write_all(b"- ")?;
+        }
+        inner.write_all(line)?;
+        inner.write_all(b"\n")
+    }
+
+    fn emit_signatures(&mut self) -> Result<()> {
+        // Flush the last line.  An empty text consists of one empty
+        // line.
+        let line = ::std::mem::replace(&mut self.line, Vec::new());
+        if ! line.is_empty() || ! self.have_lines {
+            self.emit_line(&line)?;
+        }
+
+        if let Some(ref mut sink) = self.inner {
+            let mut w = armor::Writer::new(sink, armor::Kind::Signature,
+                                           &[])?;
+            self.params.emit(&mut self.signers, &self.hash, &mut w)?;
+            w.finalize()?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Debug for ClearSigner<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ClearSigner")
+            .field("inner", &self.inner)
+            .field("hash_algo", &self.params.hash_algo)
+            .finish()
+    }
+}
 
-        written
+impl<'a> Write for ClearSigner<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pending = ::std::mem::replace(&mut self.line, Vec::new());
+        // Only the new data can contain line endings.
+        let mut i = pending.len();
+        pending.extend_from_slice(buf);
+
+        // Emit all complete lines, then keep the rest in one go.
+        let mut start = 0;
+        let mut result = Ok(buf.len());
+        while let Some(n) = pending[i..].iter().position(|&c| c == b'\n') {
+            if let Err(e) = self.emit_line(&pending[start..i + n]) {
+                result = Err(e);
+                break;
+            }
+            start = i + n + 1;
+            i = start;
+        }
+        self.line = pending.split_off(start);
+        result
     }
 
     fn flush(&mut self) -> io::Result<()> {
         match self.inner.as_mut() {
             Some(ref mut w) => w.flush(),
-            // When we are popped off the stack, we have no inner
-            // writer.  Just do nothing.
             None => Ok(()),
         }
     }
 }
 
-impl<'a> writer::Stackable<'a, Cookie> for Signer<'a> {
-    fn pop(&mut self) -> Result<Option<writer::BoxStack<'a, Cookie>>> {
+impl<'a> writer::Stackable<'a, Cookie> for ClearSigner<'a> {
+    fn into_inner(mut self: Box<Self>)
+                  -> Result<Option<writer::BoxStack<'a, Cookie>>> {
+        self.emit_signatures()?;
         Ok(self.inner.take())
     }
-    fn mount(&mut self, new: writer::BoxStack<'a, Cookie>) {
-        self.inner = Some(new);
+    fn pop(&mut self) -> Result<Option<writer::BoxStack<'a, Cookie>>> {
+        unreachable!("Only implemented by Signer")
+    }
+    /// Sets the inner stackable.
+    fn mount(&mut self, _new: writer::BoxStack<'a, Cookie>) {
+        unreachable!("Only implemented by Signer")
     }
     fn inner_mut(&mut self) -> Option<&mut writer::Stackable<'a, Cookie>> {
         if let Some(ref mut i) = self.inner {
@@ -504,11 +1203,6 @@ impl<'a> writer::Stackable<'a, Cookie> for Signer<'a> {
             None
         }
     }
-    fn into_inner(mut self: Box<Self>)
-                  -> Result<Option<writer::BoxStack<'a, Cookie>>> {
-        self.emit_signatures()?;
-        Ok(self.inner.take())
-    }
     fn cookie_set(&mut self, cookie: Cookie) -> Cookie {
         ::std::mem::replace(&mut self.cookie, cookie)
     }
@@ -564,20 +1258,39 @@ impl<'a> LiteralWriter<'a> {
     ///
     /// If `date` is `None`, then the earliest representable time will
     /// be used as a dummy value.
+    ///
+    /// When parsing the message, these fields can be recovered using
+    /// [`Literal::format`], [`Literal::filename`], and
+    /// [`Literal::date`].
+    ///
+    ///   [`Literal::format`]: ../../packet/struct.Literal.html#method.format
+    ///   [`Literal::filename`]: ../../packet/struct.Literal.html#method.filename
+    ///   [`Literal::date`]: ../../packet/struct.Literal.html#method.date
+    ///
+    /// See [`LiteralWriterBuilder`] for a more readable way to set
+    /// these fields.
+    ///
+    ///   [`LiteralWriterBuilder`]: struct.LiteralWriterBuilder.html
     pub fn new(inner: writer::Stack<'a, Cookie>,
                format: DataFormat,
                filename: Option<&[u8]>,
                date: Option<time::Tm>)
                -> Result<writer::Stack<'a, Cookie>> {
-        let mut inner = writer::BoxStack::from(inner);
-        let level = inner.cookie_ref().level + 1;
-
-        let mut template = Literal::new(format);
-        template.set_date(date);
-
+        let mut builder = LiteralWriterBuilder::new(inner, format);
         if let Some(f) = filename {
-            template.set_filename_from_bytes(f)?;
+            builder = builder.filename(f)?;
         }
+        if let Some(d) = date {
+            builder = builder.date(d);
+        }
+        builder.finalize()
+    }
+
+    fn make(builder: LiteralWriterBuilder<'a>)
+            -> Result<writer::Stack<'a, Cookie>> {
+        let LiteralWriterBuilder { inner, template } = builder;
+        let mut inner = writer::BoxStack::from(inner);
+        let level = inner.cookie_ref().level + 1;
 
         // For historical reasons, signatures over literal data
         // packets only include the body without metadata or framing.
@@ -618,6 +1331,99 @@ impl<'a> LiteralWriter<'a> {
             signature_writer: signature_writer,
         })))
     }
+
+    /// Creates a new literal writer for sensitive data.
+    ///
+    /// The literal packet is marked "for your eyes only" by using the
+    /// special filename `_CONSOLE`, which asks the recipient to
+    /// display the text instead of saving it to disk.  See
+    /// [Section 5.9 of RFC 4880].  Note: this is merely a hint, and
+    /// it is not protected by signatures.
+    ///
+    ///   [Section 5.9 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.9
+    ///
+    /// If `date` is `None`, then the earliest representable time will
+    /// be used as a dummy value.
+    pub fn for_your_eyes_only(inner: writer::Stack<'a, Cookie>,
+                              date: Option<time::Tm>)
+                              -> Result<writer::Stack<'a, Cookie>> {
+        Self::new(inner, DataFormat::Text, Some(&b"_CONSOLE"[..]), date)
+    }
+}
+
+/// Builds a `LiteralWriter`.
+///
+/// This is a more readable alternative to passing the optional
+/// filename and date to [`LiteralWriter::new`].
+///
+///   [`LiteralWriter::new`]: struct.LiteralWriter.html#method.new
+///
+/// # Example
+///
+/// ```
+/// extern crate sequoia_openpgp as openpgp;
+/// extern crate time;
+/// use std::io::Write;
+/// use openpgp::constants::DataFormat;
+/// use openpgp::serialize::stream::{Message, LiteralWriterBuilder};
+/// # use openpgp::Result;
+/// # f().unwrap();
+/// # fn f() -> Result<()> {
+///
+/// let mut o = vec![];
+/// {
+///     let message = Message::new(&mut o);
+///     let mut w = LiteralWriterBuilder::new(message, DataFormat::Binary)
+///         .filename(b"msg.txt")?
+///         .date(time::now_utc())
+///         .finalize()?;
+///     w.write_all(b"Hello world.")?;
+///     w.finalize()?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct LiteralWriterBuilder<'a> {
+    inner: writer::Stack<'a, Cookie>,
+    template: Literal,
+}
+
+impl<'a> LiteralWriterBuilder<'a> {
+    /// Starts building a literal writer for data in the given
+    /// `format`.
+    ///
+    /// If no date is set, the earliest representable time will be
+    /// used as a dummy value.
+    pub fn new(inner: writer::Stack<'a, Cookie>, format: DataFormat)
+               -> Self {
+        LiteralWriterBuilder {
+            inner: inner,
+            template: Literal::new(format),
+        }
+    }
+
+    /// Sets the filename.
+    ///
+    /// The standard does not specify the encoding.  Filenames must
+    /// not be longer than 255 bytes, longer ones are rejected.  Note
+    /// that the filename is not protected by signatures.
+    pub fn filename<F: AsRef<[u8]>>(mut self, filename: F) -> Result<Self> {
+        self.template.set_filename_from_bytes(filename.as_ref())?;
+        Ok(self)
+    }
+
+    /// Sets the date.
+    ///
+    /// Note that the date is not protected by signatures.
+    pub fn date(mut self, date: time::Tm) -> Self {
+        self.template.set_date(Some(date));
+        self
+    }
+
+    /// Creates the literal writer.
+    pub fn finalize(self) -> Result<writer::Stack<'a, Cookie>> {
+        LiteralWriter::make(self)
+    }
 }
 
 impl<'a> fmt::Debug for LiteralWriter<'a> {
@@ -707,7 +1513,7 @@ impl<'a> writer::Stackable<'a, Cookie> for LiteralWriter<'a> {
 /// {
 ///     let message = Message::new(&mut o);
 ///     let w = Compressor::new(message,
-///                             CompressionAlgorithm::Uncompressed)?;
+///                             CompressionAlgorithm::Uncompressed, None)?;
 ///     let mut w = LiteralWriter::new(w, DataFormat::Text, None, None)?;
 ///     w.write_all(b"Hello world.")?;
 ///     w.finalize()?;
@@ -722,8 +1528,16 @@ pub struct Compressor<'a> {
 
 impl<'a> Compressor<'a> {
     /// Creates a new compressor using the given algorithm.
-    pub fn new(inner: writer::Stack<'a, Cookie>, algo: CompressionAlgorithm)
-               -> Result<writer::Stack<'a, Cookie>> {
+    ///
+    /// If `compression_level` is `None`, a default level is used.
+    /// The content is emitted using partial body lengths, so the
+    /// message is streamed.  Call `finalize` to flush the compressor
+    /// and close the packet.
+    pub fn new<L>(inner: writer::Stack<'a, Cookie>, algo: CompressionAlgorithm,
+                  compression_level: L)
+                  -> Result<writer::Stack<'a, Cookie>>
+        where L: Into<Option<CompressionLevel>>
+    {
         let mut inner = writer::BoxStack::from(inner);
         let level = inner.cookie_ref().level + 1;
 
@@ -733,13 +1547,16 @@ impl<'a> Compressor<'a> {
             = PartialBodyFilter::new(writer::Stack::from(inner),
                                      Cookie::new(level));
 
-        Self::new_naked(inner, algo, level)
+        Self::new_naked(inner, algo,
+                        compression_level.into().unwrap_or_default(),
+                        level)
     }
 
 
     /// Creates a new compressor using the given algorithm.
     pub(crate) // For CompressedData::serialize.
         fn new_naked(mut inner: writer::Stack<'a, Cookie>, algo: CompressionAlgorithm,
+                     compression_level: CompressionLevel,
                      level: usize)
                  -> Result<writer::Stack<'a, Cookie>> {
         // Compressed data header.
@@ -751,13 +1568,13 @@ impl<'a> Compressor<'a> {
                 writer::Identity::new(inner, Cookie::new(level)),
             #[cfg(feature = "compression-deflate")]
             CompressionAlgorithm::Zip =>
-                writer::ZIP::new(inner, Cookie::new(level)),
+                writer::ZIP::new(inner, Cookie::new(level), compression_level),
             #[cfg(feature = "compression-deflate")]
             CompressionAlgorithm::Zlib =>
-                writer::ZLIB::new(inner, Cookie::new(level)),
+                writer::ZLIB::new(inner, Cookie::new(level), compression_level),
             #[cfg(feature = "compression-bzip2")]
             CompressionAlgorithm::BZip2 =>
-                writer::BZ::new(inner, Cookie::new(level)),
+                writer::BZ::new(inner, Cookie::new(level), compression_level),
             a =>
                 return Err(Error::UnsupportedCompressionAlgorithm(a).into()),
         };
@@ -845,8 +1662,20 @@ impl<'a> Encryptor<'a> {
     /// which will be encrypted using the given passwords, and all
     /// encryption-capable subkeys of the given TPKs.
     ///
-    /// Unless otherwise specified, the stream is encrypted using
-    /// AES256.  Key preferences of the recipients are not honored.
+    /// Unless otherwise specified, the stream is encrypted using the
+    /// strongest cipher that all recipients accept (see
+    /// `TPK::preferred_symmetric_algorithms`).  Recipients that do
+    /// not state any preferences are assumed to accept any cipher.
+    /// If there is no common cipher, TripleDES is used, which is
+    /// implicitly part of every preference list (see [Section 13.2
+    /// of RFC 4880]).
+    ///
+    /// The payload is protected using the AEAD Encrypted Data packet
+    /// if all recipients support it (see `TPK::supports_aead`) and
+    /// accept EAX.  Otherwise, the Symmetrically Encrypted Integrity
+    /// Protected Data packet is used.
+    ///
+    ///   [Section 13.2 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-13.2
     ///
     /// # Example
     ///
@@ -931,8 +1760,10 @@ impl<'a> Encryptor<'a> {
 
         // Use AEAD if there are TPKs and all of them support AEAD.
         let aead = if tpks.len() > 0 && tpks.iter().all(|t| {
-            t.primary_key_signature().map(|s| s.features().supports_aead())
-                .unwrap_or(false)
+            t.supports_aead()
+                && t.preferred_aead_algorithms()
+                .map(|p| p.contains(&AEADAlgorithm::EAX))
+                .unwrap_or(true)
         }) {
             let mut nonce = vec![0; AEADAlgorithm::EAX.iv_size()?];
             crypto::random(&mut nonce);
@@ -946,10 +1777,24 @@ impl<'a> Encryptor<'a> {
         };
 
         let level = inner.as_ref().cookie_ref().level + 1;
-        let algo = cipher_algo.into().unwrap_or(SymmetricAlgorithm::AES256);
+        let algo = cipher_algo.into().unwrap_or_else(|| {
+            let prefs = tpks.iter()
+                .filter_map(|t| t.preferred_symmetric_algorithms())
+                .collect::<Vec<_>>();
+
+            [SymmetricAlgorithm::AES256, SymmetricAlgorithm::AES192,
+             SymmetricAlgorithm::AES128, SymmetricAlgorithm::Camellia256,
+             SymmetricAlgorithm::Camellia192, SymmetricAlgorithm::Camellia128,
+             SymmetricAlgorithm::Twofish]
+                .iter()
+                .filter(|a| a.is_supported())
+                .find(|a| prefs.iter().all(|p| p.contains(*a)))
+                .cloned()
+                .unwrap_or(SymmetricAlgorithm::TripleDES)
+        });
 
         // Generate a session key.
-        let sk = SessionKey::new(algo.key_size()?);
+        let sk = SessionKey::for_algo(algo)?;
 
         // Write the PKESK packet(s).
         for tpk in tpks {
@@ -1204,6 +2049,122 @@ mod test {
         assert!(ppr.is_none());
     }
 
+    #[test]
+    fn literal_for_your_eyes_only() {
+        let mut o = vec![];
+        {
+            let m = Message::new(&mut o);
+            let mut w = LiteralWriter::for_your_eyes_only(m, None).unwrap();
+            w.write_all(b"Burn after reading.").unwrap();
+            w.finalize().unwrap();
+        }
+
+        let mut pp = PacketParser::from_bytes(&o).unwrap().unwrap();
+        if let Packet::Literal(ref l) = pp.packet {
+            assert!(l.for_your_eyes_only());
+            assert_eq!(l.format(), DataFormat::Text);
+            assert_eq!(l.filename(), Some(&b"_CONSOLE"[..]));
+        } else {
+            panic!("Unexpected packet type.");
+        }
+
+        let mut body = vec![];
+        pp.read_to_end(&mut body).unwrap();
+        assert_eq!(&body, b"Burn after reading.");
+    }
+
+    #[test]
+    fn literal_writer_builder() {
+        let date = time::Tm::from_pgp(1560281088);
+
+        let mut o = vec![];
+        {
+            let m = Message::new(&mut o);
+            let mut w = LiteralWriterBuilder::new(m, DataFormat::Binary)
+                .filename("msg.txt").unwrap()
+                .date(date.clone())
+                .finalize().unwrap();
+            w.write_all(b"Hello world.").unwrap();
+            w.finalize().unwrap();
+        }
+
+        // This is equivalent to passing the fields to
+        // LiteralWriter::new.
+        let mut p = vec![];
+        {
+            let m = Message::new(&mut p);
+            let mut w = LiteralWriter::new(m, DataFormat::Binary,
+                                           Some(&b"msg.txt"[..]),
+                                           Some(date.clone())).unwrap();
+            w.write_all(b"Hello world.").unwrap();
+            w.finalize().unwrap();
+        }
+        assert_eq!(o, p);
+
+        let pp = PacketParser::from_bytes(&o).unwrap().unwrap();
+        if let Packet::Literal(ref l) = pp.packet {
+            assert_eq!(l.filename(), Some(&b"msg.txt"[..]));
+            assert_eq!(l.date(), Some(&date));
+        } else {
+            panic!("Unexpected packet type.");
+        }
+
+        // The filename must fit into a one octet length.
+        let mut o = vec![];
+        assert!(LiteralWriterBuilder::new(Message::new(&mut o),
+                                          DataFormat::Binary)
+                .filename(&[b'a'; 256][..]).is_err());
+        assert!(LiteralWriterBuilder::new(Message::new(&mut o),
+                                          DataFormat::Binary)
+                .filename(&[b'a'; 255][..]).is_ok());
+    }
+
+    #[test]
+    fn literal_metadata() {
+        let date = time::Tm::from_pgp(1560281088);
+
+        let mut o = vec![];
+        {
+            let m = Message::new(&mut o);
+            let mut w = LiteralWriter::new(m, DataFormat::Binary,
+                                           Some(&b"manifesto.txt"[..]),
+                                           Some(date.clone())).unwrap();
+            w.write_all(b"Privacy is necessary.").unwrap();
+            w.finalize().unwrap();
+        }
+
+        let mut pp = PacketParser::from_bytes(&o).unwrap().unwrap();
+        if let Packet::Literal(ref l) = pp.packet {
+            assert_eq!(l.format(), DataFormat::Binary);
+            assert_eq!(l.filename(), Some(&b"manifesto.txt"[..]));
+            assert_eq!(l.date(), Some(&date));
+        } else {
+            panic!("Unexpected packet type.");
+        }
+
+        let mut body = vec![];
+        pp.read_to_end(&mut body).unwrap();
+        assert_eq!(&body, b"Privacy is necessary.");
+
+        // Without metadata, the accessors return None.
+        let mut o = vec![];
+        {
+            let m = Message::new(&mut o);
+            let mut w = LiteralWriter::new(m, DataFormat::Binary, None, None)
+                .unwrap();
+            w.write_all(b"Privacy is necessary.").unwrap();
+            w.finalize().unwrap();
+        }
+
+        let pp = PacketParser::from_bytes(&o).unwrap().unwrap();
+        if let Packet::Literal(ref l) = pp.packet {
+            assert_eq!(l.filename(), None);
+            assert_eq!(l.date(), None);
+        } else {
+            panic!("Unexpected packet type.");
+        }
+    }
+
     // Create some crazy nesting structures, serialize the messages,
     // reparse them, and make sure we get the same result.
     #[test]
@@ -1230,7 +2191,7 @@ mod test {
         {
             let m = Message::new(&mut o);
             let c = Compressor::new(
-                m, CompressionAlgorithm::Uncompressed).unwrap();
+                m, CompressionAlgorithm::Uncompressed, None).unwrap();
             let mut ls = LiteralWriter::new(c, T, None, None).unwrap();
             write!(ls, "one").unwrap();
             let c = ls.finalize_one().unwrap().unwrap(); // Pop the LiteralWriter.
@@ -1289,9 +2250,9 @@ mod test {
         {
             let m = Message::new(&mut o);
             let c0 = Compressor::new(
-                m, CompressionAlgorithm::Uncompressed).unwrap();
+                m, CompressionAlgorithm::Uncompressed, None).unwrap();
             let c = Compressor::new(
-                c0, CompressionAlgorithm::Uncompressed).unwrap();
+                c0, CompressionAlgorithm::Uncompressed, None).unwrap();
             let mut ls = LiteralWriter::new(c, T, None, None).unwrap();
             write!(ls, "one").unwrap();
             let c = ls.finalize_one().unwrap().unwrap();
@@ -1300,7 +2261,7 @@ mod test {
             let c = ls.finalize_one().unwrap().unwrap();
             let c0 = c.finalize_one().unwrap().unwrap();
             let c = Compressor::new(
-                c0, CompressionAlgorithm::Uncompressed).unwrap();
+                c0, CompressionAlgorithm::Uncompressed, None).unwrap();
             let mut ls = LiteralWriter::new(c, T, None, None).unwrap();
             write!(ls, "three").unwrap();
             let c = ls.finalize_one().unwrap().unwrap();
@@ -1319,6 +2280,45 @@ mod test {
         }
     }
 
+    #[cfg(all(feature = "compression-deflate", feature = "compression-bzip2"))]
+    #[test]
+    fn compression_level() {
+        let payload = b"Hello Hello Hello Hello Hello Hello Hello Hello Hello.";
+
+        for &algo in &[CompressionAlgorithm::Zip, CompressionAlgorithm::Zlib,
+                       CompressionAlgorithm::BZip2] {
+            let mut sizes = Vec::new();
+            for level in &[CompressionLevel::none(), CompressionLevel::best()] {
+                let mut o = vec![];
+                {
+                    let m = Message::new(&mut o);
+                    let c = Compressor::new(m, algo, *level).unwrap();
+                    let mut ls = LiteralWriter::new(c, T, None, None).unwrap();
+                    ls.write_all(&payload[..]).unwrap();
+                    ls.finalize().unwrap();
+                }
+
+                let pile = PacketPile::from_bytes(&o).unwrap();
+                let literal = pile.descendants()
+                    .filter_map(|p| if let Packet::Literal(ref l) = p {
+                        Some(l.body().unwrap().to_vec())
+                    } else {
+                        None
+                    })
+                    .next().unwrap();
+                assert_eq!(&literal[..], &payload[..]);
+                sizes.push(o.len());
+            }
+
+            if algo != CompressionAlgorithm::BZip2 {
+                // Level 0 stores the data.
+                assert!(sizes[0] > sizes[1]);
+            }
+        }
+
+        assert!(CompressionLevel::new(10).is_err());
+    }
+
     #[cfg(feature = "compression-deflate")]
     #[test]
     fn stream_big() {
@@ -1327,7 +2327,7 @@ mod test {
         {
             let m = Message::new(&mut o);
             let c = Compressor::new(m,
-                                    CompressionAlgorithm::BZip2).unwrap();
+                                    CompressionAlgorithm::BZip2, None).unwrap();
             let mut ls = LiteralWriter::new(c, T, None, None).unwrap();
             // Write 64 megabytes of zeroes.
             for _ in 0 .. 16 {
@@ -1392,6 +2392,363 @@ mod test {
         assert_eq!(good, 2);
     }
 
+    #[test]
+    fn signature_hash_algo() {
+        let tsk = TPK::from_bytes(::tests::key("testy-new-private.pgp"))
+            .unwrap();
+        let key = tsk.keys_all().signing_capable().nth(0).unwrap().2;
+
+        for &algo in &[HashAlgorithm::SHA256, HashAlgorithm::SHA384,
+                       HashAlgorithm::SHA512] {
+            let mut o = vec![];
+            {
+                let mut keypair = key.clone().into_keypair().unwrap();
+                let m = Message::new(&mut o);
+                let signer = Signer::new(m, vec![&mut keypair], algo)
+                    .unwrap();
+                let mut ls = LiteralWriter::new(signer, T, None, None).unwrap();
+                ls.write_all(b"Tis, tis, tis.  Tis is important.").unwrap();
+                ls.finalize().unwrap();
+            }
+
+            let pile = PacketPile::from_bytes(&o).unwrap();
+            let mut ops = 0;
+            let mut sigs = 0;
+            for p in pile.descendants() {
+                match p {
+                    &Packet::OnePassSig(ref ops_packet) => {
+                        assert_eq!(ops_packet.hash_algo(), algo);
+                        ops += 1;
+                    },
+                    &Packet::Signature(ref sig) => {
+                        assert_eq!(sig.hash_algo(), algo);
+                        assert!(sig.verify(key).unwrap());
+                        sigs += 1;
+                    },
+                    _ => (),
+                }
+            }
+            assert_eq!((ops, sigs), (1, 1));
+        }
+    }
+
+    #[test]
+    fn signature_hash_algo_for() {
+        use packet::{Key, UserID, key::Key4};
+        use constants::Curve;
+
+        let tsk = TPK::from_bytes(::tests::key("testy-new-private.pgp"))
+            .unwrap();
+        let key = tsk.keys_all().signing_capable().nth(0).unwrap().2;
+
+        // Makes a recipient preferring `prefs`.
+        let recipient = |prefs: Option<Vec<HashAlgorithm>>| {
+            let k: Key = Key4::generate_ecc(true, Curve::Ed25519).unwrap()
+                .into();
+            let mut pair = k.clone().into_keypair().unwrap();
+            let userid = UserID::from("recipient@example.org");
+            let mut builder =
+                signature::Builder::new(SignatureType::PositiveCertificate)
+                .set_signature_creation_time(time::now_utc()).unwrap()
+                .set_issuer_fingerprint(k.fingerprint()).unwrap();
+            if let Some(prefs) = prefs {
+                builder = builder.set_preferred_hash_algorithms(prefs)
+                    .unwrap();
+            }
+            let binding = builder.sign_userid_binding(
+                &mut pair, &k, &userid, HashAlgorithm::SHA512).unwrap();
+            TPK::from_packet_pile(PacketPile::from(vec![
+                k.into_packet(Tag::PublicKey).unwrap(),
+                userid.into(),
+                binding.into(),
+            ])).unwrap()
+        };
+
+        let sign_with = |key: &Key, recipients: &[&TPK]| {
+            let mut o = vec![];
+            {
+                let mut keypair = key.clone().into_keypair().unwrap();
+                let m = Message::new(&mut o);
+                let signer = SignerBuilder::new(m, vec![&mut keypair])
+                    .hash_algo_for(recipients)
+                    .finalize().unwrap();
+                let mut ls = LiteralWriter::new(signer, T, None, None).unwrap();
+                ls.write_all(b"Tis, tis, tis.  Tis is important.").unwrap();
+                ls.finalize().unwrap();
+            }
+
+            let pile = PacketPile::from_bytes(&o).unwrap();
+            let sig = pile.descendants().filter_map(|p| match p {
+                &Packet::Signature(ref sig) => Some(sig.clone()),
+                _ => None,
+            }).nth(0).unwrap();
+            assert!(sig.verify(key).unwrap());
+            sig.hash_algo()
+        };
+        let sign = |recipients: &[&TPK]| sign_with(key, recipients);
+
+        let r1 = recipient(Some(vec![HashAlgorithm::SHA256,
+                                     HashAlgorithm::SHA512]));
+        let r2 = recipient(Some(vec![HashAlgorithm::SHA384,
+                                     HashAlgorithm::SHA256]));
+        let r3 = recipient(None);
+        let r4 = recipient(Some(vec![HashAlgorithm::SHA1]));
+
+        // The strongest algorithm wins, even if it is not the most
+        // preferred one.
+        assert_eq!(sign(&[&r1]), HashAlgorithm::SHA512);
+        // The strongest algorithm all recipients accept.
+        assert_eq!(sign(&[&r1, &r2]), HashAlgorithm::SHA256);
+        // No preferences.
+        assert_eq!(sign(&[&r3]), HashAlgorithm::SHA512);
+        assert_eq!(sign(&[&r1, &r3]), HashAlgorithm::SHA512);
+        // No acceptable algorithm, use a secure default.
+        assert_eq!(sign(&[&r4]), HashAlgorithm::SHA512);
+
+        // The signing keys must be able to use the algorithm: ECDSA
+        // over P-384 requires at least SHA384.
+        let p384: Key = Key4::generate_ecc(true, Curve::NistP384).unwrap()
+            .into();
+        let r5 = recipient(Some(vec![HashAlgorithm::SHA256,
+                                     HashAlgorithm::SHA384]));
+        assert_eq!(sign_with(&p384, &[&r2, &r5]), HashAlgorithm::SHA384);
+        assert_eq!(sign_with(&p384, &[&r1, &r2]), HashAlgorithm::SHA512);
+        assert_eq!(key_can_use_hash(&p384, HashAlgorithm::SHA256), false);
+    }
+
+    #[test]
+    fn signature_creation_time() {
+        use conversions::Time;
+
+        let tsk = TPK::from_bytes(::tests::key("testy-new-private.pgp"))
+            .unwrap();
+        let key = tsk.keys_all().signing_capable().nth(0).unwrap().2;
+        let t = (key.creation_time().clone() + time::Duration::days(1))
+            .canonicalize();
+
+        let mut o = vec![];
+        {
+            let mut keypair = key.clone().into_keypair().unwrap();
+            let m = Message::new(&mut o);
+            let mut signer = SignerBuilder::new(m, vec![&mut keypair])
+                .detached()
+                .creation_time(t.clone())
+                .finalize().unwrap();
+            signer.write_all(b"Tis, tis, tis.  Tis is important.").unwrap();
+            signer.finalize().unwrap();
+        }
+
+        if let Packet::Signature(sig) = Packet::from_bytes(&o).unwrap() {
+            assert_eq!(sig.signature_creation_time(), Some(t));
+            assert!(sig.verify_message(
+                key, b"Tis, tis, tis.  Tis is important.").unwrap());
+        } else {
+            panic!("Expected a signature");
+        }
+
+        // Signatures predating the key are rejected.
+        let mut o = vec![];
+        let mut keypair = key.clone().into_keypair().unwrap();
+        let m = Message::new(&mut o);
+        let mut signer = SignerBuilder::new(m, vec![&mut keypair])
+            .detached()
+            .creation_time(key.creation_time().clone()
+                           - time::Duration::days(1))
+            .finalize().unwrap();
+        signer.write_all(b"Tis, tis, tis.  Tis is important.").unwrap();
+        assert!(signer.finalize().is_err());
+    }
+
+    #[test]
+    fn signature_notation() {
+        let tsk = TPK::from_bytes(::tests::key("testy-new-private.pgp"))
+            .unwrap();
+        let key = tsk.keys_all().signing_capable().nth(0).unwrap().2;
+        let mut keypair = key.clone().into_keypair().unwrap();
+
+        let mut o = vec![];
+        assert!(SignerBuilder::new(Message::new(&mut o), vec![&mut keypair])
+                .add_notation("no-namespace", b"", None).is_err());
+
+        let mut o = vec![];
+        {
+            let m = Message::new(&mut o);
+            let mut signer = SignerBuilder::new(m, vec![&mut keypair])
+                .detached()
+                .add_notation("commit@example.org", b"0123abc",
+                              NotationDataFlags::default()
+                              .set_human_readable(true)).unwrap()
+                .add_notation("blob@example.org", b"\x00\x01", None).unwrap()
+                .finalize().unwrap();
+            signer.write_all(b"Tis, tis, tis.  Tis is important.").unwrap();
+            signer.finalize().unwrap();
+        }
+
+        if let Packet::Signature(sig) = Packet::from_bytes(&o).unwrap() {
+            let notations = sig.notation_data();
+            assert_eq!(notations.len(), 2);
+            assert_eq!(notations[0].name(), b"commit@example.org");
+            assert_eq!(notations[0].value(), b"0123abc");
+            assert!(notations[0].flags().human_readable());
+            assert_eq!(notations[1].name(), b"blob@example.org");
+            assert_eq!(notations[1].value(), b"\x00\x01");
+            assert!(! notations[1].flags().human_readable());
+            assert!(sig.verify_message(
+                key, b"Tis, tis, tis.  Tis is important.").unwrap());
+        } else {
+            panic!("Expected a signature");
+        }
+    }
+
+    #[test]
+    fn signature_text_mode() {
+        use crypto::hash::Hash;
+
+        let tsk = TPK::from_bytes(::tests::key("testy-new-private.pgp"))
+            .unwrap();
+        let key = tsk.keys_all().signing_capable().nth(0).unwrap().2;
+        let mut keypair = key.clone().into_keypair().unwrap();
+
+        // The line endings are normalized even if a CRLF is split
+        // across writes, and lone CRs are left alone.
+        for (chunks, canonical) in &[
+            (&[&b"a\nb\n"[..]][..], &b"a\r\nb\r\n"[..]),
+            (&[&b"a\r"[..], &b"\nb\n"[..]][..], &b"a\r\nb\r\n"[..]),
+            (&[&b"a\r\n"[..], &b"\n"[..]][..], &b"a\r\n\r\n"[..]),
+            (&[&b"a\rb"[..], &b"\n"[..]][..], &b"a\rb\r\n"[..]),
+        ] {
+            let mut o = vec![];
+            {
+                let m = Message::new(&mut o);
+                let mut signer = SignerBuilder::new(m, vec![&mut keypair])
+                    .detached()
+                    .text_mode()
+                    .finalize().unwrap();
+                for chunk in chunks.iter() {
+                    signer.write_all(chunk).unwrap();
+                }
+                signer.finalize().unwrap();
+            }
+
+            let sig = if let Packet::Signature(sig) =
+                Packet::from_bytes(&o).unwrap()
+            {
+                sig
+            } else {
+                panic!("Expected a signature");
+            };
+            assert_eq!(sig.sigtype(), SignatureType::Text);
+
+            let mut hash = sig.hash_algo().context().unwrap();
+            hash.update(canonical);
+            sig.hash(&mut hash);
+            let mut digest = vec![0u8; hash.digest_size()];
+            hash.digest(&mut digest);
+            assert!(sig.verify_hash(key, sig.hash_algo(), &digest).unwrap());
+
+            let data = chunks.concat();
+            assert!(sig.verify_message(key, &data).unwrap());
+        }
+    }
+
+    #[test]
+    fn clear_signer() {
+        use parse::ClearSigned;
+
+        let tsk = TPK::from_bytes(::tests::key("testy-new-private.pgp"))
+            .unwrap();
+        let key = tsk.keys_all().signing_capable().nth(0).unwrap().2;
+        let mut keypair = key.clone().into_keypair().unwrap();
+
+        // Dashes are escaped, trailing whitespace is dropped, and
+        // line endings are normalized, even if split across writes.
+        for (chunks, cleartext, signed) in &[
+            (&[&b"-----BEGIN PGP SIGNATURE-----\n- \n--\n"[..]][..],
+             &b"- -----BEGIN PGP SIGNATURE-----\n- -\n- --\n"[..],
+             &b"-----BEGIN PGP SIGNATURE-----\r\n-\r\n--"[..]),
+            (&[&b"a \t\r"[..], &b"\nb\t"[..]][..],
+             &b"a\nb\n"[..],
+             &b"a\r\nb"[..]),
+            (&[&b"From me\n\n"[..]][..],
+             &b"From me\n\n"[..],
+             &b"From me\r\n"[..]),
+            (&[&b"a\rb"[..]][..],
+             &b"a\rb\n"[..],
+             &b"a\rb"[..]),
+            (&[][..],
+             &b"\n"[..],
+             &b""[..]),
+        ] {
+            let mut o = vec![];
+            {
+                let m = Message::new(&mut o);
+                let mut signer =
+                    ClearSigner::new(m, vec![&mut keypair], None).unwrap();
+                for chunk in chunks.iter() {
+                    signer.write_all(chunk).unwrap();
+                }
+                signer.finalize().unwrap();
+            }
+
+            let header = b"-----BEGIN PGP SIGNED MESSAGE-----\n\
+                           Hash: SHA512\n\n";
+            assert!(o.starts_with(header));
+            assert!(o[header.len()..].starts_with(cleartext));
+            assert!(o[header.len() + cleartext.len()..]
+                    .starts_with(b"-----BEGIN PGP SIGNATURE-----\n"));
+
+            let message = ClearSigned::from_bytes(&o).unwrap();
+            assert_eq!(message.hash_algos(), &[HashAlgorithm::SHA512]);
+            assert_eq!(message.text(), *signed);
+            assert_eq!(message.signatures().len(), 1);
+            let sig = &message.signatures()[0];
+            assert_eq!(sig.sigtype(), SignatureType::Text);
+            assert!(sig.verify_message(key, signed).unwrap());
+        }
+    }
+
+    #[test]
+    fn detached_signer() {
+        let tsk = TPK::from_bytes(::tests::key("testy-new-private.pgp"))
+            .unwrap();
+        let key = tsk.keys_all().signing_capable().nth(0).unwrap().2;
+        let mut keypair = key.clone().into_keypair().unwrap();
+
+        let inputs = [&b"one"[..], b"two", b"three"];
+        let creation_time = key.creation_time().clone()
+            + time::Duration::days(1);
+        let mut sigs = Vec::new();
+        {
+            let mut signer =
+                DetachedSigner::new(vec![&mut keypair], None).unwrap()
+                .creation_time(creation_time.clone())
+                .add_notation("input@example.org", b"n", None).unwrap();
+            for input in inputs.iter() {
+                signer.write_all(input).unwrap();
+                let mut o = vec![];
+                signer.emit(&mut o).unwrap();
+                sigs.push(o);
+            }
+        }
+
+        for (i, sig) in sigs.iter().enumerate() {
+            let sig = if let Packet::Signature(sig) =
+                Packet::from_bytes(sig).unwrap()
+            {
+                sig
+            } else {
+                panic!("Expected a signature");
+            };
+            assert_eq!(sig.signature_creation_time(),
+                       Some(creation_time.clone()));
+            assert_eq!(sig.notation("input@example.org"), vec![&b"n"[..]]);
+
+            for (j, input) in inputs.iter().enumerate() {
+                assert_eq!(sig.verify_message(key, input).unwrap(), i == j);
+            }
+        }
+    }
+
     #[test]
     fn encryptor() {
         let passwords: [Password; 2] = ["streng geheim".into(),
@@ -1499,4 +2856,64 @@ mod test {
             assert_eq!(state, State::Done);
         }
     }
+
+    #[test]
+    fn encryptor_honors_preferences() {
+        use SignatureType;
+
+        // Returns a key that only accepts `algo`, and its keypair.
+        let accepting = |algo| {
+            let key: Key = Key4::generate_rsa(2048).unwrap().into();
+            let mut pair = key.clone().into_keypair().unwrap();
+            let userid = UserID::from("foo@example.org");
+            let binding =
+                signature::Builder::new(SignatureType::PositiveCertificate)
+                .set_key_flags(&KeyFlags::default()
+                               .set_encrypt_for_transport(true)).unwrap()
+                .set_issuer_fingerprint(key.fingerprint()).unwrap()
+                .set_preferred_symmetric_algorithms(vec![algo]).unwrap()
+                .sign_userid_binding(&mut pair, &key, &userid,
+                                     HashAlgorithm::SHA512).unwrap();
+            let tpk = TPK::from_packet_pile(PacketPile::from(vec![
+                key.clone().into_packet(Tag::PublicKey).unwrap(),
+                userid.into(),
+                binding.into(),
+            ])).unwrap();
+            (tpk, pair)
+        };
+        let (tpk, pair) = accepting(SymmetricAlgorithm::AES128);
+        let (aes256_only, _) = accepting(SymmetricAlgorithm::AES256);
+        let testy = TPK::from_bytes(::tests::key("testy.pgp")).unwrap();
+
+        let encrypt = |tpks: &[&TPK], algo: Option<SymmetricAlgorithm>| {
+            let mut o = vec![];
+            {
+                let m = Message::new(&mut o);
+                let encryptor = Encryptor::new(
+                    m, &[], tpks, EncryptionMode::ForTransport, algo)
+                    .unwrap();
+                let mut literal = LiteralWriter::new(
+                    encryptor, DataFormat::Binary, None, None).unwrap();
+                literal.write_all(b"Hello world.").unwrap();
+            }
+
+            let pile = PacketPile::from_bytes(&o).unwrap();
+            if let Some(Packet::PKESK(ref pkesk)) = pile.path_ref(&[0]) {
+                pkesk.decrypt(&mut pair.clone()).unwrap().0
+            } else {
+                panic!("Expected a PKESK packet");
+            }
+        };
+
+        // Testy prefers AES256, but accepts AES128.
+        assert_eq!(encrypt(&[&tpk, &testy], None),
+                   SymmetricAlgorithm::AES128);
+        // An explicitly requested cipher takes precedence.
+        assert_eq!(encrypt(&[&tpk, &testy], Some(SymmetricAlgorithm::AES192)),
+                   SymmetricAlgorithm::AES192);
+        // Without a common cipher, fall back to the implicitly
+        // accepted TripleDES.
+        assert_eq!(encrypt(&[&tpk, &aes256_only], None),
+                   SymmetricAlgorithm::TripleDES);
+    }
 }
@@ -1,9 +1,9 @@
-use bzip2::Compression as BzCompression;
 use bzip2::write::BzEncoder;
 use std::fmt;
 use std::io;
 
 use Result;
+use constants::CompressionLevel;
 use super::{Generic, Stack, BoxStack, Stackable};
 
 /// BZing writer.
@@ -13,10 +13,11 @@ pub struct BZ<'a, C: 'a> {
 
 impl<'a, C: 'a> BZ<'a, C> {
     /// Makes a BZ compressing writer.
-    pub fn new(inner: Stack<'a, C>, cookie: C) -> Stack<'a, C> {
+    pub fn new(inner: Stack<'a, C>, cookie: C, level: CompressionLevel)
+               -> Stack<'a, C> {
         Stack::from(Box::new(BZ {
             inner: Generic::new_unboxed(
-                BzEncoder::new(inner.into(), BzCompression::Default),
+                BzEncoder::new(inner.into(), level.into()),
                 cookie),
         }))
     }
@@ -1,9 +1,9 @@
-use flate2::Compression as FlateCompression;
 use flate2::write::{DeflateEncoder, ZlibEncoder};
 use std::fmt;
 use std::io;
 
 use Result;
+use constants::CompressionLevel;
 use super::{Generic, Stack, BoxStack, Stackable};
 
 /// ZIPing writer.
@@ -13,10 +13,11 @@ pub struct ZIP<'a, C: 'a> {
 
 impl<'a, C: 'a> ZIP<'a, C> {
     /// Makes a ZIP compressing writer.
-    pub fn new(inner: Stack<'a, C>, cookie: C) -> Stack<'a, C> {
+    pub fn new(inner: Stack<'a, C>, cookie: C, level: CompressionLevel)
+               -> Stack<'a, C> {
         Stack::from(Box::new(ZIP {
             inner: Generic::new_unboxed(
-                DeflateEncoder::new(inner.into(), FlateCompression::default()),
+                DeflateEncoder::new(inner.into(), level.into()),
                 cookie),
         }))
     }
@@ -75,10 +76,11 @@ pub struct ZLIB<'a, C: 'a> {
 
 impl<'a, C: 'a> ZLIB<'a, C> {
     /// Makes a ZLIB compressing writer.
-    pub fn new(inner: Stack<'a, C>, cookie: C) -> Stack<'a, C> {
+    pub fn new(inner: Stack<'a, C>, cookie: C, level: CompressionLevel)
+               -> Stack<'a, C> {
         Stack::from(Box::new(ZLIB {
             inner: Generic::new_unboxed(
-                ZlibEncoder::new(inner.into(), FlateCompression::default()),
+                ZlibEncoder::new(inner.into(), level.into()),
                 cookie),
         }))
     }
@@ -11,6 +11,7 @@ use TPK;
 use Error;
 use conversions::Time;
 use crypto::Password;
+use crypto::s2k::S2K;
 use autocrypt::Autocrypt;
 use constants::{
     HashAlgorithm,
@@ -291,7 +292,8 @@ impl TPKBuilder {
         packets.push(Packet::PublicKey({
             let mut primary = primary.clone();
             if let Some(ref password) = self.password {
-                primary.secret_mut().unwrap().encrypt_in_place(password)?;
+                primary.secret_mut().unwrap().encrypt_in_place(
+                    SymmetricAlgorithm::AES256, S2K::default(), password)?;
             }
             primary
         }));
@@ -322,7 +324,8 @@ impl TPKBuilder {
             let mut subkey = self.ciphersuite.generate_key(flags)?;
 
             if let Some(ref password) = self.password {
-                subkey.secret_mut().unwrap().encrypt_in_place(password)?;
+                subkey.secret_mut().unwrap().encrypt_in_place(
+                    SymmetricAlgorithm::AES256, S2K::default(), password)?;
             }
 
             let mut builder =
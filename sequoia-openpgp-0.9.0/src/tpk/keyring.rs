@@ -0,0 +1,261 @@
+//! A collection of TPKs.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::io;
+use std::path::Path;
+use std::slice;
+use std::vec;
+
+use buffered_reader::BufferedReader;
+
+use Result;
+use TPK;
+use KeyID;
+use Fingerprint;
+use parse::{Parse, PacketParserResult, PacketParser, Cookie};
+use super::TPKParser;
+
+/// A collection of TPKs, e.g., the content of a keyring file.
+///
+/// TPKs can be looked up by the fingerprint or key ID of their
+/// primary key or any of their subkeys.  Lookups use an index, so
+/// they don't scan the whole keyring.
+///
+/// If the keyring contains several copies of a TPK, they are merged.
+///
+/// # Example
+///
+/// ```rust
+/// # extern crate sequoia_openpgp as openpgp;
+/// # use openpgp::{Result, Fingerprint};
+/// # use openpgp::parse::Parse;
+/// use openpgp::tpk::KeyRing;
+///
+/// # fn main() { f().unwrap(); }
+/// # fn f() -> Result<()> {
+/// let keyring = KeyRing::from_bytes(
+///     include_bytes!("../../tests/data/keys/keyring.pgp"))?;
+/// assert_eq!(keyring.len(), 3);
+///
+/// // Find the TPK containing a given encryption subkey.
+/// let fp = Fingerprint::from_hex(
+///     "01F1 8757 5BD4 5644 0465  64C1 49E2 1181 66C9 2632")?;
+/// let tpk = keyring.get(&fp).unwrap();
+/// assert_eq!(tpk.fingerprint().to_hex(),
+///            "3E8877C877274692975189F5D03F6F865226FE8B");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KeyRing {
+    tpks: Vec<TPK>,
+
+    // Maps the fingerprints of the primary keys and subkeys to the
+    // index of the TPK in `tpks`.
+    by_fingerprint: HashMap<Fingerprint, usize>,
+
+    // Maps the key IDs of the primary keys and subkeys to the
+    // indices of the TPKs in `tpks`.  Key IDs are not unique.
+    by_keyid: HashMap<KeyID, Vec<usize>>,
+}
+
+impl<'a> Parse<'a, KeyRing> for KeyRing {
+    /// Reads a keyring from the specified reader.
+    fn from_reader<R: 'a + io::Read>(reader: R) -> Result<Self> {
+        KeyRing::from_packet_parser(PacketParser::from_reader(reader)?)
+    }
+
+    /// Reads a keyring from the specified file.
+    fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        KeyRing::from_packet_parser(PacketParser::from_file(path)?)
+    }
+
+    /// Reads a keyring from the specified buffer.
+    fn from_bytes(data: &'a [u8]) -> Result<Self> {
+        KeyRing::from_packet_parser(PacketParser::from_bytes(data)?)
+    }
+
+    /// Reads a keyring from the specified `BufferedReader`.
+    fn from_buffered_reader(bio: Box<BufferedReader<Cookie> + 'a>)
+                            -> Result<Self> {
+        KeyRing::from_packet_parser(PacketParser::from_buffered_reader(bio)?)
+    }
+}
+
+impl KeyRing {
+    /// Returns an empty keyring.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Reads all TPKs from the packet stream.
+    ///
+    /// If a TPK is invalid, an error is returned.
+    pub fn from_packet_parser(ppr: PacketParserResult) -> Result<Self> {
+        let mut keyring = KeyRing::new();
+        for tpk in TPKParser::from_packet_parser(ppr) {
+            keyring.insert(tpk?)?;
+        }
+        Ok(keyring)
+    }
+
+    /// Adds a TPK to the keyring.
+    ///
+    /// If the keyring already contains a TPK with the same primary
+    /// key, the two are merged.
+    pub fn insert(&mut self, tpk: TPK) -> Result<()> {
+        let fp = tpk.fingerprint();
+        let i = match self.by_fingerprint.get(&fp).cloned() {
+            Some(i) if self.tpks[i].fingerprint() == fp => {
+                // Merging may add subkeys.
+                let existing = self.tpks[i].clone();
+                self.tpks[i] = existing.merge(tpk)?;
+                i
+            },
+            _ => {
+                self.tpks.push(tpk);
+                self.tpks.len() - 1
+            },
+        };
+
+        self.index(i);
+        Ok(())
+    }
+
+    // Adds the keys of the `i`th TPK to the indices.
+    fn index(&mut self, i: usize) {
+        let tpk = &self.tpks[i];
+        for (_, _, key) in tpk.keys_all() {
+            let fp = key.fingerprint();
+            if key.fingerprint() == tpk.fingerprint() {
+                // The primary key takes precedence over a subkey of
+                // another TPK.
+                self.by_fingerprint.insert(fp, i);
+            } else if let Entry::Vacant(e) = self.by_fingerprint.entry(fp) {
+                e.insert(i);
+            }
+
+            let indices = self.by_keyid.entry(key.keyid())
+                .or_insert_with(Vec::new);
+            if ! indices.contains(&i) {
+                indices.push(i);
+            }
+        }
+    }
+
+    /// Returns the TPK whose primary key or one of whose subkeys has
+    /// the given fingerprint.
+    ///
+    /// If several TPKs contain the key, the TPK whose primary key it
+    /// is, or else the first one that was added, is returned.
+    pub fn get(&self, fp: &Fingerprint) -> Option<&TPK> {
+        self.by_fingerprint.get(fp).map(|&i| &self.tpks[i])
+    }
+
+    /// Returns the TPKs whose primary key or one of whose subkeys has
+    /// the given key ID.
+    ///
+    /// Since key IDs are not unique, this may return several TPKs.
+    /// They are returned in the order they were added.
+    pub fn get_by_keyid(&self, keyid: &KeyID) -> Vec<&TPK> {
+        self.by_keyid.get(keyid)
+            .map(|indices| indices.iter().map(|&i| &self.tpks[i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of TPKs in the keyring.
+    pub fn len(&self) -> usize {
+        self.tpks.len()
+    }
+
+    /// Returns whether the keyring is empty.
+    pub fn is_empty(&self) -> bool {
+        self.tpks.is_empty()
+    }
+
+    /// Returns an iterator over the TPKs.
+    pub fn iter(&self) -> slice::Iter<TPK> {
+        self.tpks.iter()
+    }
+}
+
+impl IntoIterator for KeyRing {
+    type Item = TPK;
+    type IntoIter = vec::IntoIter<TPK>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tpks.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a KeyRing {
+    type Item = &'a TPK;
+    type IntoIter = slice::Iter<'a, TPK>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tpks.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        // testy, neal, and emmelie.
+        let keyring = KeyRing::from_bytes(::tests::key("keyring.pgp"))
+            .unwrap();
+        assert_eq!(keyring.len(), 3);
+        assert_eq!(keyring.iter().count(), 3);
+
+        let testy = "3E8877C877274692975189F5D03F6F865226FE8B";
+        let neal = "8F17777118A33DDA9BA48E62AACB3243630052D9";
+        let emmelie = "8E8C33FA4626337976D97978069C0C348DD82C19";
+
+        let get = |fp: &str| {
+            keyring.get(&Fingerprint::from_hex(fp).unwrap())
+                .map(|tpk| tpk.fingerprint().to_hex())
+        };
+
+        // Primary keys.
+        assert_eq!(get(testy).unwrap(), testy);
+        assert_eq!(get(neal).unwrap(), neal);
+        assert_eq!(get(emmelie).unwrap(), emmelie);
+
+        // Subkeys.
+        assert_eq!(get("01F187575BD45644046564C149E2118166C92632").unwrap(),
+                   testy);
+        assert_eq!(get("50E6D924308DBF223CFB510AC2B819056C652598").unwrap(),
+                   neal);
+        assert_eq!(get("061C3CA44AFF0EC58DC66E9522E3FAFE96B56C32").unwrap(),
+                   emmelie);
+
+        // Unknown key.
+        assert_eq!(get("0123456789ABCDEF0123456789ABCDEF01234567"), None);
+
+        let get_by_keyid = |keyid: &str| {
+            keyring.get_by_keyid(&KeyID::from_hex(keyid).unwrap())
+                .into_iter()
+                .map(|tpk| tpk.fingerprint().to_hex())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(get_by_keyid("D03F6F865226FE8B"), vec![ testy ]);
+        assert_eq!(get_by_keyid("A3506AFB820ABD08"), vec![ neal ]);
+        assert_eq!(get_by_keyid("22E3FAFE96B56C32"), vec![ emmelie ]);
+        assert!(get_by_keyid("0123456789ABCDEF").is_empty());
+    }
+
+    #[test]
+    fn merge_duplicates() {
+        let mut keyring = KeyRing::from_bytes(::tests::key("testy.pgp"))
+            .unwrap();
+        keyring.insert(TPK::from_bytes(::tests::key("testy-private.pgp"))
+                       .unwrap()).unwrap();
+        assert_eq!(keyring.len(), 1);
+        assert_eq!(keyring.get_by_keyid(
+            &KeyID::from_hex("D03F6F865226FE8B").unwrap()).len(), 1);
+    }
+}
@@ -17,6 +17,9 @@ use {
     RevocationStatus,
     SignatureType,
     HashAlgorithm,
+    SymmetricAlgorithm,
+    constants::AEADAlgorithm,
+    constants::CompressionAlgorithm,
     packet::Tag,
     packet::Signature,
     packet::signature,
@@ -26,13 +29,15 @@ use {
     packet::UserAttribute,
     packet::Unknown,
     packet::KeyFlags,
+    packet::KeyServerPreferences,
     Packet,
     PacketPile,
     TPK,
     KeyID,
     Fingerprint,
 };
-use parse::{Parse, PacketParserResult, PacketParser};
+use buffered_reader::BufferedReader;
+use parse::{Parse, PacketParserResult, PacketParser, Cookie};
 use serialize::SerializeInto;
 use constants::ReasonForRevocation;
 
@@ -41,10 +46,16 @@ mod lexer;
 mod grammar;
 mod builder;
 mod bindings;
+mod keyring;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "rayon")]
+pub use self::parallel::VerificationReport;
 
 use self::lexer::Lexer;
 pub use self::lexer::Token;
 pub use self::builder::{TPKBuilder, CipherSuite};
+pub use self::keyring::KeyRing;
 
 use lalrpop_util::ParseError;
 
@@ -501,6 +512,36 @@ fn active_revocation(sigs: &[Signature], revs: &[Signature], t: time::Tm)
     }
 }
 
+/// Why a subkey was rejected.
+///
+/// See [`TPK::bad_subkeys`].
+///
+///   [`TPK::bad_subkeys`]: ../struct.TPK.html#method.bad_subkeys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SubkeyError {
+    /// The subkey binding signature does not verify.
+    BadBinding,
+    /// The subkey is signing capable, but the binding signature does
+    /// not carry a primary key binding signature (a back signature).
+    MissingBacksig,
+    /// The subkey is signing capable, but the embedded primary key
+    /// binding signature does not verify.
+    BadBacksig,
+}
+
+impl fmt::Display for SubkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubkeyError::BadBinding =>
+                f.write_str("bad subkey binding signature"),
+            SubkeyError::MissingBacksig =>
+                f.write_str("missing primary key binding signature"),
+            SubkeyError::BadBacksig =>
+                f.write_str("bad primary key binding signature"),
+        }
+    }
+}
+
 /// A subkey and any associated signatures.
 #[derive(Debug, Clone, PartialEq)]
 pub struct SubkeyBinding {
@@ -538,6 +579,20 @@ impl SubkeyBinding {
         self.selfsigs.last()
     }
 
+    /// Returns the newest binding signature that is alive at time
+    /// `t`.
+    ///
+    /// If `t` is `None`, the current time is used.  Unlike
+    /// `SubkeyBinding::binding_signature`, this skips binding
+    /// signatures that were created after `t`, or that expired
+    /// before it.
+    pub fn binding_signature_at<T>(&self, t: T) -> Option<&Signature>
+        where T: Into<Option<time::Tm>>
+    {
+        let t = t.into().unwrap_or_else(time::now_utc);
+        self.selfsigs.iter().rev().find(|sig| sig.signature_alive_at(t))
+    }
+
     /// The self-signatures.
     ///
     /// All self-signatures have been validated, and the newest
@@ -643,6 +698,14 @@ impl UserIDBinding {
     /// Any third-party certifications.
     ///
     /// The signatures have *not* been validated.
+    ///
+    /// Revoking a certification does not remove it from this list.
+    /// Instead, the certification revocation is returned by
+    /// [`UserIDBinding::other_revocations`].  To find out whether a
+    /// certification is still in effect, look for a newer
+    /// revocation by the same issuer.
+    ///
+    ///   [`UserIDBinding::other_revocations`]: #method.other_revocations
     pub fn certifications(&self) -> &[Signature] {
         &self.certifications
     }
@@ -656,7 +719,8 @@ impl UserIDBinding {
 
     /// Revocations issued by other keys.
     ///
-    /// The revocations have *not* been validated.
+    /// This includes revocations of third-party certifications.  The
+    /// revocations have *not* been validated.
     pub fn other_revocations(&self) -> &[Signature] {
         &self.other_revocations
     }
@@ -863,18 +927,34 @@ impl<'a> Iterator for KeyIter<'a> {
             }
         }
 
+        // If we filter by liveness, the self-signatures and the
+        // revocation status are those in effect at that time.
+        let alive_at = self.alive_at;
+
         loop {
             let (sigo, revoked, key) = if ! self.primary {
                 self.primary = true;
 
-                (tpk.primary_key_signature(),
-                 tpk.revocation_status(),
-                 tpk.primary())
+                if let Some(t) = alive_at {
+                    (tpk.primary_key_signature_at(t),
+                     tpk.revocation_status_at(t),
+                     tpk.primary())
+                } else {
+                    (tpk.primary_key_signature(),
+                     tpk.revocation_status(),
+                     tpk.primary())
+                }
             } else {
                 self.subkey_iter.next()
-                    .map(|sk_binding| (sk_binding.binding_signature(),
-                                       sk_binding.revoked(None),
-                                       &sk_binding.subkey,))?
+                    .map(|sk_binding| if let Some(t) = alive_at {
+                        (sk_binding.binding_signature_at(t),
+                         sk_binding.revoked(t),
+                         &sk_binding.subkey)
+                    } else {
+                        (sk_binding.binding_signature(),
+                         sk_binding.revoked(None),
+                         &sk_binding.subkey)
+                    })?
             };
 
             t!("Considering key: {:?}", key);
@@ -1027,18 +1107,40 @@ impl<'a> KeyIter<'a> {
 
     /// Returns keys that are certification capable.
     ///
-    /// See `key_flags` for caveats.
+    /// This checks for the "certify" flag (0x01).  See `key_flags`
+    /// for caveats.
     pub fn certification_capable(self) -> Self {
         self.key_flags(KeyFlags::default().set_certify(true))
     }
 
     /// Returns keys that are signing capable.
     ///
-    /// See `key_flags` for caveats.
+    /// This checks for the "sign" flag (0x02).  See `key_flags` for
+    /// caveats.
     pub fn signing_capable(self) -> Self {
         self.key_flags(KeyFlags::default().set_sign(true))
     }
 
+    /// Returns keys that are encryption capable.
+    ///
+    /// This checks for the "encrypt communications" (0x04) and
+    /// "encrypt storage" (0x08) flags; a key with either of them is
+    /// returned.  To only select keys for one of the two purposes,
+    /// use `key_flags`, which also explains the caveats.
+    pub fn encryption_capable(self) -> Self {
+        self.key_flags(KeyFlags::default()
+                       .set_encrypt_for_transport(true)
+                       .set_encrypt_at_rest(true))
+    }
+
+    /// Returns keys that are authentication capable.
+    ///
+    /// This checks for the "authentication" flag (0x20).  See
+    /// `key_flags` for caveats.
+    pub fn authentication_capable(self) -> Self {
+        self.key_flags(KeyFlags::default().set_authenticate(true))
+    }
+
     /// Only returns keys that are live as of `now`.
     ///
     /// If `now` is none, then all keys are returned whether they are
@@ -1047,6 +1149,10 @@ impl<'a> KeyIter<'a> {
     /// A value of None disables this filter, which is set by default
     /// to only return live keys at the current time.
     ///
+    /// When this filter is enabled, the returned self-signatures and
+    /// revocation statuses, as well as the ones used by the other
+    /// filters, are those in effect at `now`.
+    ///
     /// If you call this function (or `alive`) multiple times, only
     /// the last value is used.
     pub fn alive_at<T>(mut self, alive_at: T) -> Self
@@ -1124,6 +1230,22 @@ enum PacketSource<'a, I: Iterator<Item=Packet>> {
 /// appropriate for in-memory structures, like a vector of `Packet`s
 /// or a `PacketPile`.)
 ///
+/// The `TPKParser` reads the packets lazily, and only buffers the
+/// packets of the TPK that it is currently assembling.  Thus, it can
+/// be used to process large keyrings, like key server dumps, one
+/// TPK at a time.  (To load a keyring into memory and look up keys,
+/// use [`KeyRing`].)
+///
+/// If a TPK is malformed, e.g., because it contains packets that
+/// don't belong in a TPK, an error is returned for that TPK, and the
+/// parser continues with the next primary key packet.  If, however,
+/// the underlying `PacketParser` fails, the parser can't continue.
+/// To turn packets that can't be parsed into `Unknown` packets
+/// instead, use [`ErrorPolicy::SkipPacket`].
+///
+///   [`KeyRing`]: struct.KeyRing.html
+///   [`ErrorPolicy::SkipPacket`]: ../parse/enum.ErrorPolicy.html#variant.SkipPacket
+///
 /// # Example
 ///
 /// ```rust
@@ -1151,6 +1273,29 @@ enum PacketSource<'a, I: Iterator<Item=Packet>> {
 /// #     Ok(())
 /// # }
 /// ```
+///
+/// To skip over packets that can't be parsed:
+///
+/// ```rust
+/// # extern crate sequoia_openpgp as openpgp;
+/// # use openpgp::Result;
+/// use openpgp::parse::{Parse, PacketParserBuilder, ErrorPolicy};
+/// use openpgp::tpk::TPKParser;
+///
+/// # fn main() { f().unwrap(); }
+/// # fn f() -> Result<()> {
+/// #     let keyring = b"";
+/// let ppr = PacketParserBuilder::from_bytes(keyring)?
+///     .on_error(ErrorPolicy::SkipPacket)
+///     .finalize()?;
+/// for tpko in TPKParser::from_packet_parser(ppr) {
+///     if let Ok(tpk) = tpko {
+///         println!("Key: {}", tpk.primary());
+///     }
+/// }
+/// #     Ok(())
+/// # }
+/// ```
 pub struct TPKParser<'a, I: Iterator<Item=Packet>> {
     source: PacketSource<'a, I>,
     packets: Vec<Packet>,
@@ -1200,6 +1345,12 @@ impl<'a> Parse<'a, TPKParser<'a, vec::IntoIter<Packet>>>
     fn from_bytes(data: &'a [u8]) -> Result<Self> {
         Ok(Self::from_packet_parser(PacketParser::from_bytes(data)?))
     }
+
+    /// Initializes a `TPKParser` from a `BufferedReader`.
+    fn from_buffered_reader(bio: Box<BufferedReader<Cookie> + 'a>)
+                            -> Result<Self> {
+        Ok(Self::from_packet_parser(PacketParser::from_buffered_reader(bio)?))
+    }
 }
 
 impl<'a, I: Iterator<Item=Packet>> TPKParser<'a, I> {
@@ -1363,36 +1514,32 @@ impl<'a, I: Iterator<Item=Packet>> TPKParser<'a, I> {
                 let mut other_revs = vec![];
 
                 for sig in sigs.into_iter() {
-                    match sig {
-                        Signature::V4(sig) => {
-                            let sigtype = sig.sigtype();
-
-                            let is_selfsig =
-                                sig.issuer_fingerprint()
-                                .map(|fp| fp == *primary)
-                                .unwrap_or(false)
-                                || sig.issuer()
-                                .map(|keyid| keyid == *primary_keyid)
-                                .unwrap_or(false);
-
-                            use self::SignatureType::*;
-                            if sigtype == KeyRevocation
-                                || sigtype == SubkeyRevocation
-                                || sigtype == CertificateRevocation
-                            {
-                                if is_selfsig {
-                                    self_revs.push(sig.into());
-                                } else {
-                                    other_revs.push(sig.into());
-                                }
-                            } else {
-                                if is_selfsig {
-                                    selfsigs.push(sig.into());
-                                } else {
-                                    certifications.push(sig.into());
-                                }
-                            }
-                        },
+                    let sigtype = sig.sigtype();
+
+                    let is_selfsig =
+                        sig.issuer_fingerprint()
+                        .map(|fp| fp == *primary)
+                        .unwrap_or(false)
+                        || sig.issuer()
+                        .map(|keyid| keyid == *primary_keyid)
+                        .unwrap_or(false);
+
+                    use self::SignatureType::*;
+                    if sigtype == KeyRevocation
+                        || sigtype == SubkeyRevocation
+                        || sigtype == CertificateRevocation
+                    {
+                        if is_selfsig {
+                            self_revs.push(sig);
+                        } else {
+                            other_revs.push(sig);
+                        }
+                    } else {
+                        if is_selfsig {
+                            selfsigs.push(sig);
+                        } else {
+                            certifications.push(sig);
+                        }
                     }
                 }
 
@@ -1607,6 +1754,12 @@ impl<'a> Parse<'a, TPK> for TPK {
     fn from_bytes(buf: &[u8]) -> Result<Self> {
         TPK::from_packet_parser(PacketParser::from_bytes(buf)?)
     }
+
+    /// Returns the first TPK encountered in the `BufferedReader`.
+    fn from_buffered_reader(bio: Box<BufferedReader<Cookie> + 'a>)
+                            -> Result<Self> {
+        TPK::from_packet_parser(PacketParser::from_buffered_reader(bio)?)
+    }
 }
 
 impl TPK {
@@ -1668,6 +1821,142 @@ impl TPK {
         }
     }
 
+    /// Returns the primary key's self-signature at time `t`.
+    ///
+    /// The signature is selected like in
+    /// `TPK::primary_key_signature_full()`, but only self-signatures
+    /// that are alive at `t` are considered, and the primary user
+    /// id's revocation status is evaluated at `t`.  That is, the
+    /// primary user id's newest self-signature is preferred, unless
+    /// the user id is revoked at `t`, in which case the newest direct
+    /// key signature is used, if any.  If `t` is `None`, the current
+    /// time is used.
+    pub fn primary_key_signature_at<T>(&self, t: T) -> Option<&Signature>
+        where T: Into<Option<time::Tm>>
+    {
+        fn newest_alive(sigs: &[Signature], t: time::Tm)
+                        -> Option<&Signature> {
+            sigs.iter().rev().find(|sig| sig.signature_alive_at(t))
+        }
+
+        let t = t.into().unwrap_or_else(time::now_utc);
+
+        // 1. Self-signature from a non-revoked UserID.
+        if let Some(userid) = self.userids.get(0) {
+            if let RevocationStatus::Revoked(_) = userid.revoked(t) {
+                // Fall through.
+            } else if let Some(sig) = newest_alive(&userid.selfsigs, t) {
+                return Some(sig);
+            }
+        }
+
+        // 2. Direct signature.
+        if let Some(sig) = newest_alive(&self.primary_selfsigs, t) {
+            return Some(sig);
+        }
+
+        // 3. Treat User IDs as if they were not revoked.
+        self.userids.get(0)
+            .and_then(|userid| newest_alive(&userid.selfsigs, t))
+    }
+
+    /// Returns the hash algorithms preferred by the TPK's holder at
+    /// time `t`, most preferred first.
+    ///
+    /// The preferences are read from the signature returned by
+    /// `TPK::primary_key_signature_at`.  If `t` is `None`, the
+    /// current time is used.  If there is no such signature, or it
+    /// does not state any preferences, this returns `None`.
+    pub fn preferred_hash_algorithms_at<T>(&self, t: T)
+                                          -> Option<Vec<HashAlgorithm>>
+        where T: Into<Option<time::Tm>>
+    {
+        self.primary_key_signature_at(t)
+            .and_then(|sig| sig.preferred_hash_algorithms())
+    }
+
+    /// Returns the symmetric algorithms preferred by the TPK's
+    /// holder, most preferred first.
+    ///
+    /// The preferences are read from the signature returned by
+    /// `TPK::primary_key_signature`, i.e. the primary user id's
+    /// newest self-signature, or the direct key signature if all
+    /// user ids are revoked.  If there is no such signature, or it
+    /// does not state any preferences, this returns `None`.
+    pub fn preferred_symmetric_algorithms(&self)
+                                          -> Option<Vec<SymmetricAlgorithm>> {
+        self.primary_key_signature()
+            .and_then(|sig| sig.preferred_symmetric_algorithms())
+    }
+
+    /// Returns the hash algorithms preferred by the TPK's holder,
+    /// most preferred first.
+    ///
+    /// See `TPK::preferred_symmetric_algorithms` for where the
+    /// preferences are read from.
+    pub fn preferred_hash_algorithms(&self) -> Option<Vec<HashAlgorithm>> {
+        self.primary_key_signature()
+            .and_then(|sig| sig.preferred_hash_algorithms())
+    }
+
+    /// Returns the compression algorithms preferred by the TPK's
+    /// holder, most preferred first.
+    ///
+    /// See `TPK::preferred_symmetric_algorithms` for where the
+    /// preferences are read from.
+    pub fn preferred_compression_algorithms(&self)
+        -> Option<Vec<CompressionAlgorithm>>
+    {
+        self.primary_key_signature()
+            .and_then(|sig| sig.preferred_compression_algorithms())
+    }
+
+    /// Returns the AEAD algorithms preferred by the TPK's holder,
+    /// most preferred first.
+    ///
+    /// See `TPK::preferred_symmetric_algorithms` for where the
+    /// preferences are read from.
+    pub fn preferred_aead_algorithms(&self) -> Option<Vec<AEADAlgorithm>> {
+        self.primary_key_signature()
+            .and_then(|sig| sig.preferred_aead_algorithms())
+    }
+
+    /// Returns the TPK holder's key server preferences.
+    ///
+    /// The preferences are read from the signature returned by
+    /// `TPK::primary_key_signature`.  If there is no such signature,
+    /// or it does not have a Key Server Preferences subpacket, the
+    /// default preferences are returned.
+    pub fn key_server_preferences(&self) -> KeyServerPreferences {
+        self.primary_key_signature()
+            .map(|sig| sig.key_server_preferences())
+            .unwrap_or_default()
+    }
+
+    /// Returns whether the TPK's holder supports the Symmetrically
+    /// Encrypted Integrity Protected Data packet.
+    ///
+    /// This reads the MDC flag of the Features subpacket of the
+    /// signature returned by `TPK::primary_key_signature`.  If there
+    /// is no such signature, or it does not have a Features
+    /// subpacket, this returns `false`.
+    pub fn supports_seip(&self) -> bool {
+        self.primary_key_signature()
+            .map(|sig| sig.features().supports_mdc())
+            .unwrap_or(false)
+    }
+
+    /// Returns whether the TPK's holder supports the AEAD Encrypted
+    /// Data packet.
+    ///
+    /// Like `TPK::supports_seip`, this reads the Features subpacket,
+    /// and returns `false` if it is absent.
+    pub fn supports_aead(&self) -> bool {
+        self.primary_key_signature()
+            .map(|sig| sig.features().supports_aead())
+            .unwrap_or(false)
+    }
+
     /// The self-signatures.
     ///
     /// All self-signatures have been validated, and the newest
@@ -1833,18 +2122,68 @@ impl TPK {
         self.merge_packets(vec![sig.into()])
     }
 
-    /// Returns whether or not the TPK has expired.
-    pub fn expired(&self) -> bool {
-        if let Some(Signature::V4(sig)) = self.primary_key_signature() {
-            sig.key_expired(self.primary())
+    /// Revokes the subkey with the fingerprint `subkey`.
+    ///
+    /// This creates a subkey revocation signature with the given
+    /// reason, and merges it into the TPK.  Afterwards, the subkey is
+    /// no longer returned by `TPK::keys_valid`.  See
+    /// `Key::revoke` to only create the revocation certificate.
+    ///
+    /// If `primary_signer` does not hold the primary key, or the TPK
+    /// has no such subkey, `Error::InvalidArgument` is returned.
+    pub fn revoke_subkey_in_place(self, primary_signer: &mut Signer,
+                                  subkey: &Fingerprint,
+                                  code: ReasonForRevocation, reason: &[u8])
+        -> Result<TPK>
+    {
+        if primary_signer.public().fingerprint() != self.fingerprint() {
+            return Err(Error::InvalidArgument(
+                "signer is not the primary key".into()).into());
+        }
+
+        let sig = match self.subkeys.iter()
+            .find(|sk| &sk.subkey().fingerprint() == subkey)
+        {
+            Some(sk) => sk.subkey().revoke(primary_signer, &self, code,
+                                           reason, None, None)?,
+            None => return Err(Error::InvalidArgument(
+                format!("No such subkey: {}", subkey)).into()),
+        };
+        self.merge_packets(vec![sig.into()])
+    }
+
+    /// Returns when the primary key expires.
+    ///
+    /// The expiration time is read from the primary key's current
+    /// self-signature (see `TPK::primary_key_signature`).  If there
+    /// is none, or the key does not expire, this returns `None`.
+    /// Use `TPK::set_expiry` to change it.
+    pub fn primary_key_expiration(&self) -> Option<time::Tm> {
+        let e = self.primary_key_signature()?.key_expiration_time()?;
+        if e.num_seconds() == 0 {
+            // Zero means that the key does not expire.
+            None
         } else {
-            false
+            Some(*self.primary().creation_time() + e)
         }
     }
 
+    /// Returns whether or not the TPK has expired.
+    ///
+    /// See `TPK::expired_at`.
+    pub fn expired(&self) -> bool {
+        self.expired_at(time::now_utc())
+    }
+
     /// Returns whether or not the key is expired at the given time.
+    ///
+    /// The expiration time is read from the self-signature in effect
+    /// at `tm` (see `TPK::primary_key_signature_at`).  If there is
+    /// none, the primary key's current self-signature is used.
     pub fn expired_at(&self, tm: time::Tm) -> bool {
-        if let Some(Signature::V4(sig)) = self.primary_key_signature() {
+        if let Some(sig) = self.primary_key_signature_at(tm)
+            .or_else(|| self.primary_key_signature())
+        {
             sig.key_expired_at(self.primary(), tm)
         } else {
             false
@@ -1852,21 +2191,42 @@ impl TPK {
     }
 
     /// Returns whether or not the TPK is alive.
-    pub fn alive(&self) -> bool {
-        if let Some(sig) = self.primary_key_signature() {
-            sig.key_alive(self.primary())
-        } else {
-            false
-        }
+    ///
+    /// See `TPK::alive_at`.
+    pub fn alive(&self) -> Result<()> {
+        self.alive_at(time::now_utc())
     }
 
-    /// Returns whether or not the key is alive at the given time.
-    pub fn alive_at(&self, tm: time::Tm) -> bool {
-        if let Some(sig) = self.primary_key_signature() {
-            sig.key_alive_at(self.primary(), tm)
-        } else {
-            false
+    /// Returns whether or not the TPK is alive at the given time.
+    ///
+    /// A TPK is alive if its primary key has been created, and is
+    /// neither expired nor revoked at `tm`.  Otherwise, an error
+    /// describing why the TPK is not usable is returned:
+    /// `Error::Revoked` if the primary key has been revoked, and
+    /// `Error::Expired` if it has expired.
+    ///
+    /// Note: revocations issued by third parties are not considered,
+    /// because they cannot be validated here (see
+    /// `RevocationStatus::CouldBe`).
+    pub fn alive_at(&self, tm: time::Tm) -> Result<()> {
+        if *self.primary().creation_time() > tm {
+            return Err(Error::InvalidOperation(
+                "The primary key was created after the reference time"
+                    .into()).into());
+        }
+
+        if let RevocationStatus::Revoked(_) = self.revocation_status_at(tm) {
+            return Err(Error::Revoked.into());
+        }
+
+        let sig = self.primary_key_signature_at(tm)
+            .ok_or_else(|| Error::MalformedTPK(
+                "No self-signature alive at the reference time".into()))?;
+        if sig.key_expired_at(self.primary(), tm) {
+            return Err(Error::Expired.into());
         }
+
+        Ok(())
     }
 
     /// Sets the key to expire in delta seconds.
@@ -1918,6 +2278,95 @@ impl TPK {
         self.set_expiry_as_of(primary_signer, expiration, time::now())
     }
 
+    /// Creates the signatures needed to add `subkey` to the TPK.
+    ///
+    /// This creates a subkey binding signature with the given key
+    /// flags and expiration time.  If `flags` indicate that the
+    /// subkey can sign or certify, the binding signature includes a
+    /// primary key binding signature ("back signature") made using
+    /// `subkey`, which therefore needs to have an unencrypted secret.
+    ///
+    /// The returned signatures, and `subkey` itself, need to be merged
+    /// into the TPK.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # use openpgp::Result;
+    /// use openpgp::constants::Curve;
+    /// use openpgp::packet::{Key, KeyFlags, Tag, key::Key4};
+    /// use openpgp::tpk::TPKBuilder;
+    /// # fn main() { f().unwrap(); }
+    /// # fn f() -> Result<()>
+    /// # {
+    /// let (tpk, _) = TPKBuilder::new().generate()?;
+    /// let mut keypair = tpk.primary().clone().into_keypair()?;
+    ///
+    /// let flags = KeyFlags::default().set_encrypt_for_transport(true);
+    /// let subkey = Key::V4(Key4::generate_ecc(false, Curve::Cv25519)?);
+    /// let sigs = tpk.add_subkey(&mut keypair, &subkey, &flags, None)?;
+    ///
+    /// let mut packets = vec![subkey.into_packet(Tag::SecretSubkey)?];
+    /// packets.extend(sigs.into_iter().map(|s| s.into()));
+    /// let tpk = tpk.merge_packets(packets)?;
+    /// assert_eq!(tpk.keys_valid().key_flags(flags).count(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_subkey(&self, primary_signer: &mut Signer, subkey: &Key,
+                      flags: &KeyFlags, expiration: Option<time::Duration>)
+        -> Result<Vec<Signature>>
+    {
+        let now = time::now_utc();
+        let mut builder =
+            signature::Builder::new(SignatureType::SubkeyBinding)
+            .set_key_flags(flags)?
+            .set_key_expiration_time(expiration)?;
+
+        if flags.can_certify() || flags.can_sign() {
+            let mut subkey_signer = subkey.clone().into_keypair()?;
+            let backsig =
+                signature::Builder::new(SignatureType::PrimaryKeyBinding)
+                .set_signature_creation_time(now)?
+                .set_issuer_fingerprint(subkey.fingerprint())?
+                .set_issuer(subkey.keyid())?
+                .sign_subkey_binding(&mut subkey_signer, self.primary(),
+                                     subkey, HashAlgorithm::SHA512)?;
+            builder = builder.set_embedded_signature(backsig)?;
+        }
+
+        Ok(vec![subkey.bind(primary_signer, self, builder, None, now)?])
+    }
+
+    /// Adds a user id to the TPK.
+    ///
+    /// This creates a positive certification binding `userid` to the
+    /// primary key, and merges both into the TPK.  `signer` must
+    /// hold the primary key.
+    ///
+    /// If the TPK already has `userid`, or `signer` does not hold the
+    /// primary key, `Error::InvalidArgument` is returned.
+    pub fn add_userid(self, userid: UserID, signer: &mut Signer,
+                      hash_algo: HashAlgorithm)
+        -> Result<TPK>
+    {
+        if signer.public().fingerprint() != self.fingerprint() {
+            return Err(Error::InvalidArgument(
+                "Signer does not hold the primary key".into()).into());
+        }
+
+        if self.userids().any(|u| u.userid() == &userid) {
+            return Err(Error::InvalidArgument(
+                format!("User ID {} already exists", userid)).into());
+        }
+
+        let builder =
+            signature::Builder::new(SignatureType::PositiveCertificate);
+        let binding = userid.bind(signer, &self, builder, hash_algo, None)?;
+        self.merge_packets(vec![userid.into(), binding.into()])
+    }
+
     /// Returns an iterator over the TPK's valid `UserIDBinding`s.
     ///
     /// The primary user id is returned first.  A valid
@@ -1926,6 +2375,24 @@ impl TPK {
         UserIDBindingIter { iter: self.userids.iter() }
     }
 
+    /// Returns the third-party certifications of `userid`.
+    ///
+    /// This is a shortcut for looking up `userid`'s
+    /// `UserIDBinding`, and calling
+    /// [`UserIDBinding::certifications`] on it.  If the TPK doesn't
+    /// have a valid binding for `userid`, an empty slice is returned.
+    ///
+    /// The certifications have *not* been validated.  Use, e.g.,
+    /// `Signature::issuer` to find out who made a certification.
+    ///
+    ///   [`UserIDBinding::certifications`]: tpk/struct.UserIDBinding.html#method.certifications
+    pub fn userid_certifications(&self, userid: &UserID) -> &[Signature] {
+        self.userids.iter()
+            .find(|b| b.userid() == userid)
+            .map(|b| b.certifications())
+            .unwrap_or(&[])
+    }
+
     /// Returns an iterator over the TPK's valid `UserAttributeBinding`s.
     ///
     /// A valid `UserIDAttributeBinding` has at least one good
@@ -1941,6 +2408,24 @@ impl TPK {
         SubkeyBindingIter { iter: Some(self.subkeys.iter()) }
     }
 
+    /// Returns the subkeys that were rejected, and why.
+    ///
+    /// A subkey is rejected if none of its binding signatures
+    /// verify.  This includes signing-capable subkeys whose binding
+    /// signature lacks a valid primary key binding signature (see
+    /// [Section 5.2.1 of RFC 4880]).  Without this back signature,
+    /// an attacker could claim somebody else's signing key as a
+    /// subkey.
+    ///
+    /// Rejected subkeys are not returned by [`TPK::subkeys`], and
+    /// are not serialized.
+    ///
+    ///   [Section 5.2.1 of RFC 4880]: https://tools.ietf.org/html/rfc4880#section-5.2.1
+    ///   [`TPK::subkeys`]: #method.subkeys
+    pub fn bad_subkeys(&self) -> Vec<(Fingerprint, SubkeyError)> {
+        self.bad_subkeys.clone()
+    }
+
     /// Returns an iterator over the TPK's valid keys (live and
     /// not-revoked).
     ///
@@ -1961,7 +2446,42 @@ impl TPK {
     /// documentation of `keys` for how to control what keys are
     /// returned.
     pub fn keys_valid(&self) -> KeyIter {
-        KeyIter::new(self).alive().revoked(false)
+        self.keys_valid_at(None)
+    }
+
+    /// Returns an iterator over the TPK's keys that were valid at
+    /// time `t`.
+    ///
+    /// This is like `TPK::keys_valid`, but the keys' liveness and
+    /// revocation status are evaluated at `t`, using the
+    /// self-signatures in effect at that time.  If `t` is `None`, the
+    /// current time is used.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # extern crate time;
+    /// # use openpgp::Result;
+    /// use openpgp::tpk::TPKBuilder;
+    ///
+    /// # fn main() { f().unwrap(); }
+    /// # fn f() -> Result<()> {
+    /// let (tpk, _) = TPKBuilder::new()
+    ///     .set_expiration(time::Duration::days(1))
+    ///     .generate()?;
+    /// let now = time::now_utc();
+    /// assert_eq!(tpk.keys_valid_at(now).count(), 1);
+    /// assert_eq!(tpk.keys_valid_at(now + time::Duration::days(2)).count(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn keys_valid_at<T>(&self, t: T) -> KeyIter
+        where T: Into<Option<time::Tm>>
+    {
+        KeyIter::new(self)
+            .alive_at(t.into().unwrap_or_else(time::now_utc))
+            .revoked(false)
     }
 
     /// Returns an iterator over the TPK's keys.
@@ -2000,9 +2520,49 @@ impl TPK {
                 .into_boxed_slice()
         }
 
+        // Returns whether the signature's issuer information names a
+        // key other than the primary key.  A signature that verifies
+        // using the primary key, but names a different issuer, is not
+        // a self-signature.  Signatures without any issuer
+        // information are not rejected.
+        fn issued_by_other(sig: &Signature, fp: &Fingerprint, keyid: &KeyID)
+                           -> bool {
+            sig.issuer_fingerprint().map(|f| f != *fp).unwrap_or(false)
+                || sig.issuer().map(|k| k != *keyid).unwrap_or(false)
+        }
+
+        // Returns why `sig` is not a valid binding signature for
+        // `subkey`, or `None` if it is not a subkey binding signature
+        // or it is valid.
+        fn subkey_error(sig: &Signature, primary: &Key, subkey: &Key)
+                        -> Option<SubkeyError> {
+            if sig.sigtype() != SignatureType::SubkeyBinding
+                || sig.verify_subkey_binding(primary, primary, subkey)
+                    .unwrap_or(false)
+            {
+                return None;
+            }
+
+            let binding_ok = Signature::subkey_binding_hash(sig, primary,
+                                                            subkey)
+                .and_then(|hash| sig.verify_hash(primary, sig.hash_algo(),
+                                                 &hash[..]))
+                .unwrap_or(false);
+            if ! binding_ok {
+                Some(SubkeyError::BadBinding)
+            } else if let Some(Packet::Signature(_)) = sig.embedded_signature() {
+                Some(SubkeyError::BadBacksig)
+            } else {
+                Some(SubkeyError::MissingBacksig)
+            }
+        }
+
         // Fallback time.
         let time_zero = time::at_utc(time::Timespec::new(0, 0));
 
+        let primary_fp = self.primary().fingerprint();
+        let primary_keyid = primary_fp.to_keyid();
+
 
         // The very first thing that we do is verify the
         // self-signatures.  There are a few things that we need to be
@@ -2031,9 +2591,12 @@ impl TPK {
                 for sig in mem::replace(&mut $binding.$sigs, Vec::new())
                     .into_iter()
                 {
-                    if let Ok(true) = sig.$verify_method(&self.primary,
-                                                         &self.primary,
-                                                         $($verify_args),*) {
+                    if ! issued_by_other(&sig, &primary_fp, &primary_keyid)
+                        && sig.$verify_method(&self.primary,
+                                              &self.primary,
+                                              $($verify_args),*)
+                        .unwrap_or(false)
+                    {
                         $binding.$sigs.push(sig);
                     } else {
                         if TRACE {
@@ -2079,6 +2642,16 @@ impl TPK {
         }
 
         for binding in self.subkeys.iter_mut() {
+            // Remember why binding signatures are bad so that we can
+            // report the subkeys that we end up dropping.
+            for sig in binding.selfsigs.iter() {
+                if let Some(err) = subkey_error(sig, &self.primary,
+                                                &binding.subkey) {
+                    self.bad_subkeys.push((binding.subkey.fingerprint(),
+                                           err));
+                }
+            }
+
             check!(format!("subkey {}", binding.subkey.keyid()),
                    binding, selfsigs, verify_subkey_binding,
                    &binding.subkey);
@@ -2094,10 +2667,11 @@ impl TPK {
             macro_rules! check_one {
                 ($desc:expr, $sigs:expr, $sig:expr,
                  $verify_method:ident, $($verify_args:expr),*) => ({
-                     if let Ok(true)
-                         = $sig.$verify_method(&self.primary,
-                                               &self.primary,
-                                               $($verify_args),*)
+                     if ! issued_by_other(&$sig, &primary_fp, &primary_keyid)
+                         && $sig.$verify_method(&self.primary,
+                                                &self.primary,
+                                                $($verify_args),*)
+                         .unwrap_or(false)
                      {
                          if TRACE {
                              eprintln!("Sig {:02X}{:02X}, {:?} \
@@ -2184,9 +2758,29 @@ impl TPK {
             subkey.selfsigs.len() > 0 || subkey.self_revocations.len() > 0
         });
 
+        // Only report subkeys that we actually dropped.
+        {
+            let subkeys = &self.subkeys;
+            self.bad_subkeys.retain(|&(ref fp, _)| {
+                ! subkeys.iter().any(|b| b.subkey.fingerprint() == *fp)
+            });
+        }
+        self.bad_subkeys.sort_by(|a, b| {
+            a.0.as_slice().cmp(b.0.as_slice()).then(a.1.cmp(&b.1))
+        });
+        self.bad_subkeys.dedup();
+
+        // Orders signatures by creation time.  Ties are broken using
+        // the serialized form so that the order does not depend on
+        // how the TPK was assembled, and so that duplicates end up
+        // next to each other.
         fn sig_cmp(a: &Signature, b: &Signature) -> Ordering {
             canonical_signature_order(a.signature_creation_time(),
                                       b.signature_creation_time())
+                .then_with(|| {
+                    a.to_vec().expect("XXX: this better not fail")
+                        .cmp(&b.to_vec().expect("XXX: this better not fail"))
+                })
         }
 
         // Sort and dedup the primary key's signatures.
@@ -2255,7 +2849,7 @@ impl TPK {
                 b.self_revocations.sort_by(sig_cmp);
                 b.self_revocations.dedup_by_key(sig_key);
 
-                b.other_revocations.append(&mut a.self_revocations);
+                b.other_revocations.append(&mut a.other_revocations);
                 b.other_revocations.sort_by(sig_cmp);
                 b.other_revocations.dedup_by_key(sig_key);
 
@@ -2399,7 +2993,7 @@ impl TPK {
                 b.self_revocations.sort_by(sig_cmp);
                 b.self_revocations.dedup_by_key(sig_key);
 
-                b.other_revocations.append(&mut a.self_revocations);
+                b.other_revocations.append(&mut a.other_revocations);
                 b.other_revocations.sort_by(sig_cmp);
                 b.other_revocations.dedup_by_key(sig_key);
 
@@ -2539,7 +3133,7 @@ impl TPK {
                 b.self_revocations.sort_by(sig_cmp);
                 b.self_revocations.dedup_by_key(sig_key);
 
-                b.other_revocations.append(&mut a.self_revocations);
+                b.other_revocations.append(&mut a.other_revocations);
                 b.other_revocations.sort_by(sig_cmp);
                 b.other_revocations.dedup_by_key(sig_key);
 
@@ -2627,6 +3221,31 @@ impl TPK {
         }
 
 
+        // Sort and dedup the unknown components.
+        for unknown in &mut self.unknowns {
+            unknown.sigs.sort_by(sig_cmp);
+            unknown.sigs.dedup_by_key(sig_key);
+        }
+
+        self.unknowns.sort_by(|a, b| {
+            u8::from(a.unknown.tag()).cmp(&u8::from(b.unknown.tag()))
+                .then_with(|| a.unknown.body().cmp(&b.unknown.body()))
+        });
+
+        self.unknowns.dedup_by(|a, b| {
+            if a.unknown == b.unknown {
+                // Recall: if a and b are equal, a will be dropped.
+                b.sigs.append(&mut a.sigs);
+                b.sigs.sort_by(sig_cmp);
+                b.sigs.dedup_by_key(sig_key);
+
+                true
+            } else {
+                false
+            }
+        });
+
+
         // XXX: Check if the sigs in other_sigs issuer are actually
         // designated revokers for this key (listed in a "Revocation
         // Key" subpacket in *any* non-revoked self-signature).  Only
@@ -2739,10 +3358,146 @@ impl TPK {
         PacketPile::from(self.into_packets())
     }
 
+    /// Exports a minimal, self-contained version of the TPK.
+    ///
+    /// The result is ASCII armored, and contains the primary key with
+    /// its newest direct key signature, the primary user id with its
+    /// current self-signature, and the valid subkeys with their
+    /// current binding signatures.  All revocations of these
+    /// components, including those issued by designated revokers,
+    /// are kept, and revoked subkeys are exported with their
+    /// revocation certificates.  Everything else, i.e.,
+    /// superseded self-signatures, third-party certifications, other
+    /// user ids, user attributes, and expired subkeys, is dropped.
+    /// Secret key material is never exported.
+    pub fn export_minimal(&self) -> Result<Vec<u8>> {
+        use serialize::Serialize;
+
+        let mut p : Vec<Packet> = Vec::new();
+
+        let mut primary = self.primary.clone();
+        primary.set_secret(None);
+        p.push(Packet::PublicKey(primary));
+        if let Some(sig) = self.primary_selfsigs.last() {
+            p.push(sig.clone().into());
+        }
+        for s in self.primary_self_revocations.iter()
+            .chain(self.primary_other_revocations.iter())
+        {
+            p.push(s.clone().into());
+        }
+
+        if let Some(u) = self.userids.get(0) {
+            if let Some(sig) = u.binding_signature() {
+                p.push(u.userid.clone().into());
+                p.push(sig.clone().into());
+                for s in u.self_revocations.iter()
+                    .chain(u.other_revocations.iter())
+                {
+                    p.push(s.clone().into());
+                }
+            }
+        }
+
+        for k in self.subkeys.iter() {
+            let sig = match k.binding_signature() {
+                Some(sig) => sig,
+                None => continue,
+            };
+            let revoked = if let RevocationStatus::Revoked(_)
+                = k.revoked(None)
+            {
+                true
+            } else {
+                false
+            };
+            if ! revoked
+                && ! (sig.signature_alive() && sig.key_alive(&k.subkey))
+            {
+                continue;
+            }
+
+            let mut subkey = k.subkey.clone();
+            subkey.set_secret(None);
+            p.push(Packet::PublicSubkey(subkey));
+            p.push(sig.clone().into());
+            for s in k.self_revocations.iter()
+                .chain(k.other_revocations.iter())
+            {
+                p.push(s.clone().into());
+            }
+        }
+
+        let tpk = TPK::from_packet_pile(PacketPile::from(p))?;
+        let mut buf = Vec::new();
+        armor::Encoder::new(&tpk).serialize(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Removes any secret key material.
+    ///
+    /// The primary key and the subkeys are turned into public keys.
+    pub fn strip_secret_keys(mut self) -> TPK {
+        self.primary.set_secret(None);
+        for k in self.subkeys.iter_mut() {
+            k.subkey.set_secret(None);
+        }
+        self
+    }
+
+    /// Removes any secret key material, third-party certifications,
+    /// and superseded self-signatures.
+    ///
+    /// Unlike `TPK::export_minimal`, this keeps all components, but
+    /// only the newest valid self-signature of each of them.  A
+    /// self-signature is valid if it is alive now, and, for subkeys,
+    /// if the binding does not expire the subkey.  If a component
+    /// has no valid self-signature, its newest one is kept.
+    /// Revocations are kept.
+    pub fn minimize(self) -> TPK {
+        fn newest_valid<F>(sigs: &mut Vec<Signature>, valid: F)
+            where F: Fn(&Signature) -> bool
+        {
+            // The newest self-signature is last.
+            let i = sigs.iter().rposition(|s| valid(s));
+            if let Some(i) = i.or(sigs.len().checked_sub(1)) {
+                let sig = sigs.swap_remove(i);
+                sigs.clear();
+                sigs.push(sig);
+            }
+        }
+
+        let mut tpk = self.strip_secret_keys();
+
+        newest_valid(&mut tpk.primary_selfsigs, |s| s.signature_alive());
+        tpk.primary_certifications.clear();
+        for u in tpk.userids.iter_mut() {
+            newest_valid(&mut u.selfsigs, |s| s.signature_alive());
+            u.certifications.clear();
+        }
+        for u in tpk.user_attributes.iter_mut() {
+            newest_valid(&mut u.selfsigs, |s| s.signature_alive());
+            u.certifications.clear();
+        }
+        for k in tpk.subkeys.iter_mut() {
+            let subkey = &k.subkey;
+            newest_valid(&mut k.selfsigs,
+                         |s| s.signature_alive() && s.key_alive(subkey));
+            k.certifications.clear();
+        }
+        tpk.bad.clear();
+
+        tpk
+    }
+
     /// Merges `other` into `self`.
     ///
-    /// If `other` is a different key, then nothing is merged into
-    /// `self`, but `self` is still canonicalized.
+    /// The components and signatures of both TPKs are combined, and
+    /// the result is canonicalized.  Duplicate packets are dropped,
+    /// but all valid signatures are kept, not just the newest ones.
+    ///
+    /// If `other` is a different key, i.e., its primary key's
+    /// fingerprint differs, `Error::InvalidArgument` is returned.
     pub fn merge(mut self, mut other: TPK) -> Result<Self> {
         if self.primary().fingerprint() != other.primary().fingerprint() {
             // The primary key is not the same.  There is nothing to
@@ -2767,7 +3522,9 @@ impl TPK {
         self.userids.append(&mut other.userids);
         self.user_attributes.append(&mut other.user_attributes);
         self.subkeys.append(&mut other.subkeys);
+        self.unknowns.append(&mut other.unknowns);
         self.bad.append(&mut other.bad);
+        self.bad_subkeys.append(&mut other.bad_subkeys);
 
         Ok(self.canonicalize())
     }
@@ -3041,6 +3798,56 @@ mod test {
         assert_eq!(tpk.subkeys.len(), 0);
     }
 
+    #[test]
+    fn canonical_serialization() {
+        use ::tests::key;
+
+        // Reordering the components and their signatures doesn't
+        // change the serialized TPK.
+        for name in &["dkg.gpg", "neal.pgp", "bannon-all-uids-subkeys.gpg"] {
+            // Note: into_packets drops bad signatures, which would
+            // otherwise be serialized.
+            let packets = TPK::from_bytes(key(name)).unwrap().into_packets();
+            let tpk = TPK::from_packet_pile(PacketPile::from(packets.clone()))
+                .unwrap();
+
+            let mut components: Vec<Vec<Packet>> = Vec::new();
+            for p in packets {
+                match p {
+                    Packet::Signature(_) =>
+                        components.last_mut().unwrap().push(p),
+                    _ => components.push(vec![p]),
+                }
+            }
+            for c in components.iter_mut() {
+                c[1..].reverse();
+            }
+            components[1..].reverse();
+
+            let shuffled = TPK::from_packet_pile(PacketPile::from(
+                components.into_iter().flat_map(|c| c).collect::<Vec<_>>()))
+                .unwrap();
+            assert_eq!(tpk.to_vec().unwrap(), shuffled.to_vec().unwrap());
+        }
+
+        // The order in which TPKs are merged doesn't matter either.
+        let tpks = [
+            "bannon-all-uids-subkeys.gpg",
+            "bannon-the-donald-signs-all-uids.gpg",
+            "bannon-ivanka-signs-all-uids.gpg",
+        ].iter().map(|name| TPK::from_bytes(key(name)).unwrap())
+            .collect::<Vec<_>>();
+
+        let forward = tpks.iter().skip(1).fold(tpks[0].clone(), |acc, tpk| {
+            acc.merge(tpk.clone()).unwrap()
+        });
+        let backward = tpks.iter().rev().skip(1)
+            .fold(tpks[2].clone(), |acc, tpk| {
+                acc.merge(tpk.clone()).unwrap()
+            });
+        assert_eq!(forward.to_vec().unwrap(), backward.to_vec().unwrap());
+    }
+
     #[test]
     fn merge() {
         use ::tests::key;
@@ -3069,6 +3876,22 @@ mod test {
         // just with a different self-signature.
         assert_eq!(tpk_all_uids.userids.len(), 3);
 
+        // The duplicate User ID keeps both self-signatures.
+        let merged = tpk_add_uid_1.clone().merge(tpk_add_uid_3.clone())
+            .unwrap();
+        let whitehouse = merged.userids()
+            .filter(|u| u.userid().value()
+                    == &b"Steve Bannon <steve@whitehouse.gov>"[..])
+            .collect::<Vec<_>>();
+        assert_eq!(whitehouse.len(), 1);
+        assert_eq!(whitehouse[0].selfsigs().len(), 2);
+
+        // Keys with different primary keys cannot be merged.
+        let other = TPK::from_bytes(key("testy.pgp")).unwrap();
+        assert_match!(Some(&Error::InvalidArgument(_)) =
+                      tpk_base.clone().merge(other).unwrap_err()
+                      .downcast_ref::<Error>());
+
         // Merge in order.
         let merged = tpk_base.clone().merge(tpk_add_uid_1.clone()).unwrap()
             .merge(tpk_add_uid_2.clone()).unwrap()
@@ -3487,6 +4310,8 @@ mod test {
         let expiry_orig = tpk.primary_key_signature().unwrap()
             .key_expiration_time()
             .expect("Keys expire by default.");
+        assert_eq!(tpk.primary_key_expiration(),
+                   Some(*tpk.primary().creation_time() + expiry_orig));
 
         let mut keypair = tpk.primary().clone().into_keypair().unwrap();
 
@@ -3500,6 +4325,7 @@ mod test {
                 .key_expiration_time();
             assert_eq!(expiry, None);
         }
+        assert_eq!(tpk.primary_key_expiration(), None);
 
         // Shorten the expiry.  (The default expiration should be at
         // least a few weeks, so removing an hour should still keep us
@@ -3516,6 +4342,8 @@ mod test {
                 .key_expiration_time();
             assert_eq!(expiry.unwrap(), expiry_expected);
         }
+        assert_eq!(tpk.primary_key_expiration(),
+                   Some(*tpk.primary().creation_time() + expiry_expected));
     }
 
     #[test]
@@ -3643,6 +4471,56 @@ mod test {
         assert_match!(RevocationStatus::Revoked(_) = tpk.revocation_status());
     }
 
+    #[test]
+    fn revoke_subkey() {
+        let (tpk, _) = TPKBuilder::new()
+            .add_encryption_subkey()
+            .add_signing_subkey()
+            .generate().unwrap();
+        assert_eq!(tpk.keys_valid().count(), 3);
+        let mut keypair = tpk.primary().clone().into_keypair().unwrap();
+
+        let flags = KeyFlags::default().set_encrypt_for_transport(true);
+        let fp = tpk.keys_valid().key_flags(flags.clone()).nth(0).unwrap()
+            .2.fingerprint();
+        let tpk = tpk.revoke_subkey_in_place(
+            &mut keypair, &fp, ReasonForRevocation::KeySuperseded,
+            b"Rotated").unwrap();
+
+        // The subkey is revoked, and no longer valid.
+        let sk = tpk.subkeys().find(|sk| sk.subkey().fingerprint() == fp)
+            .unwrap();
+        if let RevocationStatus::Revoked(sigs) = sk.revoked(None) {
+            assert_eq!(sigs.len(), 1);
+            assert_eq!(sigs[0].sigtype(), SignatureType::SubkeyRevocation);
+            assert_eq!(sigs[0].reason_for_revocation(),
+                       Some((ReasonForRevocation::KeySuperseded,
+                             &b"Rotated"[..])));
+        } else {
+            panic!("Subkey is not revoked.");
+        }
+        assert_eq!(tpk.keys_valid().count(), 2);
+        assert_eq!(tpk.keys_valid().key_flags(flags).count(), 0);
+        assert_eq!(RevocationStatus::NotAsFarAsWeKnow,
+                   tpk.revocation_status());
+
+        // Unknown subkeys and foreign signers are rejected.
+        let other = Fingerprint::from_bytes(b"bbbbbbbbbbbbbbbbbbbb");
+        assert_match!(Some(&Error::InvalidArgument(_)) =
+                      tpk.clone().revoke_subkey_in_place(
+                          &mut keypair, &other,
+                          ReasonForRevocation::KeyRetired, b"")
+                      .unwrap_err().downcast_ref::<Error>());
+        let (stranger, _) = TPKBuilder::new().generate().unwrap();
+        let mut stranger =
+            stranger.primary().clone().into_keypair().unwrap();
+        assert_match!(Some(&Error::InvalidArgument(_)) =
+                      tpk.revoke_subkey_in_place(
+                          &mut stranger, &fp,
+                          ReasonForRevocation::KeyRetired, b"")
+                      .unwrap_err().downcast_ref::<Error>());
+    }
+
     #[test]
     fn revoke_uid() {
         use std::{thread, time};
@@ -3672,6 +4550,282 @@ mod test {
         assert_match!(RevocationStatus::Revoked(_) = uid.revoked(None));
     }
 
+    #[test]
+    fn alive_at() {
+        // A valid TPK.
+        let (tpk, _) = TPKBuilder::new()
+            .add_userid("Alice")
+            .set_expiration(time::Duration::days(1))
+            .generate().unwrap();
+        // Generating the TPK may cross a second boundary, so take
+        // the reference time from the key.
+        let now = *tpk.primary().creation_time();
+        assert!(tpk.alive_at(now).is_ok());
+        assert!(tpk.alive().is_ok());
+
+        // Before it was created.
+        assert!(tpk.alive_at(now - time::Duration::days(1)).is_err());
+
+        // After it expired.
+        assert_match!(Some(&Error::Expired) =
+                      tpk.alive_at(now + time::Duration::days(2))
+                      .unwrap_err().downcast_ref::<Error>());
+
+        // After it was revoked.
+        let mut keypair = tpk.primary().clone().into_keypair().unwrap();
+        let tpk = tpk.revoke_in_place(&mut keypair,
+                                      ReasonForRevocation::KeyCompromised,
+                                      b"Oops").unwrap();
+        assert_match!(Some(&Error::Revoked) =
+                      tpk.alive().unwrap_err().downcast_ref::<Error>());
+    }
+
+    /// Tests that the filters use the same self-signature as
+    /// `TPK::primary_key_signature`, when there is both a direct key
+    /// signature and a user id binding.
+    #[test]
+    fn direct_key_signature_and_userid_binding() {
+        use packet::key::Key4;
+        use constants::Curve;
+
+        let key: Key = Key4::generate_ecc(true, Curve::Ed25519).unwrap().into();
+        let mut pair = key.clone().into_keypair().unwrap();
+        let t = *key.creation_time();
+        let userid = UserID::from("foo@example.org");
+
+        // The direct key signature says the key can sign, and does
+        // not expire.
+        let direct = signature::Builder::new(SignatureType::DirectKey)
+            .set_signature_creation_time(t).unwrap()
+            .set_key_flags(&KeyFlags::default()
+                           .set_certify(true).set_sign(true)).unwrap()
+            .set_issuer_fingerprint(key.fingerprint()).unwrap()
+            .sign_primary_key_binding(&mut pair, HashAlgorithm::SHA512)
+            .unwrap();
+        // The user id binding says the key can only certify, and
+        // expires after a day.
+        let binding = signature::Builder::new(SignatureType::PositiveCertificate)
+            .set_signature_creation_time(t).unwrap()
+            .set_primary_userid(true).unwrap()
+            .set_key_flags(&KeyFlags::default().set_certify(true)).unwrap()
+            .set_key_expiration_time(Some(time::Duration::days(1))).unwrap()
+            .set_issuer_fingerprint(key.fingerprint()).unwrap()
+            .sign_userid_binding(&mut pair, &key, &userid,
+                                 HashAlgorithm::SHA512).unwrap();
+
+        let tpk = TPK::from_packet_pile(PacketPile::from(vec![
+            key.clone().into_packet(Tag::PublicKey).unwrap(),
+            direct.into(),
+            userid.into(),
+            binding.into(),
+        ])).unwrap();
+
+        // The user id binding is in effect.
+        assert_eq!(tpk.primary_key_signature().unwrap().sigtype(),
+                   SignatureType::PositiveCertificate);
+        assert_eq!(tpk.primary_key_signature_at(None),
+                   tpk.primary_key_signature());
+
+        assert_eq!(tpk.keys_valid().certification_capable().count(), 1);
+        assert_eq!(tpk.keys_valid().signing_capable().count(), 0);
+        assert_eq!(tpk.keys_all().alive().signing_capable().count(), 0);
+
+        let later = t + time::Duration::days(2);
+        assert!(! tpk.expired());
+        assert!(tpk.expired_at(later));
+        assert_eq!(tpk.keys_valid_at(later).count(), 0);
+        assert_match!(Some(&Error::Expired) =
+                      tpk.alive_at(later).unwrap_err()
+                      .downcast_ref::<Error>());
+    }
+
+    #[test]
+    fn keys_valid_at() {
+        let (tpk, _) = TPKBuilder::new()
+            .add_userid("Alice")
+            .add_encryption_subkey()
+            .set_expiration(time::Duration::days(1))
+            .generate().unwrap();
+        let now = time::now_utc();
+
+        assert_eq!(tpk.keys_valid_at(now).count(), 2);
+        assert_eq!(tpk.keys_valid_at(None).count(), 2);
+        assert_eq!(tpk.keys_valid().count(), 2);
+        assert!(! tpk.expired_at(now));
+
+        // Before the keys were created.
+        assert_eq!(tpk.keys_valid_at(now - time::Duration::days(1)).count(),
+                   0);
+
+        // After they expired.
+        let later = now + time::Duration::days(2);
+        assert_eq!(tpk.keys_valid_at(later).count(), 0);
+        assert!(tpk.expired_at(later));
+
+        // Revocations only apply once they are made.
+        let revoked_at = now + time::Duration::hours(1);
+        let mut keypair = tpk.primary().clone().into_keypair().unwrap();
+        let rev = signature::Builder::new(SignatureType::KeyRevocation)
+            .set_signature_creation_time(revoked_at).unwrap()
+            .set_issuer_fingerprint(tpk.fingerprint()).unwrap()
+            .set_issuer(tpk.keyid()).unwrap()
+            .set_reason_for_revocation(ReasonForRevocation::KeySuperseded,
+                                       b"Moved on").unwrap()
+            .sign_primary_key_binding(&mut keypair,
+                                      HashAlgorithm::SHA512).unwrap();
+        let tpk = tpk.merge_packets(vec![rev.into()]).unwrap();
+        assert_eq!(tpk.keys_valid_at(now).count(), 2);
+        assert_eq!(tpk.keys_valid_at(revoked_at).count(), 0);
+    }
+
+    #[test]
+    fn preferred_algorithms() {
+        let tpk = TPK::from_bytes(::tests::key("testy.pgp")).unwrap();
+        assert_eq!(tpk.preferred_symmetric_algorithms(),
+                   Some(vec![SymmetricAlgorithm::AES256,
+                             SymmetricAlgorithm::AES192,
+                             SymmetricAlgorithm::AES128,
+                             SymmetricAlgorithm::TripleDES]));
+        assert_eq!(tpk.preferred_hash_algorithms(),
+                   Some(vec![HashAlgorithm::SHA256,
+                             HashAlgorithm::SHA384,
+                             HashAlgorithm::SHA512,
+                             HashAlgorithm::SHA224,
+                             HashAlgorithm::SHA1]));
+        assert_eq!(tpk.preferred_compression_algorithms(),
+                   Some(vec![CompressionAlgorithm::Zlib,
+                             CompressionAlgorithm::BZip2,
+                             CompressionAlgorithm::Zip]));
+        assert_eq!(tpk.preferred_aead_algorithms(), None);
+    }
+
+    #[test]
+    fn key_server_preferences() {
+        // GnuPG sets the no-modify flag by default.
+        let tpk = TPK::from_bytes(::tests::key("testy.pgp")).unwrap();
+        assert!(tpk.key_server_preferences().no_modify());
+
+        // We don't.
+        let (tpk, _) = TPKBuilder::new()
+            .add_userid("foo@example.org")
+            .generate().unwrap();
+        assert!(! tpk.key_server_preferences().no_modify());
+    }
+
+    #[test]
+    fn supported_features() {
+        use packet::key::Key4;
+        use constants::Curve;
+
+        // Advertises MDC, but not AEAD.
+        let tpk = TPK::from_bytes(::tests::key("testy.pgp")).unwrap();
+        assert!(tpk.supports_seip());
+        assert!(! tpk.supports_aead());
+
+        // We advertise both.
+        let (tpk, _) = TPKBuilder::new()
+            .add_userid("foo@example.org")
+            .generate().unwrap();
+        assert!(tpk.supports_seip());
+        assert!(tpk.supports_aead());
+
+        // No Features subpacket at all.
+        let key: Key = Key4::generate_ecc(true, Curve::Ed25519).unwrap().into();
+        let mut pair = key.clone().into_keypair().unwrap();
+        let userid = UserID::from("foo@example.org");
+        let binding = signature::Builder::new(SignatureType::PositiveCertificate)
+            .set_issuer_fingerprint(key.fingerprint()).unwrap()
+            .sign_userid_binding(&mut pair, &key, &userid,
+                                 HashAlgorithm::SHA512).unwrap();
+        let tpk = TPK::from_packet_pile(PacketPile::from(vec![
+            key.clone().into_packet(Tag::PublicKey).unwrap(),
+            userid.into(),
+            binding.into(),
+        ])).unwrap();
+        assert!(! tpk.supports_seip());
+        assert!(! tpk.supports_aead());
+    }
+
+    #[test]
+    fn primary_key_signature_at() {
+        use packet::key::Key4;
+        use constants::Curve;
+
+        let t0 = time::strptime("1999-1-1", "%F").unwrap();
+        let t1 = time::strptime("2000-1-1", "%F").unwrap();
+        let t2 = time::strptime("2001-1-1", "%F").unwrap();
+        let key: Key = Key4::generate_ecc(true, Curve::Ed25519).unwrap().into();
+        let mut pair = key.clone().into_keypair().unwrap();
+        let userid = UserID::from("foo@example.org");
+
+        let binding = signature::Builder::new(SignatureType::PositiveCertificate)
+            .set_signature_creation_time(t0).unwrap()
+            .set_primary_userid(true).unwrap()
+            .set_issuer_fingerprint(key.fingerprint()).unwrap()
+            .set_preferred_hash_algorithms(vec![HashAlgorithm::SHA256]).unwrap()
+            .sign_userid_binding(&mut pair, &key, &userid,
+                                 HashAlgorithm::SHA512).unwrap();
+        let direct = |t, algo| {
+            signature::Builder::new(SignatureType::DirectKey)
+                .set_signature_creation_time(t).unwrap()
+                .set_issuer_fingerprint(key.fingerprint()).unwrap()
+                .set_preferred_hash_algorithms(vec![algo]).unwrap()
+                .sign_primary_key_binding(&mut pair.clone(),
+                                          HashAlgorithm::SHA512).unwrap()
+        };
+        let direct1 = direct(t1, HashAlgorithm::SHA384);
+        let direct2 = direct(t2, HashAlgorithm::SHA512);
+
+        // The user id is revoked between the two direct key
+        // signatures.
+        let t_rev = t1 + time::Duration::days(100);
+        let revocation =
+            signature::Builder::new(SignatureType::CertificateRevocation)
+            .set_signature_creation_time(t_rev).unwrap()
+            .set_issuer_fingerprint(key.fingerprint()).unwrap()
+            .sign_userid_binding(&mut pair.clone(), &key, &userid,
+                                 HashAlgorithm::SHA512).unwrap();
+
+        let tpk = TPK::from_packet_pile(PacketPile::from(vec![
+            key.clone().into_packet(Tag::PublicKey).unwrap(),
+            direct1.into(),
+            direct2.into(),
+            userid.into(),
+            binding.into(),
+            revocation.into(),
+        ])).unwrap();
+
+        let prefs = |t: time::Tm| {
+            tpk.primary_key_signature_at(t).unwrap()
+                .preferred_hash_algorithms().unwrap()
+        };
+
+        // Before any signature was made.
+        assert!(tpk.primary_key_signature_at(
+            time::strptime("1998-1-1", "%F").unwrap()).is_none());
+        // Only the user id binding exists.
+        assert_eq!(prefs(t0 + time::Duration::days(1)),
+                   vec![HashAlgorithm::SHA256]);
+        // Like in primary_key_signature_full, the user id binding
+        // takes precedence over the direct key signatures...
+        assert_eq!(prefs(t1 + time::Duration::days(1)),
+                   vec![HashAlgorithm::SHA256]);
+        // ... until the user id is revoked.
+        assert_eq!(prefs(t_rev + time::Duration::days(1)),
+                   vec![HashAlgorithm::SHA384]);
+        assert_eq!(prefs(t2 + time::Duration::days(1)),
+                   vec![HashAlgorithm::SHA512]);
+        assert_eq!(tpk.primary_key_signature_at(None).unwrap()
+                   .sigtype(), SignatureType::DirectKey);
+
+        assert_eq!(tpk.preferred_hash_algorithms_at(t1),
+                   Some(vec![HashAlgorithm::SHA256]));
+        assert_eq!(tpk.preferred_hash_algorithms_at(t2),
+                   Some(vec![HashAlgorithm::SHA512]));
+        assert_eq!(tpk.preferred_hash_algorithms_at(
+            time::strptime("1998-1-1", "%F").unwrap()), None);
+    }
+
     #[test]
     fn revoked_time() {
         use packet::Features;
@@ -3768,6 +4922,230 @@ mod test {
         assert!(tpk.is_tsk());
     }
 
+    #[test]
+    fn add_userid() {
+        let (tpk, _) = TPKBuilder::new()
+            .add_userid("alice@example.org")
+            .generate().unwrap();
+        let mut keypair = tpk.primary().clone().into_keypair().unwrap();
+
+        let userid = UserID::from("alice@example.com");
+        let tpk = tpk.add_userid(userid.clone(), &mut keypair,
+                                 HashAlgorithm::SHA256).unwrap();
+
+        // Make sure the binding survives a roundtrip.
+        let mut buf = Vec::new();
+        tpk.serialize(&mut buf).unwrap();
+        let tpk = TPK::from_bytes(&buf).unwrap();
+        assert_eq!(tpk.userids().count(), 2);
+        let uid = tpk.userids().find(|u| u.userid() == &userid).unwrap();
+        let sig = uid.binding_signature().unwrap();
+        assert_eq!(sig.sigtype(), SignatureType::PositiveCertificate);
+        assert_eq!(sig.hash_algo(), HashAlgorithm::SHA256);
+        assert!(sig.verify_userid_binding(tpk.primary(), tpk.primary(),
+                                          &userid).unwrap());
+
+        // Adding it again fails.
+        assert_match!(Some(&Error::InvalidArgument(_)) =
+                      tpk.clone().add_userid(userid, &mut keypair,
+                                             HashAlgorithm::SHA256)
+                      .unwrap_err().downcast_ref::<Error>());
+
+        // So does using the wrong key.
+        let (other, _) = TPKBuilder::new().generate().unwrap();
+        let mut other = other.primary().clone().into_keypair().unwrap();
+        assert_match!(Some(&Error::InvalidArgument(_)) =
+                      tpk.add_userid(UserID::from("mallory@example.org"),
+                                     &mut other, HashAlgorithm::SHA256)
+                      .unwrap_err().downcast_ref::<Error>());
+    }
+
+    #[test]
+    fn export_minimal() {
+        let tpk = TPK::from_bytes(::tests::key("neal.pgp")).unwrap();
+        assert!(tpk.userids().count() > 1);
+        assert!(tpk.userids().any(|u| u.certifications().len() > 0));
+
+        let mut full = Vec::new();
+        armor::Encoder::new(&tpk).serialize(&mut full).unwrap();
+        let minimal = tpk.export_minimal().unwrap();
+        assert!(minimal.len() < full.len());
+        assert!(minimal.starts_with(b"-----BEGIN PGP PUBLIC KEY BLOCK-----"));
+
+        let min = TPK::from_bytes(&minimal).unwrap();
+        assert_eq!(min.fingerprint(), tpk.fingerprint());
+        assert!(! min.is_tsk());
+        assert!(min.bad.is_empty());
+
+        // Only the primary user id, with only its self-signature.
+        assert_eq!(min.userids().count(), 1);
+        let uid = min.userids().next().unwrap();
+        assert_eq!(uid.userid(), tpk.userids().next().unwrap().userid());
+        assert_eq!(uid.selfsigs().len(), 1);
+        assert_eq!(uid.certifications().len(), 0);
+        assert!(uid.binding_signature().unwrap()
+                .verify_userid_binding(min.primary(), min.primary(),
+                                       uid.userid()).unwrap());
+
+        // The valid subkeys.
+        assert!(min.subkeys().count() <= tpk.subkeys().count());
+        for sk in min.subkeys() {
+            assert_eq!(sk.selfsigs().len(), 1);
+            assert!(sk.binding_signature().unwrap()
+                    .verify_subkey_binding(min.primary(), min.primary(),
+                                           sk.subkey()).unwrap());
+        }
+
+        // Secrets are not exported.
+        let tsk = TPK::from_bytes(
+            ::tests::key("testy-new-private.pgp")).unwrap();
+        assert!(tsk.is_tsk());
+        let min = TPK::from_bytes(&tsk.export_minimal().unwrap()).unwrap();
+        assert!(! min.is_tsk());
+        assert_eq!(min.subkeys().count(), tsk.subkeys().count());
+    }
+
+    #[test]
+    fn export_minimal_keeps_revocations() {
+        let (tpk, _) = TPKBuilder::new()
+            .add_userid("Alice")
+            .add_encryption_subkey()
+            .generate().unwrap();
+        let mut keypair = tpk.primary().clone().into_keypair().unwrap();
+        let (revoker, _) = TPKBuilder::new().generate().unwrap();
+        let mut revoker = revoker.primary().clone().into_keypair().unwrap();
+
+        let primary_rev = tpk.revoke(&mut keypair,
+                                     ReasonForRevocation::KeyRetired,
+                                     b"Retired").unwrap();
+        let primary_other_rev = {
+            let mut hash = HashAlgorithm::SHA512.context().unwrap();
+            tpk.primary().hash(&mut hash);
+            signature::Builder::new(SignatureType::KeyRevocation)
+                .set_reason_for_revocation(
+                    ReasonForRevocation::KeyCompromised, b"Leaked").unwrap()
+                .sign_hash(&mut revoker, HashAlgorithm::SHA512, hash)
+                .unwrap()
+        };
+        let (uid_rev, uid_other_rev, subkey_other_rev) = {
+            let uid = tpk.userids().nth(0).unwrap().userid();
+            let subkey = tpk.subkeys().nth(0).unwrap().subkey();
+            (uid.revoke(&mut keypair, &tpk,
+                        ReasonForRevocation::UIDRetired, b"", None, None)
+             .unwrap(),
+             uid.revoke(&mut revoker, &tpk,
+                        ReasonForRevocation::UIDRetired, b"", None, None)
+             .unwrap(),
+             subkey.revoke(&mut revoker, &tpk,
+                           ReasonForRevocation::KeyRetired, b"", None, None)
+             .unwrap())
+        };
+        let tpk = tpk.merge_packets(vec![
+            primary_rev.clone().into(),
+            primary_other_rev.clone().into(),
+            uid_rev.clone().into(),
+            uid_other_rev.clone().into(),
+            subkey_other_rev.clone().into(),
+        ]).unwrap();
+        assert_eq!(tpk.other_revocations(), &[primary_other_rev.clone()][..]);
+
+        let min = TPK::from_bytes(&tpk.export_minimal().unwrap()).unwrap();
+        assert_eq!(min.self_revocations(), &[primary_rev][..]);
+        assert_eq!(min.other_revocations(), &[primary_other_rev][..]);
+        let uid = min.userids().nth(0).unwrap();
+        assert_eq!(uid.self_revocations(), &[uid_rev][..]);
+        assert_eq!(uid.other_revocations(), &[uid_other_rev][..]);
+        // The designated revoker's revocation does not revoke the
+        // subkey by itself, but it is exported nonetheless.
+        let sk = min.subkeys().nth(0).unwrap();
+        assert_eq!(sk.self_revocations().len(), 0);
+        assert_eq!(sk.other_revocations(), &[subkey_other_rev][..]);
+    }
+
+    #[test]
+    fn minimize() {
+        let tsk = TPK::from_bytes(
+            ::tests::key("testy-new-private.pgp")).unwrap();
+        assert!(tsk.is_tsk());
+        let tpk = tsk.clone().strip_secret_keys();
+        assert!(! tpk.is_tsk());
+        assert!(tpk.primary().secret().is_none());
+        assert!(tpk.subkeys().all(|sk| sk.subkey().secret().is_none()));
+        assert_eq!(tpk.fingerprint(), tsk.fingerprint());
+        assert_eq!(tpk.subkeys().count(), tsk.subkeys().count());
+
+        let tpk = TPK::from_bytes(::tests::key("neal.pgp")).unwrap();
+        assert!(tpk.userids().any(|u| u.certifications().len() > 0));
+        let min = tpk.clone().minimize();
+        assert_eq!(min.userids().count(), tpk.userids().count());
+        assert_eq!(min.subkeys().count(), tpk.subkeys().count());
+
+        // Make sure the result survives a roundtrip, and that the
+        // remaining signatures verify.
+        let mut buf = Vec::new();
+        min.serialize(&mut buf).unwrap();
+        let min = TPK::from_bytes(&buf).unwrap();
+        assert!(! min.is_tsk());
+        assert!(min.bad.is_empty());
+        assert_eq!(min.userids().count(), tpk.userids().count());
+        assert!(min.selfsigs().len() <= 1);
+        assert_eq!(min.certifications().len(), 0);
+        for uid in min.userids() {
+            assert_eq!(uid.selfsigs().len(), 1);
+            assert_eq!(uid.certifications().len(), 0);
+            assert!(uid.binding_signature().unwrap()
+                    .verify_userid_binding(min.primary(), min.primary(),
+                                           uid.userid()).unwrap());
+        }
+        for sk in min.subkeys() {
+            assert_eq!(sk.selfsigs().len(), 1);
+            assert!(sk.binding_signature().unwrap()
+                    .verify_subkey_binding(min.primary(), min.primary(),
+                                           sk.subkey()).unwrap());
+        }
+
+        let mut full = Vec::new();
+        tpk.serialize(&mut full).unwrap();
+        assert!(buf.len() < full.len());
+    }
+
+    #[test]
+    fn minimize_skips_expired_selfsigs() {
+        use packet::key::Key4;
+        use constants::Curve;
+
+        let t1 = time::now_utc() - time::Duration::days(20);
+        let t2 = time::now_utc() - time::Duration::days(10);
+        let key: Key = Key4::generate_ecc(true, Curve::Ed25519).unwrap().into();
+        let mut pair = key.clone().into_keypair().unwrap();
+        let userid = UserID::from("foo@example.org");
+
+        let binding = |t, expiration| {
+            signature::Builder::new(SignatureType::PositiveCertificate)
+                .set_signature_creation_time(t).unwrap()
+                .set_signature_expiration_time(expiration).unwrap()
+                .set_issuer_fingerprint(key.fingerprint()).unwrap()
+                .sign_userid_binding(&mut pair.clone(), &key, &userid,
+                                     HashAlgorithm::SHA512).unwrap()
+        };
+        let valid = binding(t1, None);
+        // The newest self-signature expired after a day.
+        let expired = binding(t2, Some(time::Duration::days(1)));
+        assert!(! expired.signature_alive());
+
+        let tpk = TPK::from_packet_pile(PacketPile::from(vec![
+            key.clone().into_packet(Tag::PublicKey).unwrap(),
+            userid.clone().into(),
+            valid.clone().into(),
+            expired.into(),
+        ])).unwrap();
+        assert_eq!(tpk.userids().nth(0).unwrap().selfsigs().len(), 2);
+
+        let min = tpk.minimize();
+        let selfsigs = min.userids().nth(0).unwrap().selfsigs();
+        assert_eq!(selfsigs, &[valid][..]);
+    }
+
     #[test]
     fn export_only_exports_public_key() {
         let tpk = TPK::from_bytes(
@@ -3822,6 +5200,34 @@ mod test {
         assert_eq!(tpk.keys_all().key_flags(flags).alive_at(now).count(), 0);
     }
 
+    #[test]
+    fn select_capabilities() {
+        let (tpk, _) = TPKBuilder::new()
+            .add_encryption_subkey()
+            .add_subkey(KeyFlags::default().set_encrypt_at_rest(true))
+            .add_signing_subkey()
+            .add_subkey(KeyFlags::default()
+                        .set_sign(true).set_authenticate(true))
+            .generate().unwrap();
+
+        // The primary key is certification capable.
+        assert_eq!(tpk.keys_valid().certification_capable().count(), 1);
+        assert_eq!(tpk.keys_valid().encryption_capable().count(), 2);
+        assert_eq!(tpk.keys_valid().signing_capable().count(), 2);
+        assert_eq!(tpk.keys_valid().authentication_capable().count(), 1);
+
+        // The filters compose.
+        assert_eq!(tpk.keys_valid().encryption_capable()
+                   .authentication_capable().count(), 3);
+        assert_eq!(tpk.keys_valid().authentication_capable()
+                   .secret(false).count(), 0);
+
+        let mut past = time::now_utc();
+        past.tm_year -= 1;
+        assert_eq!(tpk.keys_all().encryption_capable()
+                   .alive_at(past).count(), 0);
+    }
+
     #[test]
     fn select_primary() {
         let (tpk, _) = TPKBuilder::new()
@@ -3938,6 +5344,23 @@ Pu1xwz57O4zo1VYf6TqHJzVC3OMvMUM2hhdecMUe5x6GorNaj6g=
         assert_eq!(tpk.subkeys().len(), 2);
     }
 
+    #[test]
+    fn v3_certification() {
+        // Testy's primary key and user id, bound using a version 3
+        // positive certification.
+        let tpk = TPK::from_bytes(
+            ::tests::key("testy-v3-certification.pgp")).unwrap();
+        assert_eq!(tpk.userids().count(), 1);
+        let uid = tpk.userids().nth(0).unwrap();
+        assert_eq!(uid.userid().value(),
+                   &b"Testy McTestface <testy@example.org>"[..]);
+        assert_eq!(uid.selfsigs().len(), 1);
+        let sig = &uid.selfsigs()[0];
+        assert_eq!(sig.version(), 3);
+        assert!(sig.verify_userid_binding(tpk.primary(), tpk.primary(),
+                                          uid.userid()).unwrap());
+    }
+
     #[test]
     fn signature_order() {
         let neal = TPK::from_bytes(::tests::key("neal.pgp")).unwrap();
@@ -3950,4 +5373,218 @@ Pu1xwz57O4zo1VYf6TqHJzVC3OMvMUM2hhdecMUe5x6GorNaj6g=
         assert_eq!(uidb.selfsigs().last().unwrap(),
                    uidb.binding_signature().unwrap());
     }
+
+    #[test]
+    fn add_subkey() {
+        use constants::Curve;
+        use packet::key::Key4;
+
+        let (tpk, _) = TPKBuilder::new()
+            .add_userid("test@example.org")
+            .generate().unwrap();
+        let mut keypair = tpk.primary().clone().into_keypair().unwrap();
+        let encryption = KeyFlags::default()
+            .set_encrypt_for_transport(true)
+            .set_encrypt_at_rest(true);
+        let signing = KeyFlags::default().set_sign(true);
+        assert_eq!(tpk.keys_valid().key_flags(encryption.clone()).count(), 0);
+
+        // An encryption subkey.
+        let subkey = Key::V4(Key4::generate_ecc(false, Curve::Cv25519)
+                             .unwrap());
+        let sigs = tpk.add_subkey(&mut keypair, &subkey, &encryption, None)
+            .unwrap();
+        assert_eq!(sigs.len(), 1);
+        assert!(sigs[0].embedded_signature().is_none());
+        let mut packets = vec![subkey.clone().into_packet(Tag::SecretSubkey)
+                               .unwrap()];
+        packets.extend(sigs.into_iter().map(|s| s.into()));
+        let tpk = tpk.merge_packets(packets).unwrap();
+
+        assert_eq!(tpk.subkeys().count(), 1);
+        let keys = tpk.keys_valid().key_flags(encryption.clone())
+            .map(|(_, _, key)| key.fingerprint())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![subkey.fingerprint()]);
+
+        // A signing subkey needs a back signature.
+        let subkey = Key::V4(Key4::generate_ecc(true, Curve::Ed25519)
+                             .unwrap());
+        let sigs = tpk.add_subkey(&mut keypair, &subkey, &signing,
+                                  Some(time::Duration::days(365)))
+            .unwrap();
+        assert!(sigs[0].embedded_signature().is_some());
+        let mut packets = vec![subkey.clone().into_packet(Tag::SecretSubkey)
+                               .unwrap()];
+        packets.extend(sigs.into_iter().map(|s| s.into()));
+        let tpk = tpk.merge_packets(packets).unwrap();
+
+        assert_eq!(tpk.subkeys().count(), 2);
+        assert_eq!(tpk.keys_valid().signing_capable().count(), 1);
+
+        // Without the secret, we cannot create the back signature.
+        let mut public = subkey.clone();
+        public.set_secret(None);
+        assert!(tpk.add_subkey(&mut keypair, &public, &signing, None)
+                .is_err());
+    }
+
+    #[test]
+    fn third_party_userid_certification() {
+        let (alice, _) = TPKBuilder::new()
+            .add_userid("alice@example.org")
+            .generate().unwrap();
+        let (bob, _) = TPKBuilder::new()
+            .add_userid("bob@example.org")
+            .generate().unwrap();
+        let mut keypair = alice.primary().clone().into_keypair().unwrap();
+        let userid = bob.userids().nth(0).unwrap().userid().clone();
+
+        // A regular third-party certification.
+        let certification = userid.certify(&mut keypair, &bob, None, None, None)
+            .unwrap();
+
+        let mut bob_keypair = bob.primary().clone().into_keypair().unwrap();
+
+        // A signature made by Bob's primary key, which therefore
+        // verifies, but whose issuer subpacket names Alice.  Because
+        // the issuer fingerprint names Bob, it is considered a
+        // self-signature when parsing.
+        let forgery = signature::Builder::new(SignatureType::PositiveCertificate)
+            .set_signature_creation_time(time::now_utc()).unwrap()
+            .set_issuer_fingerprint(bob.fingerprint()).unwrap()
+            .set_issuer(alice.keyid()).unwrap()
+            .sign_userid_binding(&mut bob_keypair, bob.primary(), &userid,
+                                 HashAlgorithm::SHA512).unwrap();
+        assert!(forgery.verify_userid_binding(bob.primary(), bob.primary(),
+                                              &userid).unwrap());
+
+        // A self-signature without any issuer information.
+        let mut anonymous =
+            signature::Builder::new(SignatureType::PositiveCertificate)
+            .set_signature_creation_time(time::now_utc()).unwrap()
+            .sign_userid_binding(&mut bob_keypair, bob.primary(), &userid,
+                                 HashAlgorithm::SHA512).unwrap();
+        anonymous.unhashed_area_mut().clear();
+        assert!(anonymous.issuer().is_none());
+        assert!(anonymous.issuer_fingerprint().is_none());
+
+        let mut bob = bob.merge_packets(vec![certification.clone().into(),
+                                             forgery.clone().into()])
+            .unwrap();
+        {
+            let uidb = bob.userids().nth(0).unwrap();
+            assert_eq!(uidb.selfsigs().len(), 1);
+            assert_eq!(uidb.certifications(), &[certification][..]);
+            assert!(! uidb.selfsigs().contains(&forgery));
+            assert!(! uidb.certifications().contains(&forgery));
+        }
+
+        // The parser files signatures without issuer information as
+        // certifications, so add it as a self-signature by hand.
+        bob.userids[0].selfsigs.push(anonymous.clone());
+        let bob = bob.canonicalize();
+        let uidb = bob.userids().nth(0).unwrap();
+        assert_eq!(uidb.selfsigs().len(), 2);
+        assert!(uidb.selfsigs().contains(&anonymous));
+    }
+
+    #[test]
+    fn userid_certifications() {
+        let ivanka = TPK::from_bytes(::tests::key("ivanka-private.gpg"))
+            .unwrap();
+        let tpk = TPK::from_bytes(
+            ::tests::key("bannon-ivanka-signs-base.gpg")).unwrap();
+
+        let userid = tpk.userids().nth(0).unwrap().userid().clone();
+        let certs = tpk.userid_certifications(&userid);
+        assert_eq!(certs.len(), 1);
+        assert_eq!(certs[0].issuer(), Some(ivanka.keyid()));
+        assert!(certs[0].verify_userid_binding(ivanka.primary(),
+                                               tpk.primary(),
+                                               &userid).unwrap());
+
+        // No self-signatures.
+        assert!(certs.iter().all(|c| c.issuer() != Some(tpk.keyid())));
+
+        // Unknown user ids don't have any certifications.
+        assert!(tpk.userid_certifications(
+            &UserID::from("Someone Else <else@example.org>")).is_empty());
+    }
+
+    #[test]
+    fn bad_subkeys() {
+        let signing_subkey = Fingerprint::from_hex(
+            "3DDF 16B9 4CE1 9728 7641  7E7F C8E3 50AF 56FB 0014").unwrap();
+
+        let good = TPK::from_bytes(
+            ::tests::key("signing-subkey.pgp")).unwrap();
+        assert_eq!(good.subkeys().count(), 1);
+        assert!(good.bad_subkeys().is_empty());
+
+        // The binding signature is fine, but the back signature has
+        // been stripped.
+        let tpk = TPK::from_bytes(
+            ::tests::key("signing-subkey-no-backsig.pgp")).unwrap();
+        assert_eq!(tpk.subkeys().count(), 0);
+        assert_eq!(tpk.bad_subkeys(),
+                   vec![(signing_subkey.clone(), SubkeyError::MissingBacksig)]);
+
+        // Once a valid binding shows up, the subkey is no longer
+        // reported.
+        let merged = tpk.merge(good.clone()).unwrap();
+        assert_eq!(merged.subkeys().count(), 1);
+        assert!(merged.bad_subkeys().is_empty());
+
+        // Attach the subkey and its binding signature to a different
+        // primary key.
+        let mut packets = TPK::from_bytes(::tests::key("neal.pgp")).unwrap()
+            .into_packets();
+        packets.extend(good.into_packets().into_iter().skip_while(|p| {
+            if let &Packet::PublicSubkey(_) = p { false } else { true }
+        }));
+        let tpk = TPK::from_packet_pile(PacketPile::from(packets)).unwrap();
+        assert!(tpk.subkeys().all(|b| b.subkey().fingerprint()
+                                  != signing_subkey));
+        assert_eq!(tpk.bad_subkeys(),
+                   vec![(signing_subkey, SubkeyError::BadBinding)]);
+    }
+
+    #[test]
+    fn tpk_parser_recovers() {
+        use parse::{PacketParserBuilder, ErrorPolicy};
+
+        let testy = ::tests::key("testy.pgp");
+        let neal = ::tests::key("neal.pgp");
+
+        // A keyring consisting of testy, a primary key followed by a
+        // literal data packet, a SKESK packet that we can't parse
+        // (it uses OCB), and neal.
+        let mut keyring = testy.to_vec();
+        keyring.extend_from_slice(::tests::key("testy-only-a-pk.pgp"));
+        keyring.extend_from_slice(::tests::message("literal-mode-b.gpg"));
+        keyring.extend_from_slice(&[0xc3, 0x05, 0x05, 0x09, 0x02, 0x00, 0x08]);
+        keyring.extend_from_slice(neal);
+
+        let parse = |policy| {
+            let ppr = PacketParserBuilder::from_bytes(&keyring[..]).unwrap()
+                .on_error(policy)
+                .finalize().unwrap();
+            TPKParser::from_packet_parser(ppr)
+                .map(|r| r.map(|tpk| tpk.fingerprint()).ok())
+                .collect::<Vec<_>>()
+        };
+
+        let testy = TPK::from_bytes(testy).unwrap().fingerprint();
+        let neal = TPK::from_bytes(neal).unwrap().fingerprint();
+
+        // The malformed TPK is skipped.
+        assert_eq!(parse(ErrorPolicy::SkipPacket),
+                   vec![ Some(testy.clone()), None, Some(neal.clone()) ]);
+
+        // The parser fails on the SKESK packet, so we never see neal.
+        let tpks = parse(ErrorPolicy::FailFast);
+        assert_eq!(tpks[0], Some(testy));
+        assert!(! tpks.contains(&Some(neal)));
+    }
 }
@@ -0,0 +1,236 @@
+//! Parallel verification of a TPK's signatures.
+
+use rayon::ThreadPool;
+use rayon::prelude::*;
+
+use Result;
+use TPK;
+use packet::{Key, Signature, UserID, UserAttribute};
+
+/// The results of verifying a TPK's signatures.
+///
+/// Returned by [`TPK::verify_self_signatures_parallel`].
+///
+///   [`TPK::verify_self_signatures_parallel`]: ../struct.TPK.html#method.verify_self_signatures_parallel
+#[derive(Debug)]
+pub struct VerificationReport<'a> {
+    results: Vec<(&'a Signature, Result<bool>)>,
+}
+
+impl<'a> VerificationReport<'a> {
+    /// Returns the signatures and the results of verifying them.
+    ///
+    /// The order does not depend on the scheduling of the threads:
+    /// first the primary key's self-signatures and self-revocations
+    /// are listed, then those of the user ids, the user attributes,
+    /// and the subkeys, in the order in which the TPK returns them.
+    pub fn results(&self) -> &[(&'a Signature, Result<bool>)] {
+        &self.results
+    }
+
+    /// Returns whether all signatures are valid.
+    pub fn all_valid(&self) -> bool {
+        self.results.iter().all(|&(_, ref r)| {
+            r.as_ref().map(|&valid| valid).unwrap_or(false)
+        })
+    }
+}
+
+/// The component a signature is checked against.
+enum Component<'a> {
+    PrimaryKey,
+    UserID(UserID),
+    UserAttribute(&'a UserAttribute),
+    Subkey(&'a Key),
+}
+
+/// A deferred signature check.
+///
+/// `Signature`s and `UserID`s lazily parse their content, and cache
+/// the result in a `RefCell`.  Hence, they cannot be shared between
+/// threads, and every check owns a copy of them.
+struct Check<'a> {
+    sig: Signature,
+    component: Component<'a>,
+    revocation: bool,
+}
+
+impl<'a> Check<'a> {
+    fn new(sig: &Signature, component: Component<'a>, revocation: bool)
+           -> Self {
+        Check {
+            sig: sig.clone(),
+            component: component,
+            revocation: revocation,
+        }
+    }
+
+    /// Verifies the signature, which was issued by `primary`.
+    fn verify(&self, primary: &Key) -> Result<bool> {
+        let sig = &self.sig;
+        let pk = primary;
+        match (&self.component, self.revocation) {
+            (&Component::PrimaryKey, false) =>
+                sig.verify_primary_key_binding(pk, pk),
+            (&Component::PrimaryKey, true) =>
+                sig.verify_primary_key_revocation(pk, pk),
+            (&Component::UserID(ref userid), false) =>
+                sig.verify_userid_binding(pk, pk, userid),
+            (&Component::UserID(ref userid), true) =>
+                sig.verify_userid_revocation(pk, pk, userid),
+            (&Component::UserAttribute(ua), false) =>
+                sig.verify_user_attribute_binding(pk, pk, ua),
+            (&Component::UserAttribute(ua), true) =>
+                sig.verify_user_attribute_revocation(pk, pk, ua),
+            (&Component::Subkey(subkey), false) =>
+                sig.verify_subkey_binding(pk, pk, subkey),
+            (&Component::Subkey(subkey), true) =>
+                sig.verify_subkey_revocation(pk, pk, subkey),
+        }
+    }
+}
+
+impl TPK {
+    /// Verifies the TPK's self-signatures and self-revocations using
+    /// the given thread pool.
+    ///
+    /// Each signature is checked independently, so the checks are
+    /// distributed across the pool's threads.
+    ///
+    /// Only signatures issued by the primary key are checked.
+    /// Third-party certifications and revocations issued by other
+    /// keys, e.g., designated revokers, are not included in the
+    /// report, because their issuers' keys are not available.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # extern crate sequoia_openpgp as openpgp;
+    /// # extern crate rayon;
+    /// # use openpgp::Result;
+    /// # use openpgp::tpk::TPKBuilder;
+    /// # fn main() { f().unwrap(); }
+    /// # fn f() -> Result<()> {
+    /// let (tpk, _) = TPKBuilder::default()
+    ///     .add_userid("Alice")
+    ///     .add_signing_subkey()
+    ///     .generate()?;
+    ///
+    /// let pool = rayon::ThreadPoolBuilder::new().build()?;
+    /// let report = tpk.verify_self_signatures_parallel(&pool);
+    /// assert!(report.all_valid());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn verify_self_signatures_parallel(&self, pool: &ThreadPool)
+                                           -> VerificationReport {
+        let mut sigs: Vec<&Signature> = Vec::new();
+        let mut checks: Vec<Check> = Vec::new();
+
+        {
+            let mut add = |sig, component, revocation| {
+                sigs.push(sig);
+                checks.push(Check::new(sig, component, revocation));
+            };
+
+            for sig in self.selfsigs() {
+                add(sig, Component::PrimaryKey, false);
+            }
+            for sig in self.self_revocations() {
+                add(sig, Component::PrimaryKey, true);
+            }
+
+            for binding in self.userids() {
+                for sig in binding.selfsigs() {
+                    add(sig, Component::UserID(binding.userid().clone()),
+                        false);
+                }
+                for sig in binding.self_revocations() {
+                    add(sig, Component::UserID(binding.userid().clone()),
+                        true);
+                }
+            }
+
+            for binding in self.user_attributes() {
+                let ua = binding.user_attribute();
+                for sig in binding.selfsigs() {
+                    add(sig, Component::UserAttribute(ua), false);
+                }
+                for sig in binding.self_revocations() {
+                    add(sig, Component::UserAttribute(ua), true);
+                }
+            }
+
+            for binding in self.subkeys() {
+                let subkey = binding.subkey();
+                for sig in binding.selfsigs() {
+                    add(sig, Component::Subkey(subkey), false);
+                }
+                for sig in binding.self_revocations() {
+                    add(sig, Component::Subkey(subkey), true);
+                }
+            }
+        }
+
+        // Collecting an indexed parallel iterator preserves the
+        // order.
+        let primary = self.primary();
+        let results: Vec<Result<bool>> = pool.install(|| {
+            checks.into_par_iter()
+                .map(|check| check.verify(primary))
+                .collect()
+        });
+
+        VerificationReport {
+            results: sigs.into_iter().zip(results.into_iter()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rayon::ThreadPoolBuilder;
+    use parse::Parse;
+
+    #[test]
+    fn verify_self_signatures_parallel() {
+        let pool = ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+
+        for name in &["neal.pgp", "dkg.gpg", "already-revoked.pgp"] {
+            let tpk = TPK::from_bytes(::tests::key(name)).unwrap();
+            let report = tpk.verify_self_signatures_parallel(&pool);
+            assert!(report.all_valid());
+
+            // The order is deterministic.
+            let sigs = tpk.selfsigs().iter()
+                .chain(tpk.self_revocations().iter())
+                .chain(tpk.userids().flat_map(|b| {
+                    b.selfsigs().iter().chain(b.self_revocations().iter())
+                }))
+                .chain(tpk.user_attributes().flat_map(|b| {
+                    b.selfsigs().iter().chain(b.self_revocations().iter())
+                }))
+                .chain(tpk.subkeys().flat_map(|b| {
+                    b.selfsigs().iter().chain(b.self_revocations().iter())
+                }))
+                .collect::<Vec<_>>();
+            assert_eq!(report.results().iter().map(|&(sig, _)| sig)
+                       .collect::<Vec<_>>(),
+                       sigs);
+        }
+
+        // A self-signature over a different user id doesn't verify.
+        let mut tpk = TPK::from_bytes(::tests::key("neal.pgp")).unwrap();
+        assert!(tpk.userids.len() > 1);
+        let sig = tpk.userids[0].selfsigs[0].clone();
+        tpk.userids[1].selfsigs.push(sig);
+        let report = tpk.verify_self_signatures_parallel(&pool);
+        assert!(! report.all_valid());
+        assert_eq!(report.results().iter()
+                   .filter(|&&(_, ref r)| {
+                       ! r.as_ref().map(|&valid| valid).unwrap_or(false)
+                   })
+                   .count(), 1);
+    }
+}
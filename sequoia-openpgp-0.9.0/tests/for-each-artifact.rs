@@ -48,6 +48,45 @@ mod for_each_artifact {
         }).unwrap();
     }
 
+    #[test]
+    fn packet_pile_roundtrip() {
+        let data = test_data_dir();
+        for_all_files(&data, |src| {
+            let rel = src.strip_prefix(&data).unwrap();
+            if PACKET_PILE_SKIP.iter()
+                .any(|&(prefix, _)| rel.starts_with(prefix))
+            {
+                return Ok(());
+            }
+
+            let p = openpgp::PacketPile::from_file(src)?;
+
+            // Serializing uses minimal framing, so the result may
+            // differ from the source file, but the packets (including
+            // any containers' children and unknown packets) must
+            // survive unchanged.
+            let mut v = Vec::new();
+            p.serialize(&mut v)?;
+            let q = openpgp::PacketPile::from_bytes(&v)?;
+            assert_eq!(p.children().len(), q.children().len(),
+                       "roundtripping {:?} failed", src);
+            for (a, b) in p.children().zip(q.children()) {
+                assert!(a.semantic_eq(b),
+                        "roundtripping {:?} failed: {:?} != {:?}", src, a, b);
+            }
+
+            // Serializing again must be idempotent.
+            let mut w = Vec::new();
+            q.serialize(&mut w)?;
+            assert_eq!(v, w, "reserializing {:?} is not idempotent", src);
+
+            let w = p.to_vec().unwrap();
+            assert_eq!(v, w,
+                       "Serialize and SerializeInto disagree on {:?}", p);
+            Ok(())
+        }).unwrap();
+    }
+
     #[test]
     fn message_roundtrip() {
         for_all_files(&test_data_dir(), |src| {
@@ -71,6 +110,23 @@ mod for_each_artifact {
     }
 }
 
+/// Fixtures skipped by `packet_pile_roundtrip`, relative to the
+/// test directory, and the reason for skipping them.
+const PACKET_PILE_SKIP: &[(&str, &str)] = &[
+    ("armor",
+     "test vectors for the ASCII armor codec, which encode random \
+      bytes, not OpenPGP packets"),
+    ("raw",
+     "raw ciphertexts for testing the symmetric ciphers, not OpenPGP \
+      packets"),
+    ("keys/corrupted.pgp",
+     "deliberately corrupted to test the parser's error recovery; the \
+      garbage that the parser skips is lost on serialization"),
+    ("messages/compression-quine.gpg",
+     "decompresses to itself, so the pile is as deep as the recursion \
+      limit allows, and its innermost packet is an unparsed body"),
+];
+
 /// Computes the path to the test directory.
 fn test_data_dir() -> PathBuf {
     let manifest_dir = PathBuf::from(
@@ -0,0 +1,63 @@
+//! Checks that secrets are cleared when they are dropped.
+//!
+//! This is a test program of its own, because it replaces the global
+//! allocator to inspect memory right before it is freed.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+extern crate sequoia_openpgp as openpgp;
+use openpgp::crypto::{Password, SessionKey};
+
+/// Address of the allocation to inspect when it is freed, or 0.
+static WATCHED: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the watched allocation was cleared before it was freed.
+static CLEARED: AtomicBool = AtomicBool::new(false);
+
+struct Inspector;
+
+unsafe impl GlobalAlloc for Inspector {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if ptr as usize == WATCHED.load(Ordering::SeqCst) {
+            let memory = slice::from_raw_parts(ptr, layout.size());
+            CLEARED.store(memory.iter().all(|&b| b == 0), Ordering::SeqCst);
+            WATCHED.store(0, Ordering::SeqCst);
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Inspector = Inspector;
+
+/// Drops `secret`, and returns whether the allocation at `ptr` was
+/// cleared before it was freed.
+fn cleared_on_drop<T>(secret: T, ptr: *const u8) -> bool {
+    CLEARED.store(false, Ordering::SeqCst);
+    WATCHED.store(ptr as usize, Ordering::SeqCst);
+    drop(secret);
+    assert_eq!(WATCHED.load(Ordering::SeqCst), 0, "memory was not freed");
+    CLEARED.load(Ordering::SeqCst)
+}
+
+#[test]
+fn protected_memory() {
+    // Make sure that the inspector works.
+    let v = vec![0x5au8; 32];
+    let ptr = v.as_ptr();
+    assert!(! cleared_on_drop(v, ptr));
+
+    let sk: SessionKey = vec![0x5a; 32].into();
+    let ptr = sk.as_ptr();
+    assert!(cleared_on_drop(sk, ptr), "session key was not cleared");
+
+    let password: Password = "very secret".into();
+    let ptr = password.map(|p| p.as_ptr());
+    assert!(cleared_on_drop(password, ptr), "password was not cleared");
+}